@@ -0,0 +1,208 @@
+use std::fmt::{self, Display};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::tty::IsTty;
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::args::Args;
+use crate::client;
+use crate::http;
+use crate::ui::Ui;
+
+/// An account whose registry can be worked in by assuming a role into it.
+#[derive(Clone)]
+pub struct Account {
+    pub id: String,
+    /// Only known for accounts listed from the Organization.
+    pub name: Option<String>,
+    /// Role name or ARN to assume instead of `--account-role`.
+    pub role: Option<String>,
+}
+
+impl Account {
+    /// Parses an `--accounts` entry: an account id, optionally followed by
+    /// `:` and the role name or ARN to assume in it.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (id, role) = match spec.split_once(':') {
+            Some((id, role)) => (id, Some(role.to_string())),
+            None => (spec, None),
+        };
+        if id.len() != 12 || !id.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(anyhow!(
+                "--accounts expects 12 digit account ids, got `{}`",
+                id
+            ));
+        }
+        Ok(Self {
+            id: id.to_string(),
+            name: None,
+            role,
+        })
+    }
+
+    /// The ARN of the role to assume in the account, in the partition `region`
+    /// belongs to.
+    pub fn role_arn(&self, default_role: &str, region: &str) -> String {
+        match &self.role {
+            Some(arn) if arn.starts_with("arn:") => arn.clone(),
+            role => format!(
+                "arn:{}:iam::{}:role/{}",
+                partition(region),
+                self.id,
+                role.as_deref().unwrap_or(default_role)
+            ),
+        }
+    }
+}
+
+impl Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{} ({})", name, self.id),
+            None => write!(f, "{}", self.id),
+        }
+    }
+}
+
+/// The accounts given with `--accounts`, or the Organization's active
+/// accounts with `--org`; empty when neither was asked for.
+pub async fn list(args: &Args) -> Result<Vec<Account>> {
+    if !args.accounts.is_empty() {
+        return Ok(args.accounts.clone());
+    }
+    if !args.org {
+        return Ok(vec![]);
+    }
+    let mut accounts = match mock_accounts()? {
+        Some(accounts) => accounts,
+        None => organization(args).await?,
+    };
+    accounts.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(accounts)
+}
+
+/// The mock fixture's accounts, when `ECR_TAG_MOCK_REGISTRY` names one.
+#[cfg(feature = "mock")]
+fn mock_accounts() -> Result<Option<Vec<Account>>> {
+    use std::path::Path;
+
+    use crate::ecr::MockEcr;
+
+    let fixture = match std::env::var("ECR_TAG_MOCK_REGISTRY") {
+        Ok(fixture) => fixture,
+        Err(_) => return Ok(None),
+    };
+    let accounts = MockEcr::accounts(Path::new(&fixture))?
+        .into_iter()
+        .map(|(id, name)| Account {
+            id,
+            name: Some(name),
+            role: None,
+        })
+        .collect();
+    Ok(Some(accounts))
+}
+
+#[cfg(not(feature = "mock"))]
+fn mock_accounts() -> Result<Option<Vec<Account>>> {
+    Ok(None)
+}
+
+/// Picks the account to work in when `--org` or `--accounts` was given,
+/// assuming its role for the rest of the run. Commands that fan out over all
+/// the accounts are left alone.
+pub async fn pick(args: &mut Args, ui: &Ui) -> Result<()> {
+    if args.command.all_accounts() {
+        return Ok(());
+    }
+    let mut accounts = list(args).await?;
+    let account = match accounts.len() {
+        0 if args.org => bail!("the Organization has no active accounts"),
+        0 => return Ok(()),
+        1 => accounts.remove(0),
+        _ if !std::io::stdin().is_tty() => {
+            bail!("pick an account in a terminal, or pass a single one with --accounts")
+        }
+        _ => ui.picker("account:", accounts).prompt()?,
+    };
+    args.target_account = Some(account);
+    Ok(())
+}
+
+fn partition(region: &str) -> &'static str {
+    if region.starts_with("cn-") {
+        "aws-cn"
+    } else if region.starts_with("us-gov-") {
+        "aws-us-gov"
+    } else {
+        "aws"
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListAccountsResponse {
+    accounts: Vec<OrganizationAccount>,
+    next_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct OrganizationAccount {
+    id: String,
+    name: String,
+    status: String,
+}
+
+/// Calls Organizations ListAccounts, which only answers the management account
+/// or a delegated administrator.
+async fn organization(args: &Args) -> Result<Vec<Account>> {
+    let region = client::region(args)?;
+    // Organizations is a global service with one endpoint per partition.
+    let endpoint = match partition(region.name()) {
+        "aws-cn" => Region::CnNorthwest1,
+        "aws-us-gov" => Region::UsGovWest1,
+        _ => Region::UsEast1,
+    };
+    let credentials = client::credentials(args, region.name(), None)?
+        .credentials()
+        .await
+        .context("failed to resolve credentials")?;
+
+    let mut accounts = vec![];
+    let mut next_token = None;
+    loop {
+        let mut request = SignedRequest::new("POST", "organizations", &endpoint, "/");
+        request.add_header("x-amz-target", "AWSOrganizationsV20161128.ListAccounts");
+        request.set_content_type("application/x-amz-json-1.1".to_string());
+        let body = match &next_token {
+            Some(token) => json!({ "NextToken": token }),
+            None => json!({}),
+        };
+        request.set_payload(Some(body.to_string()));
+        let response = http::send_signed(request, &credentials, Duration::from_secs(30))
+            .await
+            .context("failed to list the Organization's accounts")?;
+        let page: ListAccountsResponse =
+            serde_json::from_str(&response).context("unexpected ListAccounts response")?;
+        accounts.extend(
+            page.accounts
+                .into_iter()
+                .filter(|account| account.status == "ACTIVE")
+                .map(|account| Account {
+                    id: account.id,
+                    name: Some(account.name),
+                    role: None,
+                }),
+        );
+        match page.next_token {
+            Some(token) => next_token = Some(token),
+            None => return Ok(accounts),
+        }
+    }
+}
@@ -0,0 +1,1034 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Result};
+use chrono::Duration;
+use crossterm::tty::IsTty;
+
+use crate::accounts::Account;
+use crate::backup::BackupStyle;
+use crate::config::Config;
+use crate::ecr::Destination;
+use crate::inventory::ExportFormat;
+use crate::junit::Checks;
+use crate::lock::Locks;
+use crate::metrics::Mutations;
+use crate::output::OutputFormat;
+use crate::retention::{self, Rules};
+use crate::sbom::SbomFormat;
+use crate::scan::{Gate, Severity};
+
+/// Minimal command line parser.
+///
+/// Flags are consumed as they are queried, so whatever is left at the end
+/// is something we didn't recognise.
+pub struct Parser {
+    args: Vec<String>,
+}
+
+impl Parser {
+    pub fn from_env() -> Self {
+        Self {
+            args: std::env::args().skip(1).collect(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn from_args(args: &[&str]) -> Self {
+        Self {
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+        }
+    }
+
+    /// Returns true if any of `names` was passed, removing every occurrence.
+    pub fn flag(&mut self, names: &[&str]) -> bool {
+        let before = self.args.len();
+        self.args.retain(|arg| !names.contains(&arg.as_str()));
+        self.args.len() != before
+    }
+
+    /// Returns the value of `--name value` or `--name=value`.
+    pub fn value(&mut self, names: &[&str]) -> Result<Option<String>> {
+        let mut values = self.values(names)?;
+        Ok(values.pop())
+    }
+
+    /// Returns every value of a flag that may be repeated.
+    pub fn values(&mut self, names: &[&str]) -> Result<Vec<String>> {
+        let mut values = vec![];
+        let mut i = 0;
+        while i < self.args.len() {
+            let arg = &self.args[i];
+            if names.contains(&arg.as_str()) {
+                if i + 1 >= self.args.len() {
+                    bail!("{} requires a value", arg);
+                }
+                self.args.remove(i);
+                values.push(self.args.remove(i));
+                continue;
+            }
+            if let Some((name, value)) = arg.split_once('=') {
+                if names.contains(&name) {
+                    values.push(value.to_string());
+                    self.args.remove(i);
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        Ok(values)
+    }
+
+    /// Takes the subcommand, if the first remaining argument is one.
+    pub fn subcommand(&mut self) -> Option<String> {
+        match self.args.first() {
+            Some(arg) if !arg.starts_with('-') => Some(self.args.remove(0)),
+            _ => None,
+        }
+    }
+
+    /// Takes the next positional argument.
+    pub fn positional(&mut self) -> Option<String> {
+        let index = self.args.iter().position(|arg| !arg.starts_with('-'))?;
+        Some(self.args.remove(index))
+    }
+
+    /// Fails if anything was passed that hasn't been consumed.
+    pub fn finish(self) -> Result<()> {
+        if let Some(arg) = self.args.first() {
+            bail!("unexpected argument: {}", arg);
+        }
+        Ok(())
+    }
+}
+
+pub struct Args {
+    pub command: Command,
+    pub output: OutputFormat,
+    pub bulk: bool,
+    pub tag_template: String,
+    pub no_color: bool,
+    /// Account whose registry the tag is put into, for cross-account promotion.
+    /// Read-only commands take it as the registry to read instead.
+    pub registry_id: Option<String>,
+    /// Account whose registry images are read from, e.g. a shared build account.
+    pub source_registry_id: Option<String>,
+    /// Repository the tag is put into, when it differs from the source.
+    pub to_repository: Option<String>,
+    pub region: Option<String>,
+    /// The `[context.<name>]` whose profile, region and role are used.
+    pub context: Option<String>,
+    /// Role assumed with the resolved credentials, set by the context.
+    pub role_arn: Option<String>,
+    /// The AWS profile to use instead of `AWS_PROFILE`, set by the context.
+    pub aws_profile: Option<String>,
+    /// Pick the account to work in from the Organization's accounts.
+    pub org: bool,
+    /// Pick the account to work in from these accounts instead.
+    pub accounts: Vec<Account>,
+    /// Role assumed into each account, `OrganizationAccountAccessRole` by default.
+    pub account_role: String,
+    /// The account picked from `--org`/`--accounts`, whose `account_role` is
+    /// assumed on top of everything else.
+    pub target_account: Option<Account>,
+    pub fips: bool,
+    pub endpoint_url: Option<String>,
+    /// OIDC token exchanged for credentials of `AWS_ROLE_ARN`, overriding
+    /// `AWS_WEB_IDENTITY_TOKEN_FILE`.
+    pub web_identity_token_file: Option<PathBuf>,
+    /// Refuse to tag images with scan findings at or above this severity.
+    pub fail_on: Option<Severity>,
+    pub force: bool,
+    /// Why `--force` overrides a promotion policy, for the audit log; also
+    /// recorded by `--provenance`.
+    pub reason: Option<String>,
+    /// Push a record of each tag (who, when, `--reason`, `--ticket`) to the
+    /// registry, attached to the tagged image.
+    pub provenance: bool,
+    /// The change or incident ticket `--provenance` records.
+    pub ticket: Option<String>,
+    /// Free-form `key=value`s about the run's tags (approver, reason, …),
+    /// kept in the audit log, the provenance records and the history.
+    pub annotations: BTreeMap<String, String>,
+    /// Tag the image a tag moves off with a backup tag first.
+    pub backup: Option<BackupStyle>,
+    /// Refuse to tag images without a valid cosign signature.
+    pub require_signature: bool,
+    /// Copy an image's referrers along when tagging it into another
+    /// repository or registry.
+    pub with_referrers: bool,
+    /// Config labels shown next to each image in the picker.
+    pub label_columns: Vec<String>,
+    /// Only images whose config has all of these `key=value` labels.
+    pub labels: Vec<(String, String)>,
+    /// Only offer images built from the local git history, by their revision
+    /// label, proposing the one built from HEAD.
+    pub git: bool,
+    /// Tag images with the value of this config label instead of prompting.
+    pub tag_from_label: Option<String>,
+    /// Refuse every call that would change a registry, and every write
+    /// elsewhere: S3 exports, locks and taking messages off a queue. Nothing
+    /// is moved, so the tag history and audit log get nothing either. Also
+    /// set by `[api] read_only`.
+    pub read_only: bool,
+    /// Show the listings cached by earlier runs instead of calling AWS.
+    pub offline: bool,
+    /// The outcome of every gate check, reported by `--output junit`.
+    pub checks: Checks,
+    /// Every tag put and image deleted, for the metrics pushed afterwards.
+    pub mutations: Arc<Mutations>,
+    /// The repositories locked by the run, with a `[lock] table`.
+    pub locks: Arc<Locks>,
+    /// Where `--github-output` appends the tags put, `$GITHUB_OUTPUT`.
+    pub github_output: Option<PathBuf>,
+    /// After tagging, wait up to this long for the tag to reach every
+    /// replication destination.
+    pub verify_replication: Option<std::time::Duration>,
+    /// Throttle ECR calls to this many per second.
+    pub max_rps: Option<f64>,
+    /// Continue the interrupted bulk run recorded in the state file.
+    pub resume: bool,
+    pub state_file: Option<PathBuf>,
+    /// Results requested per DescribeRepositories/DescribeImages call.
+    pub page_size: Option<i64>,
+    /// Stop listing after this many repositories or images.
+    pub max_results: Option<usize>,
+}
+
+pub enum Command {
+    /// No subcommand: pick a repository, image and tag interactively.
+    Interactive(InteractiveArgs),
+    List(ListArgs),
+    Tag(TagArgs),
+    Gc(GcArgs),
+    Simulate(SimulateArgs),
+    Aging(AgingArgs),
+    Stats(StatsArgs),
+    Diff(DiffArgs),
+    DiffState(DiffStateArgs),
+    Apply(ApplyArgs),
+    Storage(StorageArgs),
+    BlobGet(BlobGetArgs),
+    Show(ShowArgs),
+    PolicyShow(PolicyShowArgs),
+    PolicyApply(PolicyApplyArgs),
+    PolicyCheck(PolicyCheckArgs),
+    Find(FindArgs),
+    Findings(FindingsArgs),
+    Sbom(SbomArgs),
+    Export(ExportArgs),
+    Referrers(ReferrersArgs),
+    History(HistoryArgs),
+    Rollback(RollbackArgs),
+    Daemon(DaemonArgs),
+    Serve(ServeArgs),
+    Listen(ListenArgs),
+    ContextList,
+    /// `ecr-tag context use <name>`
+    ContextUse(String),
+    ContextShow,
+}
+
+/// `ecr-tag gc <repository> --keep <n>`
+pub struct GcArgs {
+    pub repository: String,
+    pub rules: Rules,
+    pub dry_run: bool,
+    pub yes: bool,
+}
+
+/// `ecr-tag simulate [repository] [--keep <n>] [--max-age <age>] [--protect <tag>]`
+pub struct SimulateArgs {
+    /// Every repository when unset.
+    pub repository: Option<String>,
+    pub rules: Rules,
+    /// Also list the images that would be kept, and why.
+    pub all: bool,
+}
+
+/// The flags of the interactive mode; given all of them it runs as `tag`.
+#[derive(Default)]
+pub struct InteractiveArgs {
+    pub repository: Option<String>,
+    pub source: Option<ImageRef>,
+    pub tag: Option<String>,
+    /// Tags the image `source` names without asking first.
+    pub yes: bool,
+}
+
+/// `ecr-tag tag -r <repo> (-d <digest>|--from-tag <tag>) -t <new-tag>`
+pub struct TagArgs {
+    pub repository: String,
+    pub source: ImageRef,
+    /// Unset when the tag comes from `--tag-from-label`.
+    pub tag: Option<String>,
+    /// Tag in every account of `--org`/`--accounts` instead of just one.
+    pub all_accounts: bool,
+}
+
+/// How an existing image is identified on the command line.
+pub enum ImageRef {
+    Digest(String),
+    Tag(String),
+}
+
+/// `ecr-tag list [repository]`
+pub struct ListArgs {
+    /// Lists the images in this repository instead of the repositories.
+    pub repository: Option<String>,
+    pub sort: String,
+    pub reverse: bool,
+    /// Only rows whose name (or digest/tags, for images) contain this.
+    pub filter: Option<String>,
+    pub tagged: bool,
+    pub untagged: bool,
+}
+
+/// `ecr-tag aging [repository] [--older-than <age>] [--tag <tag>]`
+pub struct AgingArgs {
+    /// Every repository when unset.
+    pub repository: Option<String>,
+    pub older_than: Duration,
+    /// Only report these tags (e.g. `prod`) when given.
+    pub tags: Vec<String>,
+}
+
+/// `ecr-tag stats [--sort <column>] [--reverse]`
+pub struct StatsArgs {
+    pub sort: String,
+    pub reverse: bool,
+}
+
+/// `ecr-tag diff <repository> <from> <to>`, each a tag or digest.
+pub struct DiffArgs {
+    pub repository: String,
+    pub from: String,
+    pub to: String,
+}
+
+/// `ecr-tag diff-state <file>`
+pub struct DiffStateArgs {
+    pub file: PathBuf,
+}
+
+/// `ecr-tag apply <file> [--auto-approve]`
+pub struct ApplyArgs {
+    pub file: PathBuf,
+    /// Apply the plan without asking.
+    pub auto_approve: bool,
+}
+
+/// `ecr-tag findings <repository> <tag|digest> [--against <tag|digest>]`
+pub struct FindingsArgs {
+    pub repository: String,
+    pub reference: String,
+    /// Only findings at or above this severity.
+    pub min_severity: Option<Severity>,
+    /// Compare with this image's findings instead of listing them.
+    pub against: Option<String>,
+}
+
+/// `ecr-tag sbom <repository> <tag|digest> [--format cyclonedx|spdx]`
+pub struct SbomArgs {
+    pub repository: String,
+    pub reference: String,
+    pub format: SbomFormat,
+}
+
+/// `ecr-tag export [--s3 s3://bucket/prefix/] [--format json|csv|tfvars|locals|cloudformation]`
+pub struct ExportArgs {
+    /// Printed to stdout when unset.
+    pub s3: Option<String>,
+    pub format: ExportFormat,
+}
+
+/// `ecr-tag referrers <repository> <tag|digest>`
+pub struct ReferrersArgs {
+    pub repository: String,
+    pub reference: String,
+}
+
+/// `ecr-tag history <repository> [tag]`
+pub struct HistoryArgs {
+    pub repository: String,
+    pub tag: Option<String>,
+}
+
+/// `ecr-tag rollback <repository> <tag> [--yes]`
+pub struct RollbackArgs {
+    pub repository: String,
+    pub tag: String,
+    pub yes: bool,
+}
+
+/// `ecr-tag daemon [--interval <seconds>] [--once] [--dry-run]`
+pub struct DaemonArgs {
+    /// Between checks; the `[daemon] interval` when unset.
+    pub interval: Option<std::time::Duration>,
+    /// Check the rules once and exit, failing if any drifted.
+    pub once: bool,
+    /// Alert on drift without moving any tag.
+    pub dry_run: bool,
+}
+
+/// `ecr-tag serve [--listen <addr:port>] [--insecure]`
+pub struct ServeArgs {
+    /// The `[serve] listen` address when unset.
+    pub listen: Option<String>,
+    /// Accept events without a `[serve] token`, from anyone.
+    pub insecure: bool,
+}
+
+/// `ecr-tag listen [--queue <url>]`
+pub struct ListenArgs {
+    /// The `[listen] queue` when unset.
+    pub queue: Option<String>,
+}
+
+/// `ecr-tag find <digest|tag>`
+pub struct FindArgs {
+    pub reference: String,
+    /// Search every account of `--org`/`--accounts`.
+    pub all_accounts: bool,
+    /// Regions searched in each account; the current one when empty.
+    pub regions: Vec<String>,
+}
+
+/// `ecr-tag storage [repository]`
+pub struct StorageArgs {
+    /// Every repository when unset.
+    pub repository: Option<String>,
+}
+
+/// `ecr-tag blob get -r <repository> <digest> [--file <path>]`
+pub struct BlobGetArgs {
+    pub repository: String,
+    pub digest: String,
+    /// Defaults to the digest in the current directory; `-` writes to stdout.
+    pub file: Option<String>,
+}
+
+/// `ecr-tag show <repository> <tag|digest> [--config]`
+pub struct ShowArgs {
+    pub repository: String,
+    pub reference: String,
+    /// Show the config blob instead of the manifest.
+    pub config: bool,
+}
+
+/// `ecr-tag policy show <repository>`
+pub struct PolicyShowArgs {
+    pub repository: String,
+}
+
+/// `ecr-tag policy apply <repository> (--template <name> [--account <id>]... | --file <path>)`
+pub struct PolicyApplyArgs {
+    pub repository: String,
+    pub source: PolicySource,
+    /// Replace the whole policy instead of merging statements into it.
+    pub replace: bool,
+    pub yes: bool,
+}
+
+/// `ecr-tag policy check <repository> <tag|digest> --principal <account|arn>`
+pub struct PolicyCheckArgs {
+    pub repository: String,
+    pub reference: String,
+    /// The account id, or the role/user ARN, that wants to pull.
+    pub principal: String,
+}
+
+pub enum PolicySource {
+    Template {
+        name: String,
+        accounts: Vec<String>,
+    },
+    /// A policy document, or `-` for stdin.
+    File(String),
+}
+
+impl Args {
+    pub fn parse() -> Result<Self> {
+        Self::parse_from(Parser::from_env())
+    }
+
+    pub fn parse_from(mut parser: Parser) -> Result<Self> {
+        let mut args = Self {
+            command: Command::Interactive(InteractiveArgs::default()),
+            output: match parser.value(&["-o", "--output"])? {
+                Some(format) => format.parse()?,
+                None => OutputFormat::Table,
+            },
+            bulk: parser.flag(&["-b", "--bulk"]),
+            tag_template: parser
+                .value(&["--tag-template"])?
+                .unwrap_or_else(|| "archived-{date}-{n}".to_string()),
+            no_color: parser.flag(&["--no-color"]),
+            registry_id: parser.value(&["--registry-id"])?,
+            source_registry_id: parser.value(&["--source-registry-id"])?,
+            to_repository: parser.value(&["--to-repository"])?,
+            region: parser.value(&["--region"])?,
+            context: parser.value(&["--context"])?,
+            role_arn: None,
+            aws_profile: None,
+            org: parser.flag(&["--org"]),
+            accounts: parser
+                .values(&["--accounts"])?
+                .iter()
+                .flat_map(|accounts| accounts.split(','))
+                .map(str::trim)
+                .filter(|account| !account.is_empty())
+                .map(Account::parse)
+                .collect::<Result<_>>()?,
+            account_role: parser
+                .value(&["--account-role"])?
+                .unwrap_or_else(|| "OrganizationAccountAccessRole".to_string()),
+            target_account: None,
+            fips: parser.flag(&["--fips"]),
+            endpoint_url: parser.value(&["--endpoint-url"])?,
+            web_identity_token_file: parser
+                .value(&["--web-identity-token-file"])?
+                .map(PathBuf::from),
+            fail_on: match parser.value(&["--fail-on"])? {
+                Some(severity) => Some(severity.parse()?),
+                None => None,
+            },
+            force: parser.flag(&["--force"]),
+            reason: parser.value(&["--reason"])?,
+            require_signature: parser.flag(&["--require-signature"]),
+            with_referrers: parser.flag(&["--with-referrers"]),
+            provenance: parser.flag(&["--provenance"]),
+            ticket: parser.value(&["--ticket"])?,
+            annotations: parser
+                .values(&["--annotate"])?
+                .iter()
+                .map(|annotation| match annotation.split_once('=') {
+                    Some((key, value)) if !key.is_empty() => {
+                        Ok((key.to_string(), value.to_string()))
+                    }
+                    _ => Err(anyhow!(
+                        "--annotate expects key=value, got `{}`",
+                        annotation
+                    )),
+                })
+                .collect::<Result<_>>()?,
+            backup: match parser.value(&["--backup"])? {
+                Some(style) => Some(style.parse()?),
+                None => None,
+            },
+            label_columns: parser
+                .values(&["--label-columns"])?
+                .iter()
+                .flat_map(|columns| columns.split(','))
+                .map(|column| column.trim().to_string())
+                .filter(|column| !column.is_empty())
+                .collect(),
+            labels: parser
+                .values(&["--label"])?
+                .iter()
+                .map(|label| match label.split_once('=') {
+                    Some((key, value)) => Ok((key.to_string(), value.to_string())),
+                    None => Err(anyhow!("--label expects key=value, got `{}`", label)),
+                })
+                .collect::<Result<_>>()?,
+            git: parser.flag(&["--git"]),
+            tag_from_label: parser.value(&["--tag-from-label"])?,
+            read_only: parser.flag(&["--read-only"]),
+            offline: parser.flag(&["--offline"]),
+            checks: Checks::default(),
+            mutations: Arc::default(),
+            locks: Arc::default(),
+            github_output: match parser.flag(&["--github-output"]) {
+                true => match std::env::var_os("GITHUB_OUTPUT") {
+                    Some(path) => Some(PathBuf::from(path)),
+                    None => bail!("--github-output needs $GITHUB_OUTPUT, which GitHub Actions sets for each step"),
+                },
+                false => None,
+            },
+            verify_replication: match (
+                parser.flag(&["--verify-replication"]),
+                parser.value(&["--replication-timeout"])?,
+            ) {
+                (_, Some(seconds)) => match seconds.parse() {
+                    Ok(seconds) if seconds > 0 => Some(std::time::Duration::from_secs(seconds)),
+                    _ => bail!("--replication-timeout must be a positive number of seconds"),
+                },
+                (true, None) => Some(std::time::Duration::from_secs(300)),
+                (false, None) => None,
+            },
+            resume: parser.flag(&["--resume"]),
+            state_file: parser.value(&["--state-file"])?.map(PathBuf::from),
+            page_size: match parser.value(&["--page-size"])? {
+                Some(size) => match size.parse() {
+                    Ok(size @ 1..=1000) => Some(size),
+                    _ => bail!("--page-size must be between 1 and 1000"),
+                },
+                None => None,
+            },
+            max_results: match parser.value(&["--max-results"])? {
+                Some(max) => match max.parse() {
+                    Ok(max) if max > 0 => Some(max),
+                    _ => bail!("--max-results must be a positive number"),
+                },
+                None => None,
+            },
+            max_rps: match parser.value(&["--max-rps"])? {
+                Some(max_rps) => Some(
+                    max_rps
+                        .parse()
+                        .map_err(|_| anyhow!("--max-rps must be a number"))?,
+                ),
+                None => None,
+            },
+        };
+
+        args.command = match parser.subcommand().as_deref() {
+            None => {
+                let interactive = InteractiveArgs {
+                    repository: parser.value(&["-r", "--repository"])?,
+                    source: match (
+                        parser.value(&["-d", "--digest"])?,
+                        parser.value(&["--from-tag"])?,
+                    ) {
+                        (Some(_), Some(_)) => bail!("--digest and --from-tag can't be combined"),
+                        (Some(digest), None) => Some(ImageRef::Digest(digest)),
+                        (None, tag) => tag.map(ImageRef::Tag),
+                    },
+                    tag: parser.value(&["-t", "--tag"])?,
+                    yes: parser.flag(&["-y", "--yes"]),
+                };
+                // In a terminal the prompts still confirm the source, resolve
+                // conflicts and warn about old images, unless --yes skips them.
+                let unattended =
+                    interactive.yes || !std::io::stdin().is_tty() || !std::io::stderr().is_tty();
+                match interactive {
+                    // Nothing left to prompt for: run as `ecr-tag tag`.
+                    InteractiveArgs {
+                        repository: Some(repository),
+                        source: Some(source),
+                        tag,
+                        ..
+                    } if unattended
+                        && !args.bulk
+                        && (tag.is_some() || args.tag_from_label.is_some()) =>
+                    {
+                        Command::Tag(TagArgs {
+                            repository,
+                            source,
+                            tag,
+                            all_accounts: false,
+                        })
+                    }
+                    interactive => Command::Interactive(interactive),
+                }
+            }
+            Some("list" | "ls") => Command::List(ListArgs {
+                sort: parser
+                    .value(&["--sort"])?
+                    .unwrap_or_else(|| "default".to_string()),
+                reverse: parser.flag(&["--reverse"]),
+                filter: parser.value(&["--filter"])?,
+                tagged: parser.flag(&["--tagged"]),
+                untagged: parser.flag(&["--untagged"]),
+                repository: parser.positional(),
+            }),
+            Some("tag") => Command::Tag(TagArgs {
+                repository: parser
+                    .value(&["-r", "--repository"])?
+                    .ok_or_else(|| anyhow!("tag requires --repository"))?,
+                source: match (
+                    parser.value(&["-d", "--digest"])?,
+                    parser.value(&["--from-tag"])?,
+                ) {
+                    (Some(digest), None) => ImageRef::Digest(digest),
+                    (None, Some(tag)) => ImageRef::Tag(tag),
+                    _ => bail!("tag requires exactly one of --digest or --from-tag"),
+                },
+                tag: match parser.value(&["-t", "--tag"])? {
+                    None if args.tag_from_label.is_none() => {
+                        bail!("tag requires --tag or --tag-from-label")
+                    }
+                    Some(_) if args.tag_from_label.is_some() => {
+                        bail!("--tag and --tag-from-label can't be combined")
+                    }
+                    tag => tag,
+                },
+                all_accounts: parser.flag(&["--all-accounts"]),
+            }),
+            Some("gc") => Command::Gc(GcArgs {
+                rules: match parse_rules(&mut parser)? {
+                    rules if rules.keep.is_none() && !args.resume => bail!("gc requires --keep"),
+                    rules => rules,
+                },
+                dry_run: parser.flag(&["--dry-run"]),
+                yes: parser.flag(&["-y", "--yes"]),
+                repository: parser
+                    .positional()
+                    .ok_or_else(|| anyhow!("gc requires a repository"))?,
+            }),
+            Some("simulate") => Command::Simulate(SimulateArgs {
+                rules: match parse_rules(&mut parser)? {
+                    rules if rules.keep.is_none() && rules.max_age.is_none() => {
+                        bail!("simulate requires --keep and/or --max-age")
+                    }
+                    rules => rules,
+                },
+                all: parser.flag(&["--all"]),
+                repository: parser.positional(),
+            }),
+            Some("aging") => Command::Aging(AgingArgs {
+                older_than: retention::parse_age(
+                    &parser
+                        .value(&["--older-than"])?
+                        .unwrap_or_else(|| "90d".to_string()),
+                )?,
+                tags: parser.values(&["-t", "--tag"])?,
+                repository: parser.positional(),
+            }),
+            Some("stats") => Command::Stats(StatsArgs {
+                sort: parser
+                    .value(&["--sort"])?
+                    .unwrap_or_else(|| "name".to_string()),
+                reverse: parser.flag(&["--reverse"]),
+            }),
+            Some("diff") => {
+                let mut positional = || {
+                    parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("usage: ecr-tag diff <repository> <from> <to>"))
+                };
+                Command::Diff(DiffArgs {
+                    repository: positional()?,
+                    from: positional()?,
+                    to: positional()?,
+                })
+            }
+            Some("storage") => Command::Storage(StorageArgs {
+                repository: parser.positional(),
+            }),
+            Some("blob") => match parser.subcommand().as_deref() {
+                Some("get") => Command::BlobGet(BlobGetArgs {
+                    repository: parser
+                        .value(&["-r", "--repository"])?
+                        .ok_or_else(|| anyhow!("blob get requires --repository"))?,
+                    file: parser.value(&["-f", "--file"])?,
+                    digest: parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("blob get requires a digest"))?,
+                }),
+                _ => bail!("usage: ecr-tag blob get -r <repository> <digest>"),
+            },
+            Some("show") => {
+                let config = parser.flag(&["--config"]);
+                let mut positional = || {
+                    parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("usage: ecr-tag show <repository> <tag|digest>"))
+                };
+                Command::Show(ShowArgs {
+                    repository: positional()?,
+                    reference: positional()?,
+                    config,
+                })
+            }
+            Some("findings") => {
+                let min_severity = match parser.value(&["--min-severity"])? {
+                    Some(severity) => Some(severity.parse()?),
+                    None => None,
+                };
+                let against = parser.value(&["--against"])?;
+                let mut positional = || {
+                    parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("usage: ecr-tag findings <repository> <tag|digest>"))
+                };
+                Command::Findings(FindingsArgs {
+                    repository: positional()?,
+                    reference: positional()?,
+                    min_severity,
+                    against,
+                })
+            }
+            Some("diff-state") => Command::DiffState(DiffStateArgs {
+                file: parser
+                    .positional()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| anyhow!("usage: ecr-tag diff-state <file>"))?,
+            }),
+            Some("apply") => Command::Apply(ApplyArgs {
+                auto_approve: parser.flag(&["--auto-approve"]),
+                file: parser
+                    .positional()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| anyhow!("usage: ecr-tag apply <file> [--auto-approve]"))?,
+            }),
+            Some("export") => Command::Export(ExportArgs {
+                s3: parser.value(&["--s3"])?,
+                format: match parser.value(&["--format"])? {
+                    Some(format) => format.parse()?,
+                    None => ExportFormat::Json,
+                },
+            }),
+            Some("sbom") => {
+                let format = match parser.value(&["--format"])? {
+                    Some(format) => format.parse()?,
+                    None => SbomFormat::CycloneDx,
+                };
+                let mut positional = || {
+                    parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("usage: ecr-tag sbom <repository> <tag|digest>"))
+                };
+                Command::Sbom(SbomArgs {
+                    repository: positional()?,
+                    reference: positional()?,
+                    format,
+                })
+            }
+            Some("referrers") => {
+                let mut positional = || {
+                    parser.positional().ok_or_else(|| {
+                        anyhow!("usage: ecr-tag referrers <repository> <tag|digest>")
+                    })
+                };
+                Command::Referrers(ReferrersArgs {
+                    repository: positional()?,
+                    reference: positional()?,
+                })
+            }
+            Some("history") => Command::History(HistoryArgs {
+                repository: parser
+                    .positional()
+                    .ok_or_else(|| anyhow!("usage: ecr-tag history <repository> [tag]"))?,
+                tag: parser.positional(),
+            }),
+            Some("rollback") => {
+                let yes = parser.flag(&["-y", "--yes"]);
+                let mut positional = || {
+                    parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("usage: ecr-tag rollback <repository> <tag>"))
+                };
+                Command::Rollback(RollbackArgs {
+                    repository: positional()?,
+                    tag: positional()?,
+                    yes,
+                })
+            }
+            Some("daemon") => Command::Daemon(DaemonArgs {
+                interval: match parser.value(&["--interval"])? {
+                    Some(seconds) => match seconds.parse() {
+                        Ok(seconds) if seconds > 0 => Some(std::time::Duration::from_secs(seconds)),
+                        _ => bail!("--interval must be a positive number of seconds"),
+                    },
+                    None => None,
+                },
+                once: parser.flag(&["--once"]),
+                dry_run: parser.flag(&["--dry-run"]),
+            }),
+            Some("serve") => Command::Serve(ServeArgs {
+                listen: parser.value(&["--listen"])?,
+                insecure: parser.flag(&["--insecure"]),
+            }),
+            Some("listen") => Command::Listen(ListenArgs {
+                queue: parser.value(&["--queue"])?,
+            }),
+            Some("find") => Command::Find(FindArgs {
+                all_accounts: parser.flag(&["--all-accounts"]),
+                regions: parser
+                    .values(&["--regions"])?
+                    .iter()
+                    .flat_map(|regions| regions.split(','))
+                    .map(|region| region.trim().to_string())
+                    .filter(|region| !region.is_empty())
+                    .collect(),
+                reference: parser
+                    .positional()
+                    .ok_or_else(|| anyhow!("usage: ecr-tag find [--all-accounts] <digest|tag>"))?,
+            }),
+            Some("context") => match parser.subcommand().as_deref() {
+                Some("list" | "ls") => Command::ContextList,
+                Some("use") => Command::ContextUse(
+                    parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("context use requires a context name"))?,
+                ),
+                Some("show") => Command::ContextShow,
+                _ => bail!("usage: ecr-tag context <list|use|show>"),
+            },
+            Some("policy") => match parser.subcommand().as_deref() {
+                Some("show") => Command::PolicyShow(PolicyShowArgs {
+                    repository: parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("policy show requires a repository"))?,
+                }),
+                Some("apply") => Command::PolicyApply(PolicyApplyArgs {
+                    source: match (
+                        parser.value(&["--template"])?,
+                        parser.value(&["-f", "--file"])?,
+                    ) {
+                        (Some(name), None) => PolicySource::Template {
+                            name,
+                            accounts: parser.values(&["--account"])?,
+                        },
+                        (None, Some(file)) => PolicySource::File(file),
+                        _ => bail!("policy apply requires exactly one of --template or --file"),
+                    },
+                    replace: parser.flag(&["--replace"]),
+                    yes: parser.flag(&["-y", "--yes"]),
+                    repository: parser
+                        .positional()
+                        .ok_or_else(|| anyhow!("policy apply requires a repository"))?,
+                }),
+                Some("check") => {
+                    let principal = parser
+                        .value(&["--principal"])?
+                        .ok_or_else(|| anyhow!("policy check requires --principal"))?;
+                    let mut positional = || {
+                        parser.positional().ok_or_else(|| {
+                            anyhow!("usage: ecr-tag policy check <repository> <tag|digest> --principal <account|arn>")
+                        })
+                    };
+                    Command::PolicyCheck(PolicyCheckArgs {
+                        repository: positional()?,
+                        reference: positional()?,
+                        principal,
+                    })
+                }
+                _ => bail!("usage: ecr-tag policy <show|apply|check> <repository>"),
+            },
+            Some(other) => bail!("unknown command `{}`", other),
+        };
+
+        // Nothing gets put by the read-only commands, so `--registry-id` can
+        // only mean the registry to read.
+        if args.command.is_read_only() && args.source_registry_id.is_none() {
+            args.source_registry_id = args.registry_id.take();
+        }
+
+        parser.finish()?;
+        Ok(args)
+    }
+}
+
+impl Command {
+    /// True for the commands that run against every account of
+    /// `--org`/`--accounts` rather than a picked one.
+    pub fn all_accounts(&self) -> bool {
+        match self {
+            Command::Tag(tag) => tag.all_accounts,
+            Command::Find(find) => find.all_accounts,
+            _ => false,
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        matches!(
+            self,
+            Command::List(_)
+                | Command::Simulate(_)
+                | Command::Aging(_)
+                | Command::Stats(_)
+                | Command::Diff(_)
+                | Command::DiffState(_)
+                | Command::Storage(_)
+                | Command::BlobGet(_)
+                | Command::Show(_)
+                | Command::PolicyShow(_)
+                | Command::PolicyCheck(_)
+                | Command::Find(_)
+                | Command::Findings(_)
+                | Command::Sbom(_)
+                | Command::Export(_)
+                | Command::Referrers(_)
+                | Command::History(_)
+                | Command::ContextList
+                | Command::ContextUse(_)
+                | Command::ContextShow
+        )
+    }
+}
+
+impl Args {
+    /// The AWS profile in use: the context's, else `AWS_PROFILE`, else `default`.
+    pub fn profile(&self) -> String {
+        self.aws_profile
+            .clone()
+            .or_else(|| std::env::var("AWS_PROFILE").ok())
+            .unwrap_or_else(|| "default".to_string())
+    }
+
+    pub fn destination(&self) -> Destination {
+        Destination {
+            registry_id: self.registry_id.clone(),
+            repository_name: self.to_repository.clone(),
+        }
+    }
+
+    /// The scan gate for tagging, with the config's promotion policies.
+    pub fn gate<'a>(&'a self, config: &'a Config) -> Gate<'a> {
+        Gate {
+            fail_on: self.fail_on,
+            force: self.force,
+            reason: self.reason.as_deref(),
+            repos: Some(&config.repo),
+            signing: Some(&config.signing).filter(|_| self.require_signature),
+            profile: self.aws_profile.as_deref(),
+            checks: Some(&self.checks).filter(|_| self.output == OutputFormat::Junit),
+        }
+    }
+
+    /// The mode being run, as reported by telemetry.
+    pub fn command_name(&self) -> &'static str {
+        match (&self.command, self.bulk) {
+            (Command::Interactive(_), true) => "bulk",
+            (Command::Interactive(_), false) => "interactive",
+            (Command::List(_), _) => "list",
+            (Command::Tag(_), _) => "tag",
+            (Command::Gc(_), _) => "gc",
+            (Command::Simulate(_), _) => "simulate",
+            (Command::Aging(_), _) => "aging",
+            (Command::Stats(_), _) => "stats",
+            (Command::Diff(_), _) => "diff",
+            (Command::DiffState(_), _) => "diff-state",
+            (Command::Apply(_), _) => "apply",
+            (Command::Storage(_), _) => "storage",
+            (Command::BlobGet(_), _) => "blob-get",
+            (Command::PolicyShow(_), _) => "policy-show",
+            (Command::PolicyApply(_), _) => "policy-apply",
+            (Command::PolicyCheck(_), _) => "policy-check",
+            (Command::Show(_), _) => "show",
+            (Command::Find(_), _) => "find",
+            (Command::Findings(_), _) => "findings",
+            (Command::Sbom(_), _) => "sbom",
+            (Command::Export(_), _) => "export",
+            (Command::Referrers(_), _) => "referrers",
+            (Command::History(_), _) => "history",
+            (Command::Rollback(_), _) => "rollback",
+            (Command::Daemon(_), _) => "daemon",
+            (Command::Serve(_), _) => "serve",
+            (Command::Listen(_), _) => "listen",
+            (Command::ContextList, _) => "context-list",
+            (Command::ContextUse(_), _) => "context-use",
+            (Command::ContextShow, _) => "context-show",
+        }
+    }
+}
+
+fn parse_rules(parser: &mut Parser) -> Result<Rules> {
+    Ok(Rules {
+        keep: match parser.value(&["--keep"])? {
+            Some(keep) => Some(
+                keep.parse()
+                    .map_err(|_| anyhow!("--keep must be a number"))?,
+            ),
+            None => None,
+        },
+        max_age: match parser.value(&["--max-age"])? {
+            Some(age) => Some(retention::parse_age(&age)?),
+            None => None,
+        },
+        protect: parser.values(&["--protect"])?,
+    })
+}
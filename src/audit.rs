@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::Utc;
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::args::Args;
+use crate::client;
+use crate::config::Audit;
+use crate::http;
+use crate::metrics::{Mutation, Mutations};
+
+/// A promotion policy that `--force` talked its way past.
+#[derive(Debug, Serialize)]
+pub struct Override<'a> {
+    pub repository: &'a str,
+    pub tag: &'a str,
+    pub digest: &'a str,
+    /// The policy's tag pattern and threshold, e.g. `prod (fail_on = HIGH)`.
+    pub policy: String,
+    pub findings: &'a str,
+    pub reason: &'a str,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    timestamp: String,
+    user: Option<String>,
+    #[serde(flatten)]
+    event: &'a Override<'a>,
+}
+
+/// Where overrides are appended as JSON lines.
+pub fn path() -> Option<PathBuf> {
+    dirs_next::data_dir().map(|dir| dir.join("ecr-tag").join("audit.jsonl"))
+}
+
+/// Appends the override to the audit log; tagging must not go ahead without
+/// the record, so failures are errors.
+pub fn record(event: &Override) -> Result<()> {
+    let path = path().context("no data directory for the audit log")?;
+    let entry = Entry {
+        timestamp: Utc::now().to_rfc3339(),
+        user: user(),
+        event,
+    };
+    let write = || -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)
+    };
+    write().with_context(|| format!("failed to record the override in {}", path.display()))
+}
+
+pub fn user() -> Option<String> {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok()
+}
+
+/// A change as recorded in CloudWatch Logs, with who made it and from where.
+#[derive(Serialize)]
+struct Record<'a> {
+    user: Option<String>,
+    tool: String,
+    command: &'static str,
+    context: Option<&'a str>,
+    account: Option<&'a str>,
+    region: &'a str,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: &'a BTreeMap<String, String>,
+    #[serde(flatten)]
+    mutation: &'a Mutation,
+}
+
+/// Sends a record of every change the run made to the `[audit] log_group`.
+/// The changes can't be taken back by then, so failing is left to the caller
+/// to report.
+pub async fn ship(args: &Args, config: &Audit, mutations: &Mutations) -> Result<()> {
+    let log_group = match &config.log_group {
+        Some(log_group) => log_group,
+        None => return Ok(()),
+    };
+    let mutations = mutations.list();
+    if mutations.is_empty() {
+        return Ok(());
+    }
+    let log_stream = match &config.log_stream {
+        Some(log_stream) => log_stream.clone(),
+        None => format!(
+            "ecr-tag/{}",
+            user().unwrap_or_else(|| "unknown".to_string())
+        ),
+    };
+    let region = client::region(args)?;
+    let credentials = client::credentials(args, region.name(), args.target_account.as_ref())?
+        .credentials()
+        .await
+        .context("failed to resolve credentials")?;
+    let call = |action: &str, body: Value| {
+        let mut request = SignedRequest::new("POST", "logs", &region, "/");
+        request.add_header("x-amz-target", &format!("Logs_20140328.{}", action));
+        request.set_content_type("application/x-amz-json-1.1".to_string());
+        request.set_payload(Some(body.to_string()));
+        http::send_signed(request, &credentials, Duration::from_secs(30))
+    };
+
+    match call(
+        "CreateLogStream",
+        json!({ "logGroupName": log_group, "logStreamName": log_stream }),
+    )
+    .await
+    {
+        Err(e) if !e.to_string().contains("ResourceAlreadyExistsException") => {
+            return Err(e.context(format!("failed to create log stream {}", log_stream)))
+        }
+        _ => {}
+    }
+    let events = mutations
+        .iter()
+        .map(|mutation| {
+            let record = Record {
+                user: user(),
+                tool: format!("ecr-tag/{}", env!("CARGO_PKG_VERSION")),
+                command: args.command_name(),
+                context: args.context.as_deref(),
+                account: args
+                    .target_account
+                    .as_ref()
+                    .map(|account| account.id.as_str()),
+                region: region.name(),
+                annotations: &args.annotations,
+                mutation,
+            };
+            Ok(json!({
+                "timestamp": mutation.timestamp.timestamp_millis(),
+                "message": serde_json::to_string(&record)?,
+            }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    // PutLogEvents takes up to 10,000 events a call.
+    for batch in events.chunks(10_000) {
+        call(
+            "PutLogEvents",
+            json!({
+                "logGroupName": log_group,
+                "logStreamName": log_stream,
+                "logEvents": batch,
+            }),
+        )
+        .await
+        .map_err(|e| {
+            anyhow!(
+                "failed to send {} audit record(s) to {}: {:#}",
+                mutations.len(),
+                log_group,
+                e
+            )
+        })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+
+use crate::config::Backup;
+use crate::ecr::{Decorator, Destination, EcrApi, Image};
+use crate::tags;
+
+/// The tag the image a tag is moved off keeps, so the move can be undone
+/// with a plain `ecr-tag --from-tag`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackupStyle {
+    /// `<tag>-previous`, moved along with the tag.
+    Previous,
+    /// `<tag>-YYYYMMDDHHMM`, one per move.
+    Timestamp,
+}
+
+impl FromStr for BackupStyle {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value {
+            "previous" => Self::Previous,
+            "timestamp" => Self::Timestamp,
+            _ => bail!(
+                "unknown backup style `{}` (expected previous or timestamp)",
+                value
+            ),
+        })
+    }
+}
+
+impl BackupStyle {
+    fn tag(self, tag: &str) -> String {
+        match self {
+            Self::Previous => format!("{}-previous", tag),
+            Self::Timestamp => format!("{}-{}", tag, Utc::now().format("%Y%m%d%H%M")),
+        }
+    }
+}
+
+/// Whether `candidate` is a timestamped backup of `tag`.
+fn is_timestamped_backup(candidate: &str, tag: &str) -> bool {
+    candidate
+        .strip_prefix(tag)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|stamp| stamp.len() == 12 && stamp.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// For `--backup` or `[backup] style`: before a tag is moved, tags the
+/// image it's moving off with a backup tag, so there's always a tag to roll
+/// back to, audit log or not.
+pub struct BackupEcr {
+    inner: Box<dyn EcrApi>,
+    style: BackupStyle,
+    /// How many timestamped backups of each tag are kept.
+    keep: Option<usize>,
+}
+
+impl BackupEcr {
+    /// `--backup` wins over the config.
+    pub fn wrap(
+        inner: Box<dyn EcrApi>,
+        style: Option<BackupStyle>,
+        config: &Backup,
+    ) -> Result<Box<dyn EcrApi>> {
+        let style = match (style, &config.style) {
+            (Some(style), _) => style,
+            (None, Some(style)) => style.parse().context("in [backup] style")?,
+            (None, None) => return Ok(inner),
+        };
+        Ok(Box::new(Self {
+            inner,
+            style,
+            keep: config.keep,
+        }))
+    }
+
+    /// Tags the image `tag` is on now, unless it's on none or already on `image`.
+    async fn back_up(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repository = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&image.repository_name);
+        // The image being moved off is in the registry being written to.
+        let registry_id = destination.registry_id.as_deref();
+        let previous = match self.inner.resolve_tag(registry_id, repository, tag).await? {
+            Some(previous) if previous != image.digest => previous,
+            _ => return Ok(()),
+        };
+        let backup = self.style.tag(tag);
+        tags::validate(&backup)
+            .with_context(|| format!("can't back up {}:{} as {}", repository, tag, backup))?;
+        let previous = self.inner.image(registry_id, repository, &previous).await?;
+        let destination = Destination {
+            registry_id: destination.registry_id.clone(),
+            repository_name: Some(repository.to_string()),
+        };
+        self.inner
+            .put_image(&previous, &backup, &destination)
+            .await
+            .with_context(|| format!("failed to back up {}:{} as {}", repository, tag, backup))?;
+        eprintln!(
+            "backed up {}:{} ({}) as {}",
+            repository, tag, previous.digest, backup
+        );
+        // Listing and untagging only reach the source registry, so another
+        // registry's backups are left for runs against it.
+        if self.style == BackupStyle::Timestamp && destination.registry_id.is_none() {
+            self.prune(repository, tag).await;
+        }
+        Ok(())
+    }
+
+    /// Removes the timestamped backups of `tag` beyond the newest `keep`.
+    /// Failing is only a warning: the tag is backed up either way.
+    ///
+    /// ECR deletes an image along with its last tag, so backups that are
+    /// the only tag of their image are kept; deleting those is for `gc`.
+    async fn prune(&self, repository: &str, tag: &str) {
+        let keep = match self.keep {
+            Some(keep) => keep,
+            None => return,
+        };
+        let images = match self.inner.images(repository).await {
+            Ok(images) => images,
+            Err(e) => {
+                eprintln!(
+                    "warning: failed to prune backups of {}:{}: {:#}",
+                    repository, tag, e
+                );
+                return;
+            }
+        };
+        // Each backup, and whether its image has other tags to keep it.
+        let mut backups: Vec<(String, bool)> = images
+            .iter()
+            .flat_map(|image| {
+                image
+                    .tags
+                    .iter()
+                    .filter(|candidate| is_timestamped_backup(candidate, tag))
+                    .map(|backup| (backup.clone(), image.tags.len() > 1))
+            })
+            .collect();
+        // The timestamps sort as they read, newest last.
+        backups.sort();
+        let expired = backups.len().saturating_sub(keep);
+        let (expired, last_tags): (Vec<_>, Vec<_>) = backups
+            .drain(..expired)
+            .partition(|(_, other_tags)| *other_tags);
+        if !last_tags.is_empty() {
+            let last_tags: Vec<String> = last_tags.into_iter().map(|(backup, _)| backup).collect();
+            eprintln!(
+                "note: kept backups of {}:{} that are their image's only tag, removing them would delete the image: {}",
+                repository,
+                tag,
+                last_tags.join(", ")
+            );
+        }
+        let expired: Vec<String> = expired.into_iter().map(|(backup, _)| backup).collect();
+        if expired.is_empty() {
+            return;
+        }
+        match self.inner.untag(repository, &expired).await {
+            Ok(()) => eprintln!(
+                "pruned backups of {}:{}: {}",
+                repository,
+                tag,
+                expired.join(", ")
+            ),
+            Err(e) => eprintln!(
+                "warning: failed to prune backups of {}:{}: {:#}",
+                repository, tag, e
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Decorator for BackupEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        self.back_up(image, tag, destination).await?;
+        self.inner.put_image(image, tag, destination).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecr::MockEcr;
+
+    async fn tag(ecr: &dyn EcrApi, from: &str, tag: &str, registry_id: Option<&str>) {
+        let digest = ecr.tag_digest("app", from).await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+        let destination = Destination {
+            registry_id: registry_id.map(str::to_string),
+            repository_name: None,
+        };
+        ecr.put_image(&image, tag, &destination).await.unwrap();
+    }
+
+    fn backup(style: &str, keep: Option<usize>) -> Backup {
+        Backup {
+            style: Some(style.to_string()),
+            keep,
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_image_a_tag_moves_off_as_previous() {
+        let mock = MockEcr::app(&[&["latest"], &["v2"]]);
+        let ecr = BackupEcr::wrap(Box::new(mock), None, &backup("previous", None)).unwrap();
+        tag(ecr.as_ref(), "v2", "latest", None).await;
+
+        let images = ecr.images("app").await.unwrap();
+        let old = images.iter().find(|image| image.tags.len() == 1).unwrap();
+        assert_eq!(old.tags, vec!["latest-previous"]);
+    }
+
+    #[tokio::test]
+    async fn backs_up_in_the_registry_written_to() {
+        let mock = MockEcr::app(&[&["latest"], &["v2"]]);
+        let registry_id = mock.registry_id().await.unwrap();
+        let ecr = BackupEcr::wrap(Box::new(mock), None, &backup("previous", None)).unwrap();
+        tag(ecr.as_ref(), "v2", "latest", Some(&registry_id)).await;
+
+        assert!(ecr
+            .resolve_tag(Some(&registry_id), "app", "latest-previous")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn skips_tags_not_on_another_image() {
+        let mock = MockEcr::app(&[&["latest"], &["v2"]]);
+        let ecr = BackupEcr::wrap(Box::new(mock), None, &backup("previous", None)).unwrap();
+        tag(ecr.as_ref(), "v2", "stable", None).await;
+        tag(ecr.as_ref(), "v2", "v2", None).await;
+
+        let images = ecr.images("app").await.unwrap();
+        assert!(images
+            .iter()
+            .all(|image| image.tags.iter().all(|tag| !tag.ends_with("-previous"))));
+    }
+
+    #[tokio::test]
+    async fn prunes_timestamped_backups_but_not_an_image_s_last_tag() {
+        let mock = MockEcr::app(&[
+            &["latest-202301010000"],
+            &["v2", "latest-202301020000"],
+            &["latest"],
+        ]);
+        let ecr = BackupEcr::wrap(Box::new(mock), None, &backup("timestamp", Some(1))).unwrap();
+        tag(ecr.as_ref(), "v2", "latest", None).await;
+
+        let mut tags: Vec<String> = ecr
+            .images("app")
+            .await
+            .unwrap()
+            .into_iter()
+            .flat_map(|image| image.tags)
+            .filter(|tag| tag.starts_with("latest-"))
+            .collect();
+        tags.sort();
+        // The oldest is its image's only tag; the next is pruned for the new one.
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0], "latest-202301010000");
+        assert!(is_timestamped_backup(&tags[1], "latest"));
+    }
+
+    #[test]
+    fn recognises_timestamped_backups() {
+        assert!(is_timestamped_backup("latest-202301010000", "latest"));
+        assert!(!is_timestamped_backup("latest-previous", "latest"));
+        assert!(!is_timestamped_backup("latest-2023", "latest"));
+        assert!(!is_timestamped_backup("stable-202301010000", "latest"));
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::accounts::Account;
+use crate::args::Args;
+use crate::client;
+use crate::ecr::{
+    Decorator, Destination, EcrApi, Failure, Image, ImagePage, ReplicationDestination,
+    RepositorySettings, TagStatus,
+};
+use crate::model::{Finding, ImageDetail, Respository};
+
+/// The repository and image listings of the last online runs, kept per
+/// profile, region and registry so `--offline` can show them again.
+pub struct Cache {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry<T> {
+    saved_at: DateTime<Utc>,
+    items: T,
+}
+
+impl Cache {
+    /// `<cache dir>/ecr-tag/<profile>_<region>_<registry>`.
+    pub fn open(args: &Args, account: Option<&Account>) -> Result<Self> {
+        let registry = args
+            .source_registry_id
+            .as_deref()
+            .or(account.map(|account| account.id.as_str()));
+        let mock = cfg!(feature = "mock") && std::env::var_os("ECR_TAG_MOCK_REGISTRY").is_some();
+        let scope = match mock {
+            true => match registry {
+                Some(registry) => format!("mock_{}", registry),
+                None => "mock".to_string(),
+            },
+            false => format!(
+                "{}_{}_{}",
+                args.profile(),
+                client::region(args)?.name(),
+                registry.unwrap_or("self")
+            ),
+        };
+        let dir = dirs_next::cache_dir()
+            .ok_or_else(|| anyhow!("no cache directory for --offline"))?
+            .join("ecr-tag")
+            .join(scope);
+        Ok(Self { dir })
+    }
+
+    fn path(&self, what: &str) -> PathBuf {
+        // Repository names can have slashes in them.
+        self.dir.join(format!("{}.json", what.replace('/', "%2F")))
+    }
+
+    /// Best effort: a listing that can't be cached is still shown.
+    fn save<T: Serialize>(&self, what: &str, items: &T) {
+        let entry = Entry {
+            saved_at: Utc::now(),
+            items,
+        };
+        let path = self.path(what);
+        let _ = std::fs::create_dir_all(&self.dir)
+            .and_then(|_| std::fs::write(&path, serde_json::to_vec(&entry)?));
+    }
+
+    fn load<T: DeserializeOwned>(&self, what: &str, label: &str) -> Result<T> {
+        let path = self.path(what);
+        let contents = std::fs::read_to_string(&path).with_context(|| {
+            format!(
+                "offline: {} were never cached, run once without --offline first",
+                label
+            )
+        })?;
+        let entry: Entry<T> = serde_json::from_str(&contents)
+            .with_context(|| format!("invalid cache file {}", path.display()))?;
+        eprintln!(
+            "offline: showing {} cached {} ago ({})",
+            label,
+            format_age(Utc::now() - entry.saved_at),
+            entry.saved_at.to_rfc3339()
+        );
+        Ok(entry.items)
+    }
+}
+
+fn format_age(age: chrono::Duration) -> String {
+    match age.num_minutes() {
+        minutes if minutes < 60 => format!("{}m", minutes.max(0)),
+        minutes if minutes < 48 * 60 => format!("{}h", minutes / 60),
+        minutes => format!("{}d", minutes / (24 * 60)),
+    }
+}
+
+/// Saves every repository and image listing read through `inner`.
+pub struct CachingEcr {
+    inner: Box<dyn EcrApi>,
+    cache: Cache,
+    /// The pages of each repository's listing read so far, saved as the
+    /// listing once the last one is in.
+    pages: Mutex<BTreeMap<String, Vec<ImageDetail>>>,
+}
+
+impl CachingEcr {
+    pub fn wrap(inner: Box<dyn EcrApi>, cache: Cache) -> Box<dyn EcrApi> {
+        Box::new(Self {
+            inner,
+            cache,
+            pages: Mutex::default(),
+        })
+    }
+}
+
+#[async_trait]
+impl Decorator for CachingEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn repositories(&self) -> Result<Vec<Respository>> {
+        let repositories = self.inner.repositories().await?;
+        self.cache.save("repositories", &repositories);
+        Ok(repositories)
+    }
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>> {
+        let images = self.inner.images(repository_name).await?;
+        self.cache
+            .save(&format!("images-{}", repository_name), &images);
+        Ok(images)
+    }
+
+    async fn images_page(
+        &self,
+        repository_name: &str,
+        status: TagStatus,
+        next_token: Option<&str>,
+    ) -> Result<ImagePage> {
+        let page = self
+            .inner
+            .images_page(repository_name, status, next_token)
+            .await?;
+        // Only the whole listing is what --offline shows; narrowed ones aren't.
+        if status == TagStatus::Any {
+            let mut pages = self.pages.lock().unwrap();
+            if next_token.is_none() {
+                pages.remove(repository_name);
+            }
+            let images = pages.entry(repository_name.to_string()).or_default();
+            images.extend(page.images.iter().cloned());
+            if page.next_token.is_none() {
+                let images = pages.remove(repository_name).unwrap_or_default();
+                self.cache
+                    .save(&format!("images-{}", repository_name), &images);
+            }
+        }
+        Ok(page)
+    }
+}
+
+/// `--offline`: serves the cached listings and refuses everything else,
+/// mutations included, without ever touching the network.
+pub struct OfflineEcr {
+    cache: Cache,
+}
+
+impl OfflineEcr {
+    pub fn new(cache: Cache) -> Self {
+        Self { cache }
+    }
+}
+
+fn unavailable<T>(what: &str) -> Result<T> {
+    bail!("offline: {} isn't available without a connection", what)
+}
+
+#[async_trait]
+impl EcrApi for OfflineEcr {
+    async fn repositories(&self) -> Result<Vec<Respository>> {
+        self.cache.load("repositories", "repositories")
+    }
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>> {
+        self.cache.load(
+            &format!("images-{}", repository_name),
+            &format!("images of {}", repository_name),
+        )
+    }
+
+    async fn image(
+        &self,
+        _registry_id: Option<&str>,
+        _repository_name: &str,
+        digest: &str,
+    ) -> Result<Image> {
+        unavailable(&format!("the manifest of {}", digest))
+    }
+
+    async fn blob(&self, _repository_name: &str, digest: &str) -> Result<Vec<u8>> {
+        unavailable(&format!("blob {}", digest))
+    }
+
+    async fn scan_findings(
+        &self,
+        _repository_name: &str,
+        _digest: &str,
+    ) -> Result<Option<Vec<Finding>>> {
+        unavailable("scan findings")
+    }
+
+    async fn enhanced_scanning(&self) -> Result<bool> {
+        unavailable("the scanning configuration")
+    }
+
+    async fn registry_id(&self) -> Result<String> {
+        unavailable("the registry id")
+    }
+
+    async fn replication_destinations(&self) -> Result<Vec<ReplicationDestination>> {
+        unavailable("replication")
+    }
+
+    fn replica(&self, region: &str, _registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        unavailable(&format!("the registry in {}", region))
+    }
+
+    async fn repository_policy(&self, repository_name: &str) -> Result<Option<String>> {
+        unavailable(&format!("the policy of {}", repository_name))
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, _policy: &str) -> Result<()> {
+        unavailable(&format!("changing the policy of {}", repository_name))
+    }
+
+    async fn repository_exists(
+        &self,
+        _registry_id: Option<&str>,
+        repository_name: &str,
+    ) -> Result<bool> {
+        Ok(self
+            .repositories()
+            .await?
+            .iter()
+            .any(|repository| repository.name == repository_name))
+    }
+
+    async fn create_repository(
+        &self,
+        _registry_id: Option<&str>,
+        repository_name: &str,
+        _settings: &RepositorySettings,
+    ) -> Result<()> {
+        unavailable(&format!("creating {}", repository_name))
+    }
+
+    async fn put_image(&self, _image: &Image, tag: &str, _destination: &Destination) -> Result<()> {
+        unavailable(&format!("tagging {}", tag))
+    }
+
+    async fn upload_blob(
+        &self,
+        _registry_id: Option<&str>,
+        repository_name: &str,
+        _blob: &[u8],
+    ) -> Result<String> {
+        unavailable(&format!("uploading to {}", repository_name))
+    }
+
+    async fn untag(&self, repository_name: &str, _tags: &[String]) -> Result<()> {
+        unavailable(&format!("untagging in {}", repository_name))
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        _digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        unavailable(&format!("deleting from {}", repository_name))
+    }
+}
@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use rusoto_core::credential::ProfileProvider;
+use rusoto_core::{HttpClient, Region};
+use rusoto_ecr::EcrClient;
+
+use crate::accounts::Account;
+use crate::args::Args;
+use crate::credentials::{AssumeRole, Credentials, WebIdentity};
+use crate::ecr::{EcrApi, Paging, RusotoEcr};
+use crate::throttle::RateLimiter;
+
+/// Regions where ECR offers a FIPS 140-2 validated endpoint.
+const FIPS_REGIONS: &[&str] = &[
+    "us-east-1",
+    "us-east-2",
+    "us-west-1",
+    "us-west-2",
+    "us-gov-east-1",
+    "us-gov-west-1",
+];
+
+/// The registry to talk to: AWS, at most `max_rps` requests a second, or with
+/// the `mock` feature the in-memory mock when `ECR_TAG_MOCK_REGISTRY` names a
+/// fixture file.
+pub fn ecr_api(
+    args: &Args,
+    account: Option<&Account>,
+    max_rps: Option<f64>,
+) -> Result<Box<dyn EcrApi>> {
+    let limiter = RateLimiter::new(max_rps)?;
+    if let Some(mock) = mock_api(args, account)? {
+        return Ok(mock);
+    }
+    let paging = Paging {
+        page_size: args.page_size,
+        max_results: args.max_results,
+    };
+    let region = region(args)?;
+    let credentials = credentials(args, region.name(), account)?;
+    let client = EcrClient::new_with(
+        HttpClient::new().context("failed to create the HTTP client")?,
+        credentials.clone(),
+        region.clone(),
+    );
+    Ok(Box::new(
+        RusotoEcr::new(
+            client,
+            region,
+            credentials,
+            paging,
+            args.source_registry_id.clone(),
+        )
+        .with_rate_limit(limiter),
+    ))
+}
+
+#[cfg(feature = "mock")]
+fn mock_api(args: &Args, account: Option<&Account>) -> Result<Option<Box<dyn EcrApi>>> {
+    use std::path::Path;
+
+    use crate::ecr::{MockEcr, MOCK_REGISTRY_ID};
+
+    let fixture = match std::env::var("ECR_TAG_MOCK_REGISTRY") {
+        Ok(fixture) => fixture,
+        Err(_) => return Ok(None),
+    };
+    let account = account.map(|account| account.id.as_str());
+    if let Some(registry_id) = &args.source_registry_id {
+        if Some(registry_id.as_str()) != account && registry_id != MOCK_REGISTRY_ID {
+            bail!("registry {} does not exist", registry_id);
+        }
+    }
+    let mock = MockEcr::from_file(Path::new(&fixture), account)?;
+    Ok(Some(Box::new(mock.with_page_size(args.page_size))))
+}
+
+#[cfg(not(feature = "mock"))]
+fn mock_api(_: &Args, _: Option<&Account>) -> Result<Option<Box<dyn EcrApi>>> {
+    Ok(None)
+}
+
+/// rusoto's `Region::default()`, reading the region of `profile` rather
+/// than `AWS_PROFILE`'s when one is given.
+fn default_region(profile: Option<&str>) -> Region {
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return Region::default(),
+    };
+    let from_profile = || {
+        let config = std::env::var_os("AWS_CONFIG_FILE")
+            .filter(|file| !file.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dirs_next::home_dir().map(|home| home.join(".aws").join("config")))?;
+        ProfileProvider::with_configuration(config, profile)
+            .region_from_profile()
+            .ok()
+            .flatten()
+    };
+    std::env::var("AWS_DEFAULT_REGION")
+        .or_else(|_| std::env::var("AWS_REGION"))
+        .ok()
+        .or_else(from_profile)
+        .and_then(|name| Region::from_str(&name).ok())
+        .unwrap_or(Region::UsEast1)
+}
+
+/// The default credential chain (or web identity), then the context's role
+/// and the role of `account`, when set.
+pub fn credentials(args: &Args, region: &str, account: Option<&Account>) -> Result<Credentials> {
+    let mut roles = vec![];
+    if let Some(role_arn) = &args.role_arn {
+        roles.push(AssumeRole::new(role_arn.clone(), region));
+    }
+    if let Some(account) = account {
+        let role_arn = account.role_arn(&args.account_role, region);
+        roles.push(AssumeRole::new(role_arn, region));
+    }
+    Ok(Credentials::new(
+        args.aws_profile.as_deref(),
+        WebIdentity::from_env(args.web_identity_token_file.clone(), region)?,
+        roles,
+    ))
+}
+
+/// Resolves the region from `--region` (falling back to the usual AWS
+/// environment/profile lookup), switching to the FIPS endpoint when asked for
+/// via `--fips` or `AWS_USE_FIPS_ENDPOINT=true`.
+///
+/// `--endpoint-url` (or `AWS_ENDPOINT_URL_ECR`/`AWS_ENDPOINT_URL`) points the
+/// client at LocalStack or another emulator instead.
+pub fn region(args: &Args) -> Result<Region> {
+    let region = match &args.region {
+        Some(name) => {
+            Region::from_str(name).with_context(|| format!("unknown region `{}`", name))?
+        }
+        None => default_region(args.aws_profile.as_deref()),
+    };
+
+    let fips = args.fips
+        || std::env::var("AWS_USE_FIPS_ENDPOINT").is_ok_and(|v| v.eq_ignore_ascii_case("true"));
+
+    let endpoint = args
+        .endpoint_url
+        .clone()
+        .or_else(|| std::env::var("AWS_ENDPOINT_URL_ECR").ok())
+        .or_else(|| std::env::var("AWS_ENDPOINT_URL").ok())
+        .filter(|url| !url.is_empty());
+    if let Some(endpoint) = endpoint {
+        if args.fips {
+            bail!("--fips can't be combined with a custom endpoint url");
+        }
+        return Ok(Region::Custom {
+            name: region.name().to_string(),
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+        });
+    }
+
+    if !fips {
+        return Ok(region);
+    }
+
+    let name = region.name().to_string();
+    if !FIPS_REGIONS.contains(&name.as_str()) {
+        bail!("ECR has no FIPS endpoint in {}", name);
+    }
+    Ok(Region::Custom {
+        endpoint: format!("https://ecr-fips.{}.amazonaws.com", name),
+        name,
+    })
+}
@@ -0,0 +1,64 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::args::AgingArgs;
+use crate::ecr::EcrApi;
+use crate::output::{self, OutputFormat, Table};
+use crate::retention::format_age;
+
+#[derive(Debug, Serialize)]
+struct StaleTag {
+    repository: String,
+    tag: String,
+    digest: String,
+    pushed: DateTime<Utc>,
+    age_days: i64,
+}
+
+/// Lists tags pointing at images older than the threshold, oldest first, to
+/// surface deployments that haven't moved in a long time.
+pub async fn run(ecr: &dyn EcrApi, args: &AgingArgs, format: OutputFormat) -> Result<()> {
+    let repositories = super::repository_names(ecr, args.repository.as_deref()).await?;
+
+    let now = Utc::now();
+    let mut stale = vec![];
+    for repository in &repositories {
+        for image in ecr.images(repository).await? {
+            if now - image.created <= args.older_than {
+                continue;
+            }
+            for tag in &image.tags {
+                if !args.tags.is_empty() && !args.tags.contains(tag) {
+                    continue;
+                }
+                stale.push(StaleTag {
+                    repository: repository.clone(),
+                    tag: tag.clone(),
+                    digest: image.digest.clone(),
+                    pushed: image.created,
+                    age_days: (now - image.created).num_days(),
+                });
+            }
+        }
+    }
+    stale.sort_by_key(|tag| tag.pushed);
+
+    let mut table = Table::new(&["REPOSITORY", "TAG", "AGE", "PUSHED", "DIGEST"]);
+    for tag in &stale {
+        table.push(vec![
+            tag.repository.clone(),
+            tag.tag.clone(),
+            format!("{}d", tag.age_days),
+            tag.pushed.to_rfc3339(),
+            tag.digest.clone(),
+        ]);
+    }
+    output::print(format, &table, &stale)?;
+    eprintln!(
+        "{} tag(s) point at images older than {}",
+        stale.len(),
+        format_age(args.older_than)
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::args::BlobGetArgs;
+use crate::ecr::EcrApi;
+use crate::model::format_size;
+
+/// Downloads one layer or config blob, checking it against its digest.
+pub async fn get(ecr: &dyn EcrApi, args: &BlobGetArgs) -> Result<()> {
+    let blob = ecr.blob(&args.repository, &args.digest).await?;
+
+    if let Some(expected) = args.digest.strip_prefix("sha256:") {
+        let actual = hex::encode(Sha256::digest(&blob));
+        if actual != expected {
+            bail!(
+                "downloaded blob hashes to sha256:{}, not {}",
+                actual,
+                args.digest
+            );
+        }
+    }
+
+    match args.file.as_deref() {
+        Some("-") => std::io::stdout().write_all(&blob)?,
+        file => {
+            let path = match file {
+                Some(file) => PathBuf::from(file),
+                None => PathBuf::from(args.digest.replace(':', "-")),
+            };
+            std::fs::write(&path, &blob)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            eprintln!(
+                "wrote {} to {}",
+                format_size(blob.len() as i64),
+                path.display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::args::Args;
+use crate::config::Config;
+use crate::context;
+use crate::ecr::EcrApi;
+use crate::output::{self, OutputFormat, Table};
+
+#[derive(Serialize)]
+struct ContextRow<'a> {
+    name: &'a str,
+    profile: Option<&'a str>,
+    region: Option<&'a str>,
+    role_arn: Option<&'a str>,
+    current: bool,
+}
+
+/// Lists the configured contexts, marking the saved one.
+pub fn list(config: &Config, format: OutputFormat) -> Result<()> {
+    let current = context::current();
+    let rows: Vec<ContextRow> = config
+        .context
+        .iter()
+        .map(|(name, context)| ContextRow {
+            name,
+            profile: context.profile.as_deref(),
+            region: context.region.as_deref(),
+            role_arn: context.role_arn.as_deref(),
+            current: current.as_deref() == Some(name.as_str()),
+        })
+        .collect();
+    if rows.is_empty() && format == OutputFormat::Table {
+        eprintln!("no contexts configured, add a [context.<name>] section to the config");
+        return Ok(());
+    }
+
+    let mut table = Table::new(&["CURRENT", "NAME", "PROFILE", "REGION", "ROLE"]);
+    for row in &rows {
+        table.push(vec![
+            if row.current { "*" } else { "" }.to_string(),
+            row.name.to_string(),
+            row.profile.unwrap_or("-").to_string(),
+            row.region.unwrap_or("-").to_string(),
+            row.role_arn.unwrap_or("-").to_string(),
+        ]);
+    }
+    output::print(format, &table, &rows)
+}
+
+/// Saves `name` as the context used when `--context` isn't given.
+pub fn switch(config: &Config, name: &str) -> Result<()> {
+    if !config.context.contains_key(name) {
+        bail!("no context named {}", name);
+    }
+    context::set_current(name)?;
+    eprintln!("switched to context {}", name);
+    Ok(())
+}
+
+/// Prints the active context and the account and region it resolves to.
+pub async fn show(ecr: &dyn EcrApi, args: &Args) -> Result<()> {
+    let (account, region) = context::account_and_region(ecr, args).await;
+    println!("context: {}", args.context.as_deref().unwrap_or("none"));
+    println!("profile: {}", args.profile());
+    println!("region:  {}", region);
+    println!("account: {}", account);
+    println!("role:    {}", args.role_arn.as_deref().unwrap_or("-"));
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::cmp::Reverse;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+
+use crate::args::{Args, DaemonArgs};
+use crate::config::{Config, TagRule};
+use crate::ecr::{Destination, EcrApi};
+use crate::hooks;
+use crate::metrics;
+use crate::model::ImageDetail;
+use crate::retention;
+use crate::scan::{Gate, Severity};
+use crate::tags;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(300);
+
+/// A `[[repo."<name>".rule]]`, parsed.
+struct Rule<'a> {
+    repository: &'a str,
+    rule: &'a TagRule,
+    fail_on: Option<Severity>,
+    max_age: Option<chrono::Duration>,
+}
+
+/// A rule found not to hold.
+struct Drift {
+    problem: String,
+    /// The digest moving the tag to would fix it.
+    fix: Option<String>,
+}
+
+/// Checks the configured tag rules every interval until killed, moving the
+/// tags it may and alerting on the rest. Each check's changes are recorded
+/// and reported like a run of their own.
+pub async fn run(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    daemon: &DaemonArgs,
+) -> Result<()> {
+    let rules = parse_rules(config)?;
+    if rules.is_empty() {
+        bail!("no rules to check; add some to the config as [[repo.\"<name>\".rule]]");
+    }
+    let interval = daemon
+        .interval
+        .or(config.daemon.interval.map(Duration::from_secs))
+        .unwrap_or(DEFAULT_INTERVAL);
+    loop {
+        let started = Instant::now();
+        let result = check_all(ecr, args, config, &rules, daemon.dry_run).await;
+        if daemon.once {
+            return result;
+        }
+        if let Err(e) = metrics::report(args, config, result, started.elapsed()).await {
+            eprintln!("error: {:#}", e);
+        }
+        args.locks.release().await;
+        tokio::time::sleep(interval).await;
+    }
+}
+
+fn parse_rules(config: &Config) -> Result<Vec<Rule<'_>>> {
+    let mut rules = vec![];
+    for (repository, repo) in &config.repo {
+        for rule in &repo.rule {
+            let parse = || -> Result<Rule<'_>> {
+                tags::validate(&rule.tag)?;
+                if !rule.newest && rule.max_age.is_none() {
+                    bail!("set newest = true or a max_age");
+                }
+                Ok(Rule {
+                    repository,
+                    rule,
+                    fail_on: rule.fail_on.as_deref().map(str::parse).transpose()?,
+                    max_age: rule
+                        .max_age
+                        .as_deref()
+                        .map(retention::parse_age)
+                        .transpose()?,
+                })
+            };
+            rules.push(parse().with_context(|| {
+                format!("invalid rule for {}:{} in the config", repository, rule.tag)
+            })?);
+        }
+    }
+    Ok(rules)
+}
+
+/// Checks every rule once, failing if any drifted and wasn't fixed.
+async fn check_all(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    rules: &[Rule<'_>],
+    dry_run: bool,
+) -> Result<()> {
+    let mut unfixed = 0;
+    let mut images: Option<(&str, Vec<ImageDetail>)> = None;
+    for rule in rules {
+        // The rules come grouped by repository, so each is listed once.
+        if images.as_ref().map(|(repository, _)| *repository) != Some(rule.repository) {
+            images = match ecr.images(rule.repository).await {
+                Ok(listed) => Some((rule.repository, listed)),
+                Err(e) => {
+                    alert(
+                        args,
+                        config,
+                        rule,
+                        &format!("couldn't list the images: {:#}", e),
+                    )
+                    .await;
+                    unfixed += 1;
+                    continue;
+                }
+            };
+        }
+        let listed = &images.as_ref().unwrap().1;
+        for drift in check(ecr, config, rule, listed).await {
+            match (&drift.fix, rule.rule.apply, dry_run) {
+                (Some(digest), true, false) => match fix(ecr, rule, digest).await {
+                    Ok(()) => eprintln!(
+                        "fixed {}:{}: {}",
+                        rule.repository, rule.rule.tag, drift.problem
+                    ),
+                    Err(e) => {
+                        alert(
+                            args,
+                            config,
+                            rule,
+                            &format!("{}, and moving it failed: {:#}", drift.problem, e),
+                        )
+                        .await;
+                        unfixed += 1;
+                    }
+                },
+                (Some(_), true, true) => eprintln!(
+                    "drift {}:{}: {} (not moving it, --dry-run)",
+                    rule.repository, rule.rule.tag, drift.problem
+                ),
+                _ => {
+                    alert(args, config, rule, &drift.problem).await;
+                    unfixed += 1;
+                }
+            }
+        }
+    }
+    match unfixed {
+        0 => Ok(()),
+        1 => Err(anyhow!("1 tag rule doesn't hold")),
+        n => Err(anyhow!("{} tag rules don't hold", n)),
+    }
+}
+
+/// What's wrong with `rule`'s tag among the repository's `images`.
+async fn check(
+    ecr: &dyn EcrApi,
+    config: &Config,
+    rule: &Rule<'_>,
+    images: &[ImageDetail],
+) -> Vec<Drift> {
+    let tag = &rule.rule.tag;
+    let mut drifts = vec![];
+    let mut holder = images.iter().find(|image| image.tags.contains(tag));
+    if rule.rule.newest {
+        match newest_passing(ecr, config, rule, images).await {
+            None => drifts.push(Drift {
+                problem: "no image passes the checks to carry it".to_string(),
+                fix: None,
+            }),
+            Some(newest) if holder.map(|image| &image.digest) != Some(&newest.digest) => {
+                drifts.push(Drift {
+                    problem: match holder {
+                        Some(image) => format!(
+                            "it's on {}, but the newest image passing the checks is {}",
+                            image.digest, newest.digest
+                        ),
+                        None => format!(
+                            "it's on no image, but the newest image passing the checks is {}",
+                            newest.digest
+                        ),
+                    },
+                    fix: Some(newest.digest.clone()),
+                });
+                if rule.rule.apply {
+                    holder = Some(newest);
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    if let Some(max_age) = rule.max_age {
+        match holder {
+            None if !rule.rule.newest => drifts.push(Drift {
+                problem: "it's on no image".to_string(),
+                fix: None,
+            }),
+            Some(image) if Utc::now() - image.created > max_age => drifts.push(Drift {
+                problem: format!(
+                    "it's on {}, pushed {} ago (at most {})",
+                    image.digest,
+                    retention::format_age(Utc::now() - image.created),
+                    retention::format_age(max_age)
+                ),
+                fix: None,
+            }),
+            _ => {}
+        }
+    }
+    drifts
+}
+
+/// The newest of the rule's candidates the tag's gate lets through.
+async fn newest_passing<'i>(
+    ecr: &dyn EcrApi,
+    config: &Config,
+    rule: &Rule<'_>,
+    images: &'i [ImageDetail],
+) -> Option<&'i ImageDetail> {
+    let gate = Gate {
+        fail_on: rule.fail_on,
+        repos: Some(&config.repo),
+        ..Gate::default()
+    };
+    let mut candidates: Vec<&ImageDetail> = images
+        .iter()
+        .filter(|image| {
+            rule.rule.candidates.is_empty()
+                || image.tags.iter().any(|tag| {
+                    rule.rule
+                        .candidates
+                        .iter()
+                        .any(|pattern| tags::matches(pattern, tag))
+                })
+        })
+        .collect();
+    candidates.sort_by_key(|image| Reverse(image.created));
+    for image in candidates {
+        if gate
+            .check(ecr, rule.repository, &image.digest, &rule.rule.tag)
+            .await
+            .is_ok()
+        {
+            return Some(image);
+        }
+    }
+    None
+}
+
+async fn fix(ecr: &dyn EcrApi, rule: &Rule<'_>, digest: &str) -> Result<()> {
+    let image = ecr.image(None, rule.repository, digest).await?;
+    ecr.put_image(&image, &rule.rule.tag, &Destination::default())
+        .await
+}
+
+/// Reports drift that wasn't fixed, and runs the `[daemon] alert` command.
+async fn alert(args: &Args, config: &Config, rule: &Rule<'_>, problem: &str) {
+    eprintln!("drift {}:{}: {}", rule.repository, rule.rule.tag, problem);
+    if let Some(command) = &config.daemon.alert {
+        let vars = [
+            ("repo", rule.repository),
+            ("tag", rule.rule.tag.as_str()),
+            ("problem", problem),
+        ];
+        if let Err(e) = hooks::run("alert", command, &vars, args.aws_profile.as_deref()).await {
+            eprintln!("warning: {:#}", e);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{bail, Result};
+use crossterm::style::Stylize;
+use crossterm::tty::IsTty;
+
+use crate::args::{ApplyArgs, Args, DiffStateArgs};
+use crate::config::Config;
+use crate::desired::{self, Action, DesiredState, Difference};
+use crate::ecr::{Destination, EcrApi};
+use crate::output::{self, OutputFormat, Table};
+use crate::ui::Ui;
+
+/// Lists the tags of the state file that aren't where it says, failing if
+/// any, without changing anything.
+pub async fn diff(ecr: &dyn EcrApi, args: &DiffStateArgs, format: OutputFormat) -> Result<()> {
+    let state = DesiredState::load(&args.file)?;
+    let differences = desired::compare(ecr, &state).await?;
+    let mut table = Table::new(&["REPOSITORY", "TAG", "STATUS", "CURRENT", "DESIRED"]);
+    for difference in &differences {
+        table.push(vec![
+            difference.repository.clone(),
+            difference.tag.clone(),
+            difference.action.label().to_string(),
+            difference
+                .current
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+            difference.desired.clone(),
+        ]);
+    }
+    output::print(format, &table, &differences)?;
+
+    let drifted = differences
+        .iter()
+        .filter(|difference| difference.action != Action::Unchanged)
+        .count();
+    if drifted > 0 {
+        bail!(
+            "{} of {} tags differ from {}",
+            drifted,
+            differences.len(),
+            args.file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Shows what it takes to bring the registry to the state file, and once
+/// confirmed (or with `--auto-approve`), creates and moves the tags.
+pub async fn apply(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &Args,
+    config: &Config,
+    apply: &ApplyArgs,
+) -> Result<()> {
+    let state = DesiredState::load(&apply.file)?;
+    let differences = desired::compare(ecr, &state).await?;
+    let changes: Vec<&Difference> = differences
+        .iter()
+        .filter(|difference| difference.action != Action::Unchanged)
+        .collect();
+    for change in &changes {
+        print_change(ui, change);
+    }
+    let count = |action: Action| {
+        differences
+            .iter()
+            .filter(|difference| difference.action == action)
+            .count()
+    };
+    println!(
+        "\nPlan: {} to create, {} to move, {} unchanged.",
+        count(Action::Create),
+        count(Action::Move),
+        count(Action::Unchanged)
+    );
+    if changes.is_empty() {
+        return Ok(());
+    }
+    if !apply.auto_approve {
+        if !std::io::stdin().is_tty() {
+            bail!("not applying without a terminal to confirm in; pass --auto-approve");
+        }
+        if !ui.confirm("apply these changes?").prompt()? {
+            bail!("aborted, no tags were changed");
+        }
+    }
+
+    let gate = args.gate(config);
+    let mut failed = 0;
+    for change in &changes {
+        let name = format!("{}:{}", change.repository, change.tag);
+        let result = async {
+            gate.check(ecr, &change.repository, &change.desired, &change.tag)
+                .await?;
+            gate.verify_signature(ecr, &change.repository, &change.desired)
+                .await?;
+            let image = ecr.image(None, &change.repository, &change.desired).await?;
+            super::ensure_unmoved(
+                ecr,
+                &args.locks,
+                None,
+                &change.repository,
+                &change.tag,
+                change.current.as_deref(),
+                &change.desired,
+            )
+            .await?;
+            ecr.put_image(&image, &change.tag, &Destination::default())
+                .await
+        }
+        .await;
+        match result {
+            Ok(()) => ui.status(true, &format!("{} -> {}", name, change.desired)),
+            Err(e) => {
+                ui.status(false, &format!("{}: {:#}", name, e));
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} changes failed", failed, changes.len());
+    }
+    Ok(())
+}
+
+/// One line of the plan, `+` for a tag created and `~` for one moved.
+fn print_change(ui: &Ui, change: &Difference) {
+    let name = format!("{}:{}", change.repository, change.tag);
+    let (symbol, line) = match &change.current {
+        Some(current) => ("~", format!("{} {} -> {}", name, current, change.desired)),
+        None => ("+", format!("{} {}", name, change.desired)),
+    };
+    match (ui.color, symbol) {
+        (true, "+") => println!("  {} {}", symbol.green(), line),
+        (true, _) => println!("  {} {}", symbol.yellow(), line),
+        (false, _) => println!("  {} {}", symbol, line),
+    }
+}
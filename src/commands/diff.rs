@@ -0,0 +1,93 @@
+use std::collections::HashSet;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::args::DiffArgs;
+use crate::ecr::EcrApi;
+use crate::manifest::{Descriptor, Manifest};
+use crate::model::format_size;
+use crate::output::{self, OutputFormat, Table};
+
+#[derive(Debug, Serialize)]
+struct LayerDiff {
+    from: String,
+    to: String,
+    shared: Vec<Descriptor>,
+    added: Vec<Descriptor>,
+    removed: Vec<Descriptor>,
+    shared_bytes: i64,
+    added_bytes: i64,
+    removed_bytes: i64,
+}
+
+/// Compares the layers of two images, to show how much of an update is new.
+pub async fn run(ecr: &dyn EcrApi, args: &DiffArgs, format: OutputFormat) -> Result<()> {
+    let from = manifest(ecr, &args.repository, &args.from).await?;
+    let to = manifest(ecr, &args.repository, &args.to).await?;
+
+    let from_digests: HashSet<&str> = from.layers.iter().map(|l| l.digest.as_str()).collect();
+    let to_digests: HashSet<&str> = to.layers.iter().map(|l| l.digest.as_str()).collect();
+    let (shared, added): (Vec<Descriptor>, Vec<Descriptor>) = to
+        .layers
+        .iter()
+        .cloned()
+        .partition(|layer| from_digests.contains(layer.digest.as_str()));
+    let removed: Vec<Descriptor> = from
+        .layers
+        .iter()
+        .filter(|layer| !to_digests.contains(layer.digest.as_str()))
+        .cloned()
+        .collect();
+
+    let bytes = |layers: &[Descriptor]| layers.iter().map(|layer| layer.size).sum();
+    let diff = LayerDiff {
+        from: args.from.clone(),
+        to: args.to.clone(),
+        shared_bytes: bytes(&shared),
+        added_bytes: bytes(&added),
+        removed_bytes: bytes(&removed),
+        shared,
+        added,
+        removed,
+    };
+
+    let mut table = Table::new(&["LAYER", "DIGEST", "SIZE"]);
+    for (status, layers) in [
+        ("shared", &diff.shared),
+        ("added", &diff.added),
+        ("removed", &diff.removed),
+    ] {
+        for layer in layers {
+            table.push(vec![
+                status.to_string(),
+                layer.digest.clone(),
+                format_size(layer.size),
+            ]);
+        }
+    }
+    output::print(format, &table, &diff)?;
+
+    let total = to.size();
+    let new_percent = match total {
+        0 => 0.0,
+        total => diff.added_bytes as f64 / total as f64 * 100.0,
+    };
+    eprintln!(
+        "{} shares {} layer(s) ({}) with {}; {} new layer(s) add {} ({:.0}% of the image)",
+        args.to,
+        diff.shared.len(),
+        format_size(diff.shared_bytes),
+        args.from,
+        diff.added.len(),
+        format_size(diff.added_bytes),
+        new_percent
+    );
+    Ok(())
+}
+
+async fn manifest(ecr: &dyn EcrApi, repository: &str, reference: &str) -> Result<Manifest> {
+    let digest = super::resolve_reference(ecr, repository, reference).await?;
+    let image = ecr.image(None, repository, &digest).await?;
+    Manifest::parse(&image.manifest).with_context(|| format!("reading {}", reference))
+}
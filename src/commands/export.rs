@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::signature::SignedRequest;
+
+use crate::args::{Args, ExportArgs};
+use crate::client;
+use crate::ecr::EcrApi;
+use crate::http;
+use crate::inventory::{self, Row};
+
+/// Writes every image of every repository to stdout, or to a new object
+/// under `--s3`, partitioned by day for Athena.
+pub async fn run(ecr: &dyn EcrApi, args: &Args, export: &ExportArgs) -> Result<()> {
+    let region = client::region(args)?;
+    let registry_id = ecr.registry_id().await?;
+    let exported_at = Utc::now();
+    let mut rows = vec![];
+    for repository in ecr.repositories().await? {
+        for image in ecr.images(&repository.name).await? {
+            rows.push(Row::new(&registry_id, region.name(), &image, exported_at));
+        }
+    }
+    let body = inventory::render(export.format, &rows)?;
+
+    let destination = match &export.s3 {
+        Some(destination) => destination,
+        None => {
+            print!("{}", body);
+            return Ok(());
+        }
+    };
+    if args.read_only {
+        bail!("read-only mode: refusing to upload to {}", destination);
+    }
+    let (bucket, prefix) = destination
+        .strip_prefix("s3://")
+        .map(|rest| rest.split_once('/').unwrap_or((rest, "")))
+        .filter(|(bucket, _)| !bucket.is_empty())
+        .ok_or_else(|| anyhow!("--s3 expects s3://bucket/prefix/, got `{}`", destination))?;
+    let prefix = match prefix.is_empty() || prefix.ends_with('/') {
+        true => prefix.to_string(),
+        false => format!("{}/", prefix),
+    };
+    let key = format!(
+        "{}dt={}/{}-{}-{}.{}",
+        prefix,
+        exported_at.format("%Y-%m-%d"),
+        registry_id,
+        region.name(),
+        exported_at.format("%Y%m%dT%H%M%SZ"),
+        export.format.extension()
+    );
+
+    let credentials = client::credentials(args, region.name(), args.target_account.as_ref())?
+        .credentials()
+        .await
+        .context("failed to resolve credentials")?;
+    let mut request = SignedRequest::new("PUT", "s3", &region, &format!("/{}", key));
+    request.set_hostname(Some(format!("{}.{}", bucket, request.hostname())));
+    request.set_content_type(export.format.content_type().to_string());
+    request.set_payload(Some(body));
+    http::send_signed(request, &credentials, Duration::from_secs(120))
+        .await
+        .with_context(|| format!("failed to upload s3://{}/{}", bucket, key))?;
+    eprintln!("exported {} images to s3://{}/{}", rows.len(), bucket, key);
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::accounts::Account;
+use crate::args::FindArgs;
+use crate::ecr::EcrApi;
+use crate::output::{self, OutputFormat, Table};
+
+#[derive(Serialize)]
+struct Found {
+    account: String,
+    account_name: Option<String>,
+    region: String,
+    repository: String,
+    digest: String,
+    tags: Vec<String>,
+    pushed_at: DateTime<Utc>,
+}
+
+/// Reports every repository, in every given registry and region, holding the
+/// image `args.reference` names, e.g. to see how far a compromised image got.
+///
+/// A registry that can't be searched doesn't stop the others, but fails the
+/// run once they are done so the gap isn't missed.
+pub async fn run(
+    registries: &[(Option<Account>, Box<dyn EcrApi>)],
+    args: &FindArgs,
+    current_region: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let regions = match args.regions.is_empty() {
+        true => vec![current_region.to_string()],
+        false => args.regions.clone(),
+    };
+    let digest = args.reference.starts_with("sha256:");
+
+    let mut found = vec![];
+    let mut failed = 0;
+    let searched = registries.len() * regions.len();
+    for (account, ecr) in registries {
+        let registry_id = match ecr.registry_id().await {
+            Ok(registry_id) => registry_id,
+            Err(e) => {
+                let name = account
+                    .as_ref()
+                    .map_or("the current account".to_string(), |a| a.to_string());
+                eprintln!("warning: couldn't search {}: {:#}", name, e);
+                failed += regions.len();
+                continue;
+            }
+        };
+        for region in &regions {
+            let result = async {
+                let replica;
+                let ecr = match region == current_region {
+                    true => ecr.as_ref(),
+                    false => {
+                        replica = ecr.replica(region, &registry_id)?;
+                        replica.as_ref()
+                    }
+                };
+                let mut matches = vec![];
+                for repository in ecr.repositories().await? {
+                    for image in ecr.images(&repository.name).await? {
+                        let hit = match digest {
+                            true => image.digest == args.reference,
+                            false => image.tags.contains(&args.reference),
+                        };
+                        if hit {
+                            matches.push(Found {
+                                account: registry_id.clone(),
+                                account_name: account.as_ref().and_then(|a| a.name.clone()),
+                                region: region.clone(),
+                                repository: repository.name.clone(),
+                                digest: image.digest,
+                                tags: image.tags,
+                                pushed_at: image.created,
+                            });
+                        }
+                    }
+                }
+                anyhow::Ok(matches)
+            }
+            .await;
+            match result {
+                Ok(matches) => found.extend(matches),
+                Err(e) => {
+                    eprintln!(
+                        "warning: couldn't search {} in {}: {:#}",
+                        registry_id, region, e
+                    );
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if found.is_empty() && format == OutputFormat::Table {
+        let searched = searched - failed;
+        let noun = if searched == 1 {
+            "registry"
+        } else {
+            "registries"
+        };
+        eprintln!(
+            "{} not found in the {} {} searched",
+            args.reference, searched, noun
+        );
+    } else {
+        let mut table = Table::new(&[
+            "ACCOUNT",
+            "REGION",
+            "REPOSITORY",
+            "DIGEST",
+            "TAGS",
+            "PUSHED",
+        ]);
+        for item in &found {
+            table.push(vec![
+                match &item.account_name {
+                    Some(name) => format!("{} ({})", name, item.account),
+                    None => item.account.clone(),
+                },
+                item.region.clone(),
+                item.repository.clone(),
+                item.digest.clone(),
+                item.tags.join(","),
+                item.pushed_at.format("%Y-%m-%d %H:%M").to_string(),
+            ]);
+        }
+        output::print(format, &table, &found)?;
+    }
+
+    if failed > 0 {
+        bail!("{} of {} registries couldn't be searched", failed, searched);
+    }
+    Ok(())
+}
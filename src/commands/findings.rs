@@ -0,0 +1,298 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+
+use anyhow::{bail, Result};
+use crossterm::style::Stylize;
+use crossterm::tty::IsTty;
+use inquire::InquireError;
+use serde::Serialize;
+
+use crate::args::FindingsArgs;
+use crate::ecr::EcrApi;
+use crate::model::Finding;
+use crate::output::{self, OutputFormat, Table};
+use crate::picker;
+use crate::sarif;
+use crate::scan::Severity;
+use crate::ui::Ui;
+
+#[derive(Serialize)]
+struct FindingsDiff<'a> {
+    from: String,
+    to: String,
+    new: Vec<&'a Finding>,
+    fixed: Vec<&'a Finding>,
+    unchanged: Vec<&'a Finding>,
+}
+
+/// Lists an image's scan findings, most severe first, colored by severity.
+/// In a terminal the list is a picker that prints a finding's details.
+pub async fn run(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &FindingsArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    let (digest, findings) = load(ecr, args, &args.reference).await?;
+    if let Some(against) = &args.against {
+        let (from, baseline) = load(ecr, args, against).await?;
+        return diff(
+            &args.repository,
+            (against, &from, &baseline),
+            (&args.reference, &digest, &findings),
+            format,
+        );
+    }
+
+    if format == OutputFormat::Sarif {
+        let location = format!("{}@{}", args.repository, digest);
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&sarif::log(&location, &findings))?
+        );
+        return Ok(());
+    }
+    if findings.is_empty() && format == OutputFormat::Table {
+        eprintln!("{}@{} has no findings", args.repository, digest);
+        return Ok(());
+    }
+    // Only enhanced scanning knows about fixes and exploits.
+    let enhanced = findings
+        .iter()
+        .any(|finding| finding.fix_available.is_some() || finding.exploit_available.is_some());
+    let mut headers = vec!["SEVERITY", "NAME", "PACKAGE", "VERSION", "CVSS"];
+    if enhanced {
+        headers.extend(["FIXED IN", "EXPLOIT"]);
+    }
+    let mut table = Table::new(&headers);
+    for finding in &findings {
+        let mut row = vec![
+            finding.severity.clone(),
+            finding.name.clone(),
+            finding.package.clone().unwrap_or_else(|| "-".to_string()),
+            finding
+                .package_version
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+            finding
+                .cvss_score
+                .map_or("-".to_string(), |score| format!("{:.1}", score)),
+        ];
+        if enhanced {
+            row.push(fix(finding));
+            row.push(match finding.exploit_available.as_deref() {
+                Some("YES") => "yes".to_string(),
+                Some("NO") => "no".to_string(),
+                _ => "-".to_string(),
+            });
+        }
+        table.push(row);
+    }
+    if format != OutputFormat::Table {
+        return output::print(format, &table, &findings);
+    }
+
+    let lines: Vec<String> = table.render().lines().map(str::to_string).collect();
+    if std::io::stdin().is_tty() && std::io::stdout().is_tty() {
+        return browse(ui, &lines, &findings);
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let color = i.checked_sub(1).and_then(|i| severity(&findings[i]));
+        match color {
+            Some(severity) if ui.color => {
+                println!("{}", line.as_str().with(picker::color(severity.color())))
+            }
+            _ => println!("{}", line),
+        }
+    }
+    Ok(())
+}
+
+/// One table row in the findings picker.
+struct Row<'a> {
+    index: usize,
+    line: &'a str,
+}
+
+impl Display for Row<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.line)
+    }
+}
+
+/// Lets findings be picked one at a time to print their details, until the
+/// picker is cancelled. `lines` is the rendered table, headers first.
+fn browse(ui: &Ui, lines: &[String], findings: &[Finding]) -> Result<()> {
+    let mut cursor = 0;
+    loop {
+        let rows = lines[1..]
+            .iter()
+            .enumerate()
+            .map(|(index, line)| Row { index, line })
+            .collect();
+        // The header lines up with the rows below it.
+        let mut picker = ui
+            .picker(&lines[0], rows)
+            .with_starting_cursor(cursor)
+            .with_preview(|row| summary(&findings[row.index]));
+        if ui.stderr_color {
+            picker = picker.with_colors(|row| severity(&findings[row.index]).map(Severity::color));
+        }
+        let row = match picker.prompt() {
+            Ok(row) => row,
+            Err(InquireError::OperationCanceled) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        cursor = row.index;
+        print!("{}", details(&findings[row.index]));
+    }
+}
+
+/// The highlighted finding's key facts, under the picker.
+fn summary(finding: &Finding) -> Vec<String> {
+    let mut facts = vec![package(finding)];
+    if let Some(score) = finding.cvss_score {
+        facts.push(format!("CVSS {:.1}", score));
+    }
+    if finding.fix_available.is_some() || finding.fixed_in.is_some() {
+        facts.push(format!("fix: {}", fix(finding)));
+    }
+    let mut lines = vec![format!("{}: {}", finding.name, facts.join(", "))];
+    if let Some(description) = &finding.description {
+        lines.push(description.clone());
+    }
+    lines
+}
+
+/// Everything known about a finding, printed once it's picked.
+fn details(finding: &Finding) -> String {
+    let field = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+    let mut out = format!("{} ({})\n", finding.name, finding.severity);
+    out.push_str(&format!("  package:   {}\n", package(finding)));
+    out.push_str(&format!("  fixed in:  {}\n", fix(finding)));
+    out.push_str(&format!(
+        "  cvss:      {}\n",
+        field(finding.cvss_score.map(|score| format!("{:.1}", score)))
+    ));
+    if let Some(exploit) = &finding.exploit_available {
+        out.push_str(&format!("  exploit:   {}\n", exploit.to_lowercase()));
+    }
+    out.push_str(&format!("  more:      {}\n", field(finding.uri.clone())));
+    if let Some(description) = &finding.description {
+        out.push_str(&format!("\n  {}\n", description));
+    }
+    out.push('\n');
+    out
+}
+
+fn package(finding: &Finding) -> String {
+    match (&finding.package, &finding.package_version) {
+        (Some(package), Some(version)) => format!("{} {}", package, version),
+        (Some(package), None) => package.clone(),
+        _ => "unknown package".to_string(),
+    }
+}
+
+/// The image's digest and its findings at or above `--min-severity`, most
+/// severe first.
+async fn load(
+    ecr: &dyn EcrApi,
+    args: &FindingsArgs,
+    reference: &str,
+) -> Result<(String, Vec<Finding>)> {
+    let digest = super::resolve_reference(ecr, &args.repository, reference).await?;
+    let mut findings = match ecr.scan_findings(&args.repository, &digest).await? {
+        Some(findings) => findings,
+        None => bail!("{}@{} has no scan results", args.repository, digest),
+    };
+    if let Some(min) = args.min_severity {
+        findings.retain(|finding| severity(finding).is_some_and(|severity| severity >= min));
+    }
+    findings.sort_by(|a, b| {
+        severity(b)
+            .cmp(&severity(a))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok((digest, findings))
+}
+
+/// Compares the findings of the `--against` image (`from`) with the
+/// candidate's (`to`): what the candidate would introduce, fix and keep.
+/// SARIF output carries only the new findings.
+fn diff(
+    repository: &str,
+    (from_reference, from, baseline): (&str, &str, &[Finding]),
+    (to_reference, to, candidate): (&str, &str, &[Finding]),
+    format: OutputFormat,
+) -> Result<()> {
+    // The same CVE in another package is a different finding.
+    let key = |finding: &Finding| (finding.name.clone(), finding.package.clone());
+    let baseline_keys: HashSet<_> = baseline.iter().map(key).collect();
+    let candidate_keys: HashSet<_> = candidate.iter().map(key).collect();
+    let (unchanged, new) = candidate
+        .iter()
+        .partition(|finding| baseline_keys.contains(&key(finding)));
+    let diff = FindingsDiff {
+        from: format!("{}@{}", repository, from),
+        to: format!("{}@{}", repository, to),
+        new,
+        fixed: baseline
+            .iter()
+            .filter(|finding| !candidate_keys.contains(&key(finding)))
+            .collect(),
+        unchanged,
+    };
+
+    if format == OutputFormat::Sarif {
+        let new: Vec<Finding> = diff.new.iter().map(|&finding| finding.clone()).collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&sarif::log(&diff.to, &new))?
+        );
+        return Ok(());
+    }
+    let mut table = Table::new(&["STATUS", "SEVERITY", "NAME", "PACKAGE", "VERSION"]);
+    for (status, findings) in [
+        ("new", &diff.new),
+        ("fixed", &diff.fixed),
+        ("unchanged", &diff.unchanged),
+    ] {
+        for finding in findings {
+            table.push(vec![
+                status.to_string(),
+                finding.severity.clone(),
+                finding.name.clone(),
+                finding.package.clone().unwrap_or_else(|| "-".to_string()),
+                finding
+                    .package_version
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+            ]);
+        }
+    }
+    output::print(format, &table, &diff)?;
+    eprintln!(
+        "{} against {}: {} new, {} fixed, {} unchanged",
+        to_reference,
+        from_reference,
+        diff.new.len(),
+        diff.fixed.len(),
+        diff.unchanged.len()
+    );
+    Ok(())
+}
+
+/// The fixed version, or whether Inspector knows of a fix at all.
+fn fix(finding: &Finding) -> String {
+    match (&finding.fixed_in, finding.fix_available.as_deref()) {
+        (Some(version), Some("PARTIAL")) => format!("{} (partial)", version),
+        (Some(version), _) => version.clone(),
+        (None, Some("NO")) => "no fix".to_string(),
+        _ => "-".to_string(),
+    }
+}
+
+/// `None` for `UNDEFINED` and anything else ECR doesn't rank.
+fn severity(finding: &Finding) -> Option<Severity> {
+    finding.severity.parse().ok()
+}
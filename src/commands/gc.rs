@@ -0,0 +1,405 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::args::GcArgs;
+use crate::ecr::{EcrApi, BATCH_SIZE};
+use crate::model::{format_size, ImageDetail};
+use crate::output::{self, OutputFormat, Table};
+use crate::retention::{self, format_age, Rules};
+use crate::state::{self, StateFile};
+use crate::ui::Ui;
+
+/// Deletes everything but the newest `--keep` images, sparing protected tags.
+pub async fn run(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &GcArgs,
+    state_file: Option<&Path>,
+    resume: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let path = state::path(state_file, "gc")?;
+    if resume {
+        let state = StateFile::<GcPlan>::resume(path, "gc")?;
+        if state.plan().repository != args.repository {
+            bail!(
+                "the interrupted gc run was for {}, not {}",
+                state.plan().repository,
+                args.repository
+            );
+        }
+        let rules = state.plan().rules();
+        if given(&args.rules) && !same(&args.rules, &rules) {
+            bail!(
+                "the interrupted gc run used {}, not {}; rerun without --resume to plan again",
+                describe(&rules),
+                describe(&args.rules)
+            );
+        }
+        eprintln!(
+            "resuming: {} of {} image(s) already deleted",
+            state.done(),
+            state.plan().digests.len()
+        );
+
+        // Tags may have moved since the plan was made, so the rules are
+        // applied again and only images they still condemn are deleted.
+        let doomed: Vec<String> = rules
+            .evaluate(
+                retention::standalone(ecr, ecr.images(&args.repository).await?).await?,
+                Utc::now(),
+            )
+            .into_iter()
+            .filter(|verdict| verdict.delete)
+            .map(|verdict| verdict.image.digest)
+            .collect();
+        let (remaining, spared): (Vec<String>, Vec<String>) = state
+            .plan()
+            .digests
+            .iter()
+            .filter(|digest| !state.is_done(digest))
+            .cloned()
+            .partition(|digest| doomed.contains(digest));
+        for digest in &spared {
+            eprintln!("skipping {}: it is protected or gone now", digest);
+        }
+        return delete(ecr, ui, state, remaining).await;
+    }
+
+    let images = retention::standalone(ecr, ecr.images(&args.repository).await?).await?;
+    let doomed: Vec<ImageDetail> = args
+        .rules
+        .evaluate(images, Utc::now())
+        .into_iter()
+        .filter(|verdict| verdict.delete)
+        .map(|verdict| verdict.image)
+        .collect();
+
+    let reclaimed: i64 = doomed.iter().map(|image| image.size).sum();
+    let mut table = Table::new(&["PUSHED", "DIGEST", "SIZE", "TAGS"]);
+    for image in &doomed {
+        table.push(vec![
+            image.created.to_rfc3339(),
+            image.digest.clone(),
+            format_size(image.size),
+            image.tags.join(", "),
+        ]);
+    }
+    output::print(format, &table, &doomed)?;
+
+    let summary = format!(
+        "{} image(s) to delete from {}, reclaiming {}",
+        doomed.len(),
+        args.repository,
+        format_size(reclaimed)
+    );
+    eprintln!("{}", summary);
+    if doomed.is_empty() || args.dry_run {
+        return Ok(());
+    }
+    if !args.yes && !ui.confirm("delete these images?").prompt()? {
+        bail!("aborted, nothing was deleted");
+    }
+
+    let plan = GcPlan {
+        repository: args.repository.clone(),
+        digests: doomed.iter().map(|image| image.digest.clone()).collect(),
+        keep: args.rules.keep,
+        max_age_hours: args.rules.max_age.map(|age| age.num_hours()),
+        protect: args.rules.protect.clone(),
+    };
+    let remaining = plan.digests.clone();
+    delete(ecr, ui, StateFile::start(path, "gc", plan)?, remaining).await
+}
+
+/// The images a gc run set out to delete, saved so `--resume` can finish it,
+/// along with the rules that chose them.
+#[derive(Serialize, Deserialize)]
+struct GcPlan {
+    repository: String,
+    digests: Vec<String>,
+    keep: Option<usize>,
+    max_age_hours: Option<i64>,
+    protect: Vec<String>,
+}
+
+impl GcPlan {
+    fn rules(&self) -> Rules {
+        Rules {
+            keep: self.keep,
+            max_age: self.max_age_hours.map(Duration::hours),
+            protect: self.protect.clone(),
+        }
+    }
+}
+
+/// Whether any rule was passed on the command line.
+fn given(rules: &Rules) -> bool {
+    rules.keep.is_some() || rules.max_age.is_some() || !rules.protect.is_empty()
+}
+
+fn same(a: &Rules, b: &Rules) -> bool {
+    let mut protect_a = a.protect.clone();
+    let mut protect_b = b.protect.clone();
+    protect_a.sort();
+    protect_b.sort();
+    a.keep == b.keep && a.max_age == b.max_age && protect_a == protect_b
+}
+
+fn describe(rules: &Rules) -> String {
+    let mut parts = vec![];
+    if let Some(keep) = rules.keep {
+        parts.push(format!("--keep {}", keep));
+    }
+    if let Some(max_age) = rules.max_age {
+        parts.push(format!("--max-age {}", format_age(max_age)));
+    }
+    for tag in &rules.protect {
+        parts.push(format!("--protect {}", tag));
+    }
+    parts.join(" ")
+}
+
+/// Deletes `remaining` a batch at a time, recording each batch.
+async fn delete(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    mut state: StateFile<GcPlan>,
+    remaining: Vec<String>,
+) -> Result<()> {
+    let repository = state.plan().repository.clone();
+    let mut failures = vec![];
+    for chunk in remaining.chunks(BATCH_SIZE) {
+        let failed = ecr.delete_images(&repository, chunk).await?;
+        for digest in chunk {
+            if !failed.iter().any(|failure| &failure.digest == digest) {
+                state.mark_done(digest)?;
+            }
+        }
+        failures.extend(failed);
+    }
+
+    for failure in &failures {
+        ui.status(false, &format!("{}: {}", failure.digest, failure.reason));
+    }
+    if !failures.is_empty() {
+        bail!(
+            "{} of {} image(s) could not be deleted; rerun with --resume to retry them",
+            failures.len(),
+            remaining.len()
+        );
+    }
+    ui.status(true, &format!("deleted {} image(s)", remaining.len()));
+    state.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::config::Config;
+    use sha2::{Digest, Sha256};
+
+    use crate::ecr::{Destination, Image, MockEcr};
+
+    /// Four images, the oldest tagged `v1` and the newest `latest`.
+    fn registry() -> MockEcr {
+        MockEcr::app(&[&["v1"], &[], &["v3"], &["latest"]])
+    }
+
+    fn gc_args(keep: usize, protect: &[&str]) -> GcArgs {
+        GcArgs {
+            repository: "app".to_string(),
+            rules: Rules {
+                keep: Some(keep),
+                max_age: None,
+                protect: protect.iter().map(|tag| tag.to_string()).collect(),
+            },
+            dry_run: false,
+            yes: true,
+        }
+    }
+
+    fn state_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "ecr-tag-gc-{}-{}.state.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn spares_protected_tags_and_the_newest_images() {
+        let ecr = registry();
+        let ui = Ui::from_config(&Config::default(), true).unwrap();
+        let path = state_file("protect");
+
+        run(
+            &ecr,
+            &ui,
+            &gc_args(1, &["v1"]),
+            Some(&path),
+            false,
+            OutputFormat::Json,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(ecr.tags("app"), vec![vec!["v1"], vec!["latest"]]);
+    }
+
+    #[tokio::test]
+    async fn neither_counts_nor_deletes_index_children_and_referrers() {
+        let ecr = registry();
+        let ui = Ui::from_config(&Config::default(), true).unwrap();
+        let path = state_file("attached");
+        let images = ecr.images("app").await.unwrap();
+        let digest = |tags: &[&str]| {
+            images
+                .iter()
+                .filter(|image| image.tags == tags)
+                .map(|image| image.digest.clone())
+                .collect::<Vec<_>>()
+        };
+        let children = digest(&[]);
+        let latest = digest(&["latest"]).remove(0);
+        let index = serde_json::json!({
+            "schemaVersion": 2,
+            "mediaType": "application/vnd.oci.image.index.v1+json",
+            "manifests": children
+                .iter()
+                .map(|digest| serde_json::json!({ "digest": digest }))
+                .collect::<Vec<_>>(),
+        })
+        .to_string();
+        let signature = serde_json::json!({ "schemaVersion": 2, "signature": latest }).to_string();
+        for (manifest, tag) in [
+            (index, "multi".to_string()),
+            (signature, format!("{}.sig", latest.replacen(':', "-", 1))),
+        ] {
+            let image = Image {
+                repository_name: "app".to_string(),
+                digest: format!(
+                    "sha256:{}",
+                    hex::encode(Sha256::digest(manifest.as_bytes()))
+                ),
+                manifest,
+                media_type: None,
+            };
+            ecr.put_image(&image, &tag, &Destination::default())
+                .await
+                .unwrap();
+        }
+
+        run(
+            &ecr,
+            &ui,
+            &gc_args(2, &[]),
+            Some(&path),
+            false,
+            OutputFormat::Json,
+        )
+        .await
+        .unwrap();
+
+        // The newest two are multi and latest; the untagged image is multi's.
+        let sig = format!("{}.sig", latest.replacen(':', "-", 1));
+        assert_eq!(
+            ecr.tags("app"),
+            vec![vec![], vec!["latest"], vec!["multi"], vec![sig.as_str()]]
+        );
+    }
+
+    #[tokio::test]
+    async fn dry_run_deletes_nothing() {
+        let ecr = registry();
+        let ui = Ui::from_config(&Config::default(), true).unwrap();
+        let mut args = gc_args(1, &[]);
+        args.dry_run = true;
+
+        run(&ecr, &ui, &args, None, false, OutputFormat::Json)
+            .await
+            .unwrap();
+
+        assert_eq!(ecr.tags("app").len(), 4);
+    }
+
+    #[tokio::test]
+    async fn resume_spares_images_protected_since_the_plan() {
+        let ecr = registry();
+        let ui = Ui::from_config(&Config::default(), true).unwrap();
+        let path = state_file("resume");
+        let digests: Vec<String> = ecr
+            .images("app")
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|image| image.tags.is_empty() || image.tags == ["v3"])
+            .map(|image| image.digest)
+            .collect();
+        let plan = GcPlan {
+            repository: "app".to_string(),
+            digests,
+            keep: Some(1),
+            max_age_hours: None,
+            protect: vec!["v1".to_string()],
+        };
+        drop(StateFile::start(path.clone(), "gc", plan).unwrap());
+
+        // v1 moves onto an image the plan meant to delete.
+        let untagged = ecr
+            .images("app")
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|image| image.tags.is_empty())
+            .unwrap();
+        let image = ecr.image(None, "app", &untagged.digest).await.unwrap();
+        ecr.put_image(&image, "v1", &Destination::default())
+            .await
+            .unwrap();
+
+        let mut args = gc_args(0, &[]);
+        args.rules.keep = None;
+        run(&ecr, &ui, &args, Some(&path), true, OutputFormat::Json)
+            .await
+            .unwrap();
+
+        assert_eq!(ecr.tags("app"), vec![vec![], vec!["v1"], vec!["latest"]]);
+    }
+
+    #[tokio::test]
+    async fn resume_refuses_changed_rules() {
+        let ecr = registry();
+        let ui = Ui::from_config(&Config::default(), true).unwrap();
+        let path = state_file("rules");
+        let plan = GcPlan {
+            repository: "app".to_string(),
+            digests: vec![],
+            keep: Some(1),
+            max_age_hours: None,
+            protect: vec![],
+        };
+        drop(StateFile::start(path.clone(), "gc", plan).unwrap());
+
+        let result = run(
+            &ecr,
+            &ui,
+            &gc_args(2, &[]),
+            Some(&path),
+            true,
+            OutputFormat::Json,
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(ecr.tags("app").len(), 4);
+        let _ = std::fs::remove_file(&path);
+    }
+}
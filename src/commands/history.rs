@@ -0,0 +1,204 @@
+use anyhow::{bail, Context, Result};
+
+use crate::args::{Args, HistoryArgs, RollbackArgs};
+use crate::config::Config;
+use crate::ecr::{Destination, EcrApi};
+use crate::history::{self, Move};
+use crate::output::{self, OutputFormat, Table};
+use crate::ui::Ui;
+
+/// Lists where a repository's tags were moved from and to, newest first.
+pub async fn list(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    history_args: &HistoryArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    let moves = history::list(
+        args,
+        &config.history,
+        &ecr.registry_id().await?,
+        &history_args.repository,
+        history_args.tag.as_deref(),
+    )
+    .await?;
+    let mut table = Table::new(&["MOVED", "TAG", "DIGEST", "PREVIOUS", "BY", "ANNOTATIONS"]);
+    for entry in &moves {
+        table.push(vec![
+            entry.moved_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            entry.tag.clone(),
+            entry.digest.clone(),
+            entry.previous.clone().unwrap_or_else(|| "-".to_string()),
+            entry.user.clone().unwrap_or_else(|| "-".to_string()),
+            match entry.annotations.is_empty() {
+                true => "-".to_string(),
+                false => entry
+                    .annotations
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            },
+        ]);
+    }
+    output::print(format, &table, &moves)
+}
+
+/// Moves a tag back to the digest it was on before its last recorded move.
+pub async fn rollback(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &Args,
+    config: &Config,
+    rollback: &RollbackArgs,
+) -> Result<()> {
+    let (repository, tag) = (&rollback.repository, &rollback.tag);
+    let registry_id = match &args.registry_id {
+        Some(registry_id) => registry_id.clone(),
+        None => ecr.registry_id().await?,
+    };
+    let moves = history::list(args, &config.history, &registry_id, repository, Some(tag)).await?;
+    let (last, previous, current) = last_move(ecr, &registry_id, repository, tag, moves).await?;
+    let previous = previous.as_str();
+
+    eprintln!(
+        "{}:{} -> {} (was moved to {} by {} at {})",
+        repository,
+        tag,
+        previous,
+        last.digest,
+        last.user.as_deref().unwrap_or("unknown"),
+        last.moved_at.format("%Y-%m-%d %H:%M:%S")
+    );
+    if !rollback.yes && !ui.confirm("roll back?").prompt()? {
+        bail!("aborted, the tag was not moved");
+    }
+    let image = ecr.image(Some(&registry_id), repository, previous).await?;
+    super::ensure_unmoved(
+        ecr,
+        &args.locks,
+        Some(&registry_id),
+        repository,
+        tag,
+        Some(&current),
+        previous,
+    )
+    .await?;
+    let destination = Destination {
+        registry_id: Some(registry_id),
+        repository_name: None,
+    };
+    ecr.put_image(&image, tag, &destination).await?;
+    ui.status(true, &format!("{}:{} -> {}", repository, tag, previous));
+    Ok(())
+}
+
+/// The last of `moves` (newest first), the digest it moved the tag from and
+/// the one the tag is on, when it's still where that move left it.
+async fn last_move(
+    ecr: &dyn EcrApi,
+    registry_id: &str,
+    repository: &str,
+    tag: &str,
+    moves: Vec<Move>,
+) -> Result<(Move, String, String)> {
+    let last = match moves.into_iter().next() {
+        Some(last) => last,
+        None => bail!("no recorded moves of {}:{} to roll back", repository, tag),
+    };
+    let previous = match &last.previous {
+        Some(previous) => previous.clone(),
+        None => bail!(
+            "{}:{} didn't exist before it was put on {}, there's nothing to roll back to",
+            repository,
+            tag,
+            last.digest
+        ),
+    };
+    let current = ecr
+        .resolve_tag(Some(registry_id), repository, tag)
+        .await?
+        .with_context(|| format!("tag {} not found in {}", tag, repository))?;
+    if current != last.digest {
+        bail!(
+            "{}:{} was moved to {} without being recorded, refusing to roll it back",
+            repository,
+            tag,
+            current
+        );
+    }
+    Ok((last, previous, current))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::Utc;
+
+    use super::*;
+    use crate::ecr::MockEcr;
+
+    fn moved(digest: &str, previous: Option<&str>) -> Move {
+        Move {
+            moved_at: Utc::now(),
+            repository: "app".to_string(),
+            tag: "latest".to_string(),
+            digest: digest.to_string(),
+            previous: previous.map(str::to_string),
+            user: None,
+            registry_id: None,
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn last_move_goes_back_to_where_the_tag_was() {
+        let ecr = MockEcr::app(&[&["v1"], &["v2", "latest"]]);
+        let registry_id = ecr.registry_id().await.unwrap();
+        let v1 = ecr.tag_digest("app", "v1").await.unwrap();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+
+        let (_, previous, current) = last_move(
+            &ecr,
+            &registry_id,
+            "app",
+            "latest",
+            vec![moved(&v2, Some(&v1)), moved(&v1, None)],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(previous, v1);
+        assert_eq!(current, v2);
+    }
+
+    #[tokio::test]
+    async fn last_move_refuses_unrecorded_moves_and_new_tags() {
+        let ecr = MockEcr::app(&[&["v1", "latest"], &["v2"]]);
+        let registry_id = ecr.registry_id().await.unwrap();
+        let v1 = ecr.tag_digest("app", "v1").await.unwrap();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+
+        let unrecorded = last_move(
+            &ecr,
+            &registry_id,
+            "app",
+            "latest",
+            vec![moved(&v2, Some(&v1))],
+        )
+        .await
+        .unwrap_err();
+        let new = last_move(&ecr, &registry_id, "app", "latest", vec![moved(&v1, None)])
+            .await
+            .unwrap_err();
+        let none = last_move(&ecr, &registry_id, "app", "latest", vec![])
+            .await
+            .unwrap_err();
+
+        assert!(unrecorded.to_string().contains("without being recorded"));
+        assert!(new.to_string().contains("there's nothing to roll back to"));
+        assert!(none.to_string().starts_with("no recorded moves"));
+    }
+}
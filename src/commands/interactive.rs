@@ -0,0 +1,974 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+
+use std::fmt::Display;
+
+use anyhow::{anyhow, bail, Result};
+use crossterm::tty::IsTty;
+use inquire::error::{InquireError, InquireResult};
+use inquire::ui::Color;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Handle;
+
+use crate::args::{Args, ImageRef, InteractiveArgs};
+use crate::config::Config;
+use crate::ecr::{Destination, EcrApi, TagStatus};
+use crate::git;
+use crate::github::{self, Tagged};
+use crate::manifest;
+use crate::model::{format_size, ImageDetail, Respository};
+use crate::recent::{Recent, Selections};
+use crate::replication;
+use crate::scan::Gate;
+use crate::state::{self, StateFile};
+use crate::tags;
+use crate::ui::Ui;
+
+pub async fn run(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &Args,
+    interactive: &InteractiveArgs,
+    config: &Config,
+) -> Result<()> {
+    let gate = args.gate(config);
+    if args.bulk && args.resume {
+        return resume_bulk_tag(ecr, ui, args, gate).await;
+    }
+    if args.bulk && interactive.source.is_some() {
+        bail!("--bulk picks the images itself, it can't be combined with --digest or --from-tag");
+    }
+    require_terminal(args, interactive)?;
+
+    let recent = RefCell::new(Recent::load(args.profile()));
+    let last = recent.borrow().selections();
+
+    let mut repositories = ecr.repositories().await?;
+    let configured = |name: &str| config.repo.get(name).is_some_and(|repo| repo.pinned);
+    let pinned = |name: &str| configured(name) || last.pins.contains(name);
+    // Favorites, then the most used; the sort is stable so the rest keep the API's order.
+    repositories.sort_by_key(|repository| {
+        (
+            !pinned(&repository.name),
+            std::cmp::Reverse(last.frecency(&repository.name)),
+        )
+    });
+    let names: Vec<String> = repositories.iter().map(|r| r.name.clone()).collect();
+    // Esc (or left) goes back to the prompt before, when there is one.
+    let repository_prompted = interactive.repository.is_none();
+    let image_prompted = interactive.source.is_none();
+    // What was picked before going back, highlighted again.
+    let mut picked_repository = last.repository.clone();
+    let mut picked_image = None;
+    'repository: loop {
+        let repository = match &interactive.repository {
+            // Prompts for the rest only.
+            Some(name) => repositories
+                .iter()
+                .find(|repository| &repository.name == name)
+                .ok_or_else(|| anyhow!("repository {} does not exist", name))?,
+            None => {
+                let favorites = (0..names.len()).filter(|&i| pinned(&names[i])).collect();
+                let starting_repository = picked_repository
+                    .as_ref()
+                    .and_then(|name| names.iter().position(|n| n == name))
+                    .unwrap_or(0);
+                ui.picker("repository:", repositories.iter().collect())
+                    .with_starting_cursor(starting_repository)
+                    .with_preview(|repository| repository.preview())
+                    .with_pins(favorites, |index, pin| {
+                        // Pins from the config file stay put; edit the file to drop them.
+                        if configured(&names[index]) {
+                            return false;
+                        }
+                        recent.borrow_mut().set_pinned(&names[index], pin);
+                        true
+                    })
+                    .prompt()?
+            }
+        };
+        if picked_repository.as_ref() != Some(&repository.name) {
+            picked_repository = Some(repository.name.clone());
+            picked_image = None;
+        }
+
+        let destination = args.destination();
+        let target = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&repository.name);
+        let picking = match &interactive.source {
+            Some(_) => None,
+            None => {
+                let recent = last.images.get(&repository.name);
+                let picking =
+                    ImagePicking::load(ecr, args, interactive, repository, target, recent).await?;
+                if args.bulk {
+                    return bulk_tag(ecr, ui, picking.images, args, config, gate).await;
+                }
+                Some(picking)
+            }
+        };
+        loop {
+            let image_detail = match (&interactive.source, &picking) {
+                (Some(source), _) => source_image(ecr, &repository.name, source).await?,
+                (None, Some(picking)) => {
+                    let starting = picked_image.as_ref().or(last.images.get(&repository.name));
+                    let picked =
+                        picking.prompt(ecr, ui, args, repository, starting, repository_prompted);
+                    match back(picked, repository_prompted)? {
+                        Some(image) => image,
+                        None => continue 'repository,
+                    }
+                }
+                (None, None) => {
+                    unreachable!("images are loaded unless --digest/--from-tag is given")
+                }
+            };
+            picked_image = Some(image_detail.digest.clone());
+
+            let flow = tag_image(
+                ecr,
+                ui,
+                repository_prompted || image_prompted,
+                args,
+                interactive,
+                config,
+                &recent,
+                &last,
+                repository,
+                &image_detail,
+            )
+            .await?;
+            match flow {
+                Flow::Done => return Ok(()),
+                Flow::Back if image_prompted => continue,
+                Flow::Back => continue 'repository,
+            }
+        }
+    }
+}
+
+/// The images of the repository to pick from and how they're shown.
+struct ImagePicking<'a> {
+    images: Vec<ImageDetail>,
+    /// Set when there are more pages to load as the picker scrolls.
+    next_token: Option<String>,
+    lazy: bool,
+    columns: Vec<String>,
+    /// With --tag, the image it's on now, marked as the one the move replaces.
+    current: Option<(String, &'a str)>,
+}
+
+impl<'a> ImagePicking<'a> {
+    async fn load(
+        ecr: &dyn EcrApi,
+        args: &Args,
+        interactive: &'a InteractiveArgs,
+        repository: &Respository,
+        target: &str,
+        recent: Option<&String>,
+    ) -> Result<ImagePicking<'a>> {
+        // Big repositories are picked from a page at a time, unless every image
+        // is needed up front: to filter them, or to find the one picked last
+        // time and list it among the newest. Paged rows follow ECR's order,
+        // so they're only newest first within each page.
+        let lazy = !args.bulk && !args.git && args.labels.is_empty() && recent.is_none();
+        let (mut images, next_token) = match lazy {
+            true => {
+                let page = ecr
+                    .images_page(&repository.name, TagStatus::Any, None)
+                    .await?;
+                (page.images, page.next_token)
+            }
+            false => (ecr.images(&repository.name).await?, None),
+        };
+        images.sort_by_key(|img| img.created);
+        images.reverse();
+
+        if !args.labels.is_empty() {
+            super::filter_by_labels(ecr, &mut images, &args.labels).await;
+            if images.is_empty() {
+                bail!("no images in {} match the --label filters", repository.name);
+            }
+        } else if !args.label_columns.is_empty() {
+            super::load_labels(ecr, &mut images).await;
+        }
+
+        // --git shows the revision only, unless other columns were asked for.
+        let columns = match args.git && args.label_columns.is_empty() {
+            true => vec![git::REVISION_LABEL.to_string()],
+            false => args.label_columns.clone(),
+        };
+        if args.git {
+            from_git(ecr, &mut images, &repository.name).await?;
+        }
+
+        // The holder in the registry the tag is put in.
+        let current = match &interactive.tag {
+            Some(tag) => ecr
+                .resolve_tag(args.registry_id.as_deref(), target, tag)
+                .await?
+                .map(|digest| (digest, tag.as_str())),
+            None => None,
+        };
+        Ok(Self {
+            images,
+            next_token,
+            lazy,
+            columns,
+            current,
+        })
+    }
+
+    /// Prompts for one of the images, highlighting `starting` (a digest).
+    fn prompt(
+        &self,
+        ecr: &dyn EcrApi,
+        ui: &Ui,
+        args: &Args,
+        repository: &Respository,
+        starting: Option<&String>,
+        back: bool,
+    ) -> InquireResult<ImageDetail> {
+        let columns = &self.columns[..];
+        let starting_image = match (args.git, starting) {
+            // The image built from the newest commit, HEAD if there is one.
+            (true, None) => 0,
+            (_, starting) => starting
+                .and_then(|digest| self.images.iter().position(|image| &image.digest == digest))
+                .unwrap_or(0),
+        };
+        let mut rows = choices(self.images.clone(), columns);
+        mark_holder(&mut rows, &self.current);
+        // How the picker's images are listed, and where the next page starts.
+        let listing = RefCell::new((TagStatus::Any, self.next_token.clone()));
+        // The picker is synchronous, as with the config previews.
+        let load_page = |status: TagStatus, next_token: Option<String>| {
+            let load = async {
+                let page = ecr
+                    .images_page(&repository.name, status, next_token.as_deref())
+                    .await?;
+                let mut images = page.images;
+                images.sort_by_key(|img| img.created);
+                images.reverse();
+                if !columns.is_empty() {
+                    super::load_labels(ecr, &mut images).await;
+                }
+                Ok::<_, anyhow::Error>((images, page.next_token))
+            };
+            let (images, next_token) =
+                tokio::task::block_in_place(|| Handle::current().block_on(load))
+                    .map_err(|e| format!("{:#}", e))?;
+            let mut rows = choices(images, columns);
+            mark_holder(&mut rows, &self.current);
+            *listing.borrow_mut() = (status, next_token);
+            Ok((rows, listing.borrow().1.is_some()))
+        };
+        let configs: RefCell<HashMap<String, Vec<String>>> = RefCell::default();
+        let mut picker = ui
+            .picker("image:", rows)
+            .with_starting_cursor(starting_image)
+            .with_preview(|Choice { image, .. }| {
+                let mut lines = image.preview(&repository.uri);
+                let mut configs = configs.borrow_mut();
+                let config = configs.entry(image.digest.clone()).or_insert_with(|| {
+                    // The picker is synchronous; fetch the config on first highlight and keep it.
+                    tokio::task::block_in_place(|| {
+                        Handle::current().block_on(config_preview(
+                            ecr,
+                            &image.repository_name,
+                            &image.digest,
+                        ))
+                    })
+                });
+                lines.extend(config.iter().cloned());
+                lines
+            });
+        if self.lazy {
+            let remaining = listing.borrow().1.is_some();
+            picker = picker
+                .with_more(remaining, || {
+                    let (status, next_token) = listing.borrow().clone();
+                    load_page(status, next_token)
+                })
+                // Narrowed by ECR, so big repositories needn't be read to find the untagged.
+                .with_modes(
+                    TagStatus::ALL
+                        .iter()
+                        .map(|status| status.name().to_lowercase())
+                        .collect(),
+                    |mode| load_page(TagStatus::ALL[mode], None),
+                );
+        }
+        if back {
+            picker = picker.with_back();
+        }
+        if ui.stderr_color {
+            picker = picker
+                .with_colors(|choice| choice.holds.map(|_| Color::LightCyan))
+                .with_segments(Choice::segments);
+        }
+        Ok(picker.prompt()?.image)
+    }
+}
+
+/// How the prompts after the image were left.
+enum Flow {
+    Done,
+    Back,
+}
+
+/// The answer to a prompt, or `None` when Esc left it to go back to the one
+/// before, if there is one.
+fn back<T>(result: InquireResult<T>, can_go_back: bool) -> Result<Option<T>> {
+    match result {
+        Ok(answer) => Ok(Some(answer)),
+        Err(InquireError::OperationCanceled) if can_go_back => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Asks for the tag of `image_detail`, settles conflicts and warnings, then
+/// tags it.
+#[allow(clippy::too_many_arguments)]
+async fn tag_image(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    can_go_back: bool,
+    args: &Args,
+    interactive: &InteractiveArgs,
+    config: &Config,
+    recent: &RefCell<Recent>,
+    last: &Selections,
+    repository: &Respository,
+    image_detail: &ImageDetail,
+) -> Result<Flow> {
+    let gate = args.gate(config);
+    let destination = args.destination();
+    let target = destination
+        .repository_name
+        .as_deref()
+        .unwrap_or(&repository.name);
+    let mut tag = match (&interactive.tag, &args.tag_from_label) {
+        (Some(tag), _) => {
+            tags::validate(tag)?;
+            tag.clone()
+        }
+        (None, Some(label)) => {
+            super::tag_from_label(
+                ecr,
+                &image_detail.repository_name,
+                &image_detail.digest,
+                label,
+            )
+            .await?
+        }
+        (None, None) => {
+            let initial = config
+                .repo
+                .get(&repository.name)
+                .and_then(|repo| repo.default_tag.as_ref())
+                .or_else(|| last.tags.get(&repository.name))
+                .map_or("latest", |tag| tag.as_str());
+            let answer = ui.tag("tag:").with_initial_value(initial).prompt();
+            match back(answer, can_go_back)? {
+                Some(tag) => tag,
+                None => return Ok(Flow::Back),
+            }
+        }
+    };
+    // Where the tag is as the user confirms it; checked again before it moves.
+    let registry_id = destination.registry_id.as_deref();
+    let holder = loop {
+        let holder = ecr.resolve_tag(registry_id, target, &tag).await?;
+        let current = match &holder {
+            Some(current) if current != &image_detail.digest => current,
+            _ => break holder,
+        };
+        let conflict = resolve_conflict(ecr, ui, registry_id, target, &tag, current).await?;
+        match back(conflict, can_go_back)? {
+            Some(Conflict::Overwrite) => break holder,
+            Some(Conflict::Rename) => match back(ui.tag("new tag:").prompt(), can_go_back)? {
+                Some(renamed) => tag = renamed,
+                None => return Ok(Flow::Back),
+            },
+            Some(Conflict::Abort) => bail!("aborted, {}:{} was left on {}", target, tag, current),
+            None => return Ok(Flow::Back),
+        }
+    };
+    gate.check(ecr, target, &image_detail.digest, &tag).await?;
+    gate.verify_signature(ecr, &image_detail.repository_name, &image_detail.digest)
+        .await?;
+
+    let image = ecr
+        .image(None, &image_detail.repository_name, &image_detail.digest)
+        .await?;
+
+    // Declining goes no further; Esc goes back to pick another image.
+    if let Some(warning) = super::age_warning(config, image_detail, &tag)? {
+        ui.warning(&warning);
+        match back(ui.confirm("Tag it anyway?").prompt(), can_go_back)? {
+            Some(true) => {}
+            Some(false) => bail!("not tagging {} as {}", image_detail.digest, tag),
+            None => return Ok(Flow::Back),
+        }
+    }
+    if let Some(warning) = manifest::legacy_warning(&image.manifest) {
+        let question = format!("{}. Tag it anyway?", warning);
+        match back(ui.confirm(&question).prompt(), can_go_back)? {
+            Some(true) => {}
+            Some(false) => bail!("not tagging schema v1 image {}", image.digest),
+            None => return Ok(Flow::Back),
+        }
+    }
+
+    // The image wasn't seen in the picker, so it's shown once more.
+    if interactive.source.is_some() && !interactive.yes {
+        let question = format!(
+            "Tag {}@{} ({}) as {}:{}?",
+            image_detail.repository_name,
+            image_detail.digest,
+            image_detail.created.format("%Y-%m-%d %H:%M"),
+            target,
+            tag
+        );
+        match back(ui.confirm(&question).prompt(), can_go_back)? {
+            Some(true) => {}
+            Some(false) => bail!("not tagging {} as {}", image_detail.digest, tag),
+            None => return Ok(Flow::Back),
+        }
+    }
+    super::repository::ensure_destination(ecr, ui, &destination).await?;
+    super::ensure_unmoved(
+        ecr,
+        &args.locks,
+        registry_id,
+        target,
+        &tag,
+        holder.as_deref(),
+        &image.digest,
+    )
+    .await?;
+    ecr.put_image(&image, &tag, &destination).await?;
+    recent
+        .borrow_mut()
+        .record(&repository.name, &image.digest, &tag);
+
+    if let Some(path) = &args.github_output {
+        let tagged = Tagged::new(
+            ecr,
+            &image.repository_name,
+            &destination,
+            &tag,
+            &image.digest,
+        )
+        .await?;
+        github::write(path, &[tagged])?;
+    }
+    if let Some(timeout) = args.verify_replication {
+        replication::verify(
+            ecr,
+            ui,
+            &destination,
+            &image.repository_name,
+            &[(tag, image.digest.clone())],
+            timeout,
+        )
+        .await?;
+    }
+    Ok(Flow::Done)
+}
+
+/// The image `--digest`/`--from-tag` names in `repository`.
+async fn source_image(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    source: &ImageRef,
+) -> Result<ImageDetail> {
+    let images = ecr.images(repository).await?;
+    let found = match source {
+        ImageRef::Digest(digest) => images.into_iter().find(|image| &image.digest == digest),
+        ImageRef::Tag(tag) => images.into_iter().find(|image| image.tags.contains(tag)),
+    };
+    found.ok_or_else(|| match source {
+        ImageRef::Digest(digest) => anyhow!("image {} not found in {}", digest, repository),
+        ImageRef::Tag(tag) => anyhow!("tag {} not found in {}", tag, repository),
+    })
+}
+
+/// What to do about a tag that's already on another image.
+#[derive(Clone, Copy)]
+enum Conflict {
+    Overwrite,
+    Rename,
+    Abort,
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Overwrite => "overwrite: move the tag to the selected image",
+            Self::Rename => "pick a new tag",
+            Self::Abort => "abort",
+        })
+    }
+}
+
+/// Shows the image `tag` is on now in `registry_id` and asks what to do
+/// about it.
+async fn resolve_conflict(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    registry_id: Option<&str>,
+    repository: &str,
+    tag: &str,
+    current: &str,
+) -> Result<InquireResult<Conflict>> {
+    eprintln!("{}:{} is already on another image:", repository, tag);
+    // Only the source registry's images can be listed.
+    let holder = match registry_id {
+        Some(_) => None,
+        None => ecr
+            .images(repository)
+            .await?
+            .into_iter()
+            .find(|image| image.digest == current),
+    };
+    match holder {
+        Some(holder) => {
+            // The uri is left out, the destination repository isn't at hand.
+            for line in holder
+                .preview("")
+                .iter()
+                .filter(|line| !line.starts_with("uri:"))
+            {
+                eprintln!("  {}", line);
+            }
+        }
+        None => eprintln!("  digest: {}", current),
+    }
+    let message = format!("{} already exists:", tag);
+    let choices = vec![Conflict::Overwrite, Conflict::Rename, Conflict::Abort];
+    Ok(ui.picker(&message, choices).prompt())
+}
+
+/// Fails up front, naming the flags that would do without prompts, when
+/// there's no terminal to prompt in; inquire's own error only says stdin
+/// isn't a TTY, once the first prompt is reached.
+fn require_terminal(args: &Args, interactive: &InteractiveArgs) -> Result<()> {
+    if std::io::stdin().is_tty() && std::io::stderr().is_tty() {
+        return Ok(());
+    }
+    if args.bulk {
+        bail!("--bulk picks images in a terminal, but there is none; tag them one at a time with `ecr-tag tag` instead");
+    }
+    let mut missing = vec![];
+    if interactive.repository.is_none() {
+        missing.push("--repository <repository>");
+    }
+    if interactive.source.is_none() {
+        missing.push("--digest <digest> or --from-tag <tag>");
+    }
+    if interactive.tag.is_none() && args.tag_from_label.is_none() {
+        missing.push("--tag <tag> (or --tag-from-label <label>)");
+    }
+    bail!(
+        "not running in a terminal, so there's nothing to prompt in; to tag without prompts pass {}\n  e.g. ecr-tag --repository app --from-tag latest --tag prod",
+        missing.join(", ")
+    )
+}
+
+/// For `--git`: keeps the images built from one of the local commits, by
+/// their revision label, newest commit first.
+async fn from_git(ecr: &dyn EcrApi, images: &mut Vec<ImageDetail>, repository: &str) -> Result<()> {
+    let commits = git::commits()?;
+    if images.iter().all(|image| image.labels.is_empty()) {
+        super::load_labels(ecr, images).await;
+    }
+    let position = |image: &ImageDetail| {
+        image
+            .labels
+            .get(git::REVISION_LABEL)
+            .and_then(|revision| git::position(&commits, revision))
+    };
+    images.retain(|image| position(image).is_some());
+    images.sort_by_key(position);
+    let short = |commit: &str| commit.chars().take(7).collect::<String>();
+    match images.first().map(position) {
+        None => bail!(
+            "no image in {} was built from any of the last {} commits (by its {} label)",
+            repository,
+            commits.len(),
+            git::REVISION_LABEL
+        ),
+        Some(Some(0)) => {}
+        Some(Some(behind)) => eprintln!(
+            "warning: no image in {} was built from HEAD ({}); the newest is from {}, {} commit(s) back",
+            repository,
+            short(&commits[0]),
+            short(&commits[behind]),
+            behind
+        ),
+        Some(None) => unreachable!("images without a local revision were dropped"),
+    }
+    Ok(())
+}
+
+/// An image in the picker: its push time, digest, size, `--label-columns`
+/// values and tags.
+struct Choice<'a> {
+    image: ImageDetail,
+    columns: &'a [String],
+    /// Of the size and label columns, the same in every row.
+    widths: Vec<usize>,
+    /// The `--tag` this image is on now, in the target repository.
+    holds: Option<&'a str>,
+    /// Whether the terminal is too narrow for the time and size columns.
+    narrow: bool,
+}
+
+impl Display for Choice<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (text, _) in self.segments() {
+            f.write_str(&text)?;
+        }
+        Ok(())
+    }
+}
+
+impl Choice<'_> {
+    /// The row's text in pieces, with the colour of each: tags by what they
+    /// look like, untagged images dimmed.
+    fn segments(&self) -> Vec<(String, Option<Color>)> {
+        let mut cells = match self.narrow {
+            true => vec![
+                self.image.created.format("%Y-%m-%d").to_string(),
+                short_digest(&self.image.digest).to_string(),
+            ],
+            false => vec![
+                self.image.created.format("%Y-%m-%d %H:%M").to_string(),
+                short_digest(&self.image.digest).to_string(),
+                format!(
+                    "{:>width$}",
+                    format_size(self.image.size),
+                    width = self.widths[0]
+                ),
+            ],
+        };
+        for (column, width) in self.columns.iter().zip(&self.widths[1..]) {
+            cells.push(format!(
+                "{:width$}",
+                label_cell(&self.image, column),
+                width = width
+            ));
+        }
+        let head = cells.join("  ");
+
+        let mut segments = match self.image.tags.is_empty() {
+            true => vec![(head, Some(Color::DarkGrey))],
+            false => vec![(head + "  ", None)],
+        };
+        for (i, tag) in self.image.tags.iter().enumerate() {
+            if i > 0 {
+                segments.push((", ".to_string(), None));
+            }
+            segments.push((tag.clone(), tags::color(tag)));
+        }
+        if let Some(tag) = self.holds {
+            segments.push((format!("  <- {} now", tag), None));
+        }
+        segments
+    }
+}
+
+/// `sha256:` and the first 12 hex digits, as `docker images` shows them;
+/// the preview has the whole digest.
+fn short_digest(digest: &str) -> &str {
+    let hex = digest.strip_prefix("sha256:").unwrap_or(digest);
+    &hex[..hex.len().min(12)]
+}
+
+/// `org.opencontainers.image.revision` is shown as `revision=...`.
+fn label_cell(image: &ImageDetail, column: &str) -> String {
+    let name = column.rsplit('.').next().unwrap_or(column);
+    let value = image.labels.get(column).map_or("-", |v| v.as_str());
+    format!("{}={}", name, value)
+}
+
+/// Marks the image `current`, the `--tag` and its digest, is on now.
+fn mark_holder<'a>(choices: &mut [Choice<'a>], current: &Option<(String, &'a str)>) {
+    if let Some((digest, tag)) = current {
+        for choice in choices
+            .iter_mut()
+            .filter(|choice| &choice.image.digest == digest)
+        {
+            choice.holds = Some(tag);
+        }
+    }
+}
+
+/// Terminals narrower than this get rows without the push time and size.
+const NARROW_COLUMNS: u16 = 80;
+
+/// The images as picker rows, their columns lined up, tags last.
+fn choices(images: Vec<ImageDetail>, columns: &[String]) -> Vec<Choice<'_>> {
+    let narrow = crossterm::terminal::size().is_ok_and(|(width, _)| width < NARROW_COLUMNS);
+    let mut widths = vec![0; columns.len() + 1];
+    for image in &images {
+        widths[0] = widths[0].max(format_size(image.size).len());
+        for (width, column) in widths[1..].iter_mut().zip(columns) {
+            *width = (*width).max(label_cell(image, column).chars().count());
+        }
+    }
+    images
+        .into_iter()
+        .map(|image| Choice {
+            image,
+            columns,
+            widths: widths.clone(),
+            holds: None,
+            narrow,
+        })
+        .collect()
+}
+
+async fn config_preview(ecr: &dyn EcrApi, repository: &str, digest: &str) -> Vec<String> {
+    match super::image_config(ecr, repository, digest).await {
+        Ok(config) => config.preview(),
+        Err(err) => vec![format!("config: unavailable ({:#})", err)],
+    }
+}
+
+async fn bulk_tag(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    images: Vec<ImageDetail>,
+    args: &Args,
+    config: &Config,
+    gate: Gate<'_>,
+) -> Result<()> {
+    let mut picker = ui.picker("images:", choices(images, &args.label_columns));
+    if ui.stderr_color {
+        picker = picker.with_segments(Choice::segments);
+    }
+    let selected = picker.prompt_multi()?;
+
+    let mut planned = vec![];
+    for (
+        i,
+        Choice {
+            image: image_detail,
+            ..
+        },
+    ) in selected.into_iter().enumerate()
+    {
+        let tag = match &args.tag_from_label {
+            Some(label) => {
+                super::tag_from_label(
+                    ecr,
+                    &image_detail.repository_name,
+                    &image_detail.digest,
+                    label,
+                )
+                .await?
+            }
+            None => {
+                let initial = tags::render_template(&args.tag_template, i + 1);
+                ui.tag(&format!("tag for {}:", image_detail.digest))
+                    .with_initial_value(&initial)
+                    .prompt()?
+            }
+        };
+        // Declining leaves the image out of the retag.
+        if let Some(warning) = super::age_warning(config, &image_detail, &tag)? {
+            ui.warning(&warning);
+            if !ui.confirm("Tag it anyway?").prompt()? {
+                continue;
+            }
+        }
+        let target = args
+            .to_repository
+            .as_deref()
+            .unwrap_or(&image_detail.repository_name);
+        let holder = ecr
+            .resolve_tag(args.registry_id.as_deref(), target, &tag)
+            .await?;
+        planned.push(BulkItem {
+            repository: image_detail.repository_name,
+            digest: image_detail.digest,
+            tag,
+            holder,
+        });
+    }
+
+    let plan = BulkPlan {
+        registry_id: args.registry_id.clone(),
+        to_repository: args.to_repository.clone(),
+        items: planned,
+    };
+    let path = state::path(args.state_file.as_deref(), "bulk-tag")?;
+    run_bulk_tag(
+        ecr,
+        ui,
+        args,
+        gate,
+        StateFile::start(path, "bulk-tag", plan)?,
+    )
+    .await
+}
+
+/// What a bulk retag is going to do, saved so `--resume` can finish it.
+#[derive(Serialize, Deserialize)]
+struct BulkPlan {
+    registry_id: Option<String>,
+    to_repository: Option<String>,
+    items: Vec<BulkItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BulkItem {
+    repository: String,
+    digest: String,
+    tag: String,
+    /// The digest the tag was on when it was picked.
+    #[serde(default)]
+    holder: Option<String>,
+}
+
+impl BulkItem {
+    fn step(&self) -> String {
+        format!("{} {}", self.digest, self.tag)
+    }
+}
+
+/// Continues the bulk retag an earlier run was interrupted in.
+async fn resume_bulk_tag(ecr: &dyn EcrApi, ui: &Ui, args: &Args, gate: Gate<'_>) -> Result<()> {
+    let path = state::path(args.state_file.as_deref(), "bulk-tag")?;
+    let state = StateFile::<BulkPlan>::resume(path, "bulk-tag")?;
+    eprintln!(
+        "resuming: {} of {} image(s) already tagged",
+        state.done(),
+        state.plan().items.len()
+    );
+    run_bulk_tag(ecr, ui, args, gate, state).await
+}
+
+async fn run_bulk_tag(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &Args,
+    gate: Gate<'_>,
+    mut state: StateFile<BulkPlan>,
+) -> Result<()> {
+    let destination = Destination {
+        registry_id: state.plan().registry_id.clone(),
+        repository_name: state.plan().to_repository.clone(),
+    };
+    super::repository::ensure_destination(ecr, ui, &destination).await?;
+    let mut failed = 0;
+    let mut tagged: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    for i in 0..state.plan().items.len() {
+        let item = &state.plan().items[i];
+        let step = item.step();
+        if state.is_done(&step) {
+            continue;
+        }
+        let result = async {
+            let target = destination
+                .repository_name
+                .as_deref()
+                .unwrap_or(&item.repository);
+            gate.check(ecr, target, &item.digest, &item.tag).await?;
+            gate.verify_signature(ecr, &item.repository, &item.digest)
+                .await?;
+            let image = ecr.image(None, &item.repository, &item.digest).await?;
+            if let Some(warning) = manifest::legacy_warning(&image.manifest) {
+                ui.warning(&format!("{}: {}", image.digest, warning));
+            }
+            super::ensure_unmoved(
+                ecr,
+                &args.locks,
+                destination.registry_id.as_deref(),
+                target,
+                &item.tag,
+                item.holder.as_deref(),
+                &item.digest,
+            )
+            .await?;
+            ecr.put_image(&image, &item.tag, &destination).await
+        }
+        .await;
+        match result {
+            Ok(()) => {
+                ui.status(true, &format!("{} -> {}", item.tag, item.digest));
+                tagged
+                    .entry(item.repository.clone())
+                    .or_default()
+                    .push((item.tag.clone(), item.digest.clone()));
+                state.mark_done(&step)?;
+            }
+            Err(err) => {
+                failed += 1;
+                ui.status(false, &format!("{} -> {}: {}", item.tag, item.digest, err));
+            }
+        }
+    }
+
+    if let Some(path) = &args.github_output {
+        let mut outputs = vec![];
+        for (repository, tags) in &tagged {
+            for (tag, digest) in tags {
+                outputs.push(Tagged::new(ecr, repository, &destination, tag, digest).await?);
+            }
+        }
+        github::write(path, &outputs)?;
+    }
+    if let Some(timeout) = args.verify_replication {
+        for (repository, tags) in &tagged {
+            replication::verify(ecr, ui, &destination, repository, tags, timeout).await?;
+        }
+    }
+
+    if failed > 0 {
+        bail!(
+            "{} image(s) failed to tag; fix the cause and rerun with --bulk --resume to retry them",
+            failed
+        );
+    }
+    state.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecr::MockEcr;
+
+    fn registry() -> MockEcr {
+        MockEcr::app(&[&["latest"], &["v2"]])
+    }
+
+    #[tokio::test]
+    async fn source_image_finds_the_image_by_tag_or_digest() {
+        let ecr = registry();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+
+        let by_tag = source_image(&ecr, "app", &ImageRef::Tag("v2".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(by_tag.digest, v2);
+        let by_digest = source_image(&ecr, "app", &ImageRef::Digest(v2.clone()))
+            .await
+            .unwrap();
+        assert_eq!(by_digest.tags, vec!["v2"]);
+    }
+
+    #[tokio::test]
+    async fn source_image_fails_for_unknown_images() {
+        let ecr = registry();
+        let error = source_image(&ecr, "app", &ImageRef::Tag("v9".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "tag v9 not found in app");
+    }
+}
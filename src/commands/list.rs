@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use crossterm::style::Stylize;
+
+use crate::args::{Args, ListArgs};
+use crate::config::Config;
+use crate::ecr::EcrApi;
+use crate::history::{self, Move};
+use crate::model::format_size;
+use crate::model::ImageDetail;
+use crate::output::{self, OutputFormat, Report, Table};
+use crate::picker;
+use crate::tags;
+
+pub async fn run(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    list: &ListArgs,
+    color: bool,
+) -> Result<()> {
+    match &list.repository {
+        None => repositories(ecr, list, args.output).await,
+        Some(repository) => images(ecr, args, config, repository, list, color).await,
+    }
+}
+
+async fn repositories(ecr: &dyn EcrApi, args: &ListArgs, format: OutputFormat) -> Result<()> {
+    let mut repositories = ecr.repositories().await?;
+    if let Some(filter) = &args.filter {
+        repositories.retain(|r| r.name.contains(filter.as_str()));
+    }
+    match args.sort.as_str() {
+        "default" | "name" => repositories.sort_by(|a, b| a.name.cmp(&b.name)),
+        other => bail!("can't sort repositories by `{}` (expected name)", other),
+    }
+    if args.reverse {
+        repositories.reverse();
+    }
+
+    let mut table = Table::new(&["NAME", "URI", "ENCRYPTION"]);
+    for repository in &repositories {
+        table.push(vec![
+            repository.name.clone(),
+            repository.uri.clone(),
+            repository.encryption(),
+        ]);
+    }
+    output::print(format, &table, &repositories)
+}
+
+async fn images(
+    ecr: &dyn EcrApi,
+    global: &Args,
+    config: &Config,
+    repository: &str,
+    args: &ListArgs,
+    color: bool,
+) -> Result<()> {
+    let format = global.output;
+    let mut images = ecr.images(repository).await?;
+    super::filter_by_labels(ecr, &mut images, &global.labels).await;
+    if let Some(filter) = &args.filter {
+        images.retain(|i| {
+            i.digest.contains(filter.as_str()) || i.tags.iter().any(|t| t.contains(filter.as_str()))
+        });
+    }
+    if args.tagged {
+        images.retain(|i| !i.tags.is_empty());
+    }
+    if args.untagged {
+        images.retain(|i| i.tags.is_empty());
+    }
+    // When each image's tags last moved, for `--sort moved` only.
+    let mut moved = HashMap::new();
+    match args.sort.as_str() {
+        "default" | "pushed" => {
+            images.sort_by_key(|i| i.created);
+            images.reverse();
+        }
+        "size" => {
+            images.sort_by_key(|i| i.size);
+            images.reverse();
+        }
+        "digest" => images.sort_by(|a, b| a.digest.cmp(&b.digest)),
+        "tags" => images.sort_by(|a, b| a.tags.cmp(&b.tags)),
+        "moved" => {
+            let moves = history::list(
+                global,
+                &config.history,
+                &ecr.registry_id().await?,
+                repository,
+                None,
+            )
+            .await?;
+            for image in &images {
+                moved.insert(image.digest.clone(), last_moved(image, &moves));
+            }
+            // Images whose tags were never moved by ecr-tag got them when pushed.
+            images.sort_by_key(|i| moved[&i.digest].unwrap_or(i.created));
+            images.reverse();
+        }
+        other => bail!(
+            "can't sort images by `{}` (expected pushed, size, digest, tags or moved)",
+            other
+        ),
+    }
+    if args.reverse {
+        images.reverse();
+    }
+
+    let mut headers = vec!["PUSHED"];
+    if !moved.is_empty() {
+        headers.push("TAG MOVED");
+    }
+    headers.extend(["DIGEST", "SIZE", "TAGS"]);
+    let mut table = Table::new(&headers);
+    for image in &images {
+        let mut row = vec![image.created.to_rfc3339()];
+        if let Some(moved) = moved.get(&image.digest) {
+            row.push(moved.map_or_else(
+                || "-".to_string(),
+                |moved| moved.to_rfc3339_opts(SecondsFormat::Secs, false),
+            ));
+        }
+        row.extend([
+            image.digest.clone(),
+            format_size(image.size),
+            match color && format == OutputFormat::Table {
+                // The last column, so the escape codes don't upset the widths.
+                true => colored_tags(&image.tags),
+                false => image.tags.join(", "),
+            },
+        ]);
+        table.push(row);
+    }
+    if format == OutputFormat::Html {
+        let (scans, tags) = report_tables(&images);
+        let report = Report::new(format!("{} images", repository))
+            .section("Inventory", &table)
+            .section("Scan summary", &scans)
+            .section("Tag map", &tags);
+        print!("{}", report.render_html());
+        return Ok(());
+    }
+    output::print(format, &table, &images)
+}
+
+/// When one of the tags `image` still has was last moved onto it, from the
+/// tag history.
+fn last_moved(image: &ImageDetail, moves: &[Move]) -> Option<DateTime<Utc>> {
+    moves
+        .iter()
+        .filter(|entry| entry.digest == image.digest && image.tags.contains(&entry.tag))
+        .map(|entry| entry.moved_at)
+        .max()
+}
+
+fn colored_tags(tags: &[String]) -> String {
+    if tags.is_empty() {
+        return "<untagged>".dark_grey().to_string();
+    }
+    tags.iter()
+        .map(|tag| match tags::color(tag) {
+            Some(fg) => tag.as_str().with(picker::color(fg)).to_string(),
+            None => tag.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The HTML report adds scan results and a tag to digest map to the inventory.
+fn report_tables(images: &[ImageDetail]) -> (Table, Table) {
+    let mut scans = Table::new(&["DIGEST", "STATUS", "FINDINGS"]);
+    let mut tags = Table::new(&["TAG", "DIGEST", "PUSHED"]);
+    for image in images {
+        let findings = image
+            .severity_counts
+            .iter()
+            .map(|(severity, count)| format!("{} {}", severity, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        scans.push(vec![
+            image.digest.clone(),
+            image
+                .scan_status
+                .clone()
+                .unwrap_or_else(|| "not scanned".to_string()),
+            findings,
+        ]);
+        for tag in &image.tags {
+            tags.push(vec![
+                tag.clone(),
+                image.digest.clone(),
+                image.created.to_rfc3339(),
+            ]);
+        }
+    }
+    (scans, tags)
+}
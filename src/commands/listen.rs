@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rusoto_core::credential::{AwsCredentials, ProvideAwsCredentials};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use serde_json::{json, Value};
+
+use crate::args::{Args, ListenArgs};
+use crate::client;
+use crate::config::Config;
+use crate::ecr::EcrApi;
+use crate::events::{self, Push};
+use crate::http;
+use crate::metrics;
+
+/// How long each ReceiveMessage call waits for messages.
+const WAIT_SECONDS: u64 = 20;
+
+/// Takes ECR push events off an SQS queue (the target of an EventBridge
+/// rule) until killed, and puts the configured auto tags on the images
+/// pushed.
+///
+/// Messages are deleted once handled. Those that fail are left, so they
+/// come back after the queue's visibility timeout and end up in its
+/// dead-letter queue, if it has one.
+pub async fn run(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    listen: &ListenArgs,
+) -> Result<()> {
+    let queue = listen
+        .queue
+        .as_deref()
+        .or(config.listen.queue.as_deref())
+        .ok_or_else(|| anyhow!("listen needs --queue or a [listen] queue"))?;
+    if args.read_only {
+        bail!(
+            "read-only mode: refusing to listen to {}, as handled messages are deleted",
+            queue
+        );
+    }
+    let region = client::region(args)?;
+    let provider = client::credentials(args, region.name(), None)?;
+    eprintln!("listening to {}", queue);
+    loop {
+        // Resolved for every call, so assumed roles are renewed as they expire.
+        let credentials = provider
+            .credentials()
+            .await
+            .context("failed to resolve credentials")?;
+        let sqs = Sqs {
+            queue,
+            region: &region,
+            credentials: &credentials,
+        };
+        let messages = match sqs.receive().await {
+            Ok(messages) => messages,
+            Err(e) => {
+                eprintln!("warning: {:#}", e);
+                tokio::time::sleep(Duration::from_secs(WAIT_SECONDS)).await;
+                continue;
+            }
+        };
+        for message in messages {
+            let started = Instant::now();
+            let result = handle(ecr, args, config, &message).await;
+            let handled = result.is_ok();
+            if let Err(e) = metrics::report(args, config, result, started.elapsed()).await {
+                eprintln!("error: {:#}", e);
+            }
+            args.locks.release().await;
+            if handled {
+                if let Err(e) = sqs.delete(&message).await {
+                    eprintln!("warning: {:#}", e);
+                }
+            }
+        }
+    }
+}
+
+async fn handle(ecr: &dyn EcrApi, args: &Args, config: &Config, message: &Value) -> Result<()> {
+    let body = message["Body"].as_str().unwrap_or_default();
+    let event: Value = serde_json::from_str(body).with_context(|| {
+        format!(
+            "message {} isn't an event",
+            message["MessageId"].as_str().unwrap_or("?")
+        )
+    })?;
+    let push = match Push::parse(&event) {
+        Some(push) => push,
+        None => return Ok(()),
+    };
+    for tag in events::auto_tag(ecr, args, config, &push).await? {
+        eprintln!("ok: {}:{} -> {}", push.repository, tag, push.digest);
+    }
+    Ok(())
+}
+
+struct Sqs<'a> {
+    queue: &'a str,
+    region: &'a Region,
+    credentials: &'a AwsCredentials,
+}
+
+impl Sqs<'_> {
+    async fn call(&self, action: &str, body: Value, timeout: Duration) -> Result<String> {
+        let mut request = SignedRequest::new("POST", "sqs", self.region, "/");
+        request.add_header("x-amz-target", &format!("AmazonSQS.{}", action));
+        request.set_content_type("application/x-amz-json-1.0".to_string());
+        request.set_payload(Some(body.to_string()));
+        http::send_signed(request, self.credentials, timeout)
+            .await
+            .with_context(|| format!("{} on {} failed", action, self.queue))
+    }
+
+    async fn receive(&self) -> Result<Vec<Value>> {
+        let body = json!({
+            "QueueUrl": self.queue,
+            "MaxNumberOfMessages": 10,
+            "WaitTimeSeconds": WAIT_SECONDS,
+        });
+        let timeout = Duration::from_secs(WAIT_SECONDS + 10);
+        let response: Value =
+            serde_json::from_str(&self.call("ReceiveMessage", body, timeout).await?)
+                .context("unexpected ReceiveMessage response")?;
+        Ok(response["Messages"].as_array().cloned().unwrap_or_default())
+    }
+
+    async fn delete(&self, message: &Value) -> Result<()> {
+        let body = json!({
+            "QueueUrl": self.queue,
+            "ReceiptHandle": message["ReceiptHandle"],
+        });
+        self.call("DeleteMessage", body, Duration::from_secs(10))
+            .await?;
+        Ok(())
+    }
+}
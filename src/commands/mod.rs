@@ -0,0 +1,323 @@
+pub mod aging;
+pub mod blob;
+pub mod context;
+pub mod daemon;
+pub mod desired;
+pub mod diff;
+pub mod export;
+pub mod find;
+pub mod findings;
+pub mod gc;
+pub mod history;
+pub mod interactive;
+pub mod list;
+pub mod listen;
+pub mod policy;
+pub mod referrers;
+pub mod repository;
+pub mod sbom;
+pub mod serve;
+pub mod show;
+pub mod simulate;
+pub mod stats;
+pub mod storage;
+pub mod tag;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::Utc;
+
+use crate::config::Config;
+use crate::ecr::EcrApi;
+use crate::lock::Locks;
+use crate::manifest::{ContainerConfig, Manifest};
+use crate::model::ImageDetail;
+use crate::retention;
+use crate::tags;
+
+/// Resolves `reference` to a digest; anything that isn't a digest is a tag.
+pub async fn resolve_reference(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    reference: &str,
+) -> Result<String> {
+    match reference.starts_with("sha256:") {
+        true => Ok(reference.to_string()),
+        false => ecr.tag_digest(repository, reference).await,
+    }
+}
+
+/// Checks, right before `tag` is put on `digest` in `registry_id` (the
+/// destination's, the source registry when unset), that it's still where
+/// it was when the user confirmed (`holder`), so a move made by someone
+/// else in the meantime isn't silently undone. A tag already on `digest`
+/// is fine too.
+///
+/// The repository is locked first, so the tag can't move after the check.
+pub async fn ensure_unmoved(
+    ecr: &dyn EcrApi,
+    locks: &Locks,
+    registry_id: Option<&str>,
+    repository: &str,
+    tag: &str,
+    holder: Option<&str>,
+    digest: &str,
+) -> Result<()> {
+    locks.lock(ecr, registry_id, repository).await?;
+    let current = ecr.resolve_tag(registry_id, repository, tag).await?;
+    if current.as_deref() == holder || current.as_deref() == Some(digest) {
+        return Ok(());
+    }
+    let was = holder.unwrap_or("no image");
+    let now = current.as_deref().unwrap_or("no image");
+    bail!(
+        "{}:{} moved from {} to {} since it was confirmed, someone else must have moved it; not tagging, run again to see where it is now",
+        repository,
+        tag,
+        was,
+        now
+    )
+}
+
+/// The `[age_warning]` for tagging `image` as `tag`, if it's old enough.
+pub fn age_warning(config: &Config, image: &ImageDetail, tag: &str) -> Result<Option<String>> {
+    let warning = &config.age_warning;
+    let older_than = match &warning.older_than {
+        Some(older_than) => {
+            retention::parse_age(older_than).context("in [age_warning] older_than")?
+        }
+        None => return Ok(None),
+    };
+    let watched = match warning.tags.is_empty() {
+        true => ["latest", "prod"].contains(&tag),
+        false => warning
+            .tags
+            .iter()
+            .any(|pattern| tags::matches(pattern, tag)),
+    };
+    let age = Utc::now() - image.created;
+    if !watched || age <= older_than {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "{} was pushed {} days ago (on {}), more than {}; is it the right image for {}?",
+        image.digest,
+        age.num_days(),
+        image.created.format("%Y-%m-%d"),
+        retention::format_age(older_than),
+        tag
+    )))
+}
+
+/// Downloads and parses the config blob of an image.
+pub async fn image_config(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    digest: &str,
+) -> Result<ContainerConfig> {
+    let image = ecr.image(None, repository, digest).await?;
+    let descriptor = Manifest::parse(&image.manifest)?
+        .config
+        .ok_or_else(|| anyhow!("manifest has no config"))?;
+    ContainerConfig::parse(&ecr.blob(repository, &descriptor.digest).await?)
+}
+
+/// The value of the config label `label`, checked to be usable as a tag.
+pub async fn tag_from_label(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    digest: &str,
+    label: &str,
+) -> Result<String> {
+    let config = image_config(ecr, repository, digest).await?;
+    let tag = config
+        .labels
+        .unwrap_or_default()
+        .remove(label)
+        .ok_or_else(|| anyhow!("{} has no {} label", digest, label))?;
+    tags::validate(&tag)
+        .with_context(|| format!("label {} of {} isn't a usable tag", label, digest))?;
+    Ok(tag)
+}
+
+/// Fills in [`ImageDetail::labels`]; images whose config can't be read are
+/// left without labels.
+pub async fn load_labels(ecr: &dyn EcrApi, images: &mut [ImageDetail]) {
+    for image in images {
+        if let Ok(config) = image_config(ecr, &image.repository_name, &image.digest).await {
+            image.labels = config.labels.unwrap_or_default();
+        }
+    }
+}
+
+/// Keeps the images carrying every `key=value` label, loading labels first.
+pub async fn filter_by_labels(
+    ecr: &dyn EcrApi,
+    images: &mut Vec<ImageDetail>,
+    labels: &[(String, String)],
+) {
+    if labels.is_empty() {
+        return;
+    }
+    load_labels(ecr, images).await;
+    images.retain(|image| {
+        labels
+            .iter()
+            .all(|(key, value)| image.labels.get(key) == Some(value))
+    });
+}
+
+/// The named repository, or every repository in the registry.
+pub async fn repository_names(ecr: &dyn EcrApi, repository: Option<&str>) -> Result<Vec<String>> {
+    Ok(match repository {
+        Some(repository) => vec![repository.to_string()],
+        None => ecr
+            .repositories()
+            .await?
+            .into_iter()
+            .map(|repository| repository.name)
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecr::{Destination, MockEcr};
+
+    fn registry() -> MockEcr {
+        MockEcr::app(&[&["latest"], &["v2"]])
+    }
+
+    #[tokio::test]
+    async fn age_warning_covers_old_images_on_watched_tags() {
+        let ecr = registry();
+        let image = ecr.images("app").await.unwrap().remove(0);
+        let mut config = Config::default();
+        assert_eq!(age_warning(&config, &image, "latest").unwrap(), None);
+
+        config.age_warning.older_than = Some("30d".to_string());
+        assert!(age_warning(&config, &image, "latest").unwrap().is_some());
+        assert_eq!(age_warning(&config, &image, "feature-x").unwrap(), None);
+
+        config.age_warning.tags = vec!["release-*".to_string()];
+        assert!(age_warning(&config, &image, "release-1").unwrap().is_some());
+        assert_eq!(age_warning(&config, &image, "latest").unwrap(), None);
+
+        config.age_warning.older_than = Some("30 days".to_string());
+        assert!(age_warning(&config, &image, "release-1").is_err());
+    }
+
+    #[tokio::test]
+    async fn resolve_tag_is_none_for_missing_tags_and_repositories() {
+        let ecr = registry();
+        assert!(ecr
+            .resolve_tag(None, "app", "latest")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(ecr.resolve_tag(None, "app", "v9").await.unwrap(), None);
+        assert_eq!(ecr.resolve_tag(None, "nope", "latest").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn ensure_unmoved_catches_a_tag_moved_in_the_meantime() {
+        let ecr = registry();
+        let holder = ecr.resolve_tag(None, "app", "latest").await.unwrap();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+        ensure_unmoved(
+            &ecr,
+            &Locks::default(),
+            None,
+            "app",
+            "latest",
+            holder.as_deref(),
+            &v2,
+        )
+        .await
+        .unwrap();
+
+        // Someone else moves it to v2 before we get to.
+        let image = ecr.image(None, "app", &v2).await.unwrap();
+        ecr.put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap();
+        ensure_unmoved(
+            &ecr,
+            &Locks::default(),
+            None,
+            "app",
+            "latest",
+            holder.as_deref(),
+            &v2,
+        )
+        .await
+        .unwrap();
+
+        let elsewhere = format!("sha256:{}", "0".repeat(64));
+        let error = ensure_unmoved(
+            &ecr,
+            &Locks::default(),
+            None,
+            "app",
+            "latest",
+            holder.as_deref(),
+            &elsewhere,
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("moved"));
+    }
+
+    #[tokio::test]
+    async fn ensure_unmoved_catches_a_tag_created_in_the_meantime() {
+        let ecr = registry();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+        let image = ecr.image(None, "app", &v2).await.unwrap();
+        ecr.put_image(&image, "stable", &Destination::default())
+            .await
+            .unwrap();
+        let latest = ecr.tag_digest("app", "latest").await.unwrap();
+        assert!(ensure_unmoved(
+            &ecr,
+            &Locks::default(),
+            None,
+            "app",
+            "stable",
+            None,
+            &latest
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn ensure_unmoved_checks_the_destination_registry() {
+        let ecr = registry();
+        let registry_id = ecr.registry_id().await.unwrap();
+        let holder = ecr.tag_digest("app", "latest").await.unwrap();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+        ensure_unmoved(
+            &ecr,
+            &Locks::default(),
+            Some(&registry_id),
+            "app",
+            "latest",
+            Some(&holder),
+            &v2,
+        )
+        .await
+        .unwrap();
+        // The mock has no other registry to look in.
+        assert!(ensure_unmoved(
+            &ecr,
+            &Locks::default(),
+            Some("111111111111"),
+            "app",
+            "latest",
+            Some(&holder),
+            &v2
+        )
+        .await
+        .is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Context, Result};
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+use crate::args::{PolicyApplyArgs, PolicyCheckArgs, PolicyShowArgs, PolicySource};
+use crate::ecr::EcrApi;
+use crate::junit::{self, Case};
+use crate::output::{self, OutputFormat, Table};
+use crate::policy::{self, Decision};
+use crate::ui::Ui;
+use crate::viewer;
+
+#[derive(Serialize)]
+struct PolicyReport {
+    repository: String,
+    policy: serde_json::Value,
+    grants: Vec<GrantRow>,
+}
+
+#[derive(Serialize)]
+struct GrantRow {
+    #[serde(flatten)]
+    grant: policy::Grant,
+    access: String,
+    cross_account: bool,
+}
+
+/// Prints a repository's policy and who it lets pull or push.
+pub async fn show(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &PolicyShowArgs,
+    format: OutputFormat,
+) -> Result<()> {
+    let document = match ecr.repository_policy(&args.repository).await? {
+        Some(document) => document,
+        None => {
+            eprintln!(
+                "{} has no repository policy; only its own account's IAM policies apply",
+                args.repository
+            );
+            return Ok(());
+        }
+    };
+    let uri = ecr
+        .repositories()
+        .await?
+        .into_iter()
+        .find(|repository| repository.name == args.repository)
+        .map(|repository| repository.uri)
+        .ok_or_else(|| anyhow!("repository {} does not exist", args.repository))?;
+    let own_account = policy::repository_account(&uri).unwrap_or_default();
+
+    let grants: Vec<GrantRow> = policy::grants(&document)?
+        .into_iter()
+        .map(|grant| GrantRow {
+            access: grant.access(),
+            cross_account: grant.is_cross_account(own_account),
+            grant,
+        })
+        .collect();
+
+    let mut table = Table::new(&["SID", "EFFECT", "PRINCIPAL", "ACCESS", "NOTE"]);
+    for row in &grants {
+        let mut notes = vec![];
+        if row.grant.principal == "*" && !row.grant.except.is_empty() {
+            notes.push(format!("anyone but {}", row.grant.except.join(", ")));
+        } else if row.grant.principal == "*" {
+            notes.push("anyone".to_string());
+        } else if row.cross_account {
+            notes.push("cross-account".to_string());
+        }
+        if row.grant.conditional {
+            notes.push("conditional".to_string());
+        }
+        table.push(vec![
+            row.grant.sid.clone().unwrap_or_else(|| "-".to_string()),
+            row.grant.effect.clone(),
+            row.grant.principal.clone(),
+            row.access.clone(),
+            notes.join(", "),
+        ]);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(&document)?;
+    if format == OutputFormat::Table {
+        viewer::print(&value, ui.color)?;
+        println!();
+    }
+    let cross_account: Vec<String> = grants
+        .iter()
+        .filter(|row| row.cross_account && row.grant.allows())
+        .map(|row| row.grant.principal.clone())
+        .collect();
+    let report = PolicyReport {
+        repository: args.repository.clone(),
+        policy: value,
+        grants,
+    };
+    output::print(format, &table, &report)?;
+
+    if !cross_account.is_empty() {
+        let message = format!(
+            "{} cross-account principal(s) allowed: {}",
+            cross_account.len(),
+            cross_account.join(", ")
+        );
+        match ui.stderr_color {
+            true => eprintln!("{}", message.yellow()),
+            false => eprintln!("note: {}", message),
+        }
+    }
+    Ok(())
+}
+
+/// Sets a repository policy from a built-in template or a file, merging it
+/// into the current policy unless `--replace` is given.
+pub async fn apply(ecr: &dyn EcrApi, ui: &Ui, args: &PolicyApplyArgs) -> Result<()> {
+    let statements = match &args.source {
+        PolicySource::Template { name, accounts } => policy::template(name, accounts)?,
+        PolicySource::File(file) => {
+            let mut text = String::new();
+            match file.as_str() {
+                "-" => {
+                    std::io::stdin().read_to_string(&mut text)?;
+                }
+                path => {
+                    text = std::fs::read_to_string(path)
+                        .with_context(|| format!("failed to read {}", path))?;
+                }
+            }
+            // Check it parses before anything is sent.
+            policy::grants(&text).with_context(|| format!("invalid policy in {}", file))?;
+            let document: serde_json::Value = serde_json::from_str(&text)?;
+            match &document["Statement"] {
+                serde_json::Value::Array(statements) => statements.clone(),
+                statement => vec![statement.clone()],
+            }
+        }
+    };
+    let existing = match args.replace {
+        true => None,
+        false => ecr.repository_policy(&args.repository).await?,
+    };
+    let document = policy::merge(existing.as_deref(), statements)?;
+
+    viewer::print(&document, ui.color)?;
+    if !args.yes
+        && !ui
+            .confirm(&format!("set this as the policy of {}?", args.repository))
+            .prompt()?
+    {
+        bail!("aborted, the policy was not changed");
+    }
+    ecr.set_repository_policy(&args.repository, &serde_json::to_string(&document)?)
+        .await?;
+    ui.status(true, &format!("updated the policy of {}", args.repository));
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ActionCheck {
+    action: &'static str,
+    allowed: bool,
+    reason: String,
+}
+
+/// Works out whether `--principal` may pull an image, answering "why does
+/// the other account get a 403?" without reading the policy by hand.
+pub async fn check(ecr: &dyn EcrApi, args: &PolicyCheckArgs, format: OutputFormat) -> Result<()> {
+    let digest = super::resolve_reference(ecr, &args.repository, &args.reference).await?;
+    let repository = ecr
+        .repositories()
+        .await?
+        .into_iter()
+        .find(|repository| repository.name == args.repository)
+        .ok_or_else(|| anyhow!("repository {} does not exist", args.repository))?;
+    let own_account = policy::repository_account(&repository.uri).unwrap_or_default();
+    let principal_account = policy::account_of(&args.principal)
+        .ok_or_else(|| anyhow!("`{}` is not an account id or IAM ARN", args.principal))?;
+    let same_account = principal_account == own_account;
+
+    let grants = match ecr.repository_policy(&args.repository).await? {
+        Some(document) => policy::grants(&document)?,
+        None => vec![],
+    };
+    let checks: Vec<ActionCheck> = policy::PULL_ACTIONS
+        .iter()
+        .map(|action| {
+            let (allowed, reason) = match policy::evaluate(&grants, &args.principal, action) {
+                Decision::Allowed(sid) => (true, format!("allowed by {}", sid)),
+                Decision::Conditional(sid) => {
+                    (true, format!("allowed by {} if its conditions hold", sid))
+                }
+                Decision::Denied(sid) => (false, format!("denied by {}", sid)),
+                // Within the account the identity's own IAM policy is enough.
+                Decision::NotGranted if same_account => (
+                    true,
+                    "not in the policy; up to the caller's IAM policy".to_string(),
+                ),
+                Decision::NotGranted => (false, "no statement grants it".to_string()),
+            };
+            ActionCheck {
+                action,
+                allowed,
+                reason,
+            }
+        })
+        .collect();
+
+    let image = format!("{}@{}", args.repository, digest);
+    if format == OutputFormat::Junit {
+        let cases: Vec<Case> = checks
+            .iter()
+            .map(|check| {
+                let case = Case::new(format!("{} {}", args.principal, image), check.action);
+                match check.allowed {
+                    true => case.with_output(&check.reason),
+                    false => case.failed(&check.reason),
+                }
+            })
+            .collect();
+        print!("{}", junit::render("policy check", &cases));
+    } else {
+        let mut table = Table::new(&["ACTION", "ALLOWED", "REASON"]);
+        for check in &checks {
+            table.push(vec![
+                check.action.to_string(),
+                if check.allowed { "yes" } else { "no" }.to_string(),
+                check.reason.clone(),
+            ]);
+        }
+        output::print(format, &table, &checks)?;
+    }
+
+    if !same_account {
+        eprintln!(
+            "note: {} also needs an IAM policy in {} allowing these actions on {}",
+            args.principal, principal_account, repository.uri
+        );
+        if let Some(key) = &repository.kms_key {
+            eprintln!(
+                "note: {} is encrypted with {}; cross-account pulls need kms:Decrypt on it",
+                args.repository, key
+            );
+        }
+    }
+    if checks.iter().any(|check| !check.allowed) {
+        bail!("{} can't pull {}", args.principal, image);
+    }
+    eprintln!("{} can pull {}", args.principal, image);
+    Ok(())
+}
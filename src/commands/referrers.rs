@@ -0,0 +1,30 @@
+use anyhow::Result;
+
+use crate::args::ReferrersArgs;
+use crate::ecr::EcrApi;
+use crate::model::format_size;
+use crate::output::{self, OutputFormat, Table};
+use crate::referrers;
+
+/// Lists the artifacts attached to an image.
+pub async fn run(ecr: &dyn EcrApi, args: &ReferrersArgs, format: OutputFormat) -> Result<()> {
+    let digest = super::resolve_reference(ecr, &args.repository, &args.reference).await?;
+    let referrers = referrers::list(ecr, &args.repository, &digest).await?;
+    if referrers.is_empty() && format == OutputFormat::Table {
+        eprintln!("{}@{} has no referrers", args.repository, digest);
+        return Ok(());
+    }
+    let mut table = Table::new(&["TYPE", "DIGEST", "SIZE", "TAG"]);
+    for referrer in &referrers {
+        table.push(vec![
+            referrer
+                .artifact_type
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+            referrer.digest.clone(),
+            format_size(referrer.size),
+            referrer.tag.clone(),
+        ]);
+    }
+    output::print(format, &table, &referrers)
+}
@@ -0,0 +1,79 @@
+use anyhow::{bail, Result};
+use crossterm::tty::IsTty;
+
+use crate::ecr::{Destination, EcrApi, RepositorySettings};
+use crate::ui::Ui;
+
+/// Lifecycle policies offered when creating a repository.
+const LIFECYCLE_TEMPLATES: &[(&str, &str)] = &[
+    ("none", ""),
+    (
+        "expire untagged images after 14 days",
+        r#"{"rules":[{"rulePriority":1,"description":"Expire untagged images after 14 days","selection":{"tagStatus":"untagged","countType":"sinceImagePushed","countUnit":"days","countNumber":14},"action":{"type":"expire"}}]}"#,
+    ),
+    (
+        "keep the 30 most recent images",
+        r#"{"rules":[{"rulePriority":1,"description":"Keep the 30 most recent images","selection":{"tagStatus":"any","countType":"imageCountMoreThan","countNumber":30},"action":{"type":"expire"}}]}"#,
+    ),
+];
+
+/// Makes sure the `--to-repository` a tag is put into exists, walking the
+/// user through creating it when it doesn't.
+pub async fn ensure_destination(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    destination: &Destination,
+) -> Result<()> {
+    let repository_name = match &destination.repository_name {
+        Some(name) => name,
+        None => return Ok(()),
+    };
+    let registry_id = destination.registry_id.as_deref();
+    if ecr.repository_exists(registry_id, repository_name).await? {
+        return Ok(());
+    }
+    if !std::io::stdin().is_tty() {
+        bail!(
+            "repository {} does not exist; create it first, or run in a terminal to be walked through it",
+            repository_name
+        );
+    }
+    if !ui
+        .confirm(&format!(
+            "repository {} does not exist. Create it?",
+            repository_name
+        ))
+        .prompt()?
+    {
+        bail!("repository {} does not exist", repository_name);
+    }
+
+    let settings = RepositorySettings {
+        immutable_tags: ui
+            .confirm("immutable tags?")
+            .with_help_message("tags can't be moved once pushed")
+            .prompt()?,
+        scan_on_push: ui.confirm("scan on push?").with_default(true).prompt()?,
+        kms_key: Some(
+            ui.text("KMS key:")
+                .with_help_message("ARN, id or alias; empty for AES256")
+                .prompt()?
+                .trim()
+                .to_string(),
+        )
+        .filter(|key| !key.is_empty()),
+        lifecycle_policy: {
+            let names: Vec<&str> = LIFECYCLE_TEMPLATES.iter().map(|(name, _)| *name).collect();
+            let name = ui.picker("lifecycle policy:", names).prompt()?;
+            LIFECYCLE_TEMPLATES
+                .iter()
+                .find(|(template, _)| *template == name)
+                .map(|(_, policy)| policy.to_string())
+                .filter(|policy| !policy.is_empty())
+        },
+    };
+    ecr.create_repository(registry_id, repository_name, &settings)
+        .await?;
+    ui.status(true, &format!("created repository {}", repository_name));
+    Ok(())
+}
@@ -0,0 +1,20 @@
+use anyhow::{bail, Result};
+
+use crate::args::SbomArgs;
+use crate::ecr::EcrApi;
+use crate::sbom;
+
+/// Writes an image's SBOM, built from its enhanced scan, to stdout.
+pub async fn run(ecr: &dyn EcrApi, args: &SbomArgs) -> Result<()> {
+    if !ecr.enhanced_scanning().await? {
+        bail!("the registry uses basic scanning, turn on enhanced scanning for an SBOM");
+    }
+    let digest = super::resolve_reference(ecr, &args.repository, &args.reference).await?;
+    let findings = match ecr.scan_findings(&args.repository, &digest).await? {
+        Some(findings) => findings,
+        None => bail!("{}@{} hasn't been scanned yet", args.repository, digest),
+    };
+    let document = sbom::document(args.format, &args.repository, &digest, &findings);
+    println!("{}", serde_json::to_string_pretty(&document)?);
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use std::convert::Infallible;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use hyper::body::HttpBody;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+
+use crate::args::{Args, ServeArgs};
+use crate::config::Config;
+use crate::ecr::EcrApi;
+use crate::events::{self, Push};
+use crate::metrics;
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:8080";
+
+/// Push events are a few KiB; anything past this is refused unread.
+const MAX_BODY: usize = 1024 * 1024;
+
+/// Connections are served one at a time, so one that stalls is dropped
+/// rather than holding up every push behind it.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Never called: connections are served over HTTP/1 only, which doesn't
+/// spawn anything, so requests can borrow the registry and the config.
+#[derive(Clone, Copy)]
+struct NoSpawn;
+
+impl<F: Future> hyper::rt::Executor<F> for NoSpawn {
+    fn execute(&self, _: F) {
+        unreachable!("nothing is spawned over HTTP/1");
+    }
+}
+
+/// Receives ECR push events, e.g. from an EventBridge API destination, and
+/// puts the configured auto tags on the images pushed. Requests are served
+/// one at a time, so pushes are tagged in the order they arrive.
+pub async fn run(ecr: &dyn EcrApi, args: &Args, config: &Config, serve: &ServeArgs) -> Result<()> {
+    let listen = serve
+        .listen
+        .as_deref()
+        .or(config.serve.listen.as_deref())
+        .unwrap_or(DEFAULT_LISTEN);
+    if config.serve.token.is_none() && !serve.insecure {
+        bail!("serve needs a [serve] token, or --insecure to take events from anyone");
+    }
+    let listener = TcpListener::bind(listen)
+        .await
+        .with_context(|| format!("failed to listen on {}", listen))?;
+    if config.serve.token.is_none() {
+        eprintln!(
+            "warning: no [serve] token is set, anyone who can reach {} can tag images",
+            listen
+        );
+    }
+    eprintln!("listening on {}", listen);
+    let mut http = Http::new().with_executor(NoSpawn);
+    http.http1_only(true).http1_keep_alive(false);
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                eprintln!("warning: failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let service = service_fn(|request| async {
+            Ok::<_, Infallible>(handle(ecr, args, config, request).await)
+        });
+        match tokio::time::timeout(CONNECTION_TIMEOUT, http.serve_connection(stream, service)).await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("warning: {}", e),
+            Err(_) => eprintln!(
+                "warning: dropped a connection still open after {}s",
+                CONNECTION_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+async fn handle(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    request: Request<Body>,
+) -> Response<Body> {
+    match (request.method(), request.uri().path()) {
+        (&Method::GET, "/health") => return respond(StatusCode::OK, json!({ "ok": true })),
+        (&Method::POST, "/" | "/events") => {}
+        _ => return respond(StatusCode::NOT_FOUND, json!({ "error": "not found" })),
+    }
+    if let Some(token) = &config.serve.token {
+        let authorization = request
+            .headers()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok());
+        let expected = format!("Bearer {}", token);
+        if !authorization.is_some_and(|given| same(given.as_bytes(), expected.as_bytes())) {
+            return respond(StatusCode::UNAUTHORIZED, json!({ "error": "unauthorized" }));
+        }
+    }
+    let length = request
+        .headers()
+        .get(hyper::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if length.is_some_and(|length| length > MAX_BODY) {
+        return too_large();
+    }
+    let body = match read_body(request.into_body()).await {
+        Ok(Some(body)) => body,
+        Ok(None) => return too_large(),
+        Err(e) => {
+            let error = format!("invalid event: {:#}", e);
+            return respond(StatusCode::BAD_REQUEST, json!({ "error": error }));
+        }
+    };
+    let event: Value = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => {
+            let error = format!("invalid event: {:#}", e);
+            return respond(StatusCode::BAD_REQUEST, json!({ "error": error }));
+        }
+    };
+    let push = match Push::parse(&event) {
+        Some(push) => push,
+        None => return respond(StatusCode::OK, json!({ "tagged": [] })),
+    };
+
+    let started = Instant::now();
+    let result = events::auto_tag(ecr, args, config, &push).await;
+    for tag in result.iter().flatten() {
+        eprintln!("ok: {}:{} -> {}", push.repository, tag, push.digest);
+    }
+    let response = match &result {
+        Ok(tagged) => respond(StatusCode::OK, json!({ "tagged": tagged })),
+        Err(e) => {
+            // Not worth retrying: a refused image stays refused.
+            let error = format!("{:#}", e);
+            respond(StatusCode::UNPROCESSABLE_ENTITY, json!({ "error": error }))
+        }
+    };
+    // Reports the error too, once the tags put so far are recorded.
+    let result = result.map(|_| ());
+    if let Err(e) = metrics::report(args, config, result, started.elapsed()).await {
+        eprintln!("error: {:#}", e);
+    }
+    args.locks.release().await;
+    response
+}
+
+/// Reads the body up to `MAX_BODY`, or `None` if it goes past that.
+async fn read_body(mut body: Body) -> Result<Option<Vec<u8>>> {
+    let mut bytes = vec![];
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if bytes.len() + chunk.len() > MAX_BODY {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+/// Compares in constant time, so the token can't be guessed byte by byte
+/// from how long a refusal takes.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn too_large() -> Response<Body> {
+    let error = format!("event is larger than {} bytes", MAX_BODY);
+    respond(StatusCode::PAYLOAD_TOO_LARGE, json!({ "error": error }))
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    let mut response = Response::new(Body::from(body.to_string()));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Command, Parser};
+    use crate::config::parse_toml;
+    use crate::ecr::MockEcr;
+
+    fn args(argv: &[&str]) -> Args {
+        Args::parse_from(Parser::from_args(argv)).unwrap()
+    }
+
+    fn config(toml: &str) -> Config {
+        serde_json::from_value(parse_toml(toml).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn refuses_to_start_without_a_token() {
+        let ecr = MockEcr::app(&[&["v1"]]);
+        let args = args(&["serve", "--listen", "127.0.0.1:0"]);
+        let serve = match &args.command {
+            Command::Serve(serve) => serve,
+            _ => panic!("not a serve command"),
+        };
+
+        let err = run(&ecr, &args, &Config::default(), serve)
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("--insecure"));
+    }
+
+    #[tokio::test]
+    async fn turns_away_requests_without_the_token() {
+        let ecr = MockEcr::app(&[&["v1"]]);
+        let config = config("[serve]\ntoken = \"s3cret\"\n");
+        for authorization in [None, Some("Bearer guess"), Some("s3cret")] {
+            let mut request = Request::post("/events");
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            let request = request.body(Body::from("{}")).unwrap();
+
+            let response = handle(&ecr, &args(&["serve"]), &config, request).await;
+
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_health_checks_without_the_token() {
+        let ecr = MockEcr::app(&[&["v1"]]);
+        let config = config("[serve]\ntoken = \"s3cret\"\n");
+        let request = Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = handle(&ecr, &args(&["serve"]), &config, request).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
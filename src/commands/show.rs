@@ -0,0 +1,35 @@
+use anyhow::{anyhow, Context, Result};
+
+use crate::args::ShowArgs;
+use crate::ecr::EcrApi;
+use crate::manifest::Manifest;
+use crate::output::OutputFormat;
+use crate::ui::Ui;
+use crate::viewer;
+
+/// Shows an image's manifest, or its config blob with `--config`.
+pub async fn run(ecr: &dyn EcrApi, ui: &Ui, args: &ShowArgs, format: OutputFormat) -> Result<()> {
+    let digest = super::resolve_reference(ecr, &args.repository, &args.reference).await?;
+    let image = ecr.image(None, &args.repository, &digest).await?;
+    let (what, document) = match args.config {
+        false => ("manifest", image.manifest.into_bytes()),
+        true => {
+            let config = Manifest::parse(&image.manifest)?
+                .config
+                .ok_or_else(|| anyhow!("{} has no config blob", digest))?;
+            ("config", ecr.blob(&args.repository, &config.digest).await?)
+        }
+    };
+    let value: serde_json::Value = serde_json::from_slice(&document)
+        .with_context(|| format!("{} of {} is not JSON", what, digest))?;
+
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&value)?),
+        _ => viewer::show(
+            &format!("{}@{} {}", args.repository, digest, what),
+            &value,
+            ui.color,
+        )?,
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use anyhow::Result;
+use chrono::Utc;
+
+use crate::args::SimulateArgs;
+use crate::ecr::EcrApi;
+use crate::model::format_size;
+use crate::output::{self, OutputFormat, Table};
+use crate::retention::{self, Verdict};
+
+/// Reports what the proposed retention rules would delete, without deleting.
+pub async fn run(ecr: &dyn EcrApi, args: &SimulateArgs, format: OutputFormat) -> Result<()> {
+    let repositories = super::repository_names(ecr, args.repository.as_deref()).await?;
+
+    let now = Utc::now();
+    let mut verdicts: Vec<Verdict> = vec![];
+    for repository in &repositories {
+        let images = retention::standalone(ecr, ecr.images(repository).await?).await?;
+        verdicts.extend(args.rules.evaluate(images, now));
+    }
+
+    let doomed = verdicts.iter().filter(|verdict| verdict.delete).count();
+    let reclaimed: i64 = verdicts
+        .iter()
+        .filter(|verdict| verdict.delete)
+        .map(|verdict| verdict.image.size)
+        .sum();
+    if !args.all {
+        verdicts.retain(|verdict| verdict.delete);
+    }
+
+    let mut table = Table::new(&[
+        "REPOSITORY",
+        "ACTION",
+        "PUSHED",
+        "DIGEST",
+        "SIZE",
+        "TAGS",
+        "REASON",
+    ]);
+    for verdict in &verdicts {
+        let image = &verdict.image;
+        table.push(vec![
+            image.repository_name.clone(),
+            if verdict.delete { "delete" } else { "keep" }.to_string(),
+            image.created.to_rfc3339(),
+            image.digest.clone(),
+            format_size(image.size),
+            image.tags.join(", "),
+            verdict.reason.clone(),
+        ]);
+    }
+    output::print(format, &table, &verdicts)?;
+
+    eprintln!(
+        "{} image(s) across {} repositories would be deleted, reclaiming {}",
+        doomed,
+        repositories.len(),
+        format_size(reclaimed)
+    );
+    Ok(())
+}
@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::args::StatsArgs;
+use crate::ecr::EcrApi;
+use crate::model::{format_size, ImageDetail};
+use crate::output::{self, OutputFormat, Table};
+
+#[derive(Debug, Serialize)]
+struct RepositoryStats {
+    repository: String,
+    images: usize,
+    untagged: usize,
+    size: i64,
+    newest: Option<DateTime<Utc>>,
+    oldest: Option<DateTime<Utc>>,
+}
+
+impl RepositoryStats {
+    fn new(repository: &str, images: &[ImageDetail]) -> Self {
+        Self {
+            repository: repository.to_string(),
+            images: images.len(),
+            untagged: images.iter().filter(|image| image.tags.is_empty()).count(),
+            size: images.iter().map(|image| image.size).sum(),
+            newest: images.iter().map(|image| image.created).max(),
+            oldest: images.iter().map(|image| image.created).min(),
+        }
+    }
+
+    fn untagged_ratio(&self) -> f64 {
+        match self.images {
+            0 => 0.0,
+            images => self.untagged as f64 / images as f64,
+        }
+    }
+
+    fn row(&self) -> Vec<String> {
+        let date = |date: Option<DateTime<Utc>>| {
+            date.map(|date| date.format("%Y-%m-%d").to_string())
+                .unwrap_or_else(|| "-".to_string())
+        };
+        vec![
+            self.repository.clone(),
+            self.images.to_string(),
+            format_size(self.size),
+            date(self.newest),
+            date(self.oldest),
+            format!("{:.0}%", self.untagged_ratio() * 100.0),
+        ]
+    }
+}
+
+/// Per-repository image counts and storage, plus a registry-wide total.
+pub async fn run(ecr: &dyn EcrApi, args: &StatsArgs, format: OutputFormat) -> Result<()> {
+    let mut stats = vec![];
+    let mut all_images = vec![];
+    for repository in ecr.repositories().await? {
+        let images = ecr.images(&repository.name).await?;
+        stats.push(RepositoryStats::new(&repository.name, &images));
+        all_images.extend(images);
+    }
+
+    match args.sort.as_str() {
+        "name" => stats.sort_by(|a, b| a.repository.cmp(&b.repository)),
+        "images" => stats.sort_by_key(|s| std::cmp::Reverse(s.images)),
+        "size" => stats.sort_by_key(|s| std::cmp::Reverse(s.size)),
+        "newest" => stats.sort_by_key(|s| std::cmp::Reverse(s.newest)),
+        "oldest" => stats.sort_by_key(|s| s.oldest),
+        "untagged" => stats.sort_by(|a, b| b.untagged_ratio().total_cmp(&a.untagged_ratio())),
+        other => bail!(
+            "can't sort stats by `{}` (expected name, images, size, newest, oldest or untagged)",
+            other
+        ),
+    }
+    if args.reverse {
+        stats.reverse();
+    }
+
+    let total = RepositoryStats::new("TOTAL", &all_images);
+    let mut table = Table::new(&[
+        "REPOSITORY",
+        "IMAGES",
+        "SIZE",
+        "NEWEST",
+        "OLDEST",
+        "UNTAGGED",
+    ]);
+    for repository in &stats {
+        table.push(repository.row());
+    }
+    table.push(total.row());
+
+    #[derive(Serialize)]
+    struct Report<'a> {
+        repositories: &'a [RepositoryStats],
+        total: &'a RepositoryStats,
+    }
+    output::print(
+        format,
+        &table,
+        &Report {
+            repositories: &stats,
+            total: &total,
+        },
+    )
+}
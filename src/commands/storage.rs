@@ -0,0 +1,158 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::args::StorageArgs;
+use crate::ecr::EcrApi;
+use crate::manifest::Manifest;
+use crate::model::format_size;
+use crate::output::{self, OutputFormat, Table};
+
+#[derive(Debug, Default, Serialize)]
+struct RepositoryStorage {
+    repository: String,
+    images: usize,
+    layers: usize,
+    /// What the images add up to if nothing were shared.
+    logical_bytes: i64,
+    /// Each distinct layer counted once.
+    stored_bytes: i64,
+    /// Layers only one image uses.
+    unique_bytes: i64,
+    /// Layers used by more than one image, e.g. base images.
+    shared_bytes: i64,
+}
+
+impl RepositoryStorage {
+    fn row(&self) -> Vec<String> {
+        vec![
+            self.repository.clone(),
+            self.images.to_string(),
+            self.layers.to_string(),
+            format_size(self.logical_bytes),
+            format_size(self.stored_bytes),
+            format_size(self.unique_bytes),
+            format_size(self.shared_bytes),
+        ]
+    }
+}
+
+/// Walks the manifests of a repository (or the registry) and splits storage
+/// into layers unique to one image and layers shared between images.
+pub async fn run(ecr: &dyn EcrApi, args: &StorageArgs, format: OutputFormat) -> Result<()> {
+    let repositories = super::repository_names(ecr, args.repository.as_deref()).await?;
+
+    // Layer digest -> (size, repository -> images using it)
+    let mut layers: HashMap<String, (i64, BTreeMap<String, usize>)> = HashMap::new();
+    let mut images: BTreeMap<String, usize> = BTreeMap::new();
+    let mut skipped = 0;
+    for repository in &repositories {
+        images.insert(repository.clone(), 0);
+        let digests: Vec<String> = ecr
+            .images(repository)
+            .await?
+            .into_iter()
+            .map(|detail| detail.digest)
+            .collect();
+        for image in ecr.images_by_digest(repository, &digests).await? {
+            let manifest = match Manifest::parse(&image.manifest) {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    eprintln!("skipping {}@{}: {:#}", repository, image.digest, e);
+                    skipped += 1;
+                    continue;
+                }
+            };
+            *images.get_mut(repository).unwrap() += 1;
+            for layer in manifest.layers {
+                let (_, users) = layers
+                    .entry(layer.digest)
+                    .or_insert((layer.size, BTreeMap::new()));
+                *users.entry(repository.clone()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut report: BTreeMap<String, RepositoryStorage> = images
+        .iter()
+        .map(|(repository, images)| {
+            let storage = RepositoryStorage {
+                repository: repository.clone(),
+                images: *images,
+                ..Default::default()
+            };
+            (repository.clone(), storage)
+        })
+        .collect();
+    let mut total = RepositoryStorage {
+        repository: "TOTAL".to_string(),
+        images: images.values().sum(),
+        ..Default::default()
+    };
+    for (size, users) in layers.values() {
+        let uses: usize = users.values().sum();
+        for (repository, count) in users {
+            let storage = report.get_mut(repository).unwrap();
+            storage.layers += 1;
+            storage.logical_bytes += size * *count as i64;
+            storage.stored_bytes += size;
+            match uses {
+                1 => storage.unique_bytes += size,
+                _ => storage.shared_bytes += size,
+            }
+        }
+        total.layers += 1;
+        total.logical_bytes += size * uses as i64;
+        total.stored_bytes += size;
+        match uses {
+            1 => total.unique_bytes += size,
+            _ => total.shared_bytes += size,
+        }
+    }
+
+    let report: Vec<RepositoryStorage> = report.into_values().collect();
+    let mut table = Table::new(&[
+        "REPOSITORY",
+        "IMAGES",
+        "LAYERS",
+        "LOGICAL",
+        "STORED",
+        "UNIQUE",
+        "SHARED",
+    ]);
+    for storage in &report {
+        table.push(storage.row());
+    }
+    table.push(total.row());
+
+    #[derive(Serialize)]
+    struct Report<'a> {
+        repositories: &'a [RepositoryStorage],
+        total: &'a RepositoryStorage,
+    }
+    output::print(
+        format,
+        &table,
+        &Report {
+            repositories: &report,
+            total: &total,
+        },
+    )?;
+
+    let saved = match total.logical_bytes {
+        0 => 0.0,
+        logical => (logical - total.stored_bytes) as f64 / logical as f64 * 100.0,
+    };
+    eprintln!(
+        "{} logical, {} stored with shared layers counted once ({:.0}% saved){}",
+        format_size(total.logical_bytes),
+        format_size(total.stored_bytes),
+        saved,
+        match skipped {
+            0 => String::new(),
+            n => format!("; skipped {} image(s) without a readable manifest", n),
+        }
+    );
+    Ok(())
+}
@@ -0,0 +1,271 @@
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::accounts::Account;
+use crate::args::{Args, ImageRef, TagArgs};
+use crate::config::Config;
+use crate::ecr::{Destination, EcrApi};
+use crate::github::{self, Tagged};
+use crate::manifest;
+use crate::output::{self, OutputFormat, Table};
+use crate::replication;
+use crate::scan::Gate;
+use crate::tags;
+use crate::ui::Ui;
+
+/// Tags an image without any prompts.
+pub async fn run(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    args: &Args,
+    config: &Config,
+    tag_args: &TagArgs,
+) -> Result<()> {
+    let destination = args.destination();
+    let (tag, digest) = tag_image(
+        ecr,
+        ui,
+        config,
+        tag_args,
+        &destination,
+        args.gate(config),
+        args.tag_from_label.as_deref(),
+    )
+    .await?;
+    let repository = destination
+        .repository_name
+        .as_deref()
+        .unwrap_or(&tag_args.repository);
+    ui.status(true, &format!("{}:{} -> {}", repository, tag, digest));
+
+    if let Some(path) = &args.github_output {
+        let tagged = Tagged::new(ecr, &tag_args.repository, &destination, &tag, &digest).await?;
+        github::write(path, &[tagged])?;
+    }
+    if let Some(timeout) = args.verify_replication {
+        replication::verify(
+            ecr,
+            ui,
+            &destination,
+            &tag_args.repository,
+            &[(tag, digest)],
+            timeout,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct AccountResult<'a> {
+    account: &'a str,
+    name: Option<&'a str>,
+    ok: bool,
+    digest: Option<String>,
+    error: Option<String>,
+}
+
+/// Tags the image in each account's registry, resolving `--from-tag` in every
+/// one of them, then reports how each account went.
+pub async fn run_all_accounts(
+    registries: &[(Account, Box<dyn EcrApi>)],
+    ui: &Ui,
+    args: &Args,
+    config: &Config,
+    tag_args: &TagArgs,
+) -> Result<()> {
+    let destination = args.destination();
+    let mut results = vec![];
+    let mut tagged = vec![];
+    for (account, ecr) in registries {
+        let result = async {
+            let (tag, digest) = tag_image(
+                ecr.as_ref(),
+                ui,
+                config,
+                tag_args,
+                &destination,
+                args.gate(config),
+                args.tag_from_label.as_deref(),
+            )
+            .await?;
+            if args.github_output.is_some() {
+                tagged.push(
+                    Tagged::new(
+                        ecr.as_ref(),
+                        &tag_args.repository,
+                        &destination,
+                        &tag,
+                        &digest,
+                    )
+                    .await?,
+                );
+            }
+            if let Some(timeout) = args.verify_replication {
+                replication::verify(
+                    ecr.as_ref(),
+                    ui,
+                    &destination,
+                    &tag_args.repository,
+                    &[(tag, digest.clone())],
+                    timeout,
+                )
+                .await?;
+            }
+            anyhow::Ok(digest)
+        }
+        .await;
+        results.push(AccountResult {
+            account: &account.id,
+            name: account.name.as_deref(),
+            ok: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            digest: result.ok(),
+        });
+    }
+
+    let mut table = Table::new(&["ACCOUNT", "NAME", "RESULT", "DIGEST / ERROR"]);
+    for result in &results {
+        table.push(vec![
+            result.account.to_string(),
+            result.name.unwrap_or("-").to_string(),
+            if result.ok { "tagged" } else { "failed" }.to_string(),
+            result
+                .digest
+                .clone()
+                .or_else(|| result.error.clone())
+                .unwrap_or_default(),
+        ]);
+    }
+    if args.output != OutputFormat::Junit {
+        output::print(args.output, &table, &results)?;
+    }
+    if let Some(path) = &args.github_output {
+        github::write(path, &tagged)?;
+    }
+
+    let failed = results.iter().filter(|result| !result.ok).count();
+    if failed > 0 {
+        bail!("tagging failed in {} of {} accounts", failed, results.len());
+    }
+    Ok(())
+}
+
+/// Resolves, checks and puts the tag; returns the tag and the digest it now
+/// points at.
+async fn tag_image(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    config: &Config,
+    args: &TagArgs,
+    destination: &Destination,
+    gate: Gate<'_>,
+    tag_from_label: Option<&str>,
+) -> Result<(String, String)> {
+    if let Some(tag) = &args.tag {
+        tags::validate(tag)?;
+    }
+
+    let digest = match &args.source {
+        ImageRef::Digest(digest) => digest.clone(),
+        ImageRef::Tag(tag) => ecr.tag_digest(&args.repository, tag).await?,
+    };
+    let tag = match (&args.tag, tag_from_label) {
+        (Some(tag), _) => tag.clone(),
+        (None, Some(label)) => super::tag_from_label(ecr, &args.repository, &digest, label).await?,
+        (None, None) => unreachable!("--tag is required without --tag-from-label"),
+    };
+    // Policies are those of the repository the tag ends up in.
+    let target = destination
+        .repository_name
+        .as_deref()
+        .unwrap_or(&args.repository);
+    gate.check(ecr, target, &digest, &tag).await?;
+    gate.verify_signature(ecr, &args.repository, &digest)
+        .await?;
+    super::repository::ensure_destination(ecr, ui, destination).await?;
+    let image = ecr.image(None, &args.repository, &digest).await?;
+    // Only warned about: without prompts there's no one to ask.
+    if config.age_warning.older_than.is_some() {
+        let pushed = ecr.images(&args.repository).await?;
+        if let Some(detail) = pushed.iter().find(|detail| detail.digest == digest) {
+            if let Some(warning) = super::age_warning(config, detail, &tag)? {
+                ui.warning(&warning);
+            }
+        }
+    }
+    if let Some(warning) = manifest::legacy_warning(&image.manifest) {
+        ui.warning(&warning);
+    }
+    ecr.put_image(&image, &tag, destination).await?;
+    Ok((tag, digest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Command, Parser};
+    use crate::ecr::MockEcr;
+
+    fn registry() -> MockEcr {
+        MockEcr::app(&[&["latest", "v1"], &["v2"]])
+    }
+
+    async fn run_tag(ecr: &MockEcr, argv: &[&str]) -> Result<()> {
+        let args = Args::parse_from(Parser::from_args(argv))?;
+        let config = Config::default();
+        let ui = Ui::from_config(&config, true)?;
+        let tag_args = match &args.command {
+            Command::Tag(tag_args) => tag_args,
+            _ => panic!("not a tag command"),
+        };
+        run(ecr, &ui, &args, &config, tag_args).await
+    }
+
+    #[tokio::test]
+    async fn moves_the_tag_to_the_source_image() {
+        let ecr = registry();
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+
+        run_tag(
+            &ecr,
+            &["tag", "-r", "app", "--from-tag", "v2", "-t", "latest"],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(ecr.tag_digest("app", "latest").await.unwrap(), v2);
+        let images = ecr.images("app").await.unwrap();
+        let old = images.iter().find(|image| image.digest != v2).unwrap();
+        assert_eq!(old.tags, vec!["v1"]);
+    }
+
+    #[tokio::test]
+    async fn refuses_invalid_tags() {
+        let ecr = registry();
+        let result = run_tag(
+            &ecr,
+            &["tag", "-r", "app", "--from-tag", "v2", "-t", "no way"],
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(ecr
+            .resolve_tag(None, "app", "no way")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn fails_on_an_unknown_source_tag() {
+        let ecr = registry();
+        let result = run_tag(
+            &ecr,
+            &["tag", "-r", "app", "--from-tag", "v9", "-t", "latest"],
+        )
+        .await;
+        assert!(result.is_err());
+        let v2 = ecr.tag_digest("app", "v2").await.unwrap();
+        assert_ne!(ecr.tag_digest("app", "latest").await.unwrap(), v2);
+    }
+}
@@ -0,0 +1,700 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Settings read from `~/.config/ecr-tag/config.toml` (or `$ECR_TAG_CONFIG`).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub keybindings: Keybindings,
+    pub theme: Theme,
+    pub telemetry: Telemetry,
+    pub metrics: Metrics,
+    pub audit: Audit,
+    pub history: History,
+    pub lock: Lock,
+    pub backup: Backup,
+    pub age_warning: AgeWarning,
+    pub hooks: Hooks,
+    pub daemon: Daemon,
+    pub serve: Serve,
+    pub listen: Listen,
+    pub api: Api,
+    /// Per repository settings, e.g. `[repo."team/app"]`.
+    pub repo: BTreeMap<String, RepoConfig>,
+    /// Named connection settings, e.g. `[context.prod]`.
+    pub context: BTreeMap<String, ContextConfig>,
+    pub signing: Signing,
+    pub opa: Opa,
+}
+
+/// Keys bound to each picker action, e.g. `down = ["down", "j"]`.
+///
+/// Setting `vim = true` adds j/k, g/G and ctrl-f/ctrl-b on top of the
+/// defaults; any list given explicitly replaces the defaults for that action.
+/// With letters bound, the filter is only typed after `/`, which esc or
+/// enter ends; otherwise typing anything filters.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Keybindings {
+    pub vim: bool,
+    pub up: Option<Vec<String>>,
+    pub down: Option<Vec<String>>,
+    pub page_up: Option<Vec<String>>,
+    pub page_down: Option<Vec<String>>,
+    pub top: Option<Vec<String>>,
+    pub bottom: Option<Vec<String>>,
+    pub confirm: Option<Vec<String>>,
+    pub quick_confirm: Option<Vec<String>>,
+    pub toggle: Option<Vec<String>>,
+    pub pin: Option<Vec<String>>,
+    pub mode: Option<Vec<String>>,
+    pub back: Option<Vec<String>>,
+    pub cancel: Option<Vec<String>>,
+}
+
+/// Prompt colors, e.g. `preset = "ocean"` plus
+/// `answer = { fg = "light_cyan", bold = true }` to override single elements.
+///
+/// `ascii = true` draws prompts with plain ASCII glyphs, for terminals
+/// whose font lacks the others; by default that's done when the locale
+/// isn't UTF-8.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub preset: Option<String>,
+    pub ascii: Option<bool>,
+    pub prompt_prefix: Option<ThemeStyle>,
+    pub highlighted_option: Option<ThemeStyle>,
+    pub selected_checkbox: Option<ThemeStyle>,
+    pub answer: Option<ThemeStyle>,
+    pub help_message: Option<ThemeStyle>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ThemeStyle {
+    /// Replaces the glyph for prefixes and checkboxes.
+    pub symbol: Option<String>,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub bold: Option<bool>,
+    pub italic: Option<bool>,
+}
+
+/// Anonymous usage metrics, off unless `enabled = true`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Telemetry {
+    pub enabled: bool,
+    /// Defaults to `telemetry.jsonl` in the data directory.
+    pub file: Option<PathBuf>,
+    /// Optionally also POST each event here.
+    pub endpoint: Option<String>,
+}
+
+/// Metrics reported after every run that can change a registry, e.g.
+/// `pushgateway = "http://pushgateway:9091"` or `cloudwatch = true`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    pub pushgateway: Option<String>,
+    /// The Pushgateway job, `ecr-tag` by default.
+    pub job: Option<String>,
+    /// Put a CloudWatch metric for every promotion and deletion.
+    pub cloudwatch: bool,
+    /// The CloudWatch namespace, `EcrTag` by default.
+    pub namespace: Option<String>,
+}
+
+/// Where a JSON record of every change is sent, besides the local log of
+/// promotion policy overrides, e.g. `log_group = "/ecr-tag/audit"`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Audit {
+    /// A CloudWatch Logs group, which must already exist.
+    pub log_group: Option<String>,
+    /// Created when missing; `ecr-tag/<user>` by default.
+    pub log_stream: Option<String>,
+}
+
+/// Where the tags moved are kept, for `history` and `rollback`: the
+/// `history.jsonl` in the data directory, or a DynamoDB `table` shared by
+/// the team, e.g. `table = "ecr-tag-history"`. The table needs a string
+/// partition key `repository` and a string sort key `moved`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct History {
+    pub table: Option<String>,
+}
+
+/// A DynamoDB `table` each repository is locked in while a run moves its
+/// tags, e.g. `table = "ecr-tag-locks"`, with a string partition key
+/// `repository`. A lock not renewed by a mutation expires after `ttl`
+/// seconds, 900 by default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Lock {
+    pub table: Option<String>,
+    pub ttl: Option<u64>,
+}
+
+/// The backup tag the image a tag moves off gets, e.g.
+/// `style = "previous"` for `<tag>-previous` or `"timestamp"` for
+/// `<tag>-YYYYMMDDHHMM`; `--backup` wins. With `keep = 5`, only the newest
+/// five timestamped backups of each tag are kept.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Backup {
+    pub style: Option<String>,
+    pub keep: Option<usize>,
+}
+
+/// Shell commands run around mutations, e.g.
+/// `post_tag = "./notify.sh {repo} {tag} {digest}"`.
+///
+/// Tag hooks get `{repo}`, `{source_repo}`, `{registry}`, `{tag}` and
+/// `{digest}`; delete hooks get `{repo}`, `{count}` and `{digests}`.
+/// `--backup`'s own tags and their pruning don't run them.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    pub pre_tag: Option<String>,
+    pub post_tag: Option<String>,
+    pub pre_delete: Option<String>,
+    pub post_delete: Option<String>,
+}
+
+/// How `ecr-tag daemon` runs, e.g. `interval = 300` (seconds, `--interval`
+/// wins) and `alert = "./page.sh {repo} {tag} {problem}"`, run for every
+/// drift found that isn't fixed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Daemon {
+    pub interval: Option<u64>,
+    pub alert: Option<String>,
+}
+
+/// Where `ecr-tag serve` listens for pushes, e.g. `listen = "0.0.0.0:8080"`
+/// (`--listen` wins), and the `token` requests must carry as
+/// `Authorization: Bearer <token>`; without one it only runs with
+/// `--insecure`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Serve {
+    pub listen: Option<String>,
+    pub token: Option<String>,
+}
+
+/// The SQS queue `ecr-tag listen` takes push events from (`--queue` wins),
+/// e.g. `queue = "https://sqs.eu-west-1.amazonaws.com/123456789012/pushes"`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Listen {
+    pub queue: Option<String>,
+}
+
+/// Signs each newly tagged image with cosign when `key` is set, e.g.
+/// `key = "awskms:///alias/release-signing"` or a path to a `cosign.key`,
+/// and verifies signatures for `--require-signature`.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Signing {
+    pub key: Option<String>,
+    /// The cosign binary, `cosign` from `PATH` by default.
+    pub cosign: Option<String>,
+    /// The certificate identity `--require-signature` accepts for keyless
+    /// signatures when no `key` is set, e.g. a CI workflow's URL.
+    pub identity: Option<String>,
+    /// The OIDC issuer of `identity`, e.g.
+    /// `https://token.actions.githubusercontent.com`.
+    pub issuer: Option<String>,
+}
+
+/// A Rego policy every mutation must pass, evaluated with the `opa` binary
+/// against a `bundle` (e.g. `bundle = "./policy"`), or by an OPA server at
+/// `url` (e.g. `url = "http://localhost:8181"`). The `query` (by default
+/// `data.ecr_tag.deny`) must give the messages of the rules denying the
+/// mutation; an empty or undefined result allows it.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct Opa {
+    pub bundle: Option<String>,
+    pub url: Option<String>,
+    pub query: Option<String>,
+    /// The opa binary, `opa` from `PATH` by default.
+    pub opa: Option<String>,
+}
+
+/// Asks before an image pushed more than `older_than` ago (e.g. `"30d"`)
+/// is tagged with one of `tags` in the prompts, `latest` and `prod` by
+/// default: it's usually the wrong row.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AgeWarning {
+    pub older_than: Option<String>,
+    /// Tag patterns, `*` matching any run of characters.
+    pub tags: Vec<String>,
+}
+
+/// Limits on the ECR calls made, e.g. `max_rps = 5` (`--max-rps` wins).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Api {
+    pub max_rps: Option<f64>,
+    /// Refuse tagging and deleting, as if `--read-only` were always passed.
+    pub read_only: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct RepoConfig {
+    /// Always list the repository under the picker's favorites.
+    pub pinned: bool,
+    /// Prefilled in the tag prompt instead of the last tag used.
+    pub default_tag: Option<String>,
+    /// Promotion rules the tag flow enforces, e.g. `[[repo."web".policy]]`
+    /// with `tags = ["prod", "release-*"]` and `fail_on = "high"`.
+    pub policy: Vec<PromotionPolicy>,
+    /// What `ecr-tag daemon` keeps true of the repository's tags, e.g.
+    /// `[[repo."web".rule]]` with `tag = "latest"` and `newest = true`.
+    pub rule: Vec<TagRule>,
+    /// Tags put on pushed images as they arrive, e.g.
+    /// `[[repo."web".auto_tag]]` with `pushed = ["main-*"]` and `tag = "edge"`.
+    pub auto_tag: Vec<AutoTag>,
+}
+
+/// Refuses tags matching `tags` (`*` wildcards; every tag when empty) on
+/// images with findings at or above `fail_on`, or pushed less than
+/// `min_age` ago (e.g. `"48h"`). `--force` overrides it only with
+/// `--reason`, and the override is written to the audit log.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct PromotionPolicy {
+    pub tags: Vec<String>,
+    pub fail_on: Option<String>,
+    pub min_age: Option<String>,
+}
+
+/// A tag `ecr-tag daemon` checks every interval. With `newest = true` it
+/// must be on the newest image passing `fail_on` and the repository's
+/// promotion policies, among those with a tag matching `candidates` (`*`
+/// wildcards; every image when empty); with `max_age = "24h"` its image
+/// must have been pushed since. Drift is alerted on, or fixed by moving
+/// the tag when `apply = true` and it can be.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct TagRule {
+    pub tag: String,
+    pub newest: bool,
+    pub candidates: Vec<String>,
+    pub fail_on: Option<String>,
+    pub max_age: Option<String>,
+    pub apply: bool,
+}
+
+/// Puts `tag` on every image pushed with a tag matching `pushed` (`*`
+/// wildcards; every push when empty), if it passes the tag's gate, when
+/// `ecr-tag serve` or `ecr-tag listen` hear of the push.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(default)]
+pub struct AutoTag {
+    pub pushed: Vec<String>,
+    pub tag: String,
+}
+
+/// A profile, region and role switched between as one, picked with
+/// `--context` or when starting an interactive run.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    pub role_arn: Option<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        self.pre_tag.is_none()
+            && self.post_tag.is_none()
+            && self.pre_delete.is_none()
+            && self.post_delete.is_none()
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        if let Ok(path) = std::env::var("ECR_TAG_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        dirs_next::config_dir().map(|dir| dir.join("ecr-tag").join("config.toml"))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = match Self::path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Self::default()),
+        };
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let value = parse_toml(&contents).with_context(|| format!("in {}", path.display()))?;
+        serde_json::from_value(value).with_context(|| format!("invalid config {}", path.display()))
+    }
+}
+
+/// Parses the subset of TOML we need for the config file: tables, arrays of
+/// tables, dotted/quoted keys, strings (basic ones with the `\n`, `\t`,
+/// `\"` and `\\` escapes), numbers, booleans, arrays and inline tables.
+pub fn parse_toml(input: &str) -> Result<Value> {
+    let mut root = Map::new();
+    let mut current: Vec<String> = vec![];
+    let mut lines = input.lines().enumerate();
+
+    while let Some((number, line)) = lines.next() {
+        let mut line = strip_comment(line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        // Arrays may span lines, keep reading until the brackets balance.
+        while !balanced(&line) {
+            match lines.next() {
+                Some((_, next)) => {
+                    line.push(' ');
+                    line.push_str(strip_comment(next).trim());
+                }
+                None => bail!("line {}: unterminated value", number + 1),
+            }
+        }
+        let err = |e: anyhow::Error| anyhow!("line {}: {}", number + 1, e);
+
+        if let Some(header) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            current = parse_key(header).map_err(err)?;
+            let (last, parents) = current.split_last().unwrap();
+            let table = table_at(&mut root, parents).map_err(err)?;
+            let array = table
+                .entry(last.clone())
+                .or_insert_with(|| Value::Array(vec![]));
+            match array {
+                Value::Array(items) => items.push(Value::Object(Map::new())),
+                _ => return Err(err(anyhow!("{} is not an array of tables", header))),
+            }
+        } else if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = parse_key(header).map_err(err)?;
+            table_at(&mut root, &current).map_err(err)?;
+        } else {
+            let (key, value) =
+                split_unquoted(&line, '=').ok_or_else(|| err(anyhow!("expected `key = value`")))?;
+            let key = parse_key(key).map_err(err)?;
+            let (value, rest) = parse_value(value.trim()).map_err(err)?;
+            if !rest.trim().is_empty() {
+                return Err(err(anyhow!("unexpected `{}`", rest.trim())));
+            }
+            let (last, parents) = key.split_last().unwrap();
+            let mut path = current.clone();
+            path.extend(parents.iter().cloned());
+            table_at(&mut root, &path)
+                .map_err(err)?
+                .insert(last.clone(), value);
+        }
+    }
+
+    Ok(Value::Object(root))
+}
+
+/// Walks (creating as needed) to the table at `path`. When a path segment is
+/// an array of tables the most recently added entry is used.
+fn table_at<'a>(
+    root: &'a mut Map<String, Value>,
+    path: &[String],
+) -> Result<&'a mut Map<String, Value>> {
+    let mut table = root;
+    for segment in path {
+        let entry = table
+            .entry(segment.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+        table = match entry {
+            Value::Object(map) => map,
+            Value::Array(items) => match items.last_mut() {
+                Some(Value::Object(map)) => map,
+                _ => bail!("{} is not a table", segment),
+            },
+            _ => bail!("{} is not a table", segment),
+        };
+    }
+    Ok(table)
+}
+
+/// The characters of `line` outside its strings, with their offsets. A
+/// `\"` in a basic string doesn't end it; literal strings have no escapes.
+fn unquoted(line: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut quote = None;
+    let mut escaped = false;
+    line.char_indices().filter(move |&(_, c)| {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some('"'), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, _) => return true,
+        }
+        false
+    })
+}
+
+/// `line` around its first `separator` outside a string.
+fn split_unquoted(line: &str, separator: char) -> Option<(&str, &str)> {
+    let (i, _) = unquoted(line).find(|&(_, c)| c == separator)?;
+    Some((&line[..i], &line[i + separator.len_utf8()..]))
+}
+
+fn strip_comment(line: &str) -> &str {
+    match split_unquoted(line, '#') {
+        Some((code, _)) => code,
+        None => line,
+    }
+}
+
+fn balanced(line: &str) -> bool {
+    let depth = unquoted(line).fold(0i32, |depth, (_, c)| match c {
+        '[' | '{' => depth + 1,
+        ']' | '}' => depth - 1,
+        _ => depth,
+    });
+    depth <= 0
+}
+
+fn parse_key(key: &str) -> Result<Vec<String>> {
+    let mut parts = vec![];
+    let mut rest = key.trim();
+    while !rest.is_empty() {
+        if rest.starts_with('"') || rest.starts_with('\'') {
+            let (value, remaining) = parse_string(rest)?;
+            parts.push(value);
+            rest = remaining.trim_start();
+        } else {
+            let end = rest.find('.').unwrap_or(rest.len());
+            let part = rest[..end].trim();
+            if part.is_empty()
+                || !part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            {
+                bail!("invalid key `{}`", key.trim());
+            }
+            parts.push(part.to_string());
+            rest = rest[end..].trim_start();
+        }
+        if let Some(remaining) = rest.strip_prefix('.') {
+            rest = remaining.trim_start();
+        } else if !rest.is_empty() {
+            bail!("invalid key `{}`", key.trim());
+        }
+    }
+    if parts.is_empty() {
+        bail!("empty key");
+    }
+    Ok(parts)
+}
+
+fn parse_value(input: &str) -> Result<(Value, &str)> {
+    let input = input.trim_start();
+    match input.chars().next() {
+        Some('"' | '\'') => {
+            let (value, rest) = parse_string(input)?;
+            Ok((Value::String(value), rest))
+        }
+        Some('[') => {
+            let mut items = vec![];
+            let mut rest = input[1..].trim_start();
+            loop {
+                if let Some(remaining) = rest.strip_prefix(']') {
+                    return Ok((Value::Array(items), remaining));
+                }
+                let (item, remaining) = parse_value(rest)?;
+                items.push(item);
+                rest = remaining.trim_start();
+                if let Some(remaining) = rest.strip_prefix(',') {
+                    rest = remaining.trim_start();
+                } else if !rest.starts_with(']') {
+                    bail!("expected `,` or `]` in array");
+                }
+            }
+        }
+        Some('{') => {
+            let mut table = Map::new();
+            let mut rest = input[1..].trim_start();
+            loop {
+                if let Some(remaining) = rest.strip_prefix('}') {
+                    return Ok((Value::Object(table), remaining));
+                }
+                let (key, remaining) = split_unquoted(rest, '=')
+                    .ok_or_else(|| anyhow!("expected `key = value` in inline table"))?;
+                let key = parse_key(key)?;
+                let (value, remaining) = parse_value(remaining)?;
+                let (last, parents) = key.split_last().unwrap();
+                table_at(&mut table, parents)?.insert(last.clone(), value);
+                rest = remaining.trim_start();
+                if let Some(remaining) = rest.strip_prefix(',') {
+                    rest = remaining.trim_start();
+                } else if !rest.starts_with('}') {
+                    bail!("expected `,` or `}}` in inline table");
+                }
+            }
+        }
+        Some(_) => {
+            let end = input
+                .find(|c: char| c == ',' || c == ']' || c == '}' || c.is_whitespace())
+                .unwrap_or(input.len());
+            let (token, rest) = input.split_at(end);
+            let value = match token {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => {
+                    let number = token.replace('_', "");
+                    if let Ok(int) = number.parse::<i64>() {
+                        Value::from(int)
+                    } else if let Ok(float) = number.parse::<f64>() {
+                        Value::from(float)
+                    } else {
+                        bail!("invalid value `{}`", token);
+                    }
+                }
+            };
+            Ok((value, rest))
+        }
+        None => bail!("missing value"),
+    }
+}
+
+fn parse_string(input: &str) -> Result<(String, &str)> {
+    let quote = input.chars().next().unwrap();
+    let mut value = String::new();
+    let mut chars = input.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            c if c == quote => return Ok((value, &input[i + 1..])),
+            '\\' if quote == '"' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, '"')) => value.push('"'),
+                Some((_, '\\')) => value.push('\\'),
+                Some((_, other)) => bail!("unsupported escape `\\{}`", other),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    bail!("unterminated string")
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn parses_tables_and_values() {
+        let parsed = parse_toml(
+            r#"
+            # A comment
+            top = "level"
+            [theme]
+            ascii = true
+            accent = 'red' # trailing comment
+            [repo."my-app".tags]
+            default = "latest"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "top": "level",
+                "theme": { "ascii": true, "accent": "red" },
+                "repo": { "my-app": { "tags": { "default": "latest" } } },
+            })
+        );
+    }
+
+    #[test]
+    fn parses_numbers_arrays_and_inline_tables() {
+        let parsed = parse_toml(
+            r#"
+            keep = 3
+            ratio = 0.5
+            protect = [
+                "v1", # spans lines
+                "stable",
+            ]
+            point = { x = 1, y = "two" }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "keep": 3,
+                "ratio": 0.5,
+                "protect": ["v1", "stable"],
+                "point": { "x": 1, "y": "two" },
+            })
+        );
+    }
+
+    #[test]
+    fn parses_arrays_of_tables() {
+        let parsed = parse_toml(
+            r#"
+            [[hooks.post_tag]]
+            run = "one"
+            [[hooks.post_tag]]
+            run = "two"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            json!({ "hooks": { "post_tag": [{ "run": "one" }, { "run": "two" }] } })
+        );
+    }
+
+    #[test]
+    fn reports_the_line_of_an_error() {
+        let error = parse_toml("a = 1\nb\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected `key = value`");
+        let error = parse_toml("a = [1,\n2\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: unterminated value");
+        assert!(parse_toml("a = 1 2").is_err());
+        assert!(parse_toml("a = \"1\"\n[a]").is_err());
+    }
+
+    #[test]
+    fn escaped_quotes_dont_end_strings() {
+        let parsed = parse_toml(
+            r##"
+            pre_tag = "echo \"# {tag}\" > log" # a comment
+            path = 'C:\images\' # literal strings have no escapes
+            "a=b" = 1
+            tags = [
+                "say \"]\"", # not the end of the array
+                "v1",
+            ]
+            "##,
+        )
+        .unwrap();
+        assert_eq!(
+            parsed,
+            json!({
+                "pre_tag": "echo \"# {tag}\" > log",
+                "path": "C:\\images\\",
+                "a=b": 1,
+                "tags": ["say \"]\"", "v1"],
+            })
+        );
+    }
+}
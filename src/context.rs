@@ -0,0 +1,117 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use crossterm::tty::IsTty;
+
+use crate::args::{Args, Command};
+use crate::client;
+use crate::config::{Config, ContextConfig};
+use crate::ecr::EcrApi;
+use crate::ui::Ui;
+
+/// Applies the `[context.<name>]` named by `--context`, or else the one
+/// saved by `ecr-tag context use`; interactive runs in a terminal offer a
+/// picker instead when contexts are configured but neither was given.
+/// Explicit `--region` still wins over the context's.
+pub fn apply(args: &mut Args, config: &Config, ui: &Ui) -> Result<()> {
+    // These manage the saved context, so they must work even when it's stale.
+    if matches!(args.command, Command::ContextList | Command::ContextUse(_)) {
+        return Ok(());
+    }
+    let name = match args.context.clone().or_else(current) {
+        Some(name) => name,
+        None if matches!(args.command, Command::Interactive(_))
+            && !config.context.is_empty()
+            && std::io::stdin().is_tty() =>
+        {
+            let names: Vec<String> = config.context.keys().cloned().collect();
+            ui.picker("context:", names)
+                .with_preview(|name| preview(&config.context[name]))
+                .prompt()?
+        }
+        None => return Ok(()),
+    };
+    let context = match config.context.get(&name) {
+        Some(context) => context,
+        None if args.context.is_none() => bail!(
+            "the saved context {} is no longer in the config, switch with `ecr-tag context use`",
+            name
+        ),
+        None if config.context.is_empty() => {
+            bail!("no context named {}: the config defines none", name)
+        }
+        None => bail!(
+            "no context named {}, expected one of: {}",
+            name,
+            config
+                .context
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+
+    if context.profile.is_some() {
+        args.aws_profile = context.profile.clone();
+    }
+    if args.region.is_none() {
+        args.region = context.region.clone();
+    }
+    args.role_arn = context.role_arn.clone();
+    args.context = Some(name);
+    Ok(())
+}
+
+/// Prints which account and region the active context points at, so a
+/// mutation against the wrong one is caught before it happens.
+pub async fn announce(ecr: &dyn EcrApi, args: &Args) {
+    if let Some(name) = &args.context {
+        let (account, region) = account_and_region(ecr, args).await;
+        eprintln!("context {}: account {}, {}", name, account, region);
+    }
+}
+
+/// The account of the registry being used and the region, or why either
+/// couldn't be found.
+pub async fn account_and_region(ecr: &dyn EcrApi, args: &Args) -> (String, String) {
+    let account = match ecr.registry_id().await {
+        Ok(account) => account,
+        Err(e) => format!("unknown ({:#})", e),
+    };
+    let region = match client::region(args) {
+        Ok(region) => region.name().to_string(),
+        Err(e) => format!("unknown region ({:#})", e),
+    };
+    (account, region)
+}
+
+fn current_path() -> Option<PathBuf> {
+    dirs_next::data_dir().map(|dir| dir.join("ecr-tag").join("context"))
+}
+
+/// The context saved by `ecr-tag context use`, if any.
+pub fn current() -> Option<String> {
+    let name = std::fs::read_to_string(current_path()?).ok()?;
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+/// Saves `name` as the context later runs use without `--context`.
+pub fn set_current(name: &str) -> Result<()> {
+    let path = current_path().context("no data directory to save the context in")?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, format!("{}\n", name))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn preview(context: &ContextConfig) -> Vec<String> {
+    let field = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    vec![
+        format!("profile:  {}", field(&context.profile)),
+        format!("region:   {}", field(&context.region)),
+        format!("role:     {}", field(&context.role_arn)),
+    ]
+}
@@ -0,0 +1,277 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hyper::Method;
+use rusoto_core::credential::{
+    AwsCredentials, ChainProvider, CredentialsError, ProfileProvider, ProvideAwsCredentials,
+};
+use rusoto_core::request::{BufferedHttpResponse, DispatchSignedRequest, HttpClient};
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{Region, RusotoError};
+
+use crate::http;
+
+/// How long before their expiry credentials are swapped for fresh ones.
+const REFRESH_MARGIN_MINUTES: i64 = 5;
+
+/// The usual environment/profile/container/instance credential chain (or a
+/// web identity role when one is configured), optionally used to assume
+/// another role, cached until shortly before the credentials expire.
+///
+/// Unlike rusoto's default provider the cache can also be dropped on demand,
+/// so a call rejected with an expired token (static session credentials that
+/// an SSO login or assume-role helper has since rewritten) picks up the new
+/// ones instead of failing the rest of a long run.
+#[derive(Clone)]
+pub struct Credentials {
+    chain: ChainProvider,
+    web_identity: Option<WebIdentity>,
+    roles: Vec<AssumeRole>,
+    cached: Arc<Mutex<Option<AwsCredentials>>>,
+}
+
+impl Credentials {
+    /// Each of `roles` is assumed in turn with the credentials of the one
+    /// before. `profile` replaces `AWS_PROFILE` in the chain.
+    pub fn new(
+        profile: Option<&str>,
+        web_identity: Option<WebIdentity>,
+        roles: Vec<AssumeRole>,
+    ) -> Self {
+        let chain = match profile.map(ProfileProvider::with_default_credentials) {
+            Some(Ok(profile)) => ChainProvider::with_profile_provider(profile),
+            _ => ChainProvider::new(),
+        };
+        Self {
+            chain,
+            web_identity,
+            roles,
+            cached: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Forgets the cached credentials; the next call resolves them again.
+    pub fn invalidate(&self) {
+        *self.cached.lock().unwrap() = None;
+    }
+}
+
+#[async_trait]
+impl ProvideAwsCredentials for Credentials {
+    async fn credentials(&self) -> Result<AwsCredentials, CredentialsError> {
+        if let Some(credentials) = self.cached.lock().unwrap().as_ref() {
+            let fresh = match credentials.expires_at() {
+                Some(expires_at) => {
+                    *expires_at - Utc::now() > chrono::Duration::minutes(REFRESH_MARGIN_MINUTES)
+                }
+                None => true,
+            };
+            if fresh {
+                return Ok(credentials.clone());
+            }
+        }
+        let mut credentials = match &self.web_identity {
+            Some(web_identity) => web_identity.assume_role().await?,
+            None => self.chain.credentials().await?,
+        };
+        for role in &self.roles {
+            credentials = role.assume(&credentials).await?;
+        }
+        *self.cached.lock().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+}
+
+/// A role assumed with an OIDC token, as handed out by EKS (IRSA) or CI
+/// runners such as GitHub Actions.
+#[derive(Clone)]
+pub struct WebIdentity {
+    token_file: PathBuf,
+    role_arn: String,
+    session_name: String,
+    endpoint: String,
+}
+
+impl WebIdentity {
+    /// Reads `AWS_WEB_IDENTITY_TOKEN_FILE`, `AWS_ROLE_ARN` and
+    /// `AWS_ROLE_SESSION_NAME`; `token_file` (`--web-identity-token-file`)
+    /// replaces the first. `None` when no token file is configured.
+    pub fn from_env(token_file: Option<PathBuf>, region: &str) -> anyhow::Result<Option<Self>> {
+        let explicit = token_file.is_some();
+        let token_file = match token_file.or_else(|| {
+            std::env::var_os("AWS_WEB_IDENTITY_TOKEN_FILE")
+                .filter(|file| !file.is_empty())
+                .map(PathBuf::from)
+        }) {
+            Some(token_file) => token_file,
+            None => return Ok(None),
+        };
+        let role_arn = match std::env::var("AWS_ROLE_ARN") {
+            Ok(role_arn) if !role_arn.is_empty() => role_arn,
+            _ if explicit => {
+                bail!("--web-identity-token-file needs AWS_ROLE_ARN set to the role to assume")
+            }
+            _ => return Ok(None),
+        };
+        let session_name = session_name();
+        let suffix = if region.starts_with("cn-") { ".cn" } else { "" };
+        Ok(Some(Self {
+            token_file,
+            role_arn,
+            session_name,
+            endpoint: format!("https://sts.{}.amazonaws.com{}/", region, suffix),
+        }))
+    }
+
+    async fn assume_role(&self) -> Result<AwsCredentials, CredentialsError> {
+        // Read the token every time, the kubelet and CI runners rotate it.
+        let token = std::fs::read_to_string(&self.token_file).map_err(|e| {
+            CredentialsError::new(format!(
+                "failed to read {}: {}",
+                self.token_file.display(),
+                e
+            ))
+        })?;
+        let body = form_encode(&[
+            ("Action", "AssumeRoleWithWebIdentity"),
+            ("Version", "2011-06-15"),
+            ("RoleArn", &self.role_arn),
+            ("RoleSessionName", &self.session_name),
+            ("WebIdentityToken", token.trim()),
+        ]);
+        let response = http::send(
+            Method::POST,
+            &self.endpoint,
+            "application/x-www-form-urlencoded",
+            body,
+            Duration::from_secs(30),
+        )
+        .await
+        .map_err(|e| CredentialsError::new(format!("AssumeRoleWithWebIdentity failed: {:#}", e)))?;
+        parse_credentials("AssumeRoleWithWebIdentity", &response)
+    }
+}
+
+/// A role assumed with whatever credentials were resolved first, e.g. the
+/// `role_arn` of a context or the role of an account picked from the
+/// Organization.
+#[derive(Clone)]
+pub struct AssumeRole {
+    role_arn: String,
+    session_name: String,
+    region: Region,
+}
+
+impl AssumeRole {
+    pub fn new(role_arn: String, region: &str) -> Self {
+        Self {
+            role_arn,
+            session_name: session_name(),
+            // STS lives on the standard endpoint even when ECR is FIPS or emulated.
+            region: Region::from_str(region).unwrap_or_default(),
+        }
+    }
+
+    async fn assume(&self, source: &AwsCredentials) -> Result<AwsCredentials, CredentialsError> {
+        let mut request = SignedRequest::new("POST", "sts", &self.region, "/");
+        request.set_content_type("application/x-www-form-urlencoded".to_string());
+        request.set_payload(Some(form_encode(&[
+            ("Action", "AssumeRole"),
+            ("Version", "2011-06-15"),
+            ("RoleArn", &self.role_arn),
+            ("RoleSessionName", &self.session_name),
+        ])));
+        request.sign(source);
+
+        let failed = |e: String| {
+            CredentialsError::new(format!("AssumeRole {} failed: {}", self.role_arn, e))
+        };
+        let client = HttpClient::new().map_err(|e| failed(e.to_string()))?;
+        let response = client
+            .dispatch(request, Some(Duration::from_secs(30)))
+            .await
+            .map_err(|e| failed(e.to_string()))?
+            .buffer()
+            .await
+            .map_err(|e| failed(e.to_string()))?;
+        let body = String::from_utf8_lossy(&response.body);
+        if !response.status.is_success() {
+            return Err(failed(
+                xml_text(&body, "Message").unwrap_or(&body).to_string(),
+            ));
+        }
+        parse_credentials("AssumeRole", &body)
+    }
+}
+
+fn session_name() -> String {
+    std::env::var("AWS_ROLE_SESSION_NAME")
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| format!("ecr-tag-{}", Utc::now().timestamp()))
+}
+
+/// The credentials in an STS AssumeRole* response.
+fn parse_credentials(api: &str, xml: &str) -> Result<AwsCredentials, CredentialsError> {
+    let field = |name: &str| {
+        xml_text(xml, name)
+            .ok_or_else(|| CredentialsError::new(format!("{} returned no {}", api, name)))
+    };
+    let expires_at = DateTime::parse_from_rfc3339(field("Expiration")?)
+        .map_err(|e| CredentialsError::new(format!("invalid credential expiration: {}", e)))?
+        .with_timezone(&Utc);
+    Ok(AwsCredentials::new(
+        field("AccessKeyId")?,
+        field("SecretAccessKey")?,
+        Some(field("SessionToken")?.to_string()),
+        Some(expires_at),
+    ))
+}
+
+pub fn form_encode(pairs: &[(&str, &str)]) -> String {
+    let encode = |value: &str| {
+        value
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect::<String>()
+    };
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{}={}", encode(key), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// The text of the first `<name>` element; STS responses are flat enough
+/// that this beats pulling in an XML parser.
+fn xml_text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..end].trim())
+}
+
+/// True when AWS rejected the request because its session token has expired.
+pub fn is_expired<E>(err: &RusotoError<E>) -> bool {
+    match err {
+        RusotoError::Unknown(BufferedHttpResponse { body, .. }) => {
+            says_expired(&String::from_utf8_lossy(body))
+        }
+        _ => false,
+    }
+}
+
+/// The same for an error response's text, from a request rusoto didn't make.
+pub fn says_expired(text: &str) -> bool {
+    text.contains("ExpiredToken")
+        || text.contains("security token included in the request is expired")
+}
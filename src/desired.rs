@@ -0,0 +1,109 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::config;
+use crate::ecr::EcrApi;
+use crate::tags;
+
+/// The digest each tag should be on, by repository, as declared in a
+/// state file kept in git, e.g.
+///
+/// ```toml
+/// ["team/web"]
+/// prod = "sha256:…"
+/// ```
+pub struct DesiredState {
+    pub repositories: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl DesiredState {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let value =
+            config::parse_toml(&contents).with_context(|| format!("in {}", path.display()))?;
+        let repositories: BTreeMap<String, BTreeMap<String, String>> =
+            serde_json::from_value(value).with_context(|| {
+                format!(
+                    "invalid state file {}: expected a table of tag = \"digest\" per repository",
+                    path.display()
+                )
+            })?;
+        for (repository, tags) in &repositories {
+            for (tag, digest) in tags {
+                tags::validate(tag)
+                    .with_context(|| format!("in {} of {}", repository, path.display()))?;
+                if !digest.starts_with("sha256:") {
+                    bail!(
+                        "{}:{} in {} must be a digest, got `{}`",
+                        repository,
+                        tag,
+                        path.display(),
+                        digest
+                    );
+                }
+            }
+        }
+        Ok(Self { repositories })
+    }
+}
+
+/// What bringing a tag to its desired state takes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Create,
+    Move,
+    Unchanged,
+}
+
+impl Action {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Move => "move",
+            Self::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// A declared tag, next to where it is now.
+#[derive(Debug, Serialize)]
+pub struct Difference {
+    pub repository: String,
+    pub tag: String,
+    pub desired: String,
+    /// Unset when the tag doesn't exist yet.
+    pub current: Option<String>,
+    pub action: Action,
+}
+
+/// Every tag of `state`, compared with the registry's.
+pub async fn compare(ecr: &dyn EcrApi, state: &DesiredState) -> Result<Vec<Difference>> {
+    let mut differences = vec![];
+    for (repository, desired) in &state.repositories {
+        let images = ecr.images(repository).await?;
+        for (tag, digest) in desired {
+            let current = images
+                .iter()
+                .find(|image| image.tags.contains(tag))
+                .map(|image| image.digest.clone());
+            let action = match &current {
+                None => Action::Create,
+                Some(current) if current != digest => Action::Move,
+                Some(_) => Action::Unchanged,
+            };
+            differences.push(Difference {
+                repository: repository.clone(),
+                tag: tag.clone(),
+                desired: digest.clone(),
+                current,
+                action,
+            });
+        }
+    }
+    Ok(differences)
+}
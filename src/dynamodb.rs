@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::Region;
+use serde_json::Value;
+
+use crate::http;
+
+/// Calls the DynamoDB `action` (e.g. `PutItem`) with `body`.
+pub async fn call(
+    region: &Region,
+    credentials: &AwsCredentials,
+    action: &str,
+    body: Value,
+) -> Result<Value> {
+    let mut request = SignedRequest::new("POST", "dynamodb", region, "/");
+    request.add_header("x-amz-target", &format!("DynamoDB_20120810.{}", action));
+    request.set_content_type("application/x-amz-json-1.0".to_string());
+    request.set_payload(Some(body.to_string()));
+    let response = http::send_signed(request, credentials, Duration::from_secs(30)).await?;
+    serde_json::from_str(&response).with_context(|| format!("unexpected {} response", action))
+}
@@ -0,0 +1,670 @@
+#![cfg_attr(not(feature = "mock"), allow(dead_code))]
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::{
+    Destination, EcrApi, Failure, Image, ImagePage, ReplicationDestination, RepositorySettings,
+    TagStatus,
+};
+use crate::manifest;
+use crate::model::{Finding, ImageDetail, Respository};
+
+pub const MOCK_REGISTRY_ID: &str = "000000000000";
+
+/// An in-memory registry, loaded from a JSON fixture such as
+///
+/// ```json
+/// { "repositories": [{ "name": "app", "images": [
+///     { "manifest": "{...}", "tags": ["latest"], "pushed_at": "2023-01-01T00:00:00Z" }
+/// ] }] }
+/// ```
+///
+/// Blobs (config JSON, layers) can be given under a top level
+/// `"blobs": { "sha256:...": ... }`; JSON values are served as their text.
+///
+/// An image's `"findings"` are its counts by severity, and its
+/// `"vulnerabilities"` the individual findings behind them.
+///
+/// A repository given `"kms_key": "..."` reports KMS encryption with that key,
+/// and one given `"policy": {...}` has that repository policy.
+///
+/// A top level `"replication": [{ "region": "...", "registry_id": "..." }]`
+/// makes those replication destinations; replicas share the registry's
+/// contents, so replication is instant.
+///
+/// `"scan_type": "ENHANCED"` makes the registry scan with Inspector.
+///
+/// `"accounts": [{ "id": "...", "name": "...", "repositories": [...] }]` lists
+/// the Organization's accounts; assuming a role into one of them serves its
+/// repositories instead of the top level ones.
+///
+/// `--page-size` pages `images_page` the way ECR would.
+///
+/// Mutations only live as long as the process.
+pub struct MockEcr {
+    repositories: Arc<Mutex<BTreeMap<String, Vec<MockImage>>>>,
+    blobs: Arc<Mutex<BTreeMap<String, Vec<u8>>>>,
+    kms_keys: Arc<Mutex<BTreeMap<String, String>>>,
+    policies: Arc<Mutex<BTreeMap<String, String>>>,
+    replication: Vec<ReplicationDestination>,
+    registry_id: String,
+    scan_type: String,
+    page_size: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Fixture {
+    #[serde(default)]
+    repositories: Vec<MockRepository>,
+    #[serde(default)]
+    accounts: Vec<MockAccount>,
+    #[serde(default)]
+    blobs: BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    replication: Vec<ReplicationDestination>,
+    scan_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MockAccount {
+    id: String,
+    name: String,
+    #[serde(default)]
+    repositories: Vec<MockRepository>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MockRepository {
+    name: String,
+    #[serde(default)]
+    images: Vec<MockImage>,
+    kms_key: Option<String>,
+    policy: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MockImage {
+    pub manifest: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub pushed_at: DateTime<Utc>,
+    #[serde(default)]
+    pub size: i64,
+    /// Scan finding counts by severity; unscanned when absent.
+    #[serde(default)]
+    pub findings: Option<BTreeMap<String, i64>>,
+    /// The individual findings behind those counts.
+    #[serde(default)]
+    pub vulnerabilities: Vec<Finding>,
+}
+
+impl MockImage {
+    fn digest(&self) -> String {
+        format!(
+            "sha256:{}",
+            hex::encode(Sha256::digest(self.manifest.as_bytes()))
+        )
+    }
+}
+
+impl MockEcr {
+    pub fn new(
+        repositories: BTreeMap<String, Vec<MockImage>>,
+        blobs: BTreeMap<String, Vec<u8>>,
+        kms_keys: BTreeMap<String, String>,
+        policies: BTreeMap<String, String>,
+    ) -> Self {
+        Self {
+            repositories: Arc::new(Mutex::new(repositories)),
+            blobs: Arc::new(Mutex::new(blobs)),
+            kms_keys: Arc::new(Mutex::new(kms_keys)),
+            policies: Arc::new(Mutex::new(policies)),
+            replication: vec![],
+            registry_id: MOCK_REGISTRY_ID.to_string(),
+            scan_type: "BASIC".to_string(),
+            page_size: None,
+        }
+    }
+
+    pub fn with_page_size(mut self, page_size: Option<i64>) -> Self {
+        self.page_size = page_size.map(|size| size.max(1) as usize);
+        self
+    }
+
+    /// The fixture's registry, or that of `account` when the fixture lists it.
+    pub fn from_file(path: &Path, account: Option<&str>) -> Result<Self> {
+        Ok(Self::from_fixture(read_fixture(path)?, account))
+    }
+
+    /// An `app` repository with an image for each of `tags`, oldest first,
+    /// pushed a day apart from 2023-01-01.
+    #[cfg(test)]
+    pub fn app(tags: &[&[&str]]) -> Self {
+        let start: DateTime<Utc> = "2023-01-01T00:00:00Z".parse().unwrap();
+        let images = (1..)
+            .zip(tags)
+            .map(|(n, tags)| MockImage {
+                manifest: serde_json::json!({ "schemaVersion": 2, "n": n }).to_string(),
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                pushed_at: start + chrono::Duration::days(n - 1),
+                size: 0,
+                findings: None,
+                vulnerabilities: vec![],
+            })
+            .collect();
+        let repositories = BTreeMap::from([("app".to_string(), images)]);
+        Self::new(
+            repositories,
+            BTreeMap::new(),
+            BTreeMap::new(),
+            BTreeMap::new(),
+        )
+    }
+
+    /// The tags of each of `repository_name`'s images, oldest first.
+    #[cfg(test)]
+    pub fn tags(&self, repository_name: &str) -> Vec<Vec<String>> {
+        let mut images = self.repositories.lock().unwrap()[repository_name].clone();
+        images.sort_by_key(|image| image.pushed_at);
+        images.into_iter().map(|image| image.tags).collect()
+    }
+
+    fn from_fixture(mut fixture: Fixture, account: Option<&str>) -> Self {
+        let index = account.and_then(|id| fixture.accounts.iter().position(|a| a.id == id));
+        let registry_id = match index {
+            Some(index) => {
+                let account = fixture.accounts.swap_remove(index);
+                fixture.repositories = account.repositories;
+                account.id
+            }
+            None => MOCK_REGISTRY_ID.to_string(),
+        };
+        let kms_keys = fixture
+            .repositories
+            .iter()
+            .filter_map(|repository| Some((repository.name.clone(), repository.kms_key.clone()?)))
+            .collect();
+        let policies = fixture
+            .repositories
+            .iter()
+            .filter_map(|repository| {
+                let policy = repository.policy.as_ref()?;
+                Some((repository.name.clone(), policy.to_string()))
+            })
+            .collect();
+        let mut mock = Self::new(
+            fixture
+                .repositories
+                .into_iter()
+                .map(|repository| (repository.name, repository.images))
+                .collect(),
+            fixture
+                .blobs
+                .into_iter()
+                .map(|(digest, blob)| {
+                    let blob = match blob {
+                        serde_json::Value::String(text) => text,
+                        json => json.to_string(),
+                    };
+                    (digest, blob.into_bytes())
+                })
+                .collect(),
+            kms_keys,
+            policies,
+        );
+        mock.replication = fixture.replication;
+        mock.registry_id = registry_id;
+        mock.scan_type = fixture.scan_type.unwrap_or(mock.scan_type);
+        mock
+    }
+
+    /// The fixture's `"accounts"` as (id, name) pairs.
+    pub fn accounts(path: &Path) -> Result<Vec<(String, String)>> {
+        Ok(read_fixture(path)?
+            .accounts
+            .into_iter()
+            .map(|account| (account.id, account.name))
+            .collect())
+    }
+
+    fn uri(&self, repository_name: &str) -> String {
+        format!(
+            "{}.dkr.ecr.mock.amazonaws.com/{}",
+            self.registry_id, repository_name
+        )
+    }
+}
+
+fn read_fixture(path: &Path) -> Result<Fixture> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("invalid mock registry {}", path.display()))
+}
+
+#[async_trait]
+impl EcrApi for MockEcr {
+    async fn repositories(&self) -> Result<Vec<Respository>> {
+        let repositories = self.repositories.lock().unwrap();
+        let kms_keys = self.kms_keys.lock().unwrap();
+        Ok(repositories
+            .keys()
+            .map(|name| Respository {
+                name: name.clone(),
+                uri: self.uri(name),
+                encryption_type: match kms_keys.contains_key(name) {
+                    true => "KMS".to_string(),
+                    false => "AES256".to_string(),
+                },
+                kms_key: kms_keys.get(name).cloned(),
+            })
+            .collect())
+    }
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>> {
+        let repositories = self.repositories.lock().unwrap();
+        let images = repositories
+            .get(repository_name)
+            .ok_or_else(|| anyhow!("repository {} does not exist", repository_name))?;
+        Ok(images
+            .iter()
+            .map(|image| ImageDetail {
+                tags: image.tags.clone(),
+                digest: image.digest(),
+                created: image.pushed_at,
+                repository_name: repository_name.to_string(),
+                size: image.size,
+                scan_status: image.findings.as_ref().map(|_| "COMPLETE".to_string()),
+                severity_counts: image.findings.clone().unwrap_or_default(),
+                media_type: manifest::declared_media_type(&image.manifest),
+                labels: BTreeMap::new(),
+            })
+            .collect())
+    }
+
+    async fn resolve_tag(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        tag: &str,
+    ) -> Result<Option<String>> {
+        if registry_id.is_some_and(|id| id != self.registry_id) {
+            bail!(
+                "registry {} does not exist",
+                registry_id.unwrap_or_default()
+            );
+        }
+        let repositories = self.repositories.lock().unwrap();
+        Ok(repositories
+            .get(repository_name)
+            .and_then(|images| {
+                images
+                    .iter()
+                    .find(|image| image.tags.iter().any(|t| t == tag))
+            })
+            .map(MockImage::digest))
+    }
+
+    async fn images_page(
+        &self,
+        repository_name: &str,
+        status: TagStatus,
+        next_token: Option<&str>,
+    ) -> Result<ImagePage> {
+        let mut images = self.images(repository_name).await?;
+        images.retain(|image| status.covers(image));
+        let page_size = match self.page_size {
+            Some(page_size) => page_size,
+            None => {
+                return Ok(ImagePage {
+                    images,
+                    next_token: None,
+                })
+            }
+        };
+        // The token is the index of the page's first image.
+        let start = match next_token {
+            Some(token) => token
+                .parse()
+                .with_context(|| format!("invalid next token {}", token))?,
+            None => 0,
+        };
+        let images: Vec<ImageDetail> = images.drain(start.min(images.len())..).collect();
+        let next_token = (images.len() > page_size).then(|| (start + page_size).to_string());
+        Ok(ImagePage {
+            images: images.into_iter().take(page_size).collect(),
+            next_token,
+        })
+    }
+
+    async fn image(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Image> {
+        if registry_id.is_some_and(|id| id != self.registry_id) {
+            bail!(
+                "registry {} does not exist",
+                registry_id.unwrap_or_default()
+            );
+        }
+        let repositories = self.repositories.lock().unwrap();
+        let image = repositories
+            .get(repository_name)
+            .and_then(|images| images.iter().find(|image| image.digest() == digest))
+            .ok_or_else(|| anyhow!("image {} not found in {}", digest, repository_name))?;
+        Ok(Image {
+            repository_name: repository_name.to_string(),
+            digest: digest.to_string(),
+            manifest: image.manifest.clone(),
+            media_type: manifest::declared_media_type(&image.manifest),
+        })
+    }
+
+    async fn blob(&self, repository_name: &str, digest: &str) -> Result<Vec<u8>> {
+        if !self
+            .repositories
+            .lock()
+            .unwrap()
+            .contains_key(repository_name)
+        {
+            bail!("repository {} does not exist", repository_name);
+        }
+        self.blobs
+            .lock()
+            .unwrap()
+            .get(digest)
+            .cloned()
+            .ok_or_else(|| anyhow!("blob {} not found in {}", digest, repository_name))
+    }
+
+    async fn scan_findings(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<Vec<Finding>>> {
+        let repositories = self.repositories.lock().unwrap();
+        let image = repositories
+            .get(repository_name)
+            .ok_or_else(|| anyhow!("repository {} does not exist", repository_name))?
+            .iter()
+            .find(|image| image.digest() == digest)
+            .ok_or_else(|| anyhow!("image {} not found in {}", digest, repository_name))?;
+        Ok(image
+            .findings
+            .as_ref()
+            .map(|_| image.vulnerabilities.clone()))
+    }
+
+    async fn enhanced_scanning(&self) -> Result<bool> {
+        Ok(self.scan_type == "ENHANCED")
+    }
+
+    async fn registry_id(&self) -> Result<String> {
+        Ok(self.registry_id.clone())
+    }
+
+    async fn replication_destinations(&self) -> Result<Vec<ReplicationDestination>> {
+        Ok(self.replication.clone())
+    }
+
+    fn replica(&self, _region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        if registry_id != self.registry_id {
+            bail!("registry {} does not exist", registry_id);
+        }
+        Ok(Box::new(Self {
+            repositories: self.repositories.clone(),
+            blobs: self.blobs.clone(),
+            kms_keys: self.kms_keys.clone(),
+            policies: self.policies.clone(),
+            replication: vec![],
+            registry_id: self.registry_id.clone(),
+            scan_type: self.scan_type.clone(),
+            page_size: self.page_size,
+        }))
+    }
+
+    async fn repository_policy(&self, repository_name: &str) -> Result<Option<String>> {
+        if !self
+            .repositories
+            .lock()
+            .unwrap()
+            .contains_key(repository_name)
+        {
+            bail!("repository {} does not exist", repository_name);
+        }
+        Ok(self.policies.lock().unwrap().get(repository_name).cloned())
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()> {
+        if !self
+            .repositories
+            .lock()
+            .unwrap()
+            .contains_key(repository_name)
+        {
+            bail!("repository {} does not exist", repository_name);
+        }
+        serde_json::from_str::<serde_json::Value>(policy).context("invalid repository policy")?;
+        self.policies
+            .lock()
+            .unwrap()
+            .insert(repository_name.to_string(), policy.to_string());
+        Ok(())
+    }
+
+    async fn repository_exists(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+    ) -> Result<bool> {
+        if registry_id.is_some_and(|id| id != self.registry_id) {
+            bail!(
+                "registry {} does not exist",
+                registry_id.unwrap_or_default()
+            );
+        }
+        Ok(self
+            .repositories
+            .lock()
+            .unwrap()
+            .contains_key(repository_name))
+    }
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()> {
+        if !self.repository_exists(registry_id, repository_name).await? {
+            self.repositories
+                .lock()
+                .unwrap()
+                .insert(repository_name.to_string(), vec![]);
+            if let Some(key) = &settings.kms_key {
+                self.kms_keys
+                    .lock()
+                    .unwrap()
+                    .insert(repository_name.to_string(), key.clone());
+            }
+            return Ok(());
+        }
+        bail!("repository {} already exists", repository_name)
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        if let Some(registry_id) = &destination.registry_id {
+            if *registry_id != self.registry_id {
+                bail!("registry {} does not exist", registry_id);
+            }
+        }
+        let repository_name = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&image.repository_name);
+        // Like ECR, refuse a media type that contradicts the manifest itself.
+        if let (Some(given), Some(declared)) = (
+            &image.media_type,
+            manifest::declared_media_type(&image.manifest),
+        ) {
+            if *given != declared {
+                bail!(
+                    "{} rejected the manifest as {}: it declares {}",
+                    repository_name,
+                    given,
+                    declared
+                );
+            }
+        }
+
+        let mut repositories = self.repositories.lock().unwrap();
+        let images = repositories
+            .get_mut(repository_name)
+            .ok_or_else(|| anyhow!("repository {} does not exist", repository_name))?;
+
+        for other in images.iter_mut() {
+            other.tags.retain(|t| t != tag);
+        }
+        match images.iter_mut().find(|i| i.digest() == image.digest) {
+            Some(existing) => existing.tags.push(tag.to_string()),
+            None => images.push(MockImage {
+                manifest: image.manifest.clone(),
+                tags: vec![tag.to_string()],
+                pushed_at: Utc::now(),
+                size: 0,
+                findings: None,
+                vulnerabilities: vec![],
+            }),
+        }
+        Ok(())
+    }
+
+    async fn upload_blob(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        blob: &[u8],
+    ) -> Result<String> {
+        if registry_id.is_some_and(|registry_id| registry_id != self.registry_id) {
+            bail!(
+                "registry {} does not exist",
+                registry_id.unwrap_or_default()
+            );
+        }
+        if !self
+            .repositories
+            .lock()
+            .unwrap()
+            .contains_key(repository_name)
+        {
+            bail!("repository {} does not exist", repository_name);
+        }
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(blob)));
+        self.blobs
+            .lock()
+            .unwrap()
+            .insert(digest.clone(), blob.to_vec());
+        Ok(digest)
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        let mut repositories = self.repositories.lock().unwrap();
+        let images = repositories
+            .get_mut(repository_name)
+            .ok_or_else(|| anyhow!("repository {} does not exist", repository_name))?;
+        // Images left without a tag go too, as in ECR.
+        images.retain_mut(|image| {
+            let tagged = !image.tags.is_empty();
+            image.tags.retain(|tag| !tags.contains(tag));
+            !tagged || !image.tags.is_empty()
+        });
+        Ok(())
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        let mut repositories = self.repositories.lock().unwrap();
+        let images = repositories
+            .get_mut(repository_name)
+            .ok_or_else(|| anyhow!("repository {} does not exist", repository_name))?;
+        let failures = digests
+            .iter()
+            .filter(|digest| !images.iter().any(|image| &image.digest() == *digest))
+            .map(|digest| Failure {
+                digest: digest.clone(),
+                reason: "image not found".to_string(),
+            })
+            .collect();
+        images.retain(|image| !digests.contains(&image.digest()));
+        Ok(failures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ecr::{Destination, EcrApi, MockEcr};
+
+    fn registry() -> MockEcr {
+        MockEcr::app(&[&["latest", "v1"], &["v2"], &[]])
+    }
+
+    #[tokio::test]
+    async fn untag_drops_images_left_without_tags() {
+        let ecr = registry();
+        ecr.untag("app", &["latest".to_string(), "v2".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(ecr.tags("app"), vec![vec!["v1".to_string()], vec![]]);
+    }
+
+    #[tokio::test]
+    async fn put_image_moves_the_tag() {
+        let ecr = registry();
+        let images = ecr.images("app").await.unwrap();
+        let v2 = images.iter().find(|image| image.tags == ["v2"]).unwrap();
+        let image = ecr.image(None, "app", &v2.digest).await.unwrap();
+        ecr.put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap();
+        assert_eq!(ecr.tags("app")[0], vec!["v1"]);
+        assert_eq!(ecr.tags("app")[1], vec!["v2", "latest"]);
+    }
+
+    #[tokio::test]
+    async fn resolve_tag_looks_in_the_given_registry() {
+        let ecr = registry();
+        let own = ecr.registry_id().await.unwrap();
+        assert!(ecr
+            .resolve_tag(Some(&own), "app", "v2")
+            .await
+            .unwrap()
+            .is_some());
+        assert!(ecr
+            .resolve_tag(Some("111111111111"), "app", "v2")
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn delete_images_reports_unknown_digests() {
+        let ecr = registry();
+        let unknown = vec![format!("sha256:{}", "0".repeat(64))];
+        let failures = ecr.delete_images("app", &unknown).await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].digest, unknown[0]);
+        assert_eq!(ecr.tags("app").len(), 3);
+    }
+}
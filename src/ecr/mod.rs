@@ -0,0 +1,554 @@
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod rusoto;
+
+#[cfg(any(test, feature = "mock"))]
+pub use mock::MockEcr;
+#[cfg(feature = "mock")]
+pub use mock::MOCK_REGISTRY_ID;
+pub use rusoto::RusotoEcr;
+
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::model::{Finding, ImageDetail, Respository};
+
+/// An image manifest as returned by BatchGetImage.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub repository_name: String,
+    pub digest: String,
+    pub manifest: String,
+    /// Passed back on put so an OCI manifest isn't stored as a docker one.
+    pub media_type: Option<String>,
+}
+
+/// Where a manifest gets put; unset fields fall back to the source image's
+/// repository and registry.
+#[derive(Debug, Default)]
+pub struct Destination {
+    pub registry_id: Option<String>,
+    pub repository_name: Option<String>,
+}
+
+/// How a repository is set up when the tool creates it.
+#[derive(Debug, Default, Clone)]
+pub struct RepositorySettings {
+    pub immutable_tags: bool,
+    pub scan_on_push: bool,
+    /// KMS key ARN, id or alias; AES256 when unset.
+    pub kms_key: Option<String>,
+    /// Lifecycle policy JSON, put right after the repository is created.
+    pub lifecycle_policy: Option<String>,
+}
+
+/// Which images a listing has, by whether they're tagged; ECR's
+/// DescribeImages filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TagStatus {
+    #[default]
+    Any,
+    Tagged,
+    Untagged,
+}
+
+impl TagStatus {
+    pub const ALL: [Self; 3] = [Self::Any, Self::Tagged, Self::Untagged];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Any => "ANY",
+            Self::Tagged => "TAGGED",
+            Self::Untagged => "UNTAGGED",
+        }
+    }
+
+    pub fn covers(self, image: &ImageDetail) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Tagged => !image.tags.is_empty(),
+            Self::Untagged => image.tags.is_empty(),
+        }
+    }
+}
+
+/// Some of a repository's images, and where the next ones start.
+#[derive(Debug, Default)]
+pub struct ImagePage {
+    pub images: Vec<ImageDetail>,
+    /// Unset on the last page.
+    pub next_token: Option<String>,
+}
+
+/// A region (and registry) pushed images are replicated to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ReplicationDestination {
+    pub region: String,
+    pub registry_id: String,
+}
+
+/// How DescribeRepositories/DescribeImages results are paged.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Paging {
+    /// Results per request; ECR's default when unset.
+    pub page_size: Option<i64>,
+    /// Stop paging once this many results have been read.
+    pub max_results: Option<usize>,
+}
+
+/// The most image IDs ECR's batch APIs take in one call.
+pub const BATCH_SIZE: usize = 100;
+
+/// An image a batch operation couldn't process.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub digest: String,
+    pub reason: String,
+}
+
+/// The registry operations the tool needs, so the flows can run against
+/// something other than a live AWS account.
+#[async_trait]
+pub trait EcrApi: Send + Sync {
+    async fn repositories(&self) -> Result<Vec<Respository>>;
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>>;
+
+    /// The page of images with `status` starting at `next_token`, the first
+    /// when unset, for listings shown before the whole repository is read.
+    /// Reads every image as one page unless the registry pages.
+    async fn images_page(
+        &self,
+        repository_name: &str,
+        status: TagStatus,
+        _next_token: Option<&str>,
+    ) -> Result<ImagePage> {
+        let mut images = self.images(repository_name).await?;
+        images.retain(|image| status.covers(image));
+        Ok(ImagePage {
+            images,
+            next_token: None,
+        })
+    }
+
+    /// The manifest of `digest` in `registry_id`, the source registry when
+    /// unset.
+    async fn image(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Image>;
+
+    /// Fetches several manifests at once, in no particular order.
+    async fn images_by_digest(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Image>> {
+        let mut images = vec![];
+        for digest in digests {
+            images.push(self.image(None, repository_name, digest).await?);
+        }
+        Ok(images)
+    }
+
+    /// Returns the digest `tag` currently points at in `registry_id`, the
+    /// source registry when unset, or `None` when it isn't on any image or
+    /// the repository doesn't exist (yet).
+    async fn resolve_tag(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        tag: &str,
+    ) -> Result<Option<String>> {
+        if let Some(registry_id) = registry_id {
+            if registry_id != self.registry_id().await? {
+                bail!("can't look up tags in registry {}", registry_id);
+            }
+        }
+        Ok(self
+            .images(repository_name)
+            .await?
+            .into_iter()
+            .find(|image| image.tags.iter().any(|t| t == tag))
+            .map(|image| image.digest))
+    }
+
+    /// `resolve_tag`, failing when the tag isn't there.
+    async fn tag_digest(&self, repository_name: &str, tag: &str) -> Result<String> {
+        self.resolve_tag(None, repository_name, tag)
+            .await?
+            .ok_or_else(|| anyhow!("tag {} not found in {}", tag, repository_name))
+    }
+
+    /// Downloads a layer or config blob referenced by one of the repository's manifests.
+    async fn blob(&self, repository_name: &str, digest: &str) -> Result<Vec<u8>>;
+
+    /// Finding counts by severity, or `None` if the image hasn't been scanned.
+    async fn severity_counts(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<BTreeMap<String, i64>>> {
+        let image = self
+            .images(repository_name)
+            .await?
+            .into_iter()
+            .find(|image| image.digest == digest)
+            .ok_or_else(|| anyhow!("image {} not found in {}", digest, repository_name))?;
+        Ok(image.scan_status.map(|_| image.severity_counts))
+    }
+
+    /// The image's scan findings, or `None` if it hasn't been scanned.
+    async fn scan_findings(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<Vec<Finding>>>;
+
+    /// True when the registry scans with Amazon Inspector (enhanced scanning)
+    /// rather than basic scanning.
+    async fn enhanced_scanning(&self) -> Result<bool>;
+
+    /// The account id of the registry being read.
+    async fn registry_id(&self) -> Result<String>;
+
+    /// Where the registry replicates pushed images to, if anywhere.
+    async fn replication_destinations(&self) -> Result<Vec<ReplicationDestination>>;
+
+    /// The same API against another region's registry, e.g. a replication
+    /// destination.
+    fn replica(&self, region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>>;
+
+    /// The repository's policy document, or `None` if it has none.
+    async fn repository_policy(&self, repository_name: &str) -> Result<Option<String>>;
+
+    /// Replaces the repository's policy document.
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()>;
+
+    /// Whether the repository exists in `registry_id`, the source registry
+    /// when unset.
+    async fn repository_exists(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+    ) -> Result<bool>;
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()>;
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()>;
+
+    /// Uploads a blob (config or layer) to a repository, returning its
+    /// digest; uploading one that's already there is fine.
+    async fn upload_blob(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        blob: &[u8],
+    ) -> Result<String>;
+
+    /// Removes tags from the images they're on. As with ECR's
+    /// BatchDeleteImage by tag, an image whose last tag is removed is
+    /// deleted with it.
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()>;
+
+    /// Deletes images by digest, returning the ones that couldn't be deleted.
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>>;
+}
+
+/// An [`EcrApi`] layered over another, e.g. to run hooks around mutations:
+/// every call goes through to `inner()` unless the layer overrides it.
+#[async_trait]
+pub trait Decorator: Send + Sync {
+    fn inner(&self) -> &dyn EcrApi;
+
+    async fn repositories(&self) -> Result<Vec<Respository>> {
+        self.inner().repositories().await
+    }
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>> {
+        self.inner().images(repository_name).await
+    }
+
+    async fn images_page(
+        &self,
+        repository_name: &str,
+        status: TagStatus,
+        next_token: Option<&str>,
+    ) -> Result<ImagePage> {
+        self.inner()
+            .images_page(repository_name, status, next_token)
+            .await
+    }
+
+    async fn image(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Image> {
+        self.inner()
+            .image(registry_id, repository_name, digest)
+            .await
+    }
+
+    async fn images_by_digest(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Image>> {
+        self.inner()
+            .images_by_digest(repository_name, digests)
+            .await
+    }
+
+    async fn resolve_tag(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        tag: &str,
+    ) -> Result<Option<String>> {
+        self.inner()
+            .resolve_tag(registry_id, repository_name, tag)
+            .await
+    }
+
+    async fn blob(&self, repository_name: &str, digest: &str) -> Result<Vec<u8>> {
+        self.inner().blob(repository_name, digest).await
+    }
+
+    async fn severity_counts(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<BTreeMap<String, i64>>> {
+        self.inner().severity_counts(repository_name, digest).await
+    }
+
+    async fn scan_findings(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<Vec<Finding>>> {
+        self.inner().scan_findings(repository_name, digest).await
+    }
+
+    async fn enhanced_scanning(&self) -> Result<bool> {
+        self.inner().enhanced_scanning().await
+    }
+
+    async fn registry_id(&self) -> Result<String> {
+        self.inner().registry_id().await
+    }
+
+    async fn replication_destinations(&self) -> Result<Vec<ReplicationDestination>> {
+        self.inner().replication_destinations().await
+    }
+
+    fn replica(&self, region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        self.inner().replica(region, registry_id)
+    }
+
+    async fn repository_policy(&self, repository_name: &str) -> Result<Option<String>> {
+        self.inner().repository_policy(repository_name).await
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()> {
+        self.inner()
+            .set_repository_policy(repository_name, policy)
+            .await
+    }
+
+    async fn repository_exists(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+    ) -> Result<bool> {
+        self.inner()
+            .repository_exists(registry_id, repository_name)
+            .await
+    }
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()> {
+        self.inner()
+            .create_repository(registry_id, repository_name, settings)
+            .await
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        self.inner().put_image(image, tag, destination).await
+    }
+
+    async fn upload_blob(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        blob: &[u8],
+    ) -> Result<String> {
+        self.inner()
+            .upload_blob(registry_id, repository_name, blob)
+            .await
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        self.inner().untag(repository_name, tags).await
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        self.inner().delete_images(repository_name, digests).await
+    }
+}
+
+#[async_trait]
+impl<T: Decorator> EcrApi for T {
+    async fn repositories(&self) -> Result<Vec<Respository>> {
+        Decorator::repositories(self).await
+    }
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>> {
+        Decorator::images(self, repository_name).await
+    }
+
+    async fn images_page(
+        &self,
+        repository_name: &str,
+        status: TagStatus,
+        next_token: Option<&str>,
+    ) -> Result<ImagePage> {
+        Decorator::images_page(self, repository_name, status, next_token).await
+    }
+
+    async fn image(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Image> {
+        Decorator::image(self, registry_id, repository_name, digest).await
+    }
+
+    async fn images_by_digest(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Image>> {
+        Decorator::images_by_digest(self, repository_name, digests).await
+    }
+
+    async fn resolve_tag(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        tag: &str,
+    ) -> Result<Option<String>> {
+        Decorator::resolve_tag(self, registry_id, repository_name, tag).await
+    }
+
+    async fn blob(&self, repository_name: &str, digest: &str) -> Result<Vec<u8>> {
+        Decorator::blob(self, repository_name, digest).await
+    }
+
+    async fn severity_counts(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<BTreeMap<String, i64>>> {
+        Decorator::severity_counts(self, repository_name, digest).await
+    }
+
+    async fn scan_findings(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<Vec<Finding>>> {
+        Decorator::scan_findings(self, repository_name, digest).await
+    }
+
+    async fn enhanced_scanning(&self) -> Result<bool> {
+        Decorator::enhanced_scanning(self).await
+    }
+
+    async fn registry_id(&self) -> Result<String> {
+        Decorator::registry_id(self).await
+    }
+
+    async fn replication_destinations(&self) -> Result<Vec<ReplicationDestination>> {
+        Decorator::replication_destinations(self).await
+    }
+
+    fn replica(&self, region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        Decorator::replica(self, region, registry_id)
+    }
+
+    async fn repository_policy(&self, repository_name: &str) -> Result<Option<String>> {
+        Decorator::repository_policy(self, repository_name).await
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()> {
+        Decorator::set_repository_policy(self, repository_name, policy).await
+    }
+
+    async fn repository_exists(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+    ) -> Result<bool> {
+        Decorator::repository_exists(self, registry_id, repository_name).await
+    }
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()> {
+        Decorator::create_repository(self, registry_id, repository_name, settings).await
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        Decorator::put_image(self, image, tag, destination).await
+    }
+
+    async fn upload_blob(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        blob: &[u8],
+    ) -> Result<String> {
+        Decorator::upload_blob(self, registry_id, repository_name, blob).await
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        Decorator::untag(self, repository_name, tags).await
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        Decorator::delete_images(self, repository_name, digests).await
+    }
+}
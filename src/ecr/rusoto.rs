@@ -0,0 +1,854 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{HttpClient, Region, RusotoError};
+use rusoto_ecr::{
+    BatchDeleteImageRequest, BatchGetImageRequest, CompleteLayerUploadError,
+    CompleteLayerUploadRequest, CreateRepositoryRequest, DescribeImageScanFindingsError,
+    DescribeImageScanFindingsRequest, DescribeImagesError, DescribeImagesFilter,
+    DescribeImagesRequest, DescribeRepositoriesError, DescribeRepositoriesRequest, Ecr, EcrClient,
+    EncryptionConfiguration, GetDownloadUrlForLayerRequest, GetRepositoryPolicyError,
+    GetRepositoryPolicyRequest, ImageIdentifier, ImageScanningConfiguration,
+    InitiateLayerUploadRequest, PutImageError, PutImageRequest, PutLifecyclePolicyRequest,
+    SetRepositoryPolicyRequest, UploadLayerPartRequest,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::{
+    Destination, EcrApi, Failure, Image, ImagePage, Paging, ReplicationDestination,
+    RepositorySettings, TagStatus, BATCH_SIZE,
+};
+use crate::credentials::{self, Credentials};
+use crate::http;
+use crate::manifest::MEDIA_TYPES;
+use crate::model::{Finding, ImageDetail, Respository};
+use crate::throttle::RateLimiter;
+
+pub struct RusotoEcr {
+    client: EcrClient,
+    region: Region,
+    credentials: Credentials,
+    paging: Paging,
+    /// The account whose registry is read; the caller's own when unset.
+    registry_id: Option<String>,
+    /// `--max-rps`, when set.
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RusotoEcr {
+    pub fn new(
+        client: EcrClient,
+        region: Region,
+        credentials: Credentials,
+        paging: Paging,
+        registry_id: Option<String>,
+    ) -> Self {
+        Self {
+            client,
+            region,
+            credentials,
+            paging,
+            registry_id,
+            limiter: None,
+        }
+    }
+
+    pub fn with_rate_limit(mut self, limiter: Option<Arc<RateLimiter>>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /// Waits for `--max-rps` to allow another request.
+    async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.wait().await;
+        }
+    }
+
+    /// Runs `request`, retrying it once with refreshed credentials when
+    /// `expired` says the session token ran out mid-run.
+    async fn retry_expired<T, E, F, Fut>(
+        &self,
+        request: F,
+        expired: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.throttle().await;
+        match request().await {
+            Err(err) if expired(&err) => {
+                eprintln!("note: AWS credentials expired, refreshing them");
+                self.credentials.invalidate();
+                self.throttle().await;
+                request().await
+            }
+            result => result,
+        }
+    }
+
+    /// Runs a rusoto request, see `retry_expired`.
+    async fn call<T, E, F, Fut>(&self, request: F) -> Result<T, RusotoError<E>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, RusotoError<E>>>,
+    {
+        self.retry_expired(request, credentials::is_expired).await
+    }
+
+    /// Signs and sends the request `build` makes, for the calls rusoto
+    /// doesn't model.
+    async fn send_signed(&self, build: impl Fn() -> SignedRequest) -> Result<String> {
+        let send = || async {
+            let credentials = self
+                .credentials
+                .credentials()
+                .await
+                .context("failed to resolve credentials")?;
+            http::send_signed(build(), &credentials, Duration::from_secs(30)).await
+        };
+        self.retry_expired(send, |err: &anyhow::Error| {
+            credentials::says_expired(&err.to_string())
+        })
+        .await
+    }
+
+    /// Calls an ECR action, with a JSON body, that this rusoto release doesn't
+    /// model.
+    async fn call_raw(&self, action: &str, body: Value) -> Result<Value> {
+        let build = || {
+            let mut request = SignedRequest::new("POST", "ecr", &self.region, "/");
+            request.add_header(
+                "x-amz-target",
+                &format!("AmazonEC2ContainerRegistry_V20150921.{}", action),
+            );
+            request.set_content_type("application/x-amz-json-1.1".to_string());
+            request.set_payload(Some(body.to_string()));
+            request
+        };
+        let response = self
+            .send_signed(build)
+            .await
+            .with_context(|| format!("{} failed", action))?;
+        serde_json::from_str(&response).with_context(|| format!("unexpected {} response", action))
+    }
+
+    /// An image's active findings from Inspector2 ListFindings; `None` until
+    /// Inspector has scanned it.
+    async fn inspector_findings(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<Vec<Finding>>> {
+        let equals = |value: &str| json!([{ "comparison": "EQUALS", "value": value }]);
+        let mut findings = vec![];
+        let mut next_token: Option<String> = None;
+        loop {
+            let mut body = json!({
+                "filterCriteria": {
+                    "ecrImageRepositoryName": equals(repository_name),
+                    "ecrImageHash": equals(digest),
+                    "findingStatus": equals("ACTIVE"),
+                },
+                "maxResults": 100,
+            });
+            if let Some(token) = &next_token {
+                body["nextToken"] = json!(token);
+            }
+            let build = || {
+                let mut request =
+                    SignedRequest::new("POST", "inspector2", &self.region, "/findings/list");
+                request.set_content_type("application/json".to_string());
+                request.set_payload(Some(body.to_string()));
+                request
+            };
+            let response = self
+                .send_signed(build)
+                .await
+                .context("failed to list Inspector findings")?;
+            let page: Value = serde_json::from_str(&response)
+                .context("unexpected Inspector ListFindings response")?;
+            if let Some(list) = page["findings"].as_array() {
+                findings.extend(list.iter().map(Finding::from_enhanced));
+            }
+            match page["nextToken"].as_str() {
+                Some(token) => next_token = Some(token.to_string()),
+                None => break,
+            }
+        }
+        if findings.is_empty() {
+            // Inspector lists nothing both for clean images and unscanned
+            // ones; ECR's scan status tells them apart.
+            let scanned = self
+                .call_raw(
+                    "DescribeImageScanFindings",
+                    json!({
+                        "repositoryName": repository_name,
+                        "imageId": { "imageDigest": digest },
+                        "maxResults": 1,
+                    }),
+                )
+                .await;
+            match scanned {
+                Err(err) if err.to_string().contains("ScanNotFoundException") => return Ok(None),
+                Err(err) => return Err(err),
+                Ok(_) => {}
+            }
+        }
+        Ok(Some(findings))
+    }
+
+    /// True once `--max-results` has been reached, saying so on stderr.
+    fn truncated(&self, read: usize, what: &str) -> bool {
+        match self.paging.max_results {
+            Some(max) if read >= max => {
+                eprintln!("note: stopped after {} {} (--max-results)", max, what);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[async_trait]
+impl EcrApi for RusotoEcr {
+    async fn repositories(&self) -> Result<Vec<Respository>> {
+        let mut repositories: Vec<Respository> = vec![];
+        let mut next_token = None;
+        loop {
+            let request = DescribeRepositoriesRequest {
+                registry_id: self.registry_id.clone(),
+                max_results: self.paging.page_size,
+                next_token,
+                ..Default::default()
+            };
+            let response = self
+                .call(|| self.client.describe_repositories(request.clone()))
+                .await?;
+            repositories.extend(
+                response
+                    .repositories
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|r| r.into()),
+            );
+            next_token = response.next_token;
+            if next_token.is_none() || self.truncated(repositories.len(), "repositories") {
+                break;
+            }
+        }
+        if let Some(max) = self.paging.max_results {
+            repositories.truncate(max);
+        }
+        Ok(repositories)
+    }
+
+    async fn images(&self, repository_name: &str) -> Result<Vec<ImageDetail>> {
+        let mut images: Vec<ImageDetail> = vec![];
+        let mut next_token = None;
+        loop {
+            let request = DescribeImagesRequest {
+                registry_id: self.registry_id.clone(),
+                repository_name: repository_name.to_string(),
+                max_results: self.paging.page_size,
+                next_token,
+                ..Default::default()
+            };
+            let response = self
+                .call(|| self.client.describe_images(request.clone()))
+                .await?;
+            images.extend(
+                response
+                    .image_details
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|r| r.into()),
+            );
+            next_token = response.next_token;
+            if next_token.is_none() || self.truncated(images.len(), "images") {
+                break;
+            }
+        }
+        if let Some(max) = self.paging.max_results {
+            images.truncate(max);
+        }
+        Ok(images)
+    }
+
+    async fn images_page(
+        &self,
+        repository_name: &str,
+        status: TagStatus,
+        next_token: Option<&str>,
+    ) -> Result<ImagePage> {
+        let request = DescribeImagesRequest {
+            registry_id: self.registry_id.clone(),
+            repository_name: repository_name.to_string(),
+            max_results: self.paging.page_size,
+            next_token: next_token.map(str::to_string),
+            filter: Some(DescribeImagesFilter {
+                tag_status: Some(status.name().to_string()),
+            }),
+            ..Default::default()
+        };
+        let response = self
+            .call(|| self.client.describe_images(request.clone()))
+            .await?;
+        Ok(ImagePage {
+            images: response
+                .image_details
+                .unwrap_or_default()
+                .iter()
+                .map(|r| r.into())
+                .collect(),
+            next_token: response.next_token,
+        })
+    }
+
+    async fn image(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Image> {
+        let request = BatchGetImageRequest {
+            registry_id: registry_id
+                .map(str::to_string)
+                .or_else(|| self.registry_id.clone()),
+            repository_name: repository_name.to_string(),
+            image_ids: vec![ImageIdentifier {
+                image_digest: Some(digest.to_string()),
+                image_tag: None,
+            }],
+            accepted_media_types: Some(MEDIA_TYPES.iter().map(|t| t.to_string()).collect()),
+        };
+        let response = self
+            .call(|| self.client.batch_get_image(request.clone()))
+            .await?;
+        let image = response
+            .images
+            .unwrap_or_default()
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("image {} not found in {}", digest, repository_name))?;
+        Ok(Image {
+            repository_name: repository_name.to_string(),
+            digest: digest.to_string(),
+            manifest: image
+                .image_manifest
+                .ok_or_else(|| anyhow!("{}@{} has no manifest", repository_name, digest))?,
+            media_type: image.image_manifest_media_type,
+        })
+    }
+
+    async fn images_by_digest(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Image>> {
+        let mut images = vec![];
+        for chunk in digests.chunks(BATCH_SIZE) {
+            let request = BatchGetImageRequest {
+                registry_id: self.registry_id.clone(),
+                repository_name: repository_name.to_string(),
+                image_ids: chunk
+                    .iter()
+                    .map(|digest| ImageIdentifier {
+                        image_digest: Some(digest.clone()),
+                        image_tag: None,
+                    })
+                    .collect(),
+                accepted_media_types: Some(MEDIA_TYPES.iter().map(|t| t.to_string()).collect()),
+            };
+            let response = self
+                .call(|| self.client.batch_get_image(request.clone()))
+                .await?;
+            for image in response.images.unwrap_or_default() {
+                let digest = match image.image_id.and_then(|id| id.image_digest) {
+                    Some(digest) => digest,
+                    None => continue,
+                };
+                images.push(Image {
+                    repository_name: repository_name.to_string(),
+                    digest,
+                    manifest: image.image_manifest.unwrap_or_default(),
+                    media_type: image.image_manifest_media_type,
+                });
+            }
+        }
+        Ok(images)
+    }
+
+    async fn resolve_tag(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        tag: &str,
+    ) -> Result<Option<String>> {
+        let request = DescribeImagesRequest {
+            registry_id: registry_id
+                .map(str::to_string)
+                .or_else(|| self.registry_id.clone()),
+            repository_name: repository_name.to_string(),
+            image_ids: Some(vec![ImageIdentifier {
+                image_digest: None,
+                image_tag: Some(tag.to_string()),
+            }]),
+            ..Default::default()
+        };
+        match self
+            .call(|| self.client.describe_images(request.clone()))
+            .await
+        {
+            Ok(response) => Ok(response
+                .image_details
+                .unwrap_or_default()
+                .into_iter()
+                .find_map(|image| image.image_digest)),
+            Err(RusotoError::Service(
+                DescribeImagesError::ImageNotFound(_) | DescribeImagesError::RepositoryNotFound(_),
+            )) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn blob(&self, repository_name: &str, digest: &str) -> Result<Vec<u8>> {
+        let request = GetDownloadUrlForLayerRequest {
+            registry_id: self.registry_id.clone(),
+            repository_name: repository_name.to_string(),
+            layer_digest: digest.to_string(),
+        };
+        let response = self
+            .call(|| self.client.get_download_url_for_layer(request.clone()))
+            .await?;
+        let url = response
+            .download_url
+            .ok_or_else(|| anyhow!("no download url for {}", digest))?;
+        // Layers can be large, so allow much longer than an API call would take.
+        http::download(&url, Duration::from_secs(600)).await
+    }
+
+    async fn severity_counts(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<BTreeMap<String, i64>>> {
+        let request = DescribeImageScanFindingsRequest {
+            registry_id: self.registry_id.clone(),
+            repository_name: repository_name.to_string(),
+            image_id: ImageIdentifier {
+                image_digest: Some(digest.to_string()),
+                image_tag: None,
+            },
+            max_results: Some(1),
+            ..Default::default()
+        };
+        match self
+            .call(|| self.client.describe_image_scan_findings(request.clone()))
+            .await
+        {
+            Ok(response) => Ok(Some(
+                response
+                    .image_scan_findings
+                    .and_then(|findings| findings.finding_severity_counts)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+            )),
+            Err(RusotoError::Service(DescribeImageScanFindingsError::ScanNotFound(_))) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn scan_findings(
+        &self,
+        repository_name: &str,
+        digest: &str,
+    ) -> Result<Option<Vec<Finding>>> {
+        // Inspector has the fix and exploit details ECR leaves out; registries
+        // it can't be asked about fall back to ECR's copy of the findings.
+        if self.registry_id.is_none() && matches!(self.enhanced_scanning().await, Ok(true)) {
+            return self.inspector_findings(repository_name, digest).await;
+        }
+        // Raw, as this rusoto release predates enhanced (Inspector) findings.
+        let mut findings = vec![];
+        let mut next_token: Option<String> = None;
+        loop {
+            let mut request = json!({
+                "repositoryName": repository_name,
+                "imageId": { "imageDigest": digest },
+                "maxResults": 1000,
+            });
+            if let Some(registry_id) = &self.registry_id {
+                request["registryId"] = json!(registry_id);
+            }
+            if let Some(token) = &next_token {
+                request["nextToken"] = json!(token);
+            }
+            let response = match self.call_raw("DescribeImageScanFindings", request).await {
+                Ok(response) => response,
+                Err(err) if err.to_string().contains("ScanNotFoundException") => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let scan = &response["imageScanFindings"];
+            let list = |key: &str| scan[key].as_array().cloned().unwrap_or_default();
+            findings.extend(list("findings").iter().map(Finding::from_basic));
+            findings.extend(list("enhancedFindings").iter().map(Finding::from_enhanced));
+            match response["nextToken"].as_str() {
+                Some(token) => next_token = Some(token.to_string()),
+                None => return Ok(Some(findings)),
+            }
+        }
+    }
+
+    async fn enhanced_scanning(&self) -> Result<bool> {
+        if self.registry_id.is_some() {
+            bail!("can't tell how another account's registry is scanned");
+        }
+        let response = self
+            .call_raw("GetRegistryScanningConfiguration", json!({}))
+            .await?;
+        Ok(response["scanningConfiguration"]["scanType"] == "ENHANCED")
+    }
+
+    async fn registry_id(&self) -> Result<String> {
+        if let Some(registry_id) = &self.registry_id {
+            return Ok(registry_id.clone());
+        }
+        let response = self.call(|| self.client.describe_registry()).await?;
+        response
+            .registry_id
+            .ok_or_else(|| anyhow!("DescribeRegistry returned no registry id"))
+    }
+
+    async fn replication_destinations(&self) -> Result<Vec<ReplicationDestination>> {
+        // DescribeRegistry only describes the caller's own registry.
+        if self.registry_id.is_some() {
+            return Ok(vec![]);
+        }
+        let response = self.call(|| self.client.describe_registry()).await?;
+        Ok(response
+            .replication_configuration
+            .map(|config| config.rules)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|rule| rule.destinations)
+            .map(|destination| ReplicationDestination {
+                region: destination.region,
+                registry_id: destination.registry_id,
+            })
+            .collect())
+    }
+
+    fn replica(&self, region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        let region =
+            Region::from_str(region).with_context(|| format!("unknown region `{}`", region))?;
+        let client = EcrClient::new_with(
+            HttpClient::new().context("failed to create the HTTP client")?,
+            self.credentials.clone(),
+            region.clone(),
+        );
+        Ok(Box::new(
+            Self::new(
+                client,
+                region,
+                self.credentials.clone(),
+                self.paging,
+                Some(registry_id.to_string()),
+            )
+            .with_rate_limit(self.limiter.clone()),
+        ))
+    }
+
+    async fn repository_policy(&self, repository_name: &str) -> Result<Option<String>> {
+        let request = GetRepositoryPolicyRequest {
+            registry_id: self.registry_id.clone(),
+            repository_name: repository_name.to_string(),
+        };
+        match self
+            .call(|| self.client.get_repository_policy(request.clone()))
+            .await
+        {
+            Ok(response) => Ok(response.policy_text),
+            Err(RusotoError::Service(GetRepositoryPolicyError::RepositoryPolicyNotFound(_))) => {
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()> {
+        let request = SetRepositoryPolicyRequest {
+            registry_id: self.registry_id.clone(),
+            repository_name: repository_name.to_string(),
+            policy_text: policy.to_string(),
+            force: None,
+        };
+        self.call(|| self.client.set_repository_policy(request.clone()))
+            .await?;
+        Ok(())
+    }
+
+    async fn repository_exists(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+    ) -> Result<bool> {
+        let request = DescribeRepositoriesRequest {
+            registry_id: registry_id
+                .map(str::to_string)
+                .or_else(|| self.registry_id.clone()),
+            repository_names: Some(vec![repository_name.to_string()]),
+            ..Default::default()
+        };
+        match self
+            .call(|| self.client.describe_repositories(request.clone()))
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(RusotoError::Service(DescribeRepositoriesError::RepositoryNotFound(_))) => {
+                Ok(false)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()> {
+        // CreateRepository has no registry id; it always creates in the caller's account.
+        if let Some(registry_id) = registry_id.or(self.registry_id.as_deref()) {
+            bail!(
+                "can't create {} in registry {}: repositories can only be created in your own account",
+                repository_name,
+                registry_id
+            );
+        }
+        let request = CreateRepositoryRequest {
+            repository_name: repository_name.to_string(),
+            image_tag_mutability: Some(
+                match settings.immutable_tags {
+                    true => "IMMUTABLE",
+                    false => "MUTABLE",
+                }
+                .to_string(),
+            ),
+            image_scanning_configuration: Some(ImageScanningConfiguration {
+                scan_on_push: Some(settings.scan_on_push),
+            }),
+            encryption_configuration: Some(match &settings.kms_key {
+                Some(key) => EncryptionConfiguration {
+                    encryption_type: "KMS".to_string(),
+                    kms_key: Some(key.clone()),
+                },
+                None => EncryptionConfiguration {
+                    encryption_type: "AES256".to_string(),
+                    kms_key: None,
+                },
+            }),
+            tags: None,
+        };
+        self.call(|| self.client.create_repository(request.clone()))
+            .await?;
+
+        if let Some(policy) = &settings.lifecycle_policy {
+            let request = PutLifecyclePolicyRequest {
+                registry_id: None,
+                repository_name: repository_name.to_string(),
+                lifecycle_policy_text: policy.clone(),
+            };
+            self.call(|| self.client.put_lifecycle_policy(request.clone()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repository_name = destination
+            .repository_name
+            .clone()
+            .unwrap_or_else(|| image.repository_name.clone());
+        let request = PutImageRequest {
+            registry_id: destination
+                .registry_id
+                .clone()
+                .or_else(|| self.registry_id.clone()),
+            repository_name: repository_name.clone(),
+            image_tag: Some(tag.to_string()),
+            image_manifest: image.manifest.clone(),
+            image_manifest_media_type: image.media_type.clone(),
+            ..Default::default()
+        };
+        match self.call(|| self.client.put_image(request.clone())).await {
+            Ok(_) => Ok(()),
+            Err(RusotoError::Service(PutImageError::LayersNotFound(msg))) => bail!(
+                "{} is missing layers referenced by the manifest ({}); copy the image's blobs there first",
+                repository_name,
+                msg
+            ),
+            Err(RusotoError::Service(PutImageError::InvalidParameter(msg)))
+                if image.media_type.is_some() =>
+            {
+                bail!(
+                    "{} rejected the manifest as {} ({}); it was not retried without the media type",
+                    repository_name,
+                    image.media_type.as_deref().unwrap_or_default(),
+                    msg
+                )
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn upload_blob(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        blob: &[u8],
+    ) -> Result<String> {
+        let digest = format!("sha256:{}", hex::encode(Sha256::digest(blob)));
+        let registry_id = registry_id
+            .map(str::to_string)
+            .or_else(|| self.registry_id.clone());
+        let upload = self
+            .call(|| {
+                self.client
+                    .initiate_layer_upload(InitiateLayerUploadRequest {
+                        registry_id: registry_id.clone(),
+                        repository_name: repository_name.to_string(),
+                    })
+            })
+            .await?;
+        let upload_id = upload
+            .upload_id
+            .ok_or_else(|| anyhow!("no upload id for {}", repository_name))?;
+        let part_size = upload.part_size.unwrap_or(blob.len() as i64).max(1) as usize;
+        for (i, part) in blob.chunks(part_size).enumerate() {
+            let first = (i * part_size) as i64;
+            let request = UploadLayerPartRequest {
+                registry_id: registry_id.clone(),
+                repository_name: repository_name.to_string(),
+                upload_id: upload_id.clone(),
+                part_first_byte: first,
+                part_last_byte: first + part.len() as i64 - 1,
+                layer_part_blob: part.to_vec().into(),
+            };
+            self.call(|| self.client.upload_layer_part(request.clone()))
+                .await?;
+        }
+        let request = CompleteLayerUploadRequest {
+            registry_id,
+            repository_name: repository_name.to_string(),
+            upload_id,
+            layer_digests: vec![digest.clone()],
+        };
+        match self
+            .call(|| self.client.complete_layer_upload(request.clone()))
+            .await
+        {
+            Ok(_) | Err(RusotoError::Service(CompleteLayerUploadError::LayerAlreadyExists(_))) => {
+                Ok(digest)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        for chunk in tags.chunks(BATCH_SIZE) {
+            let request = BatchDeleteImageRequest {
+                registry_id: self.registry_id.clone(),
+                repository_name: repository_name.to_string(),
+                image_ids: chunk
+                    .iter()
+                    .map(|tag| ImageIdentifier {
+                        image_digest: None,
+                        image_tag: Some(tag.clone()),
+                    })
+                    .collect(),
+            };
+            let response = self
+                .call(|| self.client.batch_delete_image(request.clone()))
+                .await?;
+            if let Some(failure) = response.failures.unwrap_or_default().into_iter().next() {
+                bail!(
+                    "failed to remove {} from {}: {}",
+                    failure
+                        .image_id
+                        .and_then(|id| id.image_tag)
+                        .unwrap_or_default(),
+                    repository_name,
+                    failure
+                        .failure_reason
+                        .or(failure.failure_code)
+                        .unwrap_or_default()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        let mut failures = vec![];
+        for chunk in digests.chunks(BATCH_SIZE) {
+            let request = BatchDeleteImageRequest {
+                registry_id: self.registry_id.clone(),
+                repository_name: repository_name.to_string(),
+                image_ids: chunk
+                    .iter()
+                    .map(|digest| ImageIdentifier {
+                        image_digest: Some(digest.clone()),
+                        image_tag: None,
+                    })
+                    .collect(),
+            };
+            // A chunk that fails outright fails its images, not the whole run.
+            let response = match self
+                .call(|| self.client.batch_delete_image(request.clone()))
+                .await
+            {
+                Ok(response) => response,
+                Err(err) => {
+                    let reason = err.to_string();
+                    failures.extend(chunk.iter().map(|digest| Failure {
+                        digest: digest.clone(),
+                        reason: reason.clone(),
+                    }));
+                    continue;
+                }
+            };
+            failures.extend(
+                response
+                    .failures
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|failure| Failure {
+                        digest: failure
+                            .image_id
+                            .and_then(|id| id.image_digest)
+                            .unwrap_or_default(),
+                        reason: failure
+                            .failure_reason
+                            .or(failure.failure_code)
+                            .unwrap_or_default(),
+                    }),
+            );
+        }
+        Ok(failures)
+    }
+}
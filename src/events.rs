@@ -0,0 +1,166 @@
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use crate::args::Args;
+use crate::client;
+use crate::config::Config;
+use crate::ecr::{Destination, EcrApi};
+use crate::tags;
+
+/// An image pushed to ECR, as told by its `ECR Image Action` event.
+#[derive(Debug)]
+pub struct Push {
+    /// The account and region of the registry pushed to.
+    pub account: String,
+    pub region: String,
+    pub repository: String,
+    pub digest: String,
+    /// Unset for pushes by digest.
+    pub tag: Option<String>,
+}
+
+impl Push {
+    /// The successful push `event` is about, or `None` for any other event.
+    pub fn parse(event: &Value) -> Option<Self> {
+        if event["source"] != "aws.ecr" || event["detail-type"] != "ECR Image Action" {
+            return None;
+        }
+        let detail = &event["detail"];
+        if detail["action-type"] != "PUSH" || detail["result"] != "SUCCESS" {
+            return None;
+        }
+        Some(Self {
+            account: event["account"].as_str()?.to_string(),
+            region: event["region"].as_str()?.to_string(),
+            repository: detail["repository-name"].as_str()?.to_string(),
+            digest: detail["image-digest"].as_str()?.to_string(),
+            tag: detail["image-tag"]
+                .as_str()
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string),
+        })
+    }
+}
+
+/// Puts the `[[repo."<name>".auto_tag]]` tags covering `push`, each checked
+/// by its gate first; returns the tags put.
+///
+/// Pushes of the auto tags themselves are ignored, as putting them sends a
+/// push event of its own. Pushes to any registry but `ecr`'s are refused:
+/// its repository of the same name holds other images, if any.
+pub async fn auto_tag(
+    ecr: &dyn EcrApi,
+    args: &Args,
+    config: &Config,
+    push: &Push,
+) -> Result<Vec<String>> {
+    let rules = match config.repo.get(&push.repository) {
+        Some(repo) => &repo.auto_tag,
+        None => return Ok(vec![]),
+    };
+    if let Some(tag) = &push.tag {
+        if rules.iter().any(|rule| &rule.tag == tag) {
+            return Ok(vec![]);
+        }
+    }
+    let registry_id = ecr.registry_id().await?;
+    let region = client::region(args)?;
+    if push.account != registry_id || push.region != region.name() {
+        bail!(
+            "the push was to {} in {}, not this registry ({} in {})",
+            push.account,
+            push.region,
+            registry_id,
+            region.name()
+        );
+    }
+    let gate = args.gate(config);
+    let mut tagged = vec![];
+    for rule in rules {
+        let covered = rule.pushed.is_empty()
+            || push.tag.as_deref().is_some_and(|tag| {
+                rule.pushed
+                    .iter()
+                    .any(|pattern| tags::matches(pattern, tag))
+            });
+        if !covered {
+            continue;
+        }
+        let put = async {
+            tags::validate(&rule.tag)?;
+            gate.check(ecr, &push.repository, &push.digest, &rule.tag)
+                .await?;
+            gate.verify_signature(ecr, &push.repository, &push.digest)
+                .await?;
+            let image = ecr.image(None, &push.repository, &push.digest).await?;
+            ecr.put_image(&image, &rule.tag, &Destination::default())
+                .await
+        };
+        put.await.with_context(|| {
+            format!(
+                "failed to tag {}@{} {}",
+                push.repository, push.digest, rule.tag
+            )
+        })?;
+        tagged.push(rule.tag.clone());
+    }
+    Ok(tagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Parser;
+    use crate::config::parse_toml;
+    use crate::ecr::MockEcr;
+
+    fn config() -> Config {
+        let toml = "[[repo.app.auto_tag]]\npushed = [\"v*\"]\ntag = \"edge\"\n";
+        serde_json::from_value(parse_toml(toml).unwrap()).unwrap()
+    }
+
+    /// The event of `digest` being pushed as `app:v1` to `account`.
+    fn push_event(account: &str, digest: &str) -> Value {
+        serde_json::json!({
+            "source": "aws.ecr",
+            "detail-type": "ECR Image Action",
+            "account": account,
+            "region": "us-east-1",
+            "detail": {
+                "action-type": "PUSH",
+                "result": "SUCCESS",
+                "repository-name": "app",
+                "image-digest": digest,
+                "image-tag": "v1",
+            },
+        })
+    }
+
+    async fn auto_tag_push(ecr: &MockEcr, account: &str) -> Result<Vec<String>> {
+        let args = Args::parse_from(Parser::from_args(&["listen", "--region", "us-east-1"]))?;
+        let event = push_event(account, &ecr.tag_digest("app", "v1").await?);
+        let push = Push::parse(&event).unwrap();
+        auto_tag(ecr, &args, &config(), &push).await
+    }
+
+    #[tokio::test]
+    async fn tags_pushes_the_rules_cover() {
+        let ecr = MockEcr::app(&[&["v1"]]);
+
+        let account = ecr.registry_id().await.unwrap();
+        let tagged = auto_tag_push(&ecr, &account).await.unwrap();
+
+        assert_eq!(tagged, ["edge"]);
+        assert_eq!(ecr.tags("app"), vec![vec!["v1", "edge"]]);
+    }
+
+    #[tokio::test]
+    async fn refuses_pushes_to_another_registry() {
+        let ecr = MockEcr::app(&[&["v1"]]);
+
+        let err = auto_tag_push(&ecr, "111111111111").await.unwrap_err();
+
+        assert!(err.to_string().contains("not this registry"));
+        assert_eq!(ecr.tags("app"), vec![vec!["v1"]]);
+    }
+}
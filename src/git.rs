@@ -0,0 +1,38 @@
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+/// The label images record the commit they were built from in.
+pub const REVISION_LABEL: &str = "org.opencontainers.image.revision";
+
+/// How far back `--git` looks for commits images were built from.
+const HISTORY: usize = 200;
+
+/// The current directory's recent commits, HEAD first.
+pub fn commits() -> Result<Vec<String>> {
+    let output = Command::new("git")
+        .args(["log", "--format=%H", &format!("-n{}", HISTORY)])
+        .output()
+        .context("failed to run git")?;
+    if !output.status.success() {
+        bail!(
+            "--git needs a git repository with commits: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// How many commits before HEAD `revision` is, accepting abbreviated ids.
+pub fn position(commits: &[String], revision: &str) -> Option<usize> {
+    let revision = revision.trim().to_lowercase();
+    if revision.len() < 7 {
+        return None;
+    }
+    commits
+        .iter()
+        .position(|commit| commit.starts_with(&revision))
+}
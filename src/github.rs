@@ -0,0 +1,69 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::ecr::{Destination, EcrApi};
+use crate::sign;
+
+/// A tag the run put, as later workflow steps get it.
+#[derive(Serialize)]
+pub struct Tagged {
+    pub repository: String,
+    pub tag: String,
+    pub digest: String,
+    /// `registry/repository:tag`
+    pub image: String,
+}
+
+impl Tagged {
+    /// `tag` on `digest`, pushed to `repository` and put in `destination`.
+    pub async fn new(
+        ecr: &dyn EcrApi,
+        repository: &str,
+        destination: &Destination,
+        tag: &str,
+        digest: &str,
+    ) -> Result<Self> {
+        let uri = sign::repository_uri(ecr, repository, destination).await?;
+        Ok(Self {
+            repository: destination
+                .repository_name
+                .clone()
+                .unwrap_or_else(|| repository.to_string()),
+            tag: tag.to_string(),
+            digest: digest.to_string(),
+            image: format!("{}:{}", uri, tag),
+        })
+    }
+}
+
+/// Appends the outputs of `--github-output` to the `$GITHUB_OUTPUT` file at
+/// `path`: `images`, a JSON array of every tag put, and when there was just
+/// one, its `repository`, `tag`, `digest`, `image` and `image-digest`.
+pub fn write(path: &Path, tagged: &[Tagged]) -> Result<()> {
+    let mut outputs = vec![("images".to_string(), serde_json::to_string(tagged)?)];
+    if let [tagged] = tagged {
+        let uri = tagged.image.rsplit_once(':').map_or("", |(uri, _)| uri);
+        outputs.extend([
+            ("repository".to_string(), tagged.repository.clone()),
+            ("tag".to_string(), tagged.tag.clone()),
+            ("digest".to_string(), tagged.digest.clone()),
+            ("image".to_string(), tagged.image.clone()),
+            (
+                "image-digest".to_string(),
+                format!("{}@{}", uri, tagged.digest),
+            ),
+        ]);
+    }
+    let write = || -> std::io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        for (name, value) in &outputs {
+            writeln!(file, "{}={}", name, value)?;
+        }
+        Ok(())
+    };
+    write().with_context(|| format!("failed to write the outputs to {}", path.display()))
+}
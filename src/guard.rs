@@ -0,0 +1,94 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use crate::ecr::{Decorator, Destination, EcrApi, Failure, Image, RepositorySettings};
+
+/// Refuses every mutating call made through `inner`, so the tool can be
+/// handed to auditors or pointed at production accounts just for browsing.
+pub struct ReadOnlyEcr {
+    inner: Box<dyn EcrApi>,
+}
+
+impl ReadOnlyEcr {
+    pub fn wrap(inner: Box<dyn EcrApi>, read_only: bool) -> Box<dyn EcrApi> {
+        match read_only {
+            true => Box::new(Self { inner }),
+            false => inner,
+        }
+    }
+}
+
+#[async_trait]
+impl Decorator for ReadOnlyEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    fn replica(&self, region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        Ok(Self::wrap(self.inner.replica(region, registry_id)?, true))
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, _policy: &str) -> Result<()> {
+        Err(anyhow!(
+            "read-only mode: refusing to change the policy of {}",
+            repository_name
+        ))
+    }
+
+    async fn create_repository(
+        &self,
+        _registry_id: Option<&str>,
+        repository_name: &str,
+        _settings: &RepositorySettings,
+    ) -> Result<()> {
+        Err(anyhow!(
+            "read-only mode: refusing to create repository {}",
+            repository_name
+        ))
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repository_name = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&image.repository_name);
+        Err(anyhow!(
+            "read-only mode: refusing to tag {} in {} as {}",
+            image.digest,
+            repository_name,
+            tag
+        ))
+    }
+
+    async fn upload_blob(
+        &self,
+        _registry_id: Option<&str>,
+        repository_name: &str,
+        _blob: &[u8],
+    ) -> Result<String> {
+        Err(anyhow!(
+            "read-only mode: refusing to upload to {}",
+            repository_name
+        ))
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        Err(anyhow!(
+            "read-only mode: refusing to remove {} from {}",
+            tags.join(", "),
+            repository_name
+        ))
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        Err(anyhow!(
+            "read-only mode: refusing to delete {} image(s) from {}",
+            digests.len(),
+            repository_name
+        ))
+    }
+}
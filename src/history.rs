@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusoto_core::credential::{AwsCredentials, ProvideAwsCredentials};
+use rusoto_core::Region;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::args::Args;
+use crate::client;
+use crate::config::History;
+use crate::dynamodb;
+use crate::metrics::{Change, Mutations};
+
+/// A tag moving onto a digest, as kept in the history.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Move {
+    pub moved_at: DateTime<Utc>,
+    pub repository: String,
+    pub tag: String,
+    pub digest: String,
+    /// The digest the tag was on before, if it existed.
+    pub previous: Option<String>,
+    pub user: Option<String>,
+    /// The registry the tag was put in.
+    pub registry_id: Option<String>,
+    /// The run's `--annotate` key/values.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+/// Where moves are appended as JSON lines without a `[history] table`.
+pub fn path() -> Option<PathBuf> {
+    dirs_next::data_dir().map(|dir| dir.join("ecr-tag").join("history.jsonl"))
+}
+
+/// Adds the tags the run moved to the history: the `[history] table` when
+/// there is one, so the whole team shares it, or else the local file.
+pub async fn record(args: &Args, config: &History, mutations: &Mutations) -> Result<()> {
+    let moves: Vec<Move> = mutations
+        .list()
+        .into_iter()
+        .filter(|mutation| mutation.error.is_none())
+        .filter_map(|mutation| match mutation.change {
+            Change::Tag {
+                registry_id,
+                repository,
+                tag,
+                digest,
+                previous,
+                ..
+            } => Some(Move {
+                moved_at: mutation.timestamp,
+                repository,
+                tag,
+                digest,
+                previous,
+                user: crate::audit::user(),
+                registry_id,
+                annotations: args.annotations.clone(),
+            }),
+            _ => None,
+        })
+        .collect();
+    if moves.is_empty() {
+        return Ok(());
+    }
+    match &config.table {
+        Some(table) => {
+            let dynamodb = DynamoDb::new(args, table).await?;
+            for entry in &moves {
+                dynamodb.put(entry).await.with_context(|| {
+                    format!(
+                        "failed to add {}:{} to {}",
+                        entry.repository, entry.tag, table
+                    )
+                })?;
+            }
+            Ok(())
+        }
+        None => append(&moves),
+    }
+}
+
+/// The moves of `repository` in `registry_id` (of `tag` only, when given),
+/// newest first.
+pub async fn list(
+    args: &Args,
+    config: &History,
+    registry_id: &str,
+    repository: &str,
+    tag: Option<&str>,
+) -> Result<Vec<Move>> {
+    let moves = match &config.table {
+        Some(table) => DynamoDb::new(args, table).await?.query(repository).await?,
+        None => read()?
+            .into_iter()
+            .filter(|entry| entry.repository == repository)
+            .collect(),
+    };
+    Ok(select(moves, registry_id, tag))
+}
+
+/// `moves` of `registry_id` (and `tag`), newest first. The table is keyed by
+/// repository alone, so it may well hold other accounts' moves too.
+fn select(mut moves: Vec<Move>, registry_id: &str, tag: Option<&str>) -> Vec<Move> {
+    moves.retain(|entry| {
+        entry.registry_id.as_deref() == Some(registry_id)
+            && (tag.is_none() || tag == Some(entry.tag.as_str()))
+    });
+    moves.sort_by_key(|entry| std::cmp::Reverse(entry.moved_at));
+    moves
+}
+
+fn append(moves: &[Move]) -> Result<()> {
+    let path = path().context("no data directory for the tag history")?;
+    let write = || -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        for entry in moves {
+            writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        }
+        Ok(())
+    };
+    write().with_context(|| format!("failed to add to the tag history in {}", path.display()))
+}
+
+fn read() -> Result<Vec<Move>> {
+    let path = match path() {
+        Some(path) if path.exists() => path,
+        _ => return Ok(vec![]),
+    };
+    let contents = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            serde_json::from_str(line)
+                .with_context(|| format!("invalid entry in {}", path.display()))
+        })
+        .collect()
+}
+
+/// The `[history] table`, keyed by `repository` and the `moved` sort key
+/// (both strings), which is the move's time and tag.
+struct DynamoDb<'a> {
+    table: &'a str,
+    region: Region,
+    credentials: AwsCredentials,
+}
+
+impl<'a> DynamoDb<'a> {
+    async fn new(args: &Args, table: &'a str) -> Result<Self> {
+        let region = client::region(args)?;
+        let credentials = client::credentials(args, region.name(), None)?
+            .credentials()
+            .await
+            .context("failed to resolve credentials")?;
+        Ok(Self {
+            table,
+            region,
+            credentials,
+        })
+    }
+
+    async fn call(&self, action: &str, body: Value) -> Result<Value> {
+        dynamodb::call(&self.region, &self.credentials, action, body).await
+    }
+
+    async fn put(&self, entry: &Move) -> Result<()> {
+        let mut item = Map::new();
+        let moved_at = entry.moved_at.to_rfc3339_opts(SecondsFormat::Nanos, true);
+        item.insert(
+            "moved".to_string(),
+            json!({ "S": format!("{}#{}", moved_at, entry.tag) }),
+        );
+        for (name, value) in [
+            ("repository", Some(&entry.repository)),
+            ("tag", Some(&entry.tag)),
+            ("digest", Some(&entry.digest)),
+            ("previous", entry.previous.as_ref()),
+            ("user", entry.user.as_ref()),
+            ("registry_id", entry.registry_id.as_ref()),
+        ] {
+            if let Some(value) = value {
+                item.insert(name.to_string(), json!({ "S": value }));
+            }
+        }
+        item.insert("moved_at".to_string(), json!({ "S": moved_at }));
+        if !entry.annotations.is_empty() {
+            let annotations: Map<String, Value> = entry
+                .annotations
+                .iter()
+                .map(|(key, value)| (key.clone(), json!({ "S": value })))
+                .collect();
+            item.insert("annotations".to_string(), json!({ "M": annotations }));
+        }
+        self.call("PutItem", json!({ "TableName": self.table, "Item": item }))
+            .await?;
+        Ok(())
+    }
+
+    async fn query(&self, repository: &str) -> Result<Vec<Move>> {
+        let mut moves = vec![];
+        let mut start_key = None;
+        loop {
+            let mut body = json!({
+                "TableName": self.table,
+                "KeyConditionExpression": "repository = :repository",
+                "ExpressionAttributeValues": { ":repository": { "S": repository } },
+                "ScanIndexForward": false,
+            });
+            if let Some(key) = start_key.take() {
+                body["ExclusiveStartKey"] = key;
+            }
+            let page = self
+                .call("Query", body)
+                .await
+                .with_context(|| format!("failed to query {}", self.table))?;
+            for item in page["Items"].as_array().into_iter().flatten() {
+                moves.push(
+                    parse_item(item)
+                        .with_context(|| format!("unexpected item in {}: {}", self.table, item))?,
+                );
+            }
+            match page.get("LastEvaluatedKey") {
+                Some(key) if !key.is_null() => start_key = Some(key.clone()),
+                _ => return Ok(moves),
+            }
+        }
+    }
+}
+
+fn parse_item(item: &Value) -> Result<Move> {
+    let field = |name: &str| item[name]["S"].as_str().map(str::to_string);
+    let required = |name: &str| field(name).ok_or_else(|| anyhow!("no {}", name));
+    Ok(Move {
+        moved_at: DateTime::parse_from_rfc3339(&required("moved_at")?)?.with_timezone(&Utc),
+        repository: required("repository")?,
+        tag: required("tag")?,
+        digest: required("digest")?,
+        previous: field("previous"),
+        user: field("user"),
+        registry_id: field("registry_id"),
+        annotations: item["annotations"]["M"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(key, value)| Some((key.clone(), value["S"].as_str()?.to_string())))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(minute: u32, registry_id: &str, tag: &str, digest: &str) -> Move {
+        Move {
+            moved_at: format!("2023-01-01T12:{:02}:00Z", minute).parse().unwrap(),
+            repository: "app".to_string(),
+            tag: tag.to_string(),
+            digest: digest.to_string(),
+            previous: None,
+            user: None,
+            registry_id: Some(registry_id.to_string()),
+            annotations: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn select_keeps_the_registrys_moves_of_the_tag_newest_first() {
+        let moves = vec![
+            moved(0, "111111111111", "latest", "sha256:a"),
+            moved(1, "222222222222", "latest", "sha256:b"),
+            moved(2, "111111111111", "stable", "sha256:c"),
+            moved(3, "111111111111", "latest", "sha256:d"),
+        ];
+
+        let digests = |moves: Vec<Move>| -> Vec<String> {
+            moves.into_iter().map(|entry| entry.digest).collect()
+        };
+        assert_eq!(
+            digests(select(moves.clone(), "111111111111", Some("latest"))),
+            ["sha256:d", "sha256:a"]
+        );
+        assert_eq!(
+            digests(select(moves, "111111111111", None)),
+            ["sha256:d", "sha256:c", "sha256:a"]
+        );
+    }
+
+    #[test]
+    fn parse_item_reads_back_a_put_move() {
+        let item = json!({
+            "repository": { "S": "app" },
+            "moved": { "S": "2023-01-01T12:00:00.000000000Z#latest" },
+            "moved_at": { "S": "2023-01-01T12:00:00.000000000Z" },
+            "tag": { "S": "latest" },
+            "digest": { "S": "sha256:b" },
+            "previous": { "S": "sha256:a" },
+            "registry_id": { "S": "111111111111" },
+            "annotations": { "M": { "ticket": { "S": "OPS-1" } } },
+        });
+
+        let entry = parse_item(&item).unwrap();
+        assert_eq!(
+            entry.moved_at,
+            "2023-01-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(entry.previous.as_deref(), Some("sha256:a"));
+        assert_eq!(entry.user, None);
+        assert_eq!(entry.registry_id.as_deref(), Some("111111111111"));
+        assert_eq!(entry.annotations["ticket"], "OPS-1");
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use crate::config::Hooks;
+use crate::ecr::{Decorator, Destination, EcrApi, Failure, Image};
+use crate::ui::Ui;
+
+/// Runs the configured `[hooks]` around every mutation made through `inner`.
+///
+/// A failing pre hook aborts the mutation; a failing post hook is reported
+/// but doesn't undo anything. Untagging has no hooks: the only untags are
+/// `--backup` pruning its own backup tags, which like the backup tags
+/// themselves are made beneath the hooks.
+pub struct HookedEcr {
+    inner: Box<dyn EcrApi>,
+    hooks: Hooks,
+    ui: Ui,
+    profile: Option<String>,
+}
+
+impl HookedEcr {
+    /// The hooks run with `AWS_PROFILE` set to `profile`, when given;
+    /// failing post hooks are warned about through `ui`.
+    pub fn wrap(
+        inner: Box<dyn EcrApi>,
+        hooks: &Hooks,
+        ui: &Ui,
+        profile: Option<&str>,
+    ) -> Box<dyn EcrApi> {
+        if hooks.is_empty() {
+            return inner;
+        }
+        Box::new(Self {
+            inner,
+            hooks: hooks.clone(),
+            ui: ui.clone(),
+            profile: profile.map(str::to_string),
+        })
+    }
+}
+
+#[async_trait]
+impl Decorator for HookedEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repo = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&image.repository_name);
+        let vars = [
+            ("repo", repo),
+            ("source_repo", image.repository_name.as_str()),
+            ("registry", destination.registry_id.as_deref().unwrap_or("")),
+            ("tag", tag),
+            ("digest", image.digest.as_str()),
+        ];
+
+        if let Some(hook) = &self.hooks.pre_tag {
+            run("pre_tag", hook, &vars, self.profile.as_deref()).await?;
+        }
+        self.inner.put_image(image, tag, destination).await?;
+        if let Some(hook) = &self.hooks.post_tag {
+            if let Err(err) = run("post_tag", hook, &vars, self.profile.as_deref()).await {
+                self.ui.warning(&format!("{:#}", err));
+            }
+        }
+        Ok(())
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        let count = digests.len().to_string();
+        let joined = digests.join(" ");
+        let vars = [
+            ("repo", repository_name),
+            ("count", count.as_str()),
+            ("digests", joined.as_str()),
+        ];
+
+        if let Some(hook) = &self.hooks.pre_delete {
+            run("pre_delete", hook, &vars, self.profile.as_deref()).await?;
+        }
+        let failures = self.inner.delete_images(repository_name, digests).await?;
+        if let Some(hook) = &self.hooks.post_delete {
+            if let Err(err) = run("post_delete", hook, &vars, self.profile.as_deref()).await {
+                self.ui.warning(&format!("{:#}", err));
+            }
+        }
+        Ok(failures)
+    }
+}
+
+/// Expands `{name}` placeholders (shell-quoted) and runs the hook with `sh -c`.
+/// The same values are exported as `ECR_TAG_<NAME>` environment variables,
+/// and `profile`, when given, as `AWS_PROFILE`.
+pub async fn run(
+    name: &str,
+    template: &str,
+    vars: &[(&str, &str)],
+    profile: Option<&str>,
+) -> Result<()> {
+    let mut command_line = template.to_string();
+    for (key, value) in vars {
+        command_line = command_line.replace(&format!("{{{}}}", key), &shell_quote(value));
+    }
+
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(&command_line);
+    for (key, value) in vars {
+        command.env(format!("ECR_TAG_{}", key.to_uppercase()), value);
+    }
+    if let Some(profile) = profile {
+        command.env("AWS_PROFILE", profile);
+    }
+    let status = command
+        .status()
+        .await
+        .with_context(|| format!("failed to run {} hook", name))?;
+    if !status.success() {
+        bail!("{} hook `{}` exited with {}", name, template, status);
+    }
+    Ok(())
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::ecr::MockEcr;
+
+    fn hooks(pre_tag: &str, post_tag: &str) -> Hooks {
+        Hooks {
+            pre_tag: Some(pre_tag.to_string()),
+            post_tag: Some(post_tag.to_string()),
+            ..Hooks::default()
+        }
+    }
+
+    fn ui() -> Ui {
+        Ui::from_config(&Config::default(), true).unwrap()
+    }
+
+    fn out_file(name: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("ecr-tag-hook-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn run_quotes_placeholders_and_exports_them() {
+        let path = out_file("vars");
+        let template = format!(
+            "printf '%s|%s|%s' {{tag}} \"$ECR_TAG_TAG\" \"$AWS_PROFILE\" > {}",
+            path.display()
+        );
+
+        run("post_tag", &template, &[("tag", "it's; true")], Some("ci"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "it's; true|it's; true|ci"
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn a_failing_pre_hook_stops_the_tag() {
+        let path = out_file("post");
+        let ecr = HookedEcr::wrap(
+            Box::new(MockEcr::app(&[&["v1"], &[]])),
+            &hooks("exit 3", &format!("touch {}", path.display())),
+            &ui(),
+            None,
+        );
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+
+        let err = ecr
+            .put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("pre_tag hook `exit 3` exited"));
+        assert_eq!(ecr.resolve_tag(None, "app", "latest").await.unwrap(), None);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn a_failing_post_hook_keeps_the_tag() {
+        let ecr = HookedEcr::wrap(
+            Box::new(MockEcr::app(&[&["v1"], &[]])),
+            &hooks("true", "exit 1"),
+            &ui(),
+            None,
+        );
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+
+        ecr.put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            ecr.resolve_tag(None, "app", "latest").await.unwrap(),
+            Some(digest)
+        );
+    }
+}
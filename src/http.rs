@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use hyper::{Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use rusoto_core::credential::AwsCredentials;
+use rusoto_core::request::{DispatchSignedRequest, HttpClient as RusotoHttpClient};
+use rusoto_core::signature::SignedRequest;
+
+/// Sends `body` to `url`, failing on non-2xx responses or after `timeout`.
+pub async fn send(
+    method: Method,
+    url: &str,
+    content_type: &str,
+    body: String,
+    timeout: Duration,
+) -> Result<String> {
+    let client = Client::builder().build::<_, Body>(HttpsConnector::new());
+    let request = Request::builder()
+        .method(method)
+        .uri(url)
+        .header("content-type", content_type)
+        .header("user-agent", concat!("ecr-tag/", env!("CARGO_PKG_VERSION")))
+        .body(Body::from(body))
+        .with_context(|| format!("invalid url {}", url))?;
+
+    let response = tokio::time::timeout(timeout, client.request(request))
+        .await
+        .with_context(|| format!("timed out calling {}", url))??;
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    let text = String::from_utf8_lossy(&bytes).to_string();
+    if !status.is_success() {
+        bail!("{} responded {}: {}", url, status, text.trim());
+    }
+    Ok(text)
+}
+
+/// Fetches `url` (e.g. a presigned layer URL) as raw bytes.
+pub async fn download(url: &str, timeout: Duration) -> Result<Vec<u8>> {
+    let client = Client::builder().build::<_, Body>(HttpsConnector::new());
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri(url)
+        .header("user-agent", concat!("ecr-tag/", env!("CARGO_PKG_VERSION")))
+        .body(Body::empty())
+        .with_context(|| format!("invalid url {}", url))?;
+
+    let response = tokio::time::timeout(timeout, client.request(request))
+        .await
+        .with_context(|| "timed out downloading blob".to_string())??;
+    let status = response.status();
+    let bytes = hyper::body::to_bytes(response.into_body()).await?;
+    if !status.is_success() {
+        bail!(
+            "download responded {}: {}",
+            status,
+            String::from_utf8_lossy(&bytes).trim()
+        );
+    }
+    Ok(bytes.to_vec())
+}
+
+/// Signs `request` with `credentials` and sends it, failing on non-2xx
+/// responses; for the AWS APIs rusoto has no crate for here.
+pub async fn send_signed(
+    mut request: SignedRequest,
+    credentials: &AwsCredentials,
+    timeout: Duration,
+) -> Result<String> {
+    let host = request.hostname();
+    request.sign(credentials);
+    let response = RusotoHttpClient::new()
+        .context("failed to create the HTTP client")?
+        .dispatch(request, Some(timeout))
+        .await
+        .with_context(|| format!("failed to call {}", host))?
+        .buffer()
+        .await
+        .with_context(|| format!("failed to read the response from {}", host))?;
+    let text = String::from_utf8_lossy(&response.body).to_string();
+    if !response.status.is_success() {
+        bail!("{} responded {}: {}", host, response.status, text.trim());
+    }
+    Ok(text)
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::model::ImageDetail;
+
+/// The file formats `ecr-tag export` writes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// One JSON object per line, which Athena reads as-is.
+    Json,
+    Csv,
+    /// An `image_digests` variable mapping repository → tag → digest.
+    Tfvars,
+    /// The same map in a Terraform `locals` block.
+    Locals,
+    /// The same map as a CloudFormation `ImageDigests` mapping.
+    CloudFormation,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "json" | "jsonl" => Self::Json,
+            "csv" => Self::Csv,
+            "tfvars" => Self::Tfvars,
+            "locals" | "terraform" => Self::Locals,
+            "cloudformation" | "cfn" => Self::CloudFormation,
+            "parquet" => bail!(
+                "parquet isn't supported; export json and convert it with an Athena CTAS query"
+            ),
+            _ => bail!(
+                "unknown export format `{}` (expected json, csv, tfvars, locals or cloudformation)",
+                s
+            ),
+        })
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "jsonl",
+            Self::Csv => "csv",
+            Self::Tfvars => "tfvars",
+            Self::Locals => "tf",
+            Self::CloudFormation => "json",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/x-ndjson",
+            Self::Csv => "text/csv",
+            Self::Tfvars | Self::Locals => "text/plain",
+            Self::CloudFormation => "application/json",
+        }
+    }
+}
+
+/// One image of the inventory, flat so each field is a column.
+#[derive(Serialize)]
+pub struct Row {
+    pub registry_id: String,
+    pub region: String,
+    pub repository: String,
+    pub digest: String,
+    pub tags: Vec<String>,
+    pub pushed_at: DateTime<Utc>,
+    pub size_bytes: i64,
+    pub scan_status: Option<String>,
+    pub critical: i64,
+    pub high: i64,
+    pub medium: i64,
+    pub low: i64,
+    pub exported_at: DateTime<Utc>,
+}
+
+impl Row {
+    pub fn new(
+        registry_id: &str,
+        region: &str,
+        image: &ImageDetail,
+        exported_at: DateTime<Utc>,
+    ) -> Self {
+        let count = |severity: &str| image.severity_counts.get(severity).copied().unwrap_or(0);
+        Self {
+            registry_id: registry_id.to_string(),
+            region: region.to_string(),
+            repository: image.repository_name.clone(),
+            digest: image.digest.clone(),
+            tags: image.tags.clone(),
+            pushed_at: image.created,
+            size_bytes: image.size,
+            scan_status: image.scan_status.clone(),
+            critical: count("CRITICAL"),
+            high: count("HIGH"),
+            medium: count("MEDIUM"),
+            low: count("LOW"),
+            exported_at,
+        }
+    }
+}
+
+/// `rows` as a whole file in `format`; CSV joins tags with `;`, and the
+/// IaC formats leave untagged images out.
+pub fn render(format: ExportFormat, rows: &[Row]) -> Result<String> {
+    let mut out = String::new();
+    match format {
+        ExportFormat::Json => {
+            for row in rows {
+                out.push_str(&serde_json::to_string(row)?);
+                out.push('\n');
+            }
+        }
+        ExportFormat::Csv => {
+            out.push_str("registry_id,region,repository,digest,tags,pushed_at,size_bytes,scan_status,critical,high,medium,low,exported_at\n");
+            for row in rows {
+                let fields = [
+                    row.registry_id.clone(),
+                    row.region.clone(),
+                    row.repository.clone(),
+                    row.digest.clone(),
+                    row.tags.join(";"),
+                    row.pushed_at.to_rfc3339(),
+                    row.size_bytes.to_string(),
+                    row.scan_status.clone().unwrap_or_default(),
+                    row.critical.to_string(),
+                    row.high.to_string(),
+                    row.medium.to_string(),
+                    row.low.to_string(),
+                    row.exported_at.to_rfc3339(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                out.push_str(&fields.join(","));
+                out.push('\n');
+            }
+        }
+        ExportFormat::Tfvars => out.push_str(&format!("image_digests = {}\n", hcl_map(rows, 0))),
+        ExportFormat::Locals => out.push_str(&format!(
+            "locals {{\n  image_digests = {}\n}}\n",
+            hcl_map(rows, 1)
+        )),
+        ExportFormat::CloudFormation => {
+            // Mapping keys only take alphanumerics, `-` and `.`.
+            let key = |name: &str| -> String {
+                name.chars()
+                    .map(
+                        |c| match c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                            true => c,
+                            false => '-',
+                        },
+                    )
+                    .collect()
+            };
+            let mut mapping = BTreeMap::new();
+            for (repository, tags) in digests(rows) {
+                let tags: BTreeMap<String, &str> = tags
+                    .into_iter()
+                    .map(|(tag, digest)| (key(tag), digest))
+                    .collect();
+                mapping.insert(key(repository), tags);
+            }
+            let template = json!({ "Mappings": { "ImageDigests": mapping } });
+            out.push_str(&serde_json::to_string_pretty(&template)?);
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// The digest each tag is on, by repository.
+fn digests(rows: &[Row]) -> BTreeMap<&str, BTreeMap<&str, &str>> {
+    let mut digests: BTreeMap<&str, BTreeMap<&str, &str>> = BTreeMap::new();
+    for row in rows {
+        for tag in &row.tags {
+            digests
+                .entry(&row.repository)
+                .or_default()
+                .insert(tag, &row.digest);
+        }
+    }
+    digests
+}
+
+/// The digests as an HCL map of maps, its nested lines indented `depth` levels.
+fn hcl_map(rows: &[Row], depth: usize) -> String {
+    let indent = |level: usize| "  ".repeat(depth + level);
+    // JSON string literals are valid HCL ones.
+    let quote = |value: &str| serde_json::to_string(value).unwrap();
+    let mut out = String::from("{\n");
+    for (repository, tags) in digests(rows) {
+        out.push_str(&format!("{}{} = {{\n", indent(1), quote(repository)));
+        for (tag, digest) in tags {
+            out.push_str(&format!(
+                "{}{} = {}\n",
+                indent(2),
+                quote(tag),
+                quote(digest)
+            ));
+        }
+        out.push_str(&format!("{}}}\n", indent(1)));
+    }
+    out.push_str(&format!("{}}}", indent(0)));
+    out
+}
+
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
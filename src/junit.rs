@@ -0,0 +1,112 @@
+use std::sync::Mutex;
+
+/// One check, rendered as a JUnit test case.
+#[derive(Debug)]
+pub struct Case {
+    /// What was checked, e.g. `app:prod`; the test class CI groups cases by.
+    pub subject: String,
+    pub name: String,
+    pub failure: Option<String>,
+    /// Shown with the case, e.g. why a failed check was forced past.
+    pub output: Option<String>,
+}
+
+impl Case {
+    pub fn new(subject: impl Into<String>, name: impl Into<String>) -> Self {
+        Self {
+            subject: subject.into(),
+            name: name.into(),
+            failure: None,
+            output: None,
+        }
+    }
+
+    pub fn failed(mut self, message: impl Into<String>) -> Self {
+        self.failure = Some(message.into());
+        self
+    }
+
+    pub fn with_output(mut self, output: impl Into<String>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+}
+
+/// The outcome of every gate check of a run, for `--output junit`.
+#[derive(Debug, Default)]
+pub struct Checks {
+    cases: Mutex<Vec<Case>>,
+}
+
+impl Checks {
+    pub fn record(&self, case: Case) {
+        self.cases.lock().unwrap().push(case);
+    }
+
+    pub fn render(&self, suite: &str) -> String {
+        render(suite, &self.cases.lock().unwrap())
+    }
+}
+
+/// A JUnit XML report with `cases` as its single test suite.
+pub fn render(suite: &str, cases: &[Case]) -> String {
+    let failures = cases.iter().filter(|case| case.failure.is_some()).count();
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<testsuites name=\"ecr-tag\" tests=\"{}\" failures=\"{}\">\n",
+        cases.len(),
+        failures
+    ));
+    out.push_str(&format!(
+        "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" timestamp=\"{}\">\n",
+        escape(suite),
+        cases.len(),
+        failures,
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S"),
+    ));
+    for case in cases {
+        out.push_str(&format!(
+            "    <testcase classname=\"{}\" name=\"{}\"",
+            escape(&case.subject),
+            escape(&case.name)
+        ));
+        if case.failure.is_none() && case.output.is_none() {
+            out.push_str("/>\n");
+            continue;
+        }
+        out.push_str(">\n");
+        if let Some(failure) = &case.failure {
+            out.push_str(&format!(
+                "      <failure message=\"{}\">{}</failure>\n",
+                escape(failure),
+                escape(failure)
+            ));
+        }
+        if let Some(output) = &case.output {
+            out.push_str(&format!(
+                "      <system-out>{}</system-out>\n",
+                escape(output)
+            ));
+        }
+        out.push_str("    </testcase>\n");
+    }
+    out.push_str("  </testsuite>\n</testsuites>\n");
+    out
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // Not allowed in XML 1.0 at all.
+            c if c.is_control() && !matches!(c, '\n' | '\t' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
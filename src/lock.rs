@@ -0,0 +1,334 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::Region;
+use serde_json::{json, Value};
+
+use crate::args::Args;
+use crate::client;
+use crate::config::Lock;
+use crate::credentials::Credentials;
+use crate::dynamodb;
+use crate::ecr::{Decorator, Destination, EcrApi, Failure, Image};
+
+/// How long a lock lasts without a mutation renewing it, by default.
+const DEFAULT_TTL_SECONDS: u64 = 900;
+
+/// The repository locks of the run, kept in the `[lock] table` so two
+/// people can't move tags in the same repository at the same time.
+#[derive(Default)]
+pub struct Locks {
+    table: Option<Table>,
+    /// Each repository locked, with when.
+    held: Mutex<BTreeMap<String, DateTime<Utc>>>,
+}
+
+struct Table {
+    name: String,
+    ttl: Duration,
+    region: Region,
+    credentials: Credentials,
+    /// Tells this run's locks apart from the same user's other runs.
+    id: String,
+    owner: String,
+    command: &'static str,
+}
+
+impl Locks {
+    /// Nothing is moved in read-only mode, so nothing is locked either.
+    pub fn new(args: &Args, config: &Lock) -> Result<Self> {
+        let name = match &config.table {
+            Some(name) if !args.read_only => name.clone(),
+            _ => return Ok(Self::default()),
+        };
+        let region = client::region(args)?;
+        let credentials = client::credentials(args, region.name(), None)?;
+        let ttl = config.ttl.unwrap_or(DEFAULT_TTL_SECONDS);
+        Ok(Self {
+            table: Some(Table {
+                name,
+                ttl: Duration::seconds(ttl as i64),
+                region,
+                credentials,
+                id: format!("{}-{}", std::process::id(), Utc::now().timestamp_nanos()),
+                owner: crate::audit::user().unwrap_or_else(|| "unknown".to_string()),
+                command: args.command_name(),
+            }),
+            held: Mutex::default(),
+        })
+    }
+
+    /// Takes the lock on `repository` in `registry_id` (the source registry
+    /// when unset), as tags are about to be moved in it. Commands checking
+    /// a tag before they move it lock first, so nobody can move it between
+    /// the check and the put.
+    pub async fn lock(
+        &self,
+        ecr: &dyn EcrApi,
+        registry_id: Option<&str>,
+        repository: &str,
+    ) -> Result<()> {
+        if self.table.is_none() {
+            return Ok(());
+        }
+        let registry_id = match registry_id {
+            Some(registry_id) => registry_id.to_string(),
+            None => ecr.registry_id().await?,
+        };
+        self.acquire(&format!("{}/{}", registry_id, repository))
+            .await
+    }
+
+    /// Takes the lock on `repository`, or renews it when the run already
+    /// holds it; fails with who holds it when someone else does.
+    async fn acquire(&self, repository: &str) -> Result<()> {
+        let table = match &self.table {
+            Some(table) => table,
+            None => return Ok(()),
+        };
+        let now = Utc::now();
+        let since = self.held.lock().unwrap().get(repository).copied();
+        let body = table.lock_request(repository, since.unwrap_or(now), now);
+        match table.call("PutItem", body).await {
+            Ok(_) => {
+                self.held
+                    .lock()
+                    .unwrap()
+                    .insert(repository.to_string(), since.unwrap_or(now));
+                Ok(())
+            }
+            Err(e) if e.to_string().contains("ConditionalCheckFailedException") => {
+                Err(table.holder(repository).await)
+            }
+            Err(e) => Err(e.context(format!("failed to lock {} in {}", repository, table.name))),
+        }
+    }
+
+    /// Gives up every lock the run took. Failing is only a warning, as the
+    /// locks expire anyway.
+    pub async fn release(&self) {
+        let table = match &self.table {
+            Some(table) => table,
+            None => return,
+        };
+        let held = std::mem::take(&mut *self.held.lock().unwrap());
+        for repository in held.into_keys() {
+            let body = json!({
+                "TableName": table.name,
+                "Key": { "repository": { "S": repository } },
+                "ConditionExpression": "lock_id = :id",
+                "ExpressionAttributeValues": { ":id": { "S": table.id } },
+            });
+            if let Err(e) = table.call("DeleteItem", body).await {
+                eprintln!("warning: failed to unlock {}: {:#}", repository, e);
+            }
+        }
+    }
+}
+
+impl Table {
+    /// The PutItem taking the lock on `repository`, held since `since`: it
+    /// only succeeds when nobody else holds it, or their lock has expired.
+    fn lock_request(&self, repository: &str, since: DateTime<Utc>, now: DateTime<Utc>) -> Value {
+        let item = json!({
+            "repository": { "S": repository },
+            "lock_id": { "S": self.id },
+            "owner": { "S": self.owner },
+            "command": { "S": self.command },
+            "acquired_at": { "S": since.to_rfc3339_opts(SecondsFormat::Secs, true) },
+            "expires_at": { "N": (now + self.ttl).timestamp().to_string() },
+        });
+        json!({
+            "TableName": self.name,
+            "Item": item,
+            "ConditionExpression":
+                "attribute_not_exists(repository) OR lock_id = :id OR expires_at < :now",
+            "ExpressionAttributeValues": {
+                ":id": { "S": self.id },
+                ":now": { "N": now.timestamp().to_string() },
+            },
+        })
+    }
+
+    async fn call(&self, action: &str, body: Value) -> Result<Value> {
+        let credentials = self
+            .credentials
+            .credentials()
+            .await
+            .context("failed to resolve credentials")?;
+        dynamodb::call(&self.region, &credentials, action, body).await
+    }
+
+    /// The error telling who holds the lock on `repository`.
+    async fn holder(&self, repository: &str) -> anyhow::Error {
+        let body = json!({
+            "TableName": self.name,
+            "Key": { "repository": { "S": repository } },
+            "ConsistentRead": true,
+        });
+        let item = match self.call("GetItem", body).await {
+            Ok(response) => response["Item"].clone(),
+            Err(_) => Value::Null,
+        };
+        let field = |name: &str| item[name]["S"].as_str().unwrap_or("someone").to_string();
+        let since = item["acquired_at"]["S"]
+            .as_str()
+            .and_then(|since| DateTime::parse_from_rfc3339(since).ok())
+            .map(|since| {
+                format!(
+                    " since {}",
+                    since.with_timezone(&Utc).format("%Y-%m-%d %H:%M:%S UTC")
+                )
+            })
+            .unwrap_or_default();
+        anyhow!(
+            "{} is locked by {} (ecr-tag {}){}; try again once they're done",
+            repository,
+            field("owner"),
+            item["command"]["S"].as_str().unwrap_or("run"),
+            since
+        )
+    }
+}
+
+/// Locks each repository in the `[lock] table` before a tag is put or an
+/// image deleted in it. Locks are keyed `<registry id>/<repository>`.
+pub struct LockingEcr {
+    inner: Box<dyn EcrApi>,
+    locks: Arc<Locks>,
+}
+
+impl LockingEcr {
+    pub fn wrap(inner: Box<dyn EcrApi>, locks: &Arc<Locks>) -> Box<dyn EcrApi> {
+        if locks.table.is_none() {
+            return inner;
+        }
+        Box::new(Self {
+            inner,
+            locks: locks.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl Decorator for LockingEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repository = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&image.repository_name);
+        self.locks
+            .lock(
+                self.inner.as_ref(),
+                destination.registry_id.as_deref(),
+                repository,
+            )
+            .await?;
+        self.inner.put_image(image, tag, destination).await
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        self.locks
+            .lock(self.inner.as_ref(), None, repository_name)
+            .await?;
+        self.inner.untag(repository_name, tags).await
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        self.locks
+            .lock(self.inner.as_ref(), None, repository_name)
+            .await?;
+        self.inner.delete_images(repository_name, digests).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::Parser;
+    use crate::ecr::MockEcr;
+
+    fn table() -> Table {
+        Table {
+            name: "locks".to_string(),
+            ttl: Duration::seconds(900),
+            region: Region::UsEast1,
+            credentials: Credentials::new(None, None, vec![]),
+            id: "run-1".to_string(),
+            owner: "alice".to_string(),
+            command: "tag",
+        }
+    }
+
+    #[test]
+    fn lock_request_only_takes_free_expired_or_own_locks() {
+        let now: DateTime<Utc> = "2023-01-01T12:00:00Z".parse().unwrap();
+        let since = now - Duration::minutes(5);
+        let body = table().lock_request("000000000000/app", since, now);
+
+        assert_eq!(body["Item"]["repository"]["S"], "000000000000/app");
+        assert_eq!(body["Item"]["lock_id"]["S"], "run-1");
+        assert_eq!(body["Item"]["acquired_at"]["S"], "2023-01-01T11:55:00Z");
+        assert_eq!(
+            body["Item"]["expires_at"]["N"],
+            (now + Duration::seconds(900)).timestamp().to_string()
+        );
+        assert_eq!(
+            body["ConditionExpression"],
+            "attribute_not_exists(repository) OR lock_id = :id OR expires_at < :now"
+        );
+        assert_eq!(body["ExpressionAttributeValues"][":id"]["S"], "run-1");
+        assert_eq!(
+            body["ExpressionAttributeValues"][":now"]["N"],
+            now.timestamp().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn without_a_table_locking_is_a_no_op() {
+        let ecr = MockEcr::app(&[&["latest"]]);
+        let locks = Arc::new(Locks::default());
+        locks.lock(&ecr, None, "app").await.unwrap();
+        assert!(locks.held.lock().unwrap().is_empty());
+
+        let wrapped = LockingEcr::wrap(Box::new(ecr), &locks);
+        assert!(wrapped
+            .resolve_tag(None, "app", "latest")
+            .await
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn read_only_runs_take_no_locks() {
+        let config = Lock {
+            table: Some("locks".to_string()),
+            ttl: None,
+        };
+        let args = Args::parse_from(Parser::from_args(&[
+            "--read-only",
+            "tag",
+            "-r",
+            "app",
+            "--from-tag",
+            "v1",
+            "-t",
+            "latest",
+        ]))
+        .unwrap();
+
+        assert!(Locks::new(&args, &config).unwrap().table.is_none());
+    }
+}
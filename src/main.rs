@@ -1,169 +1,296 @@
-use std::fmt::{write, Display, Write};
+mod accounts;
+mod args;
+mod audit;
+mod backup;
+mod cache;
+mod client;
+mod commands;
+mod config;
+mod context;
+mod credentials;
+mod desired;
+mod dynamodb;
+mod ecr;
+mod events;
+mod git;
+mod github;
+mod guard;
+mod history;
+mod hooks;
+mod http;
+mod inventory;
+mod junit;
+mod lock;
+mod manifest;
+mod metrics;
+mod model;
+mod opa;
+mod output;
+mod picker;
+mod policy;
+mod provenance;
+mod recent;
+mod referrers;
+mod replication;
+mod retention;
+mod sarif;
+mod sbom;
+mod scan;
+mod sign;
+mod state;
+mod tags;
+mod telemetry;
+mod theme;
+mod throttle;
+mod ui;
+mod viewer;
 
-use anyhow::Result;
-use chrono::{DateTime, NaiveDateTime, Utc};
-use inquire::{
-    ui::{Attributes, Color, RenderConfig, StyleSheet, Styled},
-    Select,
-};
-use rusoto_core::Region;
-use rusoto_ecr::{
-    BatchGetImageRequest, BatchGetImageResponse, DescribeImagesRequest, DescribeImagesResponse,
-    DescribeRepositoriesRequest, DescribeRepositoriesResponse, Ecr, EcrClient, ImageIdentifier,
-    PutImageRequest,
-};
+use std::sync::Arc;
+use std::time::Instant;
 
-#[derive(Debug)]
-struct Respository {
-    name: String,
-}
+use accounts::Account;
+use anyhow::{bail, Result};
+use args::{Args, Command};
+use backup::BackupEcr;
+use cache::{Cache, CachingEcr, OfflineEcr};
+use config::Config;
+use ecr::EcrApi;
+use guard::ReadOnlyEcr;
+use hooks::HookedEcr;
+use inquire::error::InquireError;
+use lock::{LockingEcr, Locks};
+use metrics::RecordingEcr;
+use opa::OpaEcr;
+use output::OutputFormat;
+use provenance::ProvenanceEcr;
+use referrers::ReferrersEcr;
+use sign::SigningEcr;
+use ui::Ui;
 
-impl From<&rusoto_ecr::Repository> for Respository {
-    fn from(value: &rusoto_ecr::Repository) -> Self {
-        Self {
-            name: value.repository_name.clone().unwrap(),
-        }
-    }
-}
-
-impl Display for Respository {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
-    }
-}
+/// The exit status of runs aborted at a prompt or with Ctrl-C, the one
+/// shells give commands killed by SIGINT.
+const ABORTED: i32 = 130;
 
+/// Ctrl-C outside of a prompt, which catches it itself.
 #[derive(Debug)]
-struct ImageDetail {
-    pub tags: Vec<String>,
-    pub digest: String,
-    pub created: DateTime<Utc>,
-    pub repository_name: String,
-}
+struct Interrupted;
 
-fn convert(timestamp: i64) -> DateTime<Utc> {
-    let naive = NaiveDateTime::from_timestamp(timestamp, 0);
-    DateTime::<Utc>::from_utc(naive, Utc)
-}
-
-impl From<&rusoto_ecr::ImageDetail> for ImageDetail {
-    fn from(value: &rusoto_ecr::ImageDetail) -> Self {
-        Self {
-            tags: value.image_tags.clone().unwrap_or_default(),
-            digest: value.image_digest.clone().unwrap(),
-            created: convert(value.image_pushed_at.unwrap() as i64),
-            repository_name: value.repository_name.clone().unwrap(),
-        }
-    }
-}
-
-impl Display for ImageDetail {
+impl std::fmt::Display for Interrupted {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self.tags.len() {
-            0 => write!(f, "{} - {}", self.created.to_rfc3339(), self.digest,),
-            _ => write!(
-                f,
-                "{} - {} - {}",
-                self.created.to_rfc3339(),
-                self.digest,
-                self.tags.join(", ")
-            ),
-        }
+        f.write_str("interrupted")
     }
 }
 
+impl std::error::Error for Interrupted {}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let ecr_client = EcrClient::new(Region::default());
-    let repositories: Vec<Respository> = fetch_repositories(&ecr_client)
-        .await?
-        .repositories
-        .unwrap()
-        .iter()
-        .map(|r| r.into())
-        .collect();
-    let repository = Select::new("repository:", repositories)
-        .with_page_size(10)
-        .with_render_config(get_render_config())
-        .prompt()?;
-
-    let mut images: Vec<ImageDetail> = fetch_images(&ecr_client, repository.name)
-        .await?
-        .image_details
-        .unwrap()
+    let mut args = Args::parse()?;
+    let config = Config::load()?;
+    let started = Instant::now();
+    // Dropping the run on Ctrl-C still records what it changed and unlocks.
+    let result = tokio::select! {
+        result = run(&mut args, &config) => result,
+        _ = tokio::signal::ctrl_c() => Err(Interrupted.into()),
+    };
+    let changes = args
+        .mutations
+        .list()
         .iter()
-        .map(|r| r.into())
-        .collect();
-    images.sort_by_key(|img| img.created);
-    images.reverse();
-
-    let image_detail = Select::new("image:", images)
-        .with_page_size(10)
-        .with_render_config(get_render_config())
-        .prompt()?;
-
-    let image = get_image(&ecr_client, image_detail).await?;
-
-    put_image(&ecr_client, image).await?;
-
-    Ok(())
+        .filter(|mutation| mutation.error.is_none())
+        .count();
+    let result = metrics::report(&args, &config, result, started.elapsed()).await;
+    args.locks.release().await;
+    if args.output == OutputFormat::Junit
+        && matches!(args.command, Command::Interactive(_) | Command::Tag(_))
+    {
+        // Failed checks fail the run, and CI wants the report most then.
+        print!("{}", args.checks.render("gate"));
+    }
+    telemetry::record(
+        &config.telemetry,
+        args.command_name(),
+        &result,
+        started.elapsed(),
+    )
+    .await;
+    match result {
+        Err(e) if aborted(&e) => {
+            let serving = matches!(
+                args.command,
+                Command::Daemon(_) | Command::Listen(_) | Command::Serve(_)
+            );
+            // Those report their changes as they go, so none are left to count.
+            match changes {
+                _ if serving => eprintln!("stopped"),
+                0 => eprintln!("aborted, nothing changed"),
+                1 => eprintln!("aborted after 1 change"),
+                n => eprintln!("aborted after {} changes", n),
+            }
+            std::process::exit(ABORTED);
+        }
+        result => result,
+    }
 }
 
-async fn fetch_repositories(client: &EcrClient) -> Result<DescribeRepositoriesResponse> {
-    let request = DescribeRepositoriesRequest {
-        ..Default::default()
-    };
-    let response = client.describe_repositories(request).await?;
-    Ok(response)
+/// Whether the run stopped because the user asked it to: Esc or Ctrl-C at
+/// a prompt, or Ctrl-C anywhere else.
+fn aborted(e: &anyhow::Error) -> bool {
+    e.is::<Interrupted>()
+        || matches!(
+            e.downcast_ref::<InquireError>(),
+            Some(InquireError::OperationCanceled | InquireError::OperationInterrupted)
+        )
 }
 
-async fn fetch_images(
-    client: &EcrClient,
-    repository_name: String,
-) -> Result<DescribeImagesResponse> {
-    let request = DescribeImagesRequest {
-        repository_name,
-        ..Default::default()
-    };
-    let response = client.describe_images(request).await?;
-    Ok(response)
-}
+async fn run(args: &mut Args, config: &Config) -> Result<()> {
+    let ui = Ui::from_config(config, args.no_color)?;
+    context::apply(args, config, &ui)?;
+    accounts::pick(args, &ui).await?;
+    args.read_only = args.read_only || config.api.read_only;
+    args.locks = Arc::new(Locks::new(args, &config.lock)?);
+    let args = &*args;
+    let ecr = registry(args, config, &ui, args.target_account.as_ref())?;
 
-async fn get_image(client: &EcrClient, image_detail: ImageDetail) -> Result<rusoto_ecr::Image> {
-    let request = BatchGetImageRequest {
-        repository_name: image_detail.repository_name,
-        image_ids: vec![ImageIdentifier {
-            image_digest: Some(image_detail.digest),
-            image_tag: None,
-        }],
-        ..Default::default()
-    };
-    let response = client.batch_get_image(request).await?;
-    Ok(response.images.unwrap().get(0).unwrap().clone())
+    if !args.command.is_read_only() && !args.command.all_accounts() {
+        context::announce(ecr.as_ref(), args).await;
+    }
+
+    match &args.command {
+        Command::Interactive(interactive) => {
+            commands::interactive::run(ecr.as_ref(), &ui, args, interactive, config).await
+        }
+        Command::List(list) => {
+            commands::list::run(ecr.as_ref(), args, config, list, ui.color).await
+        }
+        Command::Aging(aging) => commands::aging::run(ecr.as_ref(), aging, args.output).await,
+        Command::BlobGet(blob) => commands::blob::get(ecr.as_ref(), blob).await,
+        Command::Diff(diff) => commands::diff::run(ecr.as_ref(), diff, args.output).await,
+        Command::Apply(apply) => {
+            commands::desired::apply(ecr.as_ref(), &ui, args, config, apply).await
+        }
+        Command::DiffState(diff) => commands::desired::diff(ecr.as_ref(), diff, args.output).await,
+        Command::Gc(gc) => {
+            commands::gc::run(
+                ecr.as_ref(),
+                &ui,
+                gc,
+                args.state_file.as_deref(),
+                args.resume,
+                args.output,
+            )
+            .await
+        }
+        Command::Findings(findings) => {
+            commands::findings::run(ecr.as_ref(), &ui, findings, args.output).await
+        }
+        Command::History(history) => {
+            commands::history::list(ecr.as_ref(), args, config, history, args.output).await
+        }
+        Command::Rollback(rollback) => {
+            commands::history::rollback(ecr.as_ref(), &ui, args, config, rollback).await
+        }
+        Command::Daemon(daemon) => commands::daemon::run(ecr.as_ref(), args, config, daemon).await,
+        Command::Listen(listen) => commands::listen::run(ecr.as_ref(), args, config, listen).await,
+        Command::Serve(serve) => commands::serve::run(ecr.as_ref(), args, config, serve).await,
+        Command::Referrers(referrers) => {
+            commands::referrers::run(ecr.as_ref(), referrers, args.output).await
+        }
+        Command::Sbom(sbom) => commands::sbom::run(ecr.as_ref(), sbom).await,
+        Command::Export(export) => commands::export::run(ecr.as_ref(), args, export).await,
+        Command::Find(find) => {
+            let registries = match find.all_accounts {
+                true => all_registries(args, config, &ui)
+                    .await?
+                    .into_iter()
+                    .map(|(account, ecr)| (Some(account), ecr))
+                    .collect(),
+                false => vec![(args.target_account.clone(), ecr)],
+            };
+            let region = client::region(args)?;
+            commands::find::run(&registries, find, region.name(), args.output).await
+        }
+        Command::ContextList => commands::context::list(config, args.output),
+        Command::ContextUse(name) => commands::context::switch(config, name),
+        Command::ContextShow => commands::context::show(ecr.as_ref(), args).await,
+        Command::PolicyShow(policy) => {
+            commands::policy::show(ecr.as_ref(), &ui, policy, args.output).await
+        }
+        Command::PolicyApply(policy) => commands::policy::apply(ecr.as_ref(), &ui, policy).await,
+        Command::PolicyCheck(check) => {
+            commands::policy::check(ecr.as_ref(), check, args.output).await
+        }
+        Command::Show(show) => commands::show::run(ecr.as_ref(), &ui, show, args.output).await,
+        Command::Simulate(simulate) => {
+            commands::simulate::run(ecr.as_ref(), simulate, args.output).await
+        }
+        Command::Stats(stats) => commands::stats::run(ecr.as_ref(), stats, args.output).await,
+        Command::Storage(storage) => {
+            commands::storage::run(ecr.as_ref(), storage, args.output).await
+        }
+        Command::Tag(tag) if tag.all_accounts => {
+            let registries = all_registries(args, config, &ui).await?;
+            commands::tag::run_all_accounts(&registries, &ui, args, config, tag).await
+        }
+        Command::Tag(tag) => commands::tag::run(ecr.as_ref(), &ui, args, config, tag).await,
+    }
 }
 
-async fn put_image(client: &EcrClient, image: rusoto_ecr::Image) -> Result<()> {
-    let request = PutImageRequest {
-        repository_name: image.repository_name.unwrap(),
-        image_tag: Some("latest".to_string()),
-        image_manifest: image.image_manifest.unwrap(),
-        ..Default::default()
+/// The ECR API for `account` (or the credentials' own), rate limited and
+/// wrapped in the caching, hooks and read-only guard asked for.
+fn registry(
+    args: &Args,
+    config: &Config,
+    ui: &Ui,
+    account: Option<&Account>,
+) -> Result<Box<dyn EcrApi>> {
+    let max_rps = args.max_rps.or(config.api.max_rps);
+    let ecr = match args.offline {
+        true => Box::new(OfflineEcr::new(Cache::open(args, account)?)),
+        false => {
+            let ecr = client::ecr_api(args, account, max_rps)?;
+            match Cache::open(args, account) {
+                Ok(cache) => CachingEcr::wrap(ecr, cache),
+                Err(_) => ecr,
+            }
+        }
     };
-    client.put_image(request).await?;
-    Ok(())
+    let ecr = ReferrersEcr::wrap(ecr, args.with_referrers);
+    let ecr = ProvenanceEcr::wrap(
+        ecr,
+        args.provenance,
+        args.reason.as_deref(),
+        args.ticket.as_deref(),
+        &args.annotations,
+    );
+    // Inside the hooks, so post_tag hooks see the image signed.
+    let ecr = SigningEcr::wrap(ecr, &config.signing, args.aws_profile.as_deref());
+    let ecr = RecordingEcr::wrap(ecr, &args.mutations);
+    // Outside the recording, so backup tags make it into the history.
+    let ecr = BackupEcr::wrap(ecr, args.backup, &config.backup)?;
+    let ecr = HookedEcr::wrap(ecr, &config.hooks, ui, args.aws_profile.as_deref());
+    // Outside the hooks, so pre hooks don't run for calls that will be refused.
+    let ecr = OpaEcr::wrap(ecr, &config.opa)?;
+    let ecr = LockingEcr::wrap(ecr, &args.locks);
+    // Outermost, so neither runs for calls that will be refused.
+    Ok(ReadOnlyEcr::wrap(ecr, args.read_only))
 }
 
-fn get_render_config() -> RenderConfig {
-    let mut render_config = RenderConfig::default();
-    render_config.prompt_prefix = Styled::new("$").with_fg(Color::LightRed);
-    render_config.selected_checkbox = Styled::new("☑").with_fg(Color::LightGreen);
-    render_config.scroll_up_prefix = Styled::new("⇞");
-    render_config.scroll_down_prefix = Styled::new("⇟");
-
-    render_config.answer = StyleSheet::new()
-        .with_attr(Attributes::BOLD)
-        .with_fg(Color::LightGreen);
-
-    render_config.help_message = StyleSheet::new().with_fg(Color::DarkYellow);
-
-    render_config
+/// The registry of every account of `--org`/`--accounts`, for `--all-accounts`.
+async fn all_registries(
+    args: &Args,
+    config: &Config,
+    ui: &Ui,
+) -> Result<Vec<(Account, Box<dyn EcrApi>)>> {
+    let accounts = accounts::list(args).await?;
+    if accounts.is_empty() {
+        bail!("--all-accounts needs the accounts, from --org or --accounts");
+    }
+    accounts
+        .into_iter()
+        .map(|account| {
+            let ecr = registry(args, config, ui, Some(&account))?;
+            Ok((account, ecr))
+        })
+        .collect()
 }
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// Every manifest type we can pass through unchanged, so BatchGetImage
+/// returns the manifest as it was pushed instead of converting it.
+pub const MEDIA_TYPES: &[&str] = &[
+    "application/vnd.docker.distribution.manifest.v1+json",
+    "application/vnd.docker.distribution.manifest.v1+prettyjws",
+    "application/vnd.docker.distribution.manifest.v2+json",
+    "application/vnd.docker.distribution.manifest.list.v2+json",
+    "application/vnd.oci.image.manifest.v1+json",
+    "application/vnd.oci.image.index.v1+json",
+];
+
+/// Whether `media_type` is a multi-platform index (or docker manifest list),
+/// whose `manifests` are images of their own.
+pub fn is_index(media_type: &str) -> bool {
+    media_type == "application/vnd.docker.distribution.manifest.list.v2+json"
+        || media_type == "application/vnd.oci.image.index.v1+json"
+}
+
+/// The `mediaType` a manifest declares about itself, if any.
+pub fn declared_media_type(manifest: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(manifest).ok()?;
+    value.get("mediaType")?.as_str().map(str::to_string)
+}
+
+/// The parts of a docker v2 / OCI image manifest we look at.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    #[serde(default)]
+    pub config: Option<Descriptor>,
+    #[serde(default)]
+    pub layers: Vec<Descriptor>,
+}
+
+/// A content addressed blob referenced by a manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Descriptor {
+    #[serde(default)]
+    pub media_type: Option<String>,
+    pub digest: String,
+    #[serde(default)]
+    pub size: i64,
+}
+
+impl Manifest {
+    pub fn parse(manifest: &str) -> Result<Self> {
+        let value: serde_json::Value =
+            serde_json::from_str(manifest).context("manifest is not valid JSON")?;
+        if is_schema_v1(&value) {
+            bail!("manifest is a legacy Docker schema v1 manifest, which has no config or layer sizes");
+        }
+        if value.get("manifests").is_some() {
+            bail!("manifest is a multi-platform index, pick a platform image by digest");
+        }
+        serde_json::from_value(value).context("unsupported manifest")
+    }
+
+    pub fn size(&self) -> i64 {
+        self.layers.iter().map(|layer| layer.size).sum()
+    }
+}
+
+/// Docker schema v1 (`fsLayers`, usually signed) manifests predate schema 2/OCI.
+pub fn is_schema_v1(manifest: &serde_json::Value) -> bool {
+    manifest.get("schemaVersion").and_then(|v| v.as_i64()) == Some(1)
+        || manifest.get("fsLayers").is_some()
+}
+
+/// Why tagging a schema v1 image is risky, or `None` for anything newer.
+pub fn legacy_warning(manifest: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(manifest).ok()?;
+    if !is_schema_v1(&value) {
+        return None;
+    }
+    Some(
+        "this image has a legacy Docker schema v1 manifest; ECR may reject the new tag, and \
+         signed v1 manifests can lose their signature when retagged. Re-push it with a current \
+         docker or buildx to convert it to schema 2"
+            .to_string(),
+    )
+}
+
+/// The runtime settings from an image's config blob.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ContainerConfig {
+    #[serde(default)]
+    pub entrypoint: Option<Vec<String>>,
+    #[serde(default)]
+    pub cmd: Option<Vec<String>>,
+    #[serde(default)]
+    pub exposed_ports: Option<BTreeMap<String, serde_json::Value>>,
+    #[serde(default)]
+    pub env: Option<Vec<String>>,
+    #[serde(default)]
+    pub labels: Option<BTreeMap<String, String>>,
+}
+
+impl ContainerConfig {
+    pub fn parse(blob: &[u8]) -> Result<Self> {
+        #[derive(Deserialize)]
+        struct ImageConfig {
+            #[serde(default)]
+            config: Option<ContainerConfig>,
+        }
+        let config: ImageConfig =
+            serde_json::from_slice(blob).context("config blob is not an image config")?;
+        Ok(config.config.unwrap_or_default())
+    }
+
+    /// The extra lines shown in the image picker's detail pane.
+    pub fn preview(&self) -> Vec<String> {
+        let list = |values: &Option<Vec<String>>| match values {
+            Some(values) if !values.is_empty() => values.join(" "),
+            _ => "-".to_string(),
+        };
+        let ports = match &self.exposed_ports {
+            Some(ports) if !ports.is_empty() => {
+                ports.keys().cloned().collect::<Vec<_>>().join(", ")
+            }
+            _ => "-".to_string(),
+        };
+        let mut lines = vec![
+            format!("entry:  {}", list(&self.entrypoint)),
+            format!("cmd:    {}", list(&self.cmd)),
+            format!("ports:  {}", ports),
+        ];
+        for env in self.env.iter().flatten() {
+            lines.push(format!("env:    {}", env));
+        }
+        for (key, value) in self.labels.iter().flatten() {
+            lines.push(format!("label:  {}={}", key, value));
+        }
+        lines
+    }
+}
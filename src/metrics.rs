@@ -0,0 +1,429 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use hyper::Method;
+use rusoto_core::credential::ProvideAwsCredentials;
+use rusoto_core::signature::SignedRequest;
+use serde::Serialize;
+
+use crate::args::Args;
+use crate::client;
+use crate::config::{Config, Metrics};
+use crate::credentials;
+use crate::ecr::{Decorator, Destination, EcrApi, Failure, Image, RepositorySettings};
+use crate::http;
+
+/// A change made, or attempted, to a registry during the run.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mutation {
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub change: Change,
+    /// Why it failed; deletes of which only some images failed carry those
+    /// in `failed` instead.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Change {
+    Tag {
+        registry_id: Option<String>,
+        repository: String,
+        source_repository: String,
+        tag: String,
+        digest: String,
+        /// The digest the tag was on before, if it existed.
+        previous: Option<String>,
+    },
+    Delete {
+        repository: String,
+        deleted: Vec<String>,
+        failed: Vec<String>,
+    },
+    Untag {
+        repository: String,
+        tags: Vec<String>,
+    },
+    SetPolicy {
+        repository: String,
+    },
+    CreateRepository {
+        registry_id: Option<String>,
+        repository: String,
+    },
+}
+
+/// Every mutation of the run, shared by the registries of all its accounts.
+#[derive(Debug, Default)]
+pub struct Mutations {
+    list: Mutex<Vec<Mutation>>,
+}
+
+impl Mutations {
+    fn record<T>(&self, change: Change, result: &Result<T>) {
+        self.list.lock().unwrap().push(Mutation {
+            timestamp: Utc::now(),
+            change,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+    }
+
+    pub fn list(&self) -> Vec<Mutation> {
+        self.list.lock().unwrap().clone()
+    }
+
+    fn clear(&self) {
+        self.list.lock().unwrap().clear();
+    }
+}
+
+/// Adds the mutations made so far to the tag history and audit log and
+/// reports them as metrics, then forgets them. What was changed stays
+/// changed, so failing to record only fails a run that succeeded.
+pub async fn report(
+    args: &Args,
+    config: &Config,
+    mut result: Result<()>,
+    duration: Duration,
+) -> Result<()> {
+    let mutations = &args.mutations;
+    for recorded in [
+        crate::history::record(args, &config.history, mutations).await,
+        crate::audit::ship(args, &config.audit, mutations).await,
+    ] {
+        match (&result, recorded) {
+            (Ok(()), recorded) => result = recorded,
+            (Err(_), Err(e)) => eprintln!("error: {:#}", e),
+            (Err(_), Ok(())) => {}
+        }
+    }
+    if !args.command.is_read_only() {
+        push(
+            &config.metrics,
+            args.command_name(),
+            mutations,
+            &result,
+            duration,
+        )
+        .await;
+        put_cloudwatch(args, &config.metrics, mutations).await;
+    }
+    mutations.clear();
+    result
+}
+
+/// Records every change made through `inner`, for the tag history, metrics
+/// and audit log written once the run is over.
+pub struct RecordingEcr {
+    inner: Box<dyn EcrApi>,
+    mutations: Arc<Mutations>,
+}
+
+impl RecordingEcr {
+    pub fn wrap(inner: Box<dyn EcrApi>, mutations: &Arc<Mutations>) -> Box<dyn EcrApi> {
+        Box::new(Self {
+            inner,
+            mutations: mutations.clone(),
+        })
+    }
+}
+
+/// Puts `PromotionCount` (by `Repository` and `Tag`) and `ImagesDeleted`
+/// (by `Repository`) into the `[metrics] namespace` in CloudWatch, when
+/// `cloudwatch = true`, for the account the run worked in. Like the push,
+/// failing is only a warning.
+async fn put_cloudwatch(args: &Args, config: &Metrics, mutations: &Mutations) {
+    if !config.cloudwatch {
+        return;
+    }
+    let mut promotions: BTreeMap<(String, String), usize> = BTreeMap::new();
+    let mut deletions: BTreeMap<String, usize> = BTreeMap::new();
+    for mutation in mutations.list() {
+        match mutation.change {
+            _ if mutation.error.is_some() => {}
+            Change::Tag {
+                repository, tag, ..
+            } => {
+                *promotions.entry((repository, tag)).or_default() += 1;
+            }
+            Change::Delete {
+                repository,
+                deleted,
+                ..
+            } if !deleted.is_empty() => {
+                *deletions.entry(repository).or_default() += deleted.len();
+            }
+            _ => {}
+        }
+    }
+    let mut data = vec![];
+    for ((repository, tag), count) in &promotions {
+        data.push(Datum {
+            name: "PromotionCount",
+            dimensions: vec![("Repository", repository), ("Tag", tag)],
+            value: *count,
+        });
+    }
+    for (repository, count) in &deletions {
+        data.push(Datum {
+            name: "ImagesDeleted",
+            dimensions: vec![("Repository", repository)],
+            value: *count,
+        });
+    }
+    if data.is_empty() {
+        return;
+    }
+    let namespace = config.namespace.as_deref().unwrap_or("EcrTag");
+    if let Err(e) = put_metric_data(args, namespace, &data).await {
+        eprintln!("warning: failed to put CloudWatch metrics: {:#}", e);
+    }
+}
+
+/// One CloudWatch count.
+struct Datum<'a> {
+    name: &'static str,
+    dimensions: Vec<(&'static str, &'a str)>,
+    value: usize,
+}
+
+/// Calls PutMetricData, which only speaks the query protocol, in batches
+/// of its 20 datum limit.
+async fn put_metric_data(args: &Args, namespace: &str, data: &[Datum<'_>]) -> Result<()> {
+    let region = client::region(args)?;
+    let credentials = client::credentials(args, region.name(), args.target_account.as_ref())?
+        .credentials()
+        .await
+        .context("failed to resolve credentials")?;
+    for batch in data.chunks(20) {
+        let mut params = vec![
+            ("Action".to_string(), "PutMetricData".to_string()),
+            ("Version".to_string(), "2010-08-01".to_string()),
+            ("Namespace".to_string(), namespace.to_string()),
+        ];
+        for (i, datum) in batch.iter().enumerate() {
+            let member = format!("MetricData.member.{}", i + 1);
+            params.push((format!("{}.MetricName", member), datum.name.to_string()));
+            params.push((format!("{}.Value", member), datum.value.to_string()));
+            params.push((format!("{}.Unit", member), "Count".to_string()));
+            for (j, (dimension, value)) in datum.dimensions.iter().enumerate() {
+                let dimension_member = format!("{}.Dimensions.member.{}", member, j + 1);
+                params.push((format!("{}.Name", dimension_member), dimension.to_string()));
+                params.push((format!("{}.Value", dimension_member), value.to_string()));
+            }
+        }
+        let pairs: Vec<(&str, &str)> = params
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        let mut request = SignedRequest::new("POST", "monitoring", &region, "/");
+        request.set_content_type("application/x-www-form-urlencoded".to_string());
+        request.set_payload(Some(credentials::form_encode(&pairs)));
+        http::send_signed(request, &credentials, Duration::from_secs(10)).await?;
+    }
+    Ok(())
+}
+
+/// Pushes the run's metrics to the `[metrics] pushgateway`, replacing the
+/// last run's for the same command. A failed push is only a warning.
+async fn push(
+    config: &Metrics,
+    command: &str,
+    mutations: &Mutations,
+    result: &Result<()>,
+    duration: Duration,
+) {
+    let pushgateway = match &config.pushgateway {
+        Some(pushgateway) => pushgateway,
+        None => return,
+    };
+    let (mut tagged, mut deleted, mut failures) = (0, 0, 0);
+    for mutation in mutations.list() {
+        match mutation.change {
+            Change::Tag { .. } if mutation.error.is_none() => tagged += 1,
+            Change::Tag { .. } => failures += 1,
+            Change::Delete {
+                deleted: ok,
+                failed,
+                ..
+            } => {
+                deleted += ok.len();
+                failures += failed.len();
+            }
+            _ => {}
+        }
+    }
+    let metrics = [
+        (
+            "images_tagged",
+            "Tags put by the last run.",
+            tagged.to_string(),
+        ),
+        (
+            "images_deleted",
+            "Images deleted by the last run.",
+            deleted.to_string(),
+        ),
+        (
+            "failures",
+            "Tags and deletes that failed in the last run.",
+            failures.to_string(),
+        ),
+        (
+            "duration_seconds",
+            "How long the last run took.",
+            format!("{:.3}", duration.as_secs_f64()),
+        ),
+        (
+            "last_run_success",
+            "1 if the last run succeeded, 0 if it failed.",
+            (result.is_ok() as u8).to_string(),
+        ),
+        (
+            "last_run_timestamp_seconds",
+            "When the last run finished.",
+            chrono::Utc::now().timestamp().to_string(),
+        ),
+    ];
+    let mut body = String::new();
+    for (name, help, value) in metrics {
+        body.push_str(&format!(
+            "# HELP ecr_tag_{name} {help}\n# TYPE ecr_tag_{name} gauge\necr_tag_{name} {value}\n"
+        ));
+    }
+    let url = format!(
+        "{}/metrics/job/{}/command/{}",
+        pushgateway.trim_end_matches('/'),
+        config.job.as_deref().unwrap_or("ecr-tag"),
+        command
+    );
+    if let Err(e) = http::send(
+        Method::PUT,
+        &url,
+        "text/plain; version=0.0.4",
+        body,
+        Duration::from_secs(5),
+    )
+    .await
+    {
+        eprintln!("warning: failed to push metrics: {:#}", e);
+    }
+}
+
+#[async_trait]
+impl Decorator for RecordingEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()> {
+        let result = self
+            .inner
+            .set_repository_policy(repository_name, policy)
+            .await;
+        self.mutations.record(
+            Change::SetPolicy {
+                repository: repository_name.to_string(),
+            },
+            &result,
+        );
+        result
+    }
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()> {
+        let result = self
+            .inner
+            .create_repository(registry_id, repository_name, settings)
+            .await;
+        self.mutations.record(
+            Change::CreateRepository {
+                registry_id: registry_id.map(str::to_string),
+                repository: repository_name.to_string(),
+            },
+            &result,
+        );
+        result
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repository = destination
+            .repository_name
+            .clone()
+            .unwrap_or_else(|| image.repository_name.clone());
+        let previous = self
+            .inner
+            .resolve_tag(destination.registry_id.as_deref(), &repository, tag)
+            .await
+            .ok()
+            .flatten();
+        // The registry by id even when it's the caller's own, so a history
+        // shared by several accounts can tell their moves apart.
+        let registry_id = match &destination.registry_id {
+            Some(registry_id) => Some(registry_id.clone()),
+            None => self.inner.registry_id().await.ok(),
+        };
+        let result = self.inner.put_image(image, tag, destination).await;
+        self.mutations.record(
+            Change::Tag {
+                registry_id,
+                repository,
+                source_repository: image.repository_name.clone(),
+                tag: tag.to_string(),
+                digest: image.digest.clone(),
+                previous,
+            },
+            &result,
+        );
+        result
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        let result = self.inner.untag(repository_name, tags).await;
+        self.mutations.record(
+            Change::Untag {
+                repository: repository_name.to_string(),
+                tags: tags.to_vec(),
+            },
+            &result,
+        );
+        result
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        let result = self.inner.delete_images(repository_name, digests).await;
+        let failed: Vec<String> = match &result {
+            Ok(failures) => failures
+                .iter()
+                .map(|failure| failure.digest.clone())
+                .collect(),
+            Err(_) => digests.to_vec(),
+        };
+        self.mutations.record(
+            Change::Delete {
+                repository: repository_name.to_string(),
+                deleted: digests
+                    .iter()
+                    .filter(|digest| !failed.contains(digest))
+                    .cloned()
+                    .collect(),
+                failed,
+            },
+            &result,
+        );
+        result
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Respository {
+    pub name: String,
+    pub uri: String,
+    /// `AES256` or `KMS`.
+    pub encryption_type: String,
+    /// The key used when encrypted with KMS.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kms_key: Option<String>,
+}
+
+impl From<&rusoto_ecr::Repository> for Respository {
+    fn from(value: &rusoto_ecr::Repository) -> Self {
+        let encryption = value.encryption_configuration.as_ref();
+        Self {
+            name: value.repository_name.clone().unwrap(),
+            uri: value.repository_uri.clone().unwrap_or_default(),
+            encryption_type: encryption
+                .map(|config| config.encryption_type.clone())
+                .unwrap_or_else(|| "AES256".to_string()),
+            kms_key: encryption.and_then(|config| config.kms_key.clone()),
+        }
+    }
+}
+
+impl Respository {
+    /// How the repository is encrypted at rest, e.g. `KMS (arn:aws:kms:...)`.
+    pub fn encryption(&self) -> String {
+        match &self.kms_key {
+            Some(key) => format!("{} ({})", self.encryption_type, key),
+            None => self.encryption_type.clone(),
+        }
+    }
+
+    /// The lines shown under the repository picker for the highlighted repository.
+    pub fn preview(&self) -> Vec<String> {
+        vec![
+            format!("uri:        {}", self.uri),
+            format!("encryption: {}", self.encryption()),
+        ]
+    }
+}
+
+impl Display for Respository {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// One vulnerability reported by an image scan.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    /// Usually the CVE id.
+    pub name: String,
+    pub severity: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Where to read more about it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package_version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cvss_score: Option<f64>,
+    /// The package version the vulnerability is fixed in; only enhanced
+    /// scanning knows.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fixed_in: Option<String>,
+    /// Inspector's `YES`, `NO` or `PARTIAL`: whether a fix is released.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fix_available: Option<String>,
+    /// Inspector's `YES` or `NO`: whether a public exploit is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exploit_available: Option<String>,
+}
+
+impl Finding {
+    /// A basic scanning finding, as DescribeImageScanFindings returns it.
+    pub fn from_basic(value: &Value) -> Self {
+        let attribute = |key: &str| {
+            value["attributes"]
+                .as_array()?
+                .iter()
+                .find(|attribute| attribute["key"] == key)?["value"]
+                .as_str()
+                .map(str::to_string)
+        };
+        Self {
+            name: text(&value["name"]).unwrap_or_default(),
+            severity: text(&value["severity"]).unwrap_or_else(|| "UNDEFINED".to_string()),
+            description: text(&value["description"]),
+            uri: text(&value["uri"]),
+            package: attribute("package_name"),
+            package_version: attribute("package_version"),
+            cvss_score: ["CVSS3_SCORE", "CVSS2_SCORE"]
+                .iter()
+                .find_map(|key| attribute(key)?.parse().ok()),
+            fixed_in: None,
+            fix_available: None,
+            exploit_available: None,
+        }
+    }
+
+    /// An enhanced (Inspector) finding, as Inspector2 ListFindings returns it
+    /// and DescribeImageScanFindings does for registries with enhanced
+    /// scanning.
+    pub fn from_enhanced(value: &Value) -> Self {
+        let details = &value["packageVulnerabilityDetails"];
+        let package = &details["vulnerablePackages"][0];
+        Self {
+            name: text(&details["vulnerabilityId"])
+                .or_else(|| text(&value["title"]))
+                .unwrap_or_default(),
+            severity: text(&value["severity"]).unwrap_or_else(|| "UNDEFINED".to_string()),
+            description: text(&value["description"]),
+            uri: text(&details["sourceUrl"]),
+            package: text(&package["name"]),
+            package_version: text(&package["version"]),
+            cvss_score: value["score"]
+                .as_f64()
+                .or_else(|| value["inspectorScore"].as_f64()),
+            fixed_in: text(&package["fixedInVersion"]).filter(|version| version != "NotAvailable"),
+            fix_available: text(&value["fixAvailable"]),
+            exploit_available: text(&value["exploitAvailable"]),
+        }
+    }
+}
+
+fn text(value: &Value) -> Option<String> {
+    value.as_str().map(str::to_string)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageDetail {
+    pub tags: Vec<String>,
+    pub digest: String,
+    pub created: DateTime<Utc>,
+    pub repository_name: String,
+    pub size: i64,
+    pub scan_status: Option<String>,
+    pub severity_counts: BTreeMap<String, i64>,
+    /// The manifest's media type, as the registry reports it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// Config labels; only filled in when something asked for them, as it
+    /// takes a manifest and a blob download per image.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+}
+
+fn convert(timestamp: i64) -> DateTime<Utc> {
+    let naive = NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap();
+    DateTime::<Utc>::from_utc(naive, Utc)
+}
+
+impl From<&rusoto_ecr::ImageDetail> for ImageDetail {
+    fn from(value: &rusoto_ecr::ImageDetail) -> Self {
+        Self {
+            tags: value.image_tags.clone().unwrap_or_default(),
+            digest: value.image_digest.clone().unwrap(),
+            created: convert(value.image_pushed_at.unwrap() as i64),
+            repository_name: value.repository_name.clone().unwrap(),
+            size: value.image_size_in_bytes.unwrap_or_default(),
+            scan_status: value
+                .image_scan_status
+                .as_ref()
+                .and_then(|status| status.status.clone()),
+            severity_counts: value
+                .image_scan_findings_summary
+                .as_ref()
+                .and_then(|summary| summary.finding_severity_counts.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            media_type: value.image_manifest_media_type.clone(),
+            labels: BTreeMap::new(),
+        }
+    }
+}
+
+impl ImageDetail {
+    /// The lines shown under the image picker for the highlighted image.
+    pub fn preview(&self, repository_uri: &str) -> Vec<String> {
+        let tags = match self.tags.len() {
+            0 => "<untagged>".to_string(),
+            _ => self.tags.join(", "),
+        };
+        let scan = match (&self.scan_status, self.severity_counts.is_empty()) {
+            (None, _) => "not scanned".to_string(),
+            (Some(status), true) => status.clone(),
+            (Some(status), false) => format!(
+                "{} - {}",
+                status,
+                self.severity_counts
+                    .iter()
+                    .map(|(severity, count)| format!("{} {}", severity, count))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        };
+        vec![
+            format!("digest: {}", self.digest),
+            format!("tags:   {}", tags),
+            format!("pushed: {}", self.created.to_rfc3339()),
+            format!("size:   {}", format_size(self.size)),
+            format!("scan:   {}", scan),
+            format!("uri:    {}@{}", repository_uri, self.digest),
+        ]
+    }
+}
+
+pub fn format_size(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} {}", bytes, UNITS[0]),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
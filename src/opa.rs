@@ -0,0 +1,279 @@
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use hyper::Method;
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::audit;
+use crate::config::Opa;
+use crate::ecr::{Decorator, Destination, EcrApi, Failure, Image, RepositorySettings};
+use crate::http;
+
+const DEFAULT_QUERY: &str = "data.ecr_tag.deny";
+
+/// Asks the `[opa]` policy about every mutation made through `inner`,
+/// refusing the ones it denies.
+///
+/// The input document has the `action` (`tag`, `delete`, `set_policy` or
+/// `create_repository`), the `repository`, the `actor` and what else the
+/// action concerns: a tag's `tag`, `digest`, `source_repository`,
+/// `registry_id` and `findings` (counts by severity, `null` when unscanned).
+pub struct OpaEcr {
+    inner: Box<dyn EcrApi>,
+    opa: Opa,
+}
+
+impl OpaEcr {
+    pub fn wrap(inner: Box<dyn EcrApi>, opa: &Opa) -> Result<Box<dyn EcrApi>> {
+        match (&opa.bundle, &opa.url) {
+            (None, None) => Ok(inner),
+            (Some(_), Some(_)) => bail!("[opa] takes a bundle or a url, not both"),
+            _ => Ok(Box::new(Self {
+                inner,
+                opa: opa.clone(),
+            })),
+        }
+    }
+
+    /// Fails with the policy's messages when it denies `input`.
+    async fn authorize(&self, mut input: Value) -> Result<()> {
+        input["actor"] = json!(audit::user());
+        let query = self.opa.query.as_deref().unwrap_or(DEFAULT_QUERY);
+        let result = match (&self.opa.bundle, &self.opa.url) {
+            (Some(bundle), _) => self.eval(bundle, query, &input).await?,
+            (None, Some(url)) => query_server(url, query, &input).await?,
+            (None, None) => return Ok(()),
+        };
+        let messages: Vec<String> = match result {
+            None | Some(Value::Null) => vec![],
+            Some(Value::Array(messages)) => messages
+                .into_iter()
+                .map(|message| match message {
+                    Value::String(message) => message,
+                    other => other.to_string(),
+                })
+                .collect(),
+            Some(other) => bail!(
+                "{} should give the messages denying the change, got {}",
+                query,
+                other
+            ),
+        };
+        if !messages.is_empty() {
+            bail!("denied by the OPA policy: {}", messages.join("; "));
+        }
+        Ok(())
+    }
+
+    /// Runs `opa eval` against the bundle; `None` when the query is undefined.
+    async fn eval(&self, bundle: &str, query: &str, input: &Value) -> Result<Option<Value>> {
+        let opa = self.opa.opa.as_deref().unwrap_or("opa");
+        let mut child = Command::new(opa)
+            .args([
+                "eval",
+                "--format",
+                "json",
+                "--stdin-input",
+                "--bundle",
+                bundle,
+            ])
+            .arg(query)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {}", opa))?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.to_string().as_bytes())
+            .await?;
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            bail!("{} eval exited with {}", opa, output.status);
+        }
+        let output: Value = serde_json::from_slice(&output.stdout)
+            .with_context(|| format!("unexpected {} eval output", opa))?;
+        Ok(output["result"][0]["expressions"][0].get("value").cloned())
+    }
+}
+
+/// Asks the OPA server at `url` for `query`'s document, e.g.
+/// `data.ecr_tag.deny` from `/v1/data/ecr_tag/deny`.
+async fn query_server(url: &str, query: &str, input: &Value) -> Result<Option<Value>> {
+    let path = query
+        .strip_prefix("data.")
+        .ok_or_else(|| anyhow!("[opa] query must be a data. path with a url, got {}", query))?
+        .replace('.', "/");
+    let url = format!("{}/v1/data/{}", url.trim_end_matches('/'), path);
+    let response = http::send(
+        Method::POST,
+        &url,
+        "application/json",
+        json!({ "input": input }).to_string(),
+        Duration::from_secs(10),
+    )
+    .await
+    .context("failed to query the OPA server")?;
+    let response: Value = serde_json::from_str(&response)
+        .with_context(|| format!("unexpected response from {}", url))?;
+    Ok(response.get("result").cloned())
+}
+
+#[async_trait]
+impl Decorator for OpaEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    fn replica(&self, region: &str, registry_id: &str) -> Result<Box<dyn EcrApi>> {
+        Ok(Box::new(Self {
+            inner: self.inner.replica(region, registry_id)?,
+            opa: self.opa.clone(),
+        }))
+    }
+
+    async fn set_repository_policy(&self, repository_name: &str, policy: &str) -> Result<()> {
+        let document: Value = serde_json::from_str(policy).unwrap_or(Value::Null);
+        self.authorize(json!({
+            "action": "set_policy",
+            "repository": repository_name,
+            "policy": document,
+        }))
+        .await?;
+        self.inner
+            .set_repository_policy(repository_name, policy)
+            .await
+    }
+
+    async fn create_repository(
+        &self,
+        registry_id: Option<&str>,
+        repository_name: &str,
+        settings: &RepositorySettings,
+    ) -> Result<()> {
+        self.authorize(json!({
+            "action": "create_repository",
+            "repository": repository_name,
+            "registry_id": registry_id,
+        }))
+        .await?;
+        self.inner
+            .create_repository(registry_id, repository_name, settings)
+            .await
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let findings = self
+            .inner
+            .severity_counts(&image.repository_name, &image.digest)
+            .await?;
+        self.authorize(json!({
+            "action": "tag",
+            "repository": destination.repository_name.as_deref().unwrap_or(&image.repository_name),
+            "source_repository": image.repository_name,
+            "registry_id": destination.registry_id,
+            "tag": tag,
+            "digest": image.digest,
+            "findings": findings,
+        }))
+        .await?;
+        self.inner.put_image(image, tag, destination).await
+    }
+
+    async fn untag(&self, repository_name: &str, tags: &[String]) -> Result<()> {
+        self.authorize(json!({
+            "action": "untag",
+            "repository": repository_name,
+            "tags": tags,
+        }))
+        .await?;
+        self.inner.untag(repository_name, tags).await
+    }
+
+    async fn delete_images(
+        &self,
+        repository_name: &str,
+        digests: &[String],
+    ) -> Result<Vec<Failure>> {
+        self.authorize(json!({
+            "action": "delete",
+            "repository": repository_name,
+            "digests": digests,
+        }))
+        .await?;
+        self.inner.delete_images(repository_name, digests).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+    use crate::ecr::MockEcr;
+
+    /// A stand-in for the opa binary, denying tags named `latest`.
+    fn fake_opa() -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("ecr-tag-opa-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"#!/bin/sh
+[ "$1" = eval ] || exit 2
+if grep -q '"tag":"latest"'; then
+  echo '{"result":[{"expressions":[{"value":["latest is frozen"]}]}]}'
+else
+  echo '{"result":[{"expressions":[{"value":[]}]}]}'
+fi
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn eval_denies_what_the_bundle_denies() {
+        let opa = Opa {
+            bundle: Some("./policy".to_string()),
+            opa: Some(fake_opa().display().to_string()),
+            ..Opa::default()
+        };
+        let ecr = OpaEcr::wrap(Box::new(MockEcr::app(&[&["v1"], &[]])), &opa).unwrap();
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+
+        let err = ecr
+            .put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap_err();
+        ecr.put_image(&image, "stable", &Destination::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            format!("{:#}", err),
+            "denied by the OPA policy: latest is frozen"
+        );
+        assert_eq!(ecr.resolve_tag(None, "app", "latest").await.unwrap(), None);
+        assert_eq!(
+            ecr.resolve_tag(None, "app", "stable").await.unwrap(),
+            Some(digest)
+        );
+    }
+
+    #[test]
+    fn wrap_takes_a_bundle_or_a_url() {
+        let both = Opa {
+            bundle: Some("./policy".to_string()),
+            url: Some("http://localhost:8181".to_string()),
+            ..Opa::default()
+        };
+
+        assert!(OpaEcr::wrap(Box::new(MockEcr::app(&[])), &both).is_err());
+    }
+}
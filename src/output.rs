@@ -0,0 +1,196 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Error, Result};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    /// A self-contained HTML page, for attaching to release or audit tickets.
+    Html,
+    /// GitHub flavoured markdown tables, for PR descriptions and runbooks.
+    Markdown,
+    /// SARIF 2.1.0, for GitHub code scanning; only scan findings have it.
+    Sarif,
+    /// JUnit XML with a test case per check, for CI test reports; only the
+    /// tagging gates and `policy check` have it.
+    Junit,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value {
+            "table" | "text" => Self::Table,
+            "json" => Self::Json,
+            "html" => Self::Html,
+            "markdown" | "md" => Self::Markdown,
+            "sarif" => Self::Sarif,
+            "junit" => Self::Junit,
+            _ => bail!(
+                "unknown output format `{}` (expected table, json, html, markdown, sarif or junit)",
+                value
+            ),
+        })
+    }
+}
+
+/// Rows for the human readable formats; JSON output serializes the
+/// underlying items instead so it keeps proper types.
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: vec![],
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    /// Left-aligned columns separated by two spaces.
+    pub fn render(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let headers: Vec<String> = self.headers.iter().map(|h| h.to_string()).collect();
+        let mut out = String::new();
+        for row in std::iter::once(&headers).chain(&self.rows) {
+            let line: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    fn render_markdown(&self) -> String {
+        let line = |cells: Vec<String>| format!("| {} |\n", cells.join(" | "));
+        let mut out = line(self.headers.iter().map(|h| h.to_string()).collect());
+        out.push_str(&line(
+            self.headers.iter().map(|_| "---".to_string()).collect(),
+        ));
+        for row in &self.rows {
+            out.push_str(&line(
+                row.iter().map(|cell| escape_markdown(cell)).collect(),
+            ));
+        }
+        out
+    }
+
+    fn render_html(&self) -> String {
+        let mut out = String::from("<table>\n<thead><tr>");
+        for header in &self.headers {
+            out.push_str(&format!("<th>{}</th>", escape_html(header)));
+        }
+        out.push_str("</tr></thead>\n<tbody>\n");
+        for row in &self.rows {
+            out.push_str("<tr>");
+            for cell in row {
+                out.push_str(&format!("<td>{}</td>", escape_html(cell)));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody>\n</table>\n");
+        out
+    }
+}
+
+/// Several titled tables rendered as one HTML page.
+pub struct Report<'a> {
+    title: String,
+    sections: Vec<(&'a str, &'a Table)>,
+}
+
+impl<'a> Report<'a> {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            sections: vec![],
+        }
+    }
+
+    pub fn section(mut self, heading: &'a str, table: &'a Table) -> Self {
+        self.sections.push((heading, table));
+        self
+    }
+
+    /// The whole page, styles included, so it can be attached as-is.
+    pub fn render_html(&self) -> String {
+        let title = escape_html(&self.title);
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n<p class=\"generated\">Generated by ecr-tag on {}</p>\n",
+            title,
+            STYLE,
+            title,
+            chrono::Utc::now().to_rfc3339(),
+        );
+        for (heading, table) in &self.sections {
+            if !heading.is_empty() {
+                out.push_str(&format!("<h2>{}</h2>\n", escape_html(heading)));
+            }
+            out.push_str(&table.render_html());
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+const STYLE: &str =
+    "body{font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;margin:2em;color:#222}\
+h1{font-size:1.5em}h2{font-size:1.2em;margin-top:2em}.generated{color:#666}\
+table{border-collapse:collapse;font-size:0.9em}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left;font-family:monospace}\
+th{background:#f0f0f0}tr:nth-child(even) td{background:#fafafa}";
+
+/// Pipes would end the cell early and newlines the row.
+fn escape_markdown(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+fn escape_html(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+pub fn print<T: Serialize + ?Sized>(format: OutputFormat, table: &Table, items: &T) -> Result<()> {
+    match format {
+        OutputFormat::Table => print!("{}", table.render()),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(items)?),
+        OutputFormat::Markdown => print!("{}", table.render_markdown()),
+        OutputFormat::Html => print!(
+            "{}",
+            Report::new("ecr-tag").section("", table).render_html()
+        ),
+        OutputFormat::Sarif => bail!("--output sarif is only available for scan findings"),
+        OutputFormat::Junit => {
+            bail!("--output junit is only available when tagging and for policy check")
+        }
+    }
+    Ok(())
+}
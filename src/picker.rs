@@ -0,0 +1,951 @@
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::io::{stderr, Stderr, Write};
+
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    queue,
+    style::{Attribute, Color as TermColor, ContentStyle, PrintStyledContent, StyledContent},
+    terminal::{self, Clear, ClearType},
+    tty::IsTty,
+};
+use inquire::{
+    error::{InquireError, InquireResult},
+    ui::{Attributes, Color, RenderConfig, StyleSheet, Styled},
+};
+
+use crate::config::Keybindings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+    Confirm,
+    QuickConfirm,
+    Toggle,
+    Pin,
+    Mode,
+    Back,
+    Cancel,
+}
+
+/// Maps key presses to picker actions.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: Vec<(KeyEvent, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::from_config(&Keybindings::default()).unwrap()
+    }
+}
+
+impl Keymap {
+    pub fn from_config(config: &Keybindings) -> anyhow::Result<Self> {
+        let vim = |default: &[&str], extra: &[&str]| -> Vec<String> {
+            let mut keys: Vec<String> = default.iter().map(|k| k.to_string()).collect();
+            if config.vim {
+                keys.extend(extra.iter().map(|k| k.to_string()));
+            }
+            keys
+        };
+        let actions = [
+            (Action::Up, &config.up, vim(&["up"], &["k"])),
+            (Action::Down, &config.down, vim(&["down"], &["j"])),
+            (
+                Action::PageUp,
+                &config.page_up,
+                vim(&["pageup"], &["ctrl-b", "ctrl-u"]),
+            ),
+            (
+                Action::PageDown,
+                &config.page_down,
+                vim(&["pagedown"], &["ctrl-f", "ctrl-d"]),
+            ),
+            (Action::Top, &config.top, vim(&["home"], &["g"])),
+            (Action::Bottom, &config.bottom, vim(&["end"], &["G"])),
+            (Action::Confirm, &config.confirm, vim(&["enter"], &[])),
+            (Action::QuickConfirm, &config.quick_confirm, vim(&[], &[])),
+            (Action::Toggle, &config.toggle, vim(&["space"], &[])),
+            (Action::Pin, &config.pin, vim(&["ctrl-p"], &[])),
+            (Action::Mode, &config.mode, vim(&["ctrl-t"], &[])),
+            (Action::Back, &config.back, vim(&["left"], &[])),
+            (Action::Cancel, &config.cancel, vim(&["esc"], &[])),
+        ];
+
+        let mut bindings = vec![];
+        for (action, configured, default) in actions {
+            for key in configured.as_ref().unwrap_or(&default) {
+                bindings.push((parse_key(key)?, action));
+            }
+        }
+        Ok(Self { bindings })
+    }
+
+    fn action(&self, key: &KeyEvent, multi: bool) -> Option<Action> {
+        self.bindings
+            .iter()
+            .filter(|(_, action)| multi || *action != Action::Toggle)
+            .find(|(binding, _)| {
+                binding.code == key.code
+                    && binding.modifiers == (key.modifiers - KeyModifiers::SHIFT)
+            })
+            .map(|(_, action)| *action)
+    }
+
+    /// Whether some letter is bound, so the filter is typed after `/`.
+    fn binds_letters(&self) -> bool {
+        self.bindings.iter().any(|(binding, _)| {
+            matches!(binding.code, KeyCode::Char(c) if c != ' ')
+                && (binding.modifiers - KeyModifiers::SHIFT).is_empty()
+        })
+    }
+}
+
+/// Parses key names such as `j`, `G`, `pagedown`, `ctrl-f` or `alt-enter`.
+fn parse_key(spec: &str) -> anyhow::Result<KeyEvent> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = spec;
+    loop {
+        if let Some(rest) = name.strip_prefix("ctrl-") {
+            modifiers |= KeyModifiers::CONTROL;
+            name = rest;
+        } else if let Some(rest) = name.strip_prefix("alt-") {
+            modifiers |= KeyModifiers::ALT;
+            name = rest;
+        } else {
+            break;
+        }
+    }
+    let code = match name.to_lowercase().as_str() {
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "space" => KeyCode::Char(' '),
+        _ if name.chars().count() == 1 => KeyCode::Char(name.chars().next().unwrap()),
+        _ => anyhow::bail!("unknown key `{}` in keybindings", spec),
+    };
+    Ok(KeyEvent::new(code, modifiers))
+}
+
+type Preview<'a, T> = Box<dyn Fn(&T) -> Vec<String> + 'a>;
+type Colors<'a, T> = Box<dyn Fn(&T) -> Option<Color> + 'a>;
+type Segments<'a, T> = Box<dyn Fn(&T) -> Vec<(String, Option<Color>)> + 'a>;
+type More<'a, T> = Box<dyn Fn() -> Result<(Vec<T>, bool), String> + 'a>;
+type Load<'a, T> = Box<dyn Fn(usize) -> Result<(Vec<T>, bool), String> + 'a>;
+
+/// Ways of listing the options, and how to load them each way.
+struct Modes<'a, T> {
+    names: Vec<String>,
+    current: usize,
+    load: Load<'a, T>,
+}
+
+/// Whether there are options left to load, as far as the picker knows.
+enum Loading {
+    Pending,
+    Done,
+    Failed(String),
+}
+
+/// Options marked as favorites, and what to do when one is (un)pinned.
+struct Pins<'a> {
+    pinned: BTreeSet<usize>,
+    /// Called with the option and whether it is now pinned; returning false
+    /// keeps it as it was.
+    on_toggle: Box<dyn Fn(usize, bool) -> bool + 'a>,
+}
+
+/// A select/multi-select prompt like inquire's, but driven by a [`Keymap`].
+pub struct Picker<'a, T> {
+    message: &'a str,
+    options: Vec<T>,
+    page_size: usize,
+    render_config: RenderConfig,
+    keymap: Keymap,
+    preview: Option<Preview<'a, T>>,
+    colors: Option<Colors<'a, T>>,
+    segments: Option<Segments<'a, T>>,
+    pins: Option<Pins<'a>>,
+    starting_cursor: usize,
+    unicode: bool,
+    more: Option<More<'a, T>>,
+    loading: Loading,
+    modes: Option<Modes<'a, T>>,
+    back: bool,
+}
+
+impl<'a, T: Display> Picker<'a, T> {
+    pub fn new(message: &'a str, options: Vec<T>) -> Self {
+        Self {
+            message,
+            options,
+            page_size: 7,
+            render_config: RenderConfig::default(),
+            keymap: Keymap::default(),
+            preview: None,
+            colors: None,
+            segments: None,
+            pins: None,
+            starting_cursor: 0,
+            unicode: true,
+            more: None,
+            loading: Loading::Done,
+            modes: None,
+            back: false,
+        }
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn with_render_config(mut self, render_config: RenderConfig) -> Self {
+        self.render_config = render_config;
+        self
+    }
+
+    pub fn with_keymap(mut self, keymap: Keymap) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    /// Whether glyphs beyond ASCII can be drawn; the pin star, ellipsis and
+    /// arrows fall back to ASCII ones when not.
+    pub fn with_unicode(mut self, unicode: bool) -> Self {
+        self.unicode = unicode;
+        self
+    }
+
+    /// Highlights the option at `index` to begin with.
+    pub fn with_starting_cursor(mut self, index: usize) -> Self {
+        self.starting_cursor = index;
+        self
+    }
+
+    /// Marks the `pinned` options with a star and lets the pin key (ctrl-p)
+    /// toggle the highlighted one, reporting each change to `on_toggle`.
+    pub fn with_pins(
+        mut self,
+        pinned: BTreeSet<usize>,
+        on_toggle: impl Fn(usize, bool) -> bool + 'a,
+    ) -> Self {
+        self.pins = Some(Pins {
+            pinned,
+            on_toggle: Box::new(on_toggle),
+        });
+        self
+    }
+
+    /// Shows extra lines about the highlighted option below the list.
+    pub fn with_preview(mut self, preview: impl Fn(&T) -> Vec<String> + 'a) -> Self {
+        self.preview = Some(Box::new(preview));
+        self
+    }
+
+    /// Draws each option in the colour `colors` gives it, or the theme's.
+    pub fn with_colors(mut self, colors: impl Fn(&T) -> Option<Color> + 'a) -> Self {
+        self.colors = Some(Box::new(colors));
+        self
+    }
+
+    /// Draws each option as the pieces `segments` splits it in, each in its
+    /// own colour or the option's. The pieces must add up to its text.
+    pub fn with_segments(
+        mut self,
+        segments: impl Fn(&T) -> Vec<(String, Option<Color>)> + 'a,
+    ) -> Self {
+        self.segments = Some(Box::new(segments));
+        self
+    }
+
+    /// Calls `more` for the next options each time the cursor reaches the
+    /// last loaded, as long as there are `remaining` ones; it returns them,
+    /// and whether there are more still, or why they couldn't be loaded.
+    pub fn with_more(
+        mut self,
+        remaining: bool,
+        more: impl Fn() -> Result<(Vec<T>, bool), String> + 'a,
+    ) -> Self {
+        self.more = Some(Box::new(more));
+        if remaining {
+            self.loading = Loading::Pending;
+        }
+        self
+    }
+
+    /// Names the way the options were listed, the first of `names`, and
+    /// lets the mode key (ctrl-t) switch to the next, replacing the options
+    /// with those `load` gives for it and whether there are more to load.
+    /// Not for use with pins, which are kept by index.
+    pub fn with_modes(
+        mut self,
+        names: Vec<String>,
+        load: impl Fn(usize) -> Result<(Vec<T>, bool), String> + 'a,
+    ) -> Self {
+        self.modes = Some(Modes {
+            names,
+            current: 0,
+            load: Box::new(load),
+        });
+        self
+    }
+
+    /// Lets the back key (left) leave the prompt as esc does, and says so:
+    /// the caller takes `OperationCanceled` as going back to the prompt
+    /// before.
+    pub fn with_back(mut self) -> Self {
+        self.back = true;
+        self
+    }
+
+    pub fn prompt(self) -> InquireResult<T> {
+        let (mut options, mut selected) = self.run(false)?;
+        Ok(options.swap_remove(selected.pop().unwrap()))
+    }
+
+    pub fn prompt_multi(self) -> InquireResult<Vec<T>> {
+        let (options, selected) = self.run(true)?;
+        Ok(options
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| selected.contains(i))
+            .map(|(_, option)| option)
+            .collect())
+    }
+
+    fn run(mut self, multi: bool) -> InquireResult<(Vec<T>, Vec<usize>)> {
+        if self.options.is_empty() {
+            return Err(InquireError::InvalidConfiguration(
+                "Available options can not be empty".into(),
+            ));
+        }
+        if !std::io::stdin().is_tty() {
+            return Err(InquireError::NotTTY);
+        }
+
+        let labels: Vec<String> = self.options.iter().map(|o| o.to_string()).collect();
+        let mut state = State {
+            visible: (0..labels.len()).collect(),
+            cursor: self.starting_cursor.min(labels.len() - 1),
+            labels,
+            filter: String::new(),
+            typing: false,
+            checked: BTreeSet::new(),
+            pinned: self
+                .pins
+                .as_ref()
+                .map(|pins| pins.pinned.clone())
+                .unwrap_or_default(),
+            lines: 0,
+        };
+
+        let mut out = stderr();
+        terminal::enable_raw_mode()?;
+        let result = self.event_loop(&mut state, &mut out, multi);
+        state.clear(&mut out)?;
+        terminal::disable_raw_mode()?;
+
+        let (prefix, answer) = match &result {
+            Ok(selected) => (
+                self.render_config.answered_prompt_prefix,
+                Styled::new(
+                    selected
+                        .iter()
+                        .map(|i| state.labels[*i].as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                )
+                .with_style_sheet(self.render_config.answer),
+            ),
+            Err(_) => (
+                self.render_config.prompt_prefix,
+                Styled::new(
+                    self.render_config
+                        .canceled_prompt_indicator
+                        .content
+                        .to_string(),
+                )
+                .with_style_sheet(self.render_config.canceled_prompt_indicator.style),
+            ),
+        };
+        queue!(
+            out,
+            PrintStyledContent(style(prefix.content, prefix.style)),
+            PrintStyledContent(style(
+                &format!(" {} ", self.message),
+                self.render_config.prompt
+            )),
+            PrintStyledContent(style(&answer.content, answer.style)),
+            cursor::Show,
+        )?;
+        writeln!(out)?;
+        out.flush()?;
+
+        let selected = result?;
+        Ok((self.options, selected))
+    }
+
+    fn event_loop(
+        &mut self,
+        state: &mut State,
+        out: &mut Stderr,
+        multi: bool,
+    ) -> InquireResult<Vec<usize>> {
+        loop {
+            self.render(state, out, multi)?;
+            // Drawn with the loading row, which stays until the options are in.
+            // A filter only pulls in more once it's down to the last loaded.
+            if state.cursor + 1 >= state.visible.len()
+                && state.shows_last()
+                && self.load_more(state)
+            {
+                continue;
+            }
+
+            let key = match event::read()? {
+                Event::Key(key) => key,
+                _ => continue,
+            };
+            if key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL {
+                return Err(InquireError::OperationInterrupted);
+            }
+
+            if state.typing {
+                match key.code {
+                    KeyCode::Esc | KeyCode::Enter => {
+                        state.typing = false;
+                        continue;
+                    }
+                    KeyCode::Char(c) if (key.modifiers - KeyModifiers::SHIFT).is_empty() => {
+                        state.filter.push(c);
+                        state.refilter();
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            let current = state.visible.get(state.cursor).copied();
+            match self.keymap.action(&key, multi) {
+                Some(Action::Up) => state.move_up(1, true),
+                Some(Action::Down) => state.move_down(1, true),
+                Some(Action::PageUp) => state.move_up(self.page_size, false),
+                Some(Action::PageDown) => state.move_down(self.page_size, false),
+                Some(Action::Top) => state.move_up(usize::MAX, false),
+                Some(Action::Bottom) => state.move_down(usize::MAX, false),
+                Some(Action::Toggle) => {
+                    if let Some(index) = current {
+                        if !state.checked.remove(&index) {
+                            state.checked.insert(index);
+                        }
+                    }
+                }
+                Some(Action::Pin) => {
+                    if let (Some(pins), Some(index)) = (&self.pins, current) {
+                        let pin = !state.pinned.contains(&index);
+                        if (pins.on_toggle)(index, pin) {
+                            match pin {
+                                true => state.pinned.insert(index),
+                                false => state.pinned.remove(&index),
+                            };
+                        }
+                    }
+                }
+                Some(Action::Mode) => self.switch_mode(state),
+                Some(Action::Confirm) if multi => {
+                    return Ok(state.checked.iter().copied().collect());
+                }
+                Some(Action::QuickConfirm) if multi => {
+                    if let Some(index) = current {
+                        state.checked.insert(index);
+                    }
+                    return Ok(state.checked.iter().copied().collect());
+                }
+                Some(Action::Confirm | Action::QuickConfirm) => {
+                    if let Some(index) = current {
+                        return Ok(vec![index]);
+                    }
+                }
+                Some(Action::Back) if !self.back => {}
+                Some(Action::Back | Action::Cancel) => return Err(InquireError::OperationCanceled),
+                None => match key.code {
+                    KeyCode::Char('/') if !state.typing && self.keymap.binds_letters() => {
+                        state.typing = true;
+                    }
+                    // With letters bound, the filter is only typed after `/`.
+                    KeyCode::Char(c)
+                        if !key.modifiers.contains(KeyModifiers::CONTROL)
+                            && !self.keymap.binds_letters() =>
+                    {
+                        state.filter.push(c);
+                        state.refilter();
+                    }
+                    KeyCode::Backspace => {
+                        state.filter.pop();
+                        state.refilter();
+                    }
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    /// Loads the next options, if there may be any; returns whether it tried.
+    fn load_more(&mut self, state: &mut State) -> bool {
+        let more = match (&self.more, &self.loading) {
+            (Some(more), Loading::Pending) => more,
+            _ => return false,
+        };
+        match more() {
+            Ok((options, remaining)) => {
+                state
+                    .labels
+                    .extend(options.iter().map(|option| option.to_string()));
+                self.options.extend(options);
+                state.refilter();
+                if !remaining {
+                    self.loading = Loading::Done;
+                }
+            }
+            Err(e) => self.loading = Loading::Failed(format!("couldn't load more: {}", e)),
+        }
+        true
+    }
+
+    /// Lists the options the next way; they're left as they were if that fails.
+    fn switch_mode(&mut self, state: &mut State) {
+        let modes = match &mut self.modes {
+            Some(modes) => modes,
+            None => return,
+        };
+        let next = (modes.current + 1) % modes.names.len();
+        match (modes.load)(next) {
+            Ok((options, remaining)) => {
+                modes.current = next;
+                state.labels = options.iter().map(|option| option.to_string()).collect();
+                self.options = options;
+                state.checked.clear();
+                state.cursor = 0;
+                state.refilter();
+                self.loading = match remaining && self.more.is_some() {
+                    true => Loading::Pending,
+                    false => Loading::Done,
+                };
+            }
+            Err(e) => {
+                let name = &modes.names[next];
+                self.loading = Loading::Failed(format!("couldn't list {}: {}", name, e));
+            }
+        }
+    }
+
+    fn ellipsis(&self) -> &'static str {
+        match self.unicode {
+            true => "…",
+            false => "...",
+        }
+    }
+
+    fn render(&self, state: &mut State, out: &mut Stderr, multi: bool) -> InquireResult<()> {
+        let width = terminal::size()
+            .map(|(w, _)| w as usize)
+            .unwrap_or(80)
+            .max(10);
+        state.clear(out)?;
+        queue!(out, cursor::Hide)?;
+
+        let prefix = self.render_config.prompt_prefix;
+        let mut lines = vec![vec![
+            style(prefix.content, prefix.style),
+            style(&format!(" {} ", self.message), self.render_config.prompt),
+        ]];
+        if let Some(modes) = &self.modes {
+            lines[0].push(style(
+                &format!("[{}] ", modes.names[modes.current]),
+                self.render_config.help_message,
+            ));
+        }
+        if state.typing {
+            lines[0].push(style("/", self.render_config.help_message));
+        }
+        lines[0].push(style(&state.filter, self.render_config.text_input));
+        // A wrapped line would throw off how many `clear` moves up.
+        lines[0] = fit(std::mem::take(&mut lines[0]), width - 1, self.ellipsis());
+
+        let start = state
+            .cursor
+            .saturating_sub(self.page_size.saturating_sub(1))
+            .min(state.visible.len().saturating_sub(self.page_size));
+        let end = (start + self.page_size).min(state.visible.len());
+        for (row, index) in state.visible[start..end].iter().enumerate() {
+            let position = start + row;
+            let marker = if position == state.cursor {
+                self.render_config.highlighted_option_prefix
+            } else if row == 0 && start > 0 {
+                self.render_config.scroll_up_prefix
+            } else if position + 1 == end && end < state.visible.len() {
+                self.render_config.scroll_down_prefix
+            } else {
+                Styled::new(" ")
+            };
+            let mut line = vec![
+                style(marker.content, marker.style),
+                style(" ", StyleSheet::empty()),
+            ];
+            if multi {
+                let checkbox = if state.checked.contains(index) {
+                    self.render_config.selected_checkbox
+                } else {
+                    self.render_config.unselected_checkbox
+                };
+                line.push(style(checkbox.content, checkbox.style));
+                line.push(style(" ", StyleSheet::empty()));
+            }
+            if self.pins.is_some() {
+                let star = if !state.pinned.contains(index) {
+                    " "
+                } else if self.unicode {
+                    "★"
+                } else {
+                    "*"
+                };
+                line.push(style(star, self.render_config.selected_checkbox.style));
+                line.push(style(" ", StyleSheet::empty()));
+            }
+            let used: usize = line.iter().map(|s| s.content().chars().count()).sum();
+            let mut sheet = self.render_config.option;
+            if let Some(fg) = self
+                .colors
+                .as_ref()
+                .and_then(|colors| colors(&self.options[*index]))
+            {
+                sheet.fg = Some(fg);
+            }
+            let available = width.saturating_sub(used + 1);
+            let ellipsis = self.ellipsis();
+            match &self.segments {
+                Some(segments) => {
+                    let mut left = state.labels[*index].chars().count().min(available);
+                    let truncated = left < state.labels[*index].chars().count();
+                    for (text, fg) in segments(&self.options[*index]) {
+                        if left == 0 {
+                            break;
+                        }
+                        let count = text.chars().count();
+                        let text = match count < left || (count == left && !truncated) {
+                            true => text,
+                            // The last piece that fits, ending in the ellipsis.
+                            false => {
+                                let ellipsis: String = ellipsis.chars().take(left).collect();
+                                let mut text: String =
+                                    text.chars().take(left - ellipsis.chars().count()).collect();
+                                text.push_str(&ellipsis);
+                                text
+                            }
+                        };
+                        left = left.saturating_sub(count);
+                        let mut sheet = sheet;
+                        if let Some(fg) = fg {
+                            sheet.fg = Some(fg);
+                        }
+                        line.push(style(&text, sheet));
+                    }
+                }
+                None => line.push(style(
+                    &truncate(&state.labels[*index], available, ellipsis),
+                    sheet,
+                )),
+            }
+            lines.push(line);
+        }
+        if end == state.visible.len() {
+            let row = match &self.loading {
+                Loading::Pending if !state.shows_last() => {
+                    Some("  more not loaded yet; clear the filter to load them".to_string())
+                }
+                Loading::Pending => Some(format!("  loading{}", self.ellipsis())),
+                Loading::Failed(e) => Some(format!("  {}", e)),
+                Loading::Done => None,
+            };
+            if let Some(row) = row {
+                lines.push(vec![style(
+                    &truncate(&row, width - 1, self.ellipsis()),
+                    self.render_config.help_message,
+                )]);
+            }
+        }
+
+        if let (Some(preview), Some(index)) = (&self.preview, state.visible.get(state.cursor)) {
+            lines.push(vec![]);
+            for line in preview(&self.options[*index]) {
+                lines.push(vec![style(
+                    &truncate(&line, width - 1, self.ellipsis()),
+                    StyleSheet::empty(),
+                )]);
+            }
+        }
+
+        let mut keys = vec![match self.unicode {
+            true => "↑↓ to move",
+            false => "up/down to move",
+        }];
+        match (multi, self.pins.is_some()) {
+            (true, _) => keys.extend(["space to select one", "enter to confirm"]),
+            (false, true) => keys.extend(["enter to select", "ctrl-p to pin"]),
+            (false, false) => keys.push("enter to select"),
+        }
+        if self.modes.is_some() {
+            keys.push("ctrl-t to switch listing");
+        }
+        if self.back {
+            keys.push("esc to go back");
+        }
+        keys.push(match (state.typing, self.keymap.binds_letters()) {
+            (true, _) => "esc to stop filtering",
+            (false, true) => "/ to filter",
+            (false, false) => "type to filter",
+        });
+        let help = format!("[{}]", keys.join(", "));
+        lines.push(vec![style(
+            &truncate(&help, width - 1, self.ellipsis()),
+            self.render_config.help_message,
+        )]);
+
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                queue!(out, crossterm::style::Print("\r\n"))?;
+            }
+            for part in line {
+                queue!(out, PrintStyledContent(part.clone()))?;
+            }
+        }
+        state.lines = lines.len() - 1;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+struct State {
+    labels: Vec<String>,
+    filter: String,
+    /// Whether keys go to the filter, after `/`, rather than the keymap.
+    typing: bool,
+    visible: Vec<usize>,
+    cursor: usize,
+    checked: BTreeSet<usize>,
+    pinned: BTreeSet<usize>,
+    lines: usize,
+}
+
+impl State {
+    fn refilter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        self.visible = (0..self.labels.len())
+            .filter(|i| self.labels[*i].to_lowercase().contains(&filter))
+            .collect();
+        self.cursor = self.cursor.min(self.visible.len().saturating_sub(1));
+    }
+
+    /// Whether the last loaded option is among the visible ones.
+    fn shows_last(&self) -> bool {
+        match self.visible.last() {
+            Some(index) => index + 1 == self.labels.len(),
+            None => self.filter.is_empty(),
+        }
+    }
+
+    fn move_up(&mut self, qty: usize, wrap: bool) {
+        self.cursor = match self.cursor.checked_sub(qty) {
+            Some(cursor) => cursor,
+            None if wrap => self.visible.len().saturating_sub(1),
+            None => 0,
+        };
+    }
+
+    fn move_down(&mut self, qty: usize, wrap: bool) {
+        let last = self.visible.len().saturating_sub(1);
+        self.cursor = match self.cursor.saturating_add(qty) {
+            cursor if cursor <= last => cursor,
+            _ if wrap && self.cursor == last => 0,
+            _ => last,
+        };
+    }
+
+    /// Erases the previously rendered frame, leaving the cursor where it began.
+    fn clear(&mut self, out: &mut Stderr) -> std::io::Result<()> {
+        queue!(out, cursor::MoveToColumn(0))?;
+        if self.lines > 0 {
+            queue!(out, cursor::MoveUp(self.lines as u16))?;
+        }
+        queue!(out, Clear(ClearType::FromCursorDown))?;
+        self.lines = 0;
+        Ok(())
+    }
+}
+
+/// `text` cut down to `width` characters, ending in `ellipsis` when cut.
+fn truncate(text: &str, width: usize, ellipsis: &str) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let ellipsis: String = ellipsis.chars().take(width).collect();
+    let mut truncated: String = text
+        .chars()
+        .take(width - ellipsis.chars().count())
+        .collect();
+    truncated.push_str(&ellipsis);
+    truncated
+}
+
+/// `line` cut down to `width` characters like `truncate`, each part keeping
+/// its style.
+fn fit(
+    line: Vec<StyledContent<String>>,
+    width: usize,
+    ellipsis: &str,
+) -> Vec<StyledContent<String>> {
+    let count: usize = line.iter().map(|part| part.content().chars().count()).sum();
+    if count <= width {
+        return line;
+    }
+    let ellipsis: String = ellipsis.chars().take(width).collect();
+    let mut left = width - ellipsis.chars().count();
+    let mut fitted = vec![];
+    for part in line {
+        let text: String = part.content().chars().take(left).collect();
+        left -= text.chars().count();
+        fitted.push(StyledContent::new(*part.style(), text));
+        if left == 0 {
+            break;
+        }
+    }
+    if let Some(last) = fitted.last_mut() {
+        *last = StyledContent::new(*last.style(), format!("{}{}", last.content(), ellipsis));
+    }
+    fitted
+}
+
+/// Converts an inquire style sheet into its crossterm equivalent.
+fn style(text: &str, sheet: StyleSheet) -> StyledContent<String> {
+    let mut content_style = ContentStyle {
+        foreground_color: sheet.fg.map(color),
+        background_color: sheet.bg.map(color),
+        ..ContentStyle::default()
+    };
+    if sheet.att.contains(Attributes::BOLD) {
+        content_style.attributes.set(Attribute::Bold);
+    }
+    if sheet.att.contains(Attributes::ITALIC) {
+        content_style.attributes.set(Attribute::Italic);
+    }
+    StyledContent::new(content_style, text.to_string())
+}
+
+pub fn color(color: Color) -> TermColor {
+    match color {
+        Color::Black => TermColor::Black,
+        Color::LightRed => TermColor::Red,
+        Color::DarkRed => TermColor::DarkRed,
+        Color::LightGreen => TermColor::Green,
+        Color::DarkGreen => TermColor::DarkGreen,
+        Color::LightYellow => TermColor::Yellow,
+        Color::DarkYellow => TermColor::DarkYellow,
+        Color::LightBlue => TermColor::Blue,
+        Color::DarkBlue => TermColor::DarkBlue,
+        Color::LightMagenta => TermColor::Magenta,
+        Color::DarkMagenta => TermColor::DarkMagenta,
+        Color::LightCyan => TermColor::Cyan,
+        Color::DarkCyan => TermColor::DarkCyan,
+        Color::White => TermColor::White,
+        Color::Grey => TermColor::Grey,
+        Color::DarkGrey => TermColor::DarkGrey,
+        Color::Rgb { r, g, b } => TermColor::Rgb { r, g, b },
+        Color::AnsiValue(value) => TermColor::AnsiValue(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(spec: &str) -> KeyEvent {
+        parse_key(spec).unwrap()
+    }
+
+    #[test]
+    fn vim_keys_are_only_bound_when_asked_for() {
+        let default = Keymap::default();
+        assert_eq!(default.action(&key("down"), false), Some(Action::Down));
+        assert_eq!(default.action(&key("j"), false), None);
+        assert!(!default.binds_letters());
+
+        let vim = Keymap::from_config(&Keybindings {
+            vim: true,
+            ..Keybindings::default()
+        })
+        .unwrap();
+        assert_eq!(vim.action(&key("j"), false), Some(Action::Down));
+        assert_eq!(vim.action(&key("ctrl-f"), false), Some(Action::PageDown));
+        assert!(vim.binds_letters());
+    }
+
+    #[test]
+    fn toggle_is_only_bound_in_multi_selects() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(&key("space"), true), Some(Action::Toggle));
+        assert_eq!(keymap.action(&key("space"), false), None);
+    }
+
+    #[test]
+    fn fit_cuts_across_parts_keeping_their_styles() {
+        let bold = ContentStyle {
+            attributes: Attribute::Bold.into(),
+            ..ContentStyle::default()
+        };
+        let line = vec![
+            StyledContent::new(ContentStyle::default(), "? ".to_string()),
+            StyledContent::new(bold, "Pick a tag ".to_string()),
+            StyledContent::new(ContentStyle::default(), "filter".to_string()),
+        ];
+
+        let fitted = fit(line.clone(), 10, "...");
+        let contents: Vec<&str> = fitted.iter().map(|part| part.content().as_str()).collect();
+        assert_eq!(contents, ["? ", "Pick ..."]);
+        assert_eq!(*fitted[1].style(), bold);
+        assert_eq!(fit(line, 19, "...").len(), 3);
+    }
+
+    #[test]
+    fn refilter_keeps_the_cursor_on_a_visible_option() {
+        let mut state = State {
+            labels: ["latest", "v1", "v2"].map(str::to_string).to_vec(),
+            filter: "v".to_string(),
+            typing: false,
+            visible: vec![],
+            cursor: 2,
+            checked: BTreeSet::new(),
+            pinned: BTreeSet::new(),
+            lines: 0,
+        };
+        state.refilter();
+        assert_eq!(state.visible, [1, 2]);
+        assert_eq!(state.cursor, 1);
+    }
+}
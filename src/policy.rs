@@ -0,0 +1,450 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Actions a principal needs to pull an image.
+pub const PULL_ACTIONS: &[&str] = &[
+    "ecr:BatchCheckLayerAvailability",
+    "ecr:BatchGetImage",
+    "ecr:GetDownloadUrlForLayer",
+];
+
+/// Actions a principal needs to push an image.
+const PUSH_ACTIONS: &[&str] = &[
+    "ecr:BatchCheckLayerAvailability",
+    "ecr:CompleteLayerUpload",
+    "ecr:InitiateLayerUpload",
+    "ecr:PutImage",
+    "ecr:UploadLayerPart",
+];
+
+/// Built-in statements for `policy apply --template`.
+pub const TEMPLATES: &[&str] = &["pull-from-account", "push-from-account", "lambda"];
+
+/// The statements a template adds; the account templates grant each of
+/// `accounts` (`--account`) access.
+pub fn template(name: &str, accounts: &[String]) -> Result<Vec<Value>> {
+    let principals = |what: &str| -> Result<Value> {
+        if accounts.is_empty() {
+            bail!("the {} template needs --account", what);
+        }
+        for account in accounts {
+            if account_of(account) != Some(account.as_str()) {
+                bail!("`{}` is not a 12 digit account id", account);
+            }
+        }
+        let arns: Vec<String> = accounts
+            .iter()
+            .map(|account| format!("arn:aws:iam::{}:root", account))
+            .collect();
+        Ok(json!({ "AWS": arns }))
+    };
+    let statement = match name {
+        "pull-from-account" => json!({
+            "Sid": "AllowCrossAccountPull",
+            "Effect": "Allow",
+            "Principal": principals(name)?,
+            "Action": PULL_ACTIONS,
+        }),
+        "push-from-account" => {
+            let mut actions: Vec<&str> = PULL_ACTIONS.iter().chain(PUSH_ACTIONS).copied().collect();
+            actions.sort_unstable();
+            actions.dedup();
+            json!({
+                "Sid": "AllowCrossAccountPush",
+                "Effect": "Allow",
+                "Principal": principals(name)?,
+                "Action": actions,
+            })
+        }
+        "lambda" => json!({
+            "Sid": "LambdaECRImageRetrievalPolicy",
+            "Effect": "Allow",
+            "Principal": { "Service": "lambda.amazonaws.com" },
+            "Action": ["ecr:BatchGetImage", "ecr:GetDownloadUrlForLayer"],
+        }),
+        _ => bail!(
+            "unknown policy template `{}` (expected one of: {})",
+            name,
+            TEMPLATES.join(", ")
+        ),
+    };
+    Ok(vec![statement])
+}
+
+/// Adds `statements` to the `existing` policy, replacing any statement with
+/// the same `Sid` so applying a template twice doesn't duplicate it.
+pub fn merge(existing: Option<&str>, statements: Vec<Value>) -> Result<Value> {
+    let mut current = match existing {
+        Some(existing) => {
+            let document: Value =
+                serde_json::from_str(existing).context("existing policy is not valid JSON")?;
+            match &document["Statement"] {
+                Value::Array(statements) => statements.clone(),
+                Value::Object(_) => vec![document["Statement"].clone()],
+                _ => vec![],
+            }
+        }
+        None => vec![],
+    };
+    for statement in statements {
+        match current
+            .iter_mut()
+            .find(|other| !statement["Sid"].is_null() && other["Sid"] == statement["Sid"])
+        {
+            Some(other) => *other = statement,
+            None => current.push(statement),
+        }
+    }
+    Ok(json!({ "Version": "2012-10-17", "Statement": current }))
+}
+
+/// One principal's entry in a repository policy statement.
+#[derive(Debug, Serialize)]
+pub struct Grant {
+    pub sid: Option<String>,
+    pub effect: String,
+    /// An ARN, account id, service name or `*`.
+    pub principal: String,
+    /// The principals of a `NotPrincipal` statement, which covers everyone
+    /// else; `principal` is `*` then.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<String>,
+    pub actions: Vec<String>,
+    /// Set for a `NotAction` statement, which covers every action but `actions`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub not_action: bool,
+    /// True when the statement has a `Condition`, which this doesn't evaluate.
+    pub conditional: bool,
+}
+
+impl Grant {
+    pub fn allows(&self) -> bool {
+        self.effect.eq_ignore_ascii_case("allow")
+    }
+
+    pub fn grants(&self, action: &str) -> bool {
+        self.actions
+            .iter()
+            .any(|pattern| action_matches(pattern, action))
+            != self.not_action
+    }
+
+    /// Whether the statement is about `principal`. IAM only leaves out the
+    /// principals a `NotPrincipal` names exactly: an account doesn't cover
+    /// its roles there, nor a role its sessions.
+    pub fn covers(&self, principal: &str) -> bool {
+        principal_matches(&self.principal, principal)
+            && !self.except.iter().any(|except| except == principal)
+    }
+
+    pub fn can_pull(&self) -> bool {
+        PULL_ACTIONS.iter().all(|action| self.grants(action))
+    }
+
+    pub fn can_push(&self) -> bool {
+        PUSH_ACTIONS.iter().all(|action| self.grants(action))
+    }
+
+    /// `pull`, `push`, `pull, push`, or the actions themselves.
+    pub fn access(&self) -> String {
+        match (self.can_pull(), self.can_push()) {
+            (true, true) => "pull, push".to_string(),
+            (true, false) => "pull".to_string(),
+            (false, true) => "push".to_string(),
+            (false, false) if self.not_action => format!("all but {}", self.actions.join(", ")),
+            (false, false) => self.actions.join(", "),
+        }
+    }
+
+    /// The account an AWS principal belongs to.
+    pub fn account(&self) -> Option<&str> {
+        account_of(&self.principal)
+    }
+
+    /// True for AWS principals outside `own_account`, and for `*`.
+    pub fn is_cross_account(&self, own_account: &str) -> bool {
+        self.principal == "*" || self.account().is_some_and(|account| account != own_account)
+    }
+}
+
+/// What a policy says about one principal calling one action.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Allowed by the statement with this sid (or index).
+    Allowed(String),
+    /// Only allowed if the statement's conditions hold, which isn't checked.
+    Conditional(String),
+    /// Explicitly denied by this statement.
+    Denied(String),
+    /// No statement applies.
+    NotGranted,
+}
+
+/// Evaluates `action` for `principal` the way IAM does for a resource
+/// policy: an explicit deny wins over any allow. Conditional denies are
+/// treated as applying.
+pub fn evaluate(grants: &[Grant], principal: &str, action: &str) -> Decision {
+    let applicable = || {
+        grants
+            .iter()
+            .enumerate()
+            .filter(move |(_, grant)| grant.grants(action) && grant.covers(principal))
+    };
+    let name = |index: usize, grant: &Grant| {
+        grant
+            .sid
+            .clone()
+            .unwrap_or_else(|| format!("statement {}", index + 1))
+    };
+    if let Some((index, grant)) = applicable().find(|(_, grant)| !grant.allows()) {
+        return Decision::Denied(name(index, grant));
+    }
+    let mut conditional = None;
+    for (index, grant) in applicable() {
+        match grant.conditional {
+            false => return Decision::Allowed(name(index, grant)),
+            true => conditional = conditional.or(Some(name(index, grant))),
+        }
+    }
+    conditional.map_or(Decision::NotGranted, Decision::Conditional)
+}
+
+/// Whether a policy `Principal` entry covers `principal`: `*`, the account
+/// (`123456789012` or `arn:aws:iam::123456789012:root`), or the exact ARN.
+/// An assumed role session is matched by its role's ARN, whatever the
+/// role's path, which session ARNs leave out.
+pub fn principal_matches(pattern: &str, principal: &str) -> bool {
+    if pattern == "*" || pattern == principal {
+        return true;
+    }
+    let account = match account_of(principal) {
+        Some(account) => account,
+        None => return false,
+    };
+    if pattern == account || pattern == format!("arn:aws:iam::{}:root", account) {
+        return true;
+    }
+    // arn:aws:sts::123456789012:assumed-role/Role/session
+    let role = principal
+        .split_once(":assumed-role/")
+        .and_then(|(_, rest)| rest.split('/').next());
+    match role {
+        Some(role) => pattern
+            .strip_prefix(&format!("arn:aws:iam::{}:role/", account))
+            .is_some_and(|path| path.rsplit('/').next() == Some(role)),
+        None => false,
+    }
+}
+
+/// Flattens a policy document into one grant per principal.
+pub fn grants(document: &str) -> Result<Vec<Grant>> {
+    let document: Value = serde_json::from_str(document).context("policy is not valid JSON")?;
+    let statements = match &document["Statement"] {
+        Value::Array(statements) => statements.clone(),
+        Value::Object(_) => vec![document["Statement"].clone()],
+        _ => bail!("policy has no Statement"),
+    };
+    let mut grants = vec![];
+    for statement in statements {
+        let not_action = !statement["NotAction"].is_null();
+        let actions = match not_action {
+            true => strings(&statement["NotAction"]),
+            false => strings(&statement["Action"]),
+        };
+        let principals = |principals: &Value| -> Vec<String> {
+            match principals {
+                Value::Object(principals) => principals.values().flat_map(strings).collect(),
+                principal => strings(principal),
+            }
+        };
+        let (principals, except) = match &statement["NotPrincipal"] {
+            Value::Null => (principals(&statement["Principal"]), vec![]),
+            except => (vec!["*".to_string()], principals(except)),
+        };
+        for principal in principals {
+            grants.push(Grant {
+                sid: statement["Sid"].as_str().map(str::to_string),
+                effect: statement["Effect"].as_str().unwrap_or("Allow").to_string(),
+                principal,
+                except: except.clone(),
+                actions: actions.clone(),
+                not_action,
+                conditional: !statement["Condition"].is_null(),
+            });
+        }
+    }
+    Ok(grants)
+}
+
+/// The account id in an IAM ARN, or a bare 12 digit account id.
+pub fn account_of(principal: &str) -> Option<&str> {
+    if principal.len() == 12 && principal.chars().all(|c| c.is_ascii_digit()) {
+        return Some(principal);
+    }
+    match principal.strip_prefix("arn:")?.split(':').nth(3) {
+        Some(account) if !account.is_empty() => Some(account),
+        _ => None,
+    }
+}
+
+/// The account a repository lives in, from its `<account>.dkr.ecr...` URI.
+pub fn repository_account(uri: &str) -> Option<&str> {
+    uri.split('.').next().and_then(account_of)
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(value) => vec![value.clone()],
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|value| value.as_str().map(str::to_string))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// IAM action matching: case insensitive, with `*` and `?` wildcards.
+fn action_matches(pattern: &str, action: &str) -> bool {
+    fn matches(pattern: &[char], action: &[char]) -> bool {
+        match (pattern.first(), action.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                matches(&pattern[1..], action)
+                    || (!action.is_empty() && matches(pattern, &action[1..]))
+            }
+            (Some('?'), Some(_)) => matches(&pattern[1..], &action[1..]),
+            (Some(p), Some(a)) if p.eq_ignore_ascii_case(a) => matches(&pattern[1..], &action[1..]),
+            _ => false,
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let action: Vec<char> = action.chars().collect();
+    matches(&pattern, &action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(effect: &str, principal: &str, actions: &[&str], conditional: bool) -> Grant {
+        Grant {
+            sid: None,
+            effect: effect.to_string(),
+            principal: principal.to_string(),
+            except: vec![],
+            actions: actions.iter().map(|action| action.to_string()).collect(),
+            not_action: false,
+            conditional,
+        }
+    }
+
+    #[test]
+    fn matches_actions_with_wildcards() {
+        assert!(action_matches("ecr:BatchGetImage", "ecr:BatchGetImage"));
+        assert!(action_matches("ECR:batchgetimage", "ecr:BatchGetImage"));
+        assert!(action_matches("ecr:*", "ecr:PutImage"));
+        assert!(action_matches("*", "ecr:PutImage"));
+        assert!(action_matches("ecr:Batch*Image", "ecr:BatchGetImage"));
+        assert!(action_matches("ecr:?utImage", "ecr:PutImage"));
+        assert!(!action_matches("ecr:Put*", "ecr:BatchGetImage"));
+        assert!(!action_matches("ecr:?", "ecr:"));
+        assert!(!action_matches("ecr:PutImage", "ecr:PutImages"));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let grants = vec![
+            grant("Allow", "*", &["ecr:*"], false),
+            grant("Deny", "123456789012", &["ecr:PutImage"], false),
+        ];
+        let role = "arn:aws:iam::123456789012:role/ci";
+        assert_eq!(
+            evaluate(&grants, role, "ecr:PutImage"),
+            Decision::Denied("statement 2".to_string())
+        );
+        assert_eq!(
+            evaluate(&grants, role, "ecr:BatchGetImage"),
+            Decision::Allowed("statement 1".to_string())
+        );
+    }
+
+    #[test]
+    fn conditional_allows_are_reported_as_such() {
+        let mut conditional = grant("Allow", "123456789012", &["ecr:BatchGetImage"], true);
+        conditional.sid = Some("OnlyFromVpc".to_string());
+        let grants = vec![conditional];
+        assert_eq!(
+            evaluate(&grants, "123456789012", "ecr:BatchGetImage"),
+            Decision::Conditional("OnlyFromVpc".to_string())
+        );
+        assert_eq!(
+            evaluate(&grants, "210987654321", "ecr:BatchGetImage"),
+            Decision::NotGranted
+        );
+    }
+
+    #[test]
+    fn assumed_roles_match_their_role() {
+        let grants = vec![grant(
+            "Allow",
+            "arn:aws:iam::123456789012:role/deploy",
+            &["ecr:BatchGetImage"],
+            false,
+        )];
+        let session = "arn:aws:sts::123456789012:assumed-role/deploy/session";
+        assert_eq!(
+            evaluate(&grants, session, "ecr:BatchGetImage"),
+            Decision::Allowed("statement 1".to_string())
+        );
+        let pathed = vec![grant(
+            "Allow",
+            "arn:aws:iam::123456789012:role/teams/deploy",
+            &["ecr:BatchGetImage"],
+            false,
+        )];
+        assert_eq!(
+            evaluate(&pathed, session, "ecr:BatchGetImage"),
+            Decision::Allowed("statement 1".to_string())
+        );
+        let other = "arn:aws:sts::123456789012:assumed-role/other/session";
+        assert_eq!(
+            evaluate(&grants, other, "ecr:BatchGetImage"),
+            Decision::NotGranted
+        );
+    }
+
+    #[test]
+    fn not_principal_and_not_action_denies_apply_to_everyone_else() {
+        let policy = r#"{"Statement": [
+            {"Effect": "Allow", "Principal": "*", "Action": "ecr:*"},
+            {"Sid": "OnlyCi", "Effect": "Deny",
+             "NotPrincipal": {"AWS": "arn:aws:iam::123456789012:role/ci"},
+             "Action": "ecr:PutImage"},
+            {"Sid": "ReadOnly", "Effect": "Deny", "Principal": {"AWS": "210987654321"},
+             "NotAction": ["ecr:BatchGetImage", "ecr:GetDownloadUrlForLayer"]}
+        ]}"#;
+        let grants = grants(policy).unwrap();
+
+        let ci = "arn:aws:iam::123456789012:role/ci";
+        assert_eq!(
+            evaluate(&grants, ci, "ecr:PutImage"),
+            Decision::Allowed("statement 1".to_string())
+        );
+        // Only the role itself is left out, not its sessions.
+        let session = "arn:aws:sts::123456789012:assumed-role/ci/build";
+        assert_eq!(
+            evaluate(&grants, session, "ecr:PutImage"),
+            Decision::Denied("OnlyCi".to_string())
+        );
+        let other = "arn:aws:iam::210987654321:role/app";
+        assert_eq!(
+            evaluate(&grants, other, "ecr:BatchGetImage"),
+            Decision::Allowed("statement 1".to_string())
+        );
+        assert_eq!(
+            evaluate(&grants, other, "ecr:BatchCheckLayerAvailability"),
+            Decision::Denied("ReadOnly".to_string())
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::ecr::{Decorator, Destination, EcrApi, Image};
+use crate::manifest;
+use crate::referrers;
+use crate::tags;
+
+/// The `artifactType` of the promotion records.
+const ARTIFACT_TYPE: &str = "application/vnd.ecr-tag.promotion.v1+json";
+
+const OCI_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+const EMPTY_CONFIG: &str = "application/vnd.oci.empty.v1+json";
+
+/// Who moved a tag onto a digest, when and why, as stored in the registry.
+#[derive(Serialize)]
+struct Record<'a> {
+    repository: &'a str,
+    tag: &'a str,
+    digest: &'a str,
+    promoted_by: Option<String>,
+    promoted_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ticket: Option<&'a str>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    annotations: &'a BTreeMap<String, String>,
+    tool: String,
+}
+
+/// For `--provenance`: after every tag put through `inner`, pushes a small
+/// OCI artifact recording the promotion, with the tagged image as its
+/// `subject`. It's tagged `sha256-<hex>.provenance-<tag>`, so the latest
+/// record per tag is kept and `ecr-tag referrers` lists it.
+pub struct ProvenanceEcr {
+    inner: Box<dyn EcrApi>,
+    reason: Option<String>,
+    ticket: Option<String>,
+    annotations: BTreeMap<String, String>,
+}
+
+impl ProvenanceEcr {
+    pub fn wrap(
+        inner: Box<dyn EcrApi>,
+        enabled: bool,
+        reason: Option<&str>,
+        ticket: Option<&str>,
+        annotations: &BTreeMap<String, String>,
+    ) -> Box<dyn EcrApi> {
+        if !enabled {
+            return inner;
+        }
+        Box::new(Self {
+            inner,
+            reason: reason.map(str::to_string),
+            ticket: ticket.map(str::to_string),
+            annotations: annotations.clone(),
+        })
+    }
+
+    async fn attach(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        let repository = destination
+            .repository_name
+            .as_deref()
+            .unwrap_or(&image.repository_name);
+        let registry_id = destination.registry_id.as_deref();
+        let record = serde_json::to_vec_pretty(&Record {
+            repository,
+            tag,
+            digest: &image.digest,
+            promoted_by: std::env::var("USER")
+                .or_else(|_| std::env::var("USERNAME"))
+                .ok(),
+            promoted_at: Utc::now().to_rfc3339(),
+            reason: self.reason.as_deref(),
+            ticket: self.ticket.as_deref(),
+            annotations: &self.annotations,
+            tool: format!("ecr-tag/{}", env!("CARGO_PKG_VERSION")),
+        })?;
+        let config = b"{}";
+        let config_digest = self
+            .inner
+            .upload_blob(registry_id, repository, config)
+            .await?;
+        let record_digest = self
+            .inner
+            .upload_blob(registry_id, repository, &record)
+            .await?;
+
+        let subject_type = image
+            .media_type
+            .clone()
+            .or_else(|| manifest::declared_media_type(&image.manifest))
+            .unwrap_or_else(|| "application/vnd.docker.distribution.manifest.v2+json".to_string());
+        let artifact = json!({
+            "schemaVersion": 2,
+            "mediaType": OCI_MANIFEST,
+            "artifactType": ARTIFACT_TYPE,
+            "config": { "mediaType": EMPTY_CONFIG, "digest": config_digest, "size": config.len() },
+            "layers": [{ "mediaType": ARTIFACT_TYPE, "digest": record_digest, "size": record.len() }],
+            "subject": {
+                "mediaType": subject_type,
+                "digest": image.digest,
+                "size": image.manifest.len(),
+            },
+            "annotations": { "org.opencontainers.image.created": Utc::now().to_rfc3339() },
+        })
+        .to_string();
+        let record_tag = format!(
+            "{}.provenance-{}",
+            referrers::fallback_tag(&image.digest),
+            tag
+        );
+        tags::validate(&record_tag).context("the tag is too long to record its provenance")?;
+        let artifact = Image {
+            repository_name: image.repository_name.clone(),
+            digest: format!(
+                "sha256:{}",
+                hex::encode(Sha256::digest(artifact.as_bytes()))
+            ),
+            manifest: artifact,
+            media_type: Some(OCI_MANIFEST.to_string()),
+        };
+        self.inner
+            .put_image(&artifact, &record_tag, destination)
+            .await
+    }
+}
+
+#[async_trait]
+impl Decorator for ProvenanceEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        self.inner.put_image(image, tag, destination).await?;
+        self.attach(image, tag, destination)
+            .await
+            .with_context(|| format!("tagged {} but failed to record its provenance", tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::ecr::MockEcr;
+
+    /// Tags `app`'s `v1` `latest` through a `ProvenanceEcr`, giving the
+    /// artifact's manifest and record.
+    async fn promote(
+        reason: Option<&str>,
+        annotations: &BTreeMap<String, String>,
+    ) -> (String, Value, Value) {
+        let ecr = ProvenanceEcr::wrap(
+            Box::new(MockEcr::app(&[&["v1"]])),
+            true,
+            reason,
+            None,
+            annotations,
+        );
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+        ecr.put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap();
+
+        let record_tag = format!("{}.provenance-latest", referrers::fallback_tag(&digest));
+        let artifact = ecr.tag_digest("app", &record_tag).await.unwrap();
+        let artifact = ecr.image(None, "app", &artifact).await.unwrap();
+        let artifact: Value = serde_json::from_str(&artifact.manifest).unwrap();
+        let layer = artifact["layers"][0]["digest"].as_str().unwrap();
+        let record = serde_json::from_slice(&ecr.blob("app", layer).await.unwrap()).unwrap();
+        (digest, artifact, record)
+    }
+
+    #[tokio::test]
+    async fn attaches_a_record_referring_to_the_tagged_image() {
+        let (digest, artifact, record) = promote(Some("hotfix"), &BTreeMap::new()).await;
+
+        assert_eq!(artifact["artifactType"], ARTIFACT_TYPE);
+        assert_eq!(artifact["subject"]["digest"], digest.as_str());
+        assert_eq!(record["tag"], "latest");
+        assert_eq!(record["digest"], digest.as_str());
+        assert_eq!(record["reason"], "hotfix");
+        assert!(record.get("ticket").is_none());
+        assert!(record.get("annotations").is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_annotations_in_the_record() {
+        let annotations = BTreeMap::from([("change".to_string(), "CHG-42".to_string())]);
+
+        let (_, _, record) = promote(None, &annotations).await;
+
+        assert_eq!(record["annotations"], json!({ "change": "CHG-42" }));
+    }
+}
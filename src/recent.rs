@@ -0,0 +1,117 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How many uses of each repository are remembered for [`Selections::frecency`].
+const MAX_VISITS: usize = 10;
+
+/// What was picked in earlier interactive runs, per AWS profile, so the
+/// pickers can start on it again and list often used repositories first.
+///
+/// Kept in `<data dir>/ecr-tag/recent.json`; failing to read or write it
+/// never fails a run.
+pub struct Recent {
+    path: Option<PathBuf>,
+    profile: String,
+    profiles: BTreeMap<String, Selections>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Selections {
+    pub repository: Option<String>,
+    /// Last image digest picked, by repository.
+    pub images: BTreeMap<String, String>,
+    /// Last tag given, by repository.
+    pub tags: BTreeMap<String, String>,
+    /// When each repository was last tagged in, most recent last.
+    pub visits: BTreeMap<String, Vec<DateTime<Utc>>>,
+    /// Repositories pinned from the picker.
+    pub pins: BTreeSet<String>,
+}
+
+impl Selections {
+    /// Frequency and recency of use rolled into one score: each remembered
+    /// use counts for less the older it is.
+    pub fn frecency(&self, repository: &str) -> u64 {
+        let now = Utc::now();
+        self.visits
+            .get(repository)
+            .into_iter()
+            .flatten()
+            .map(|visit| match (now - *visit).num_days() {
+                0..=3 => 100,
+                4..=13 => 70,
+                14..=30 => 50,
+                31..=90 => 30,
+                _ => 10,
+            })
+            .sum()
+    }
+}
+
+impl Recent {
+    /// The picks made with `profile`.
+    pub fn load(profile: String) -> Self {
+        let path = dirs_next::data_dir().map(|dir| dir.join("ecr-tag").join("recent.json"));
+        let profiles = path
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            profile,
+            profiles,
+        }
+    }
+
+    pub fn selections(&self) -> Selections {
+        self.profiles
+            .get(&self.profile)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Remembers a completed pick.
+    pub fn record(&mut self, repository: &str, digest: &str, tag: &str) {
+        let selections = self.profiles.entry(self.profile.clone()).or_default();
+        selections.repository = Some(repository.to_string());
+        selections
+            .images
+            .insert(repository.to_string(), digest.to_string());
+        selections
+            .tags
+            .insert(repository.to_string(), tag.to_string());
+        let visits = selections.visits.entry(repository.to_string()).or_default();
+        visits.push(Utc::now());
+        if visits.len() > MAX_VISITS {
+            visits.drain(..visits.len() - MAX_VISITS);
+        }
+        self.save();
+    }
+
+    /// Adds `repository` to, or drops it from, the favorites.
+    pub fn set_pinned(&mut self, repository: &str, pinned: bool) {
+        let pins = &mut self.profiles.entry(self.profile.clone()).or_default().pins;
+        match pinned {
+            true => pins.insert(repository.to_string()),
+            false => pins.remove(repository),
+        };
+        self.save();
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let (Some(dir), Ok(contents)) =
+            (path.parent(), serde_json::to_vec_pretty(&self.profiles))
+        {
+            let _ = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(path, contents));
+        }
+    }
+}
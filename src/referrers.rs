@@ -0,0 +1,151 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ecr::{Decorator, Destination, EcrApi, Image};
+
+/// Copies each image's referrers along when it's put into another
+/// repository or registry through `inner`, for `--with-referrers`.
+pub struct ReferrersEcr {
+    inner: Box<dyn EcrApi>,
+}
+
+impl ReferrersEcr {
+    pub fn wrap(inner: Box<dyn EcrApi>, enabled: bool) -> Box<dyn EcrApi> {
+        match enabled {
+            true => Box::new(Self { inner }),
+            false => inner,
+        }
+    }
+}
+
+#[async_trait]
+impl Decorator for ReferrersEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        self.inner.put_image(image, tag, destination).await?;
+        let elsewhere = destination.registry_id.is_some()
+            || destination
+                .repository_name
+                .as_ref()
+                .is_some_and(|repository| *repository != image.repository_name);
+        if elsewhere {
+            let copied = copy(
+                self.inner.as_ref(),
+                &image.repository_name,
+                &image.digest,
+                destination,
+            )
+            .await?;
+            if copied > 0 {
+                eprintln!("copied {} referrer(s) of {}", copied, image.digest);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An artifact (signature, SBOM, attestation, ...) attached to an image.
+#[derive(Debug, Serialize)]
+pub struct Referrer {
+    pub digest: String,
+    /// The OCI `artifactType`, or the kind of cosign tag it was found under.
+    pub artifact_type: Option<String>,
+    pub size: i64,
+    /// The tag it was found through.
+    pub tag: String,
+    /// Listed in the fallback referrers index rather than tagged itself.
+    pub in_index: bool,
+}
+
+/// The OCI referrers tag scheme's tag for `digest`, e.g. `sha256-abc...`.
+/// cosign's `.sig`, `.att` and `.sbom` tags start with it too.
+pub fn fallback_tag(digest: &str) -> String {
+    digest.replacen(':', "-", 1)
+}
+
+/// Whether `tag` belongs to the fallback scheme: a referrers index's
+/// `sha256-<hex>`, or a cosign or provenance tag starting with it.
+pub fn is_fallback_tag(tag: &str) -> bool {
+    let hex = match tag.strip_prefix("sha256-") {
+        Some(rest) => rest.split('.').next().unwrap_or_default(),
+        None => return false,
+    };
+    hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// The referrers of `repository@digest`, found through the fallback tag
+/// scheme: the referrers index tagged `sha256-<hex>` and cosign's
+/// `sha256-<hex>.sig`/`.att`/`.sbom` tags.
+pub async fn list(ecr: &dyn EcrApi, repository: &str, digest: &str) -> Result<Vec<Referrer>> {
+    let base = fallback_tag(digest);
+    let mut referrers = vec![];
+    for image in ecr.images(repository).await? {
+        for tag in &image.tags {
+            if *tag == base {
+                let index = ecr.image(None, repository, &image.digest).await?;
+                let index: Value = serde_json::from_str(&index.manifest)
+                    .with_context(|| format!("the referrers index {} is not valid JSON", tag))?;
+                for manifest in index["manifests"].as_array().into_iter().flatten() {
+                    referrers.push(Referrer {
+                        digest: manifest["digest"].as_str().unwrap_or_default().to_string(),
+                        artifact_type: manifest["artifactType"].as_str().map(str::to_string),
+                        size: manifest["size"].as_i64().unwrap_or_default(),
+                        tag: tag.clone(),
+                        in_index: true,
+                    });
+                }
+            } else if let Some(kind) = tag.strip_prefix(&format!("{}.", base)) {
+                let artifact_type = match kind {
+                    "sig" => "cosign signature",
+                    "att" => "cosign attestation",
+                    "sbom" => "cosign sbom",
+                    other if other.starts_with("provenance-") => "ecr-tag provenance",
+                    other => other,
+                };
+                referrers.push(Referrer {
+                    digest: image.digest.clone(),
+                    artifact_type: Some(artifact_type.to_string()),
+                    size: image.size,
+                    tag: tag.clone(),
+                    in_index: false,
+                });
+            }
+        }
+    }
+    Ok(referrers)
+}
+
+/// Copies the referrers of `repository@digest` to `destination` under the
+/// same tags; their blobs must already be there, as for any copied image.
+/// Returns how many were copied.
+pub async fn copy(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    digest: &str,
+    destination: &Destination,
+) -> Result<usize> {
+    let referrers = list(ecr, repository, digest).await?;
+    let base = fallback_tag(digest);
+    // ECR only takes an index whose manifests are already in the repository,
+    // and only puts tagged manifests, so the index's tag carries each of
+    // them in on the way to the index itself.
+    for referrer in &referrers {
+        let image = ecr.image(None, repository, &referrer.digest).await?;
+        ecr.put_image(&image, &referrer.tag, destination)
+            .await
+            .with_context(|| format!("failed to copy referrer {}", referrer.digest))?;
+    }
+    if referrers.iter().any(|referrer| referrer.in_index) {
+        let index_digest = ecr.tag_digest(repository, &base).await?;
+        let index = ecr.image(None, repository, &index_digest).await?;
+        ecr.put_image(&index, &base, destination)
+            .await
+            .context("failed to copy the referrers index")?;
+    }
+    Ok(referrers.len())
+}
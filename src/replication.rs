@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+
+use crate::ecr::{Destination, EcrApi};
+use crate::ui::Ui;
+
+/// How often each destination is polled.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Polls every replication destination of the registry until each of `tags`
+/// (tag, digest) shows up in the replicated repository, reporting each
+/// destination as it lands or once `timeout` runs out.
+///
+/// Fails if anything didn't arrive in time, so a release pipeline doesn't go
+/// on to deploy in a region that can't pull the image yet.
+pub async fn verify(
+    ecr: &dyn EcrApi,
+    ui: &Ui,
+    destination: &Destination,
+    source_repository: &str,
+    tags: &[(String, String)],
+    timeout: Duration,
+) -> Result<()> {
+    if destination.registry_id.is_some() {
+        eprintln!("note: not verifying replication, it's only known for your own registry");
+        return Ok(());
+    }
+    let repository_name = destination
+        .repository_name
+        .as_deref()
+        .unwrap_or(source_repository);
+    let destinations = ecr.replication_destinations().await?;
+    if destinations.is_empty() {
+        eprintln!("note: the registry has no replication destinations to verify");
+        return Ok(());
+    }
+    let replicas = destinations
+        .iter()
+        .map(|destination| ecr.replica(&destination.region, &destination.registry_id))
+        .collect::<Result<Vec<_>>>()?;
+
+    let started = Instant::now();
+    let mut pending: Vec<(usize, &str, &str)> = (0..destinations.len())
+        .flat_map(|i| {
+            tags.iter()
+                .map(move |(tag, digest)| (i, tag.as_str(), digest.as_str()))
+        })
+        .collect();
+    loop {
+        let mut still_pending = vec![];
+        for (i, tag, digest) in pending {
+            // Not there, or failing, just means it hasn't arrived yet.
+            match replicas[i].resolve_tag(None, repository_name, tag).await {
+                Ok(Some(found)) if found == digest => ui.status(
+                    true,
+                    &format!(
+                        "{} ({}): {}:{} replicated after {}s",
+                        destinations[i].region,
+                        destinations[i].registry_id,
+                        repository_name,
+                        tag,
+                        started.elapsed().as_secs()
+                    ),
+                ),
+                _ => still_pending.push((i, tag, digest)),
+            }
+        }
+        pending = still_pending;
+        if pending.is_empty() {
+            return Ok(());
+        }
+        if started.elapsed() + POLL_INTERVAL > timeout {
+            break;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+
+    for (i, tag, digest) in &pending {
+        ui.status(
+            false,
+            &format!(
+                "{} ({}): {}:{} -> {} not replicated within {}s",
+                destinations[*i].region,
+                destinations[*i].registry_id,
+                repository_name,
+                tag,
+                digest,
+                timeout.as_secs()
+            ),
+        );
+    }
+    bail!(
+        "{} tag(s) didn't reach every replication destination in time",
+        pending.len()
+    )
+}
@@ -0,0 +1,216 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::ecr::EcrApi;
+use crate::manifest;
+use crate::model::ImageDetail;
+use crate::referrers;
+
+/// Which images a cleanup may remove.
+///
+/// Protected tags always survive, as do the newest `keep` images. Of the
+/// rest, everything is removed unless `max_age` is set, in which case only
+/// images pushed longer ago than that are. Images that belong to another
+/// are left out first, see [`standalone`].
+#[derive(Debug, Default)]
+pub struct Rules {
+    pub keep: Option<usize>,
+    pub max_age: Option<Duration>,
+    pub protect: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Verdict {
+    pub image: ImageDetail,
+    pub delete: bool,
+    pub reason: String,
+}
+
+impl Rules {
+    /// Decides the fate of every image, newest first.
+    pub fn evaluate(&self, mut images: Vec<ImageDetail>, now: DateTime<Utc>) -> Vec<Verdict> {
+        images.sort_by_key(|image| image.created);
+        images.reverse();
+
+        images
+            .into_iter()
+            .enumerate()
+            .map(|(position, image)| {
+                let (delete, reason) = self.judge(position, &image, now);
+                Verdict {
+                    image,
+                    delete,
+                    reason,
+                }
+            })
+            .collect()
+    }
+
+    fn judge(&self, position: usize, image: &ImageDetail, now: DateTime<Utc>) -> (bool, String) {
+        if let Some(tag) = image.tags.iter().find(|tag| self.protect.contains(tag)) {
+            return (false, format!("protected tag {}", tag));
+        }
+        if let Some(keep) = self.keep {
+            if position < keep {
+                return (false, format!("within newest {}", keep));
+            }
+        }
+        match self.max_age {
+            Some(max_age) if now - image.created <= max_age => {
+                (false, format!("younger than {}", format_age(max_age)))
+            }
+            Some(max_age) => (true, format!("older than {}", format_age(max_age))),
+            None => (true, format!("beyond newest {}", self.keep.unwrap_or(0))),
+        }
+    }
+}
+
+/// `images` without those that only exist for another image's sake: the
+/// platform images of multi-platform indexes, and the signatures,
+/// attestations and other referrers tagged through the fallback scheme.
+/// Counting them would push real images out of `keep`, and deleting them
+/// would break the index or strip the image of its signature.
+pub async fn standalone(ecr: &dyn EcrApi, images: Vec<ImageDetail>) -> Result<Vec<ImageDetail>> {
+    let mut attached = HashSet::new();
+    for image in &images {
+        if image.media_type.as_deref().is_some_and(manifest::is_index) {
+            let index = ecr
+                .image(None, &image.repository_name, &image.digest)
+                .await?;
+            let index: Value = serde_json::from_str(&index.manifest)
+                .with_context(|| format!("the index {} is not valid JSON", image.digest))?;
+            for child in index["manifests"].as_array().into_iter().flatten() {
+                if let Some(digest) = child["digest"].as_str() {
+                    attached.insert(digest.to_string());
+                }
+            }
+        }
+    }
+    Ok(images
+        .into_iter()
+        .filter(|image| {
+            let referrer = !image.tags.is_empty()
+                && image.tags.iter().all(|tag| referrers::is_fallback_tag(tag));
+            !referrer && !attached.contains(&image.digest)
+        })
+        .collect())
+}
+
+/// Parses ages like `36h`, `90d` or `4w`.
+pub fn parse_age(value: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid age `{}` (expected e.g. 36h, 90d or 4w)", value);
+    let (at, _) = value.char_indices().last().ok_or_else(invalid)?;
+    let (number, unit) = value.split_at(at);
+    if !number.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let number = match number.parse::<u32>() {
+        Ok(number) if number > 0 => i64::from(number),
+        _ => return Err(invalid()),
+    };
+    Ok(match unit {
+        "h" => Duration::hours(number),
+        "d" => Duration::days(number),
+        "w" => Duration::weeks(number),
+        _ => bail!(invalid()),
+    })
+}
+
+pub fn format_age(age: Duration) -> String {
+    match age.num_hours() {
+        hours if hours % 24 != 0 => format!("{}h", hours),
+        hours => format!("{}d", hours / 24),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ages() {
+        assert_eq!(parse_age("36h").unwrap(), Duration::hours(36));
+        assert_eq!(parse_age("90d").unwrap(), Duration::days(90));
+        assert_eq!(parse_age("4w").unwrap(), Duration::weeks(4));
+    }
+
+    #[test]
+    fn rejects_invalid_ages() {
+        for age in [
+            "",
+            "d",
+            "90",
+            "90m",
+            "-3d",
+            "0d",
+            "+3d",
+            "1.5d",
+            "3é",
+            "é",
+            "99999999999w",
+        ] {
+            assert!(parse_age(age).is_err(), "{} should be rejected", age);
+        }
+    }
+
+    #[test]
+    fn formats_ages() {
+        assert_eq!(format_age(Duration::hours(36)), "36h");
+        assert_eq!(format_age(Duration::weeks(2)), "14d");
+    }
+
+    fn image(day: u32, tags: &[&str]) -> ImageDetail {
+        ImageDetail {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            digest: format!("sha256:{}", day),
+            created: format!("2023-01-{:02}T00:00:00Z", day).parse().unwrap(),
+            repository_name: "app".to_string(),
+            size: 0,
+            scan_status: None,
+            severity_counts: Default::default(),
+            media_type: None,
+            labels: Default::default(),
+        }
+    }
+
+    fn deleted(rules: &Rules, images: Vec<ImageDetail>, now: &str) -> Vec<String> {
+        rules
+            .evaluate(images, now.parse().unwrap())
+            .into_iter()
+            .filter(|verdict| verdict.delete)
+            .map(|verdict| verdict.image.digest)
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_newest_and_protected_images() {
+        let rules = Rules {
+            keep: Some(1),
+            max_age: None,
+            protect: vec!["stable".to_string()],
+        };
+        let images = vec![image(1, &["stable"]), image(2, &[]), image(3, &["latest"])];
+        assert_eq!(
+            deleted(&rules, images, "2023-02-01T00:00:00Z"),
+            vec!["sha256:2"]
+        );
+    }
+
+    #[test]
+    fn max_age_spares_younger_images() {
+        let rules = Rules {
+            keep: Some(0),
+            max_age: Some(Duration::days(7)),
+            protect: vec![],
+        };
+        let images = vec![image(1, &[]), image(25, &[]), image(28, &[])];
+        assert_eq!(
+            deleted(&rules, images, "2023-01-30T00:00:00Z"),
+            vec!["sha256:1"]
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use serde_json::{json, Value};
+
+use crate::model::Finding;
+
+/// A SARIF 2.1.0 log of `findings` for the image at `location` (a
+/// `repository@digest` reference), one rule per CVE, ready for GitHub code
+/// scanning's upload-sarif.
+pub fn log(location: &str, findings: &[Finding]) -> Value {
+    let mut rules: Vec<Value> = vec![];
+    let mut results = vec![];
+    for finding in findings {
+        if !rules.iter().any(|rule| rule["id"] == finding.name.as_str()) {
+            rules.push(rule(finding));
+        }
+        let package = match (&finding.package, &finding.package_version) {
+            (Some(package), Some(version)) => format!(" in {} {}", package, version),
+            (Some(package), None) => format!(" in {}", package),
+            _ => String::new(),
+        };
+        results.push(json!({
+            "ruleId": finding.name,
+            "level": level(&finding.severity),
+            "message": {
+                "text": format!("{} {}{} ({})", finding.severity, finding.name, package, location),
+            },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": location },
+                },
+            }],
+        }));
+    }
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "ecr-tag",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn rule(finding: &Finding) -> Value {
+    let description = finding.description.as_deref().unwrap_or(&finding.name);
+    let mut rule = json!({
+        "id": finding.name,
+        "shortDescription": { "text": finding.name },
+        "fullDescription": { "text": description },
+        "defaultConfiguration": { "level": level(&finding.severity) },
+        "properties": {
+            "tags": ["security", "vulnerability"],
+            // GitHub ranks alerts by this CVSS-style score.
+            "security-severity": format!("{:.1}", security_severity(finding)),
+        },
+    });
+    if let Some(uri) = &finding.uri {
+        rule["helpUri"] = json!(uri);
+    }
+    rule
+}
+
+fn level(severity: &str) -> &'static str {
+    match severity {
+        "CRITICAL" | "HIGH" => "error",
+        "MEDIUM" => "warning",
+        _ => "note",
+    }
+}
+
+fn security_severity(finding: &Finding) -> f64 {
+    finding
+        .cvss_score
+        .unwrap_or(match finding.severity.as_str() {
+            "CRITICAL" => 9.5,
+            "HIGH" => 8.0,
+            "MEDIUM" => 5.5,
+            "LOW" => 2.0,
+            _ => 0.0,
+        })
+}
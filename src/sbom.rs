@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::model::Finding;
+
+/// The SBOM document formats `ecr-tag sbom` writes.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SbomFormat {
+    CycloneDx,
+    Spdx,
+}
+
+impl std::str::FromStr for SbomFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "cyclonedx" => Self::CycloneDx,
+            "spdx" => Self::Spdx,
+            _ => bail!("unknown SBOM format `{}` (expected cyclonedx or spdx)", s),
+        })
+    }
+}
+
+/// An SBOM of the image at `repository@digest` in `format`. Its inventory is
+/// the packages the enhanced scan reported findings in, which is what ECR
+/// exposes without Inspector's own SBOM export.
+pub fn document(format: SbomFormat, repository: &str, digest: &str, findings: &[Finding]) -> Value {
+    match format {
+        SbomFormat::CycloneDx => cyclonedx(repository, digest, findings),
+        SbomFormat::Spdx => spdx(repository, digest, findings),
+    }
+}
+
+/// The distinct `(name, version)` packages of `findings`, each with a stable
+/// reference, and the findings against it.
+fn packages(findings: &[Finding]) -> BTreeMap<(String, String), Vec<&Finding>> {
+    let mut packages: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for finding in findings {
+        if let Some(package) = &finding.package {
+            let version = finding.package_version.clone().unwrap_or_default();
+            packages
+                .entry((package.clone(), version))
+                .or_default()
+                .push(finding);
+        }
+    }
+    packages
+}
+
+fn reference(name: &str, version: &str) -> String {
+    match version.is_empty() {
+        true => name.to_string(),
+        false => format!("{}@{}", name, version),
+    }
+}
+
+fn cyclonedx(repository: &str, digest: &str, findings: &[Finding]) -> Value {
+    let packages = packages(findings);
+    let components: Vec<Value> = packages
+        .keys()
+        .map(|(name, version)| {
+            json!({
+                "type": "library",
+                "bom-ref": reference(name, version),
+                "name": name,
+                "version": version,
+            })
+        })
+        .collect();
+    let mut vulnerabilities: BTreeMap<&str, Value> = BTreeMap::new();
+    for ((name, version), findings) in &packages {
+        for finding in findings {
+            let vulnerability = vulnerabilities.entry(&finding.name).or_insert_with(|| {
+                let mut rating = json!({ "severity": finding.severity.to_lowercase() });
+                if let Some(score) = finding.cvss_score {
+                    rating["score"] = json!(score);
+                }
+                let mut vulnerability = json!({
+                    "id": finding.name,
+                    "ratings": [rating],
+                    "affects": [],
+                });
+                if let Some(description) = &finding.description {
+                    vulnerability["description"] = json!(description);
+                }
+                if let Some(uri) = &finding.uri {
+                    vulnerability["advisories"] = json!([{ "url": uri }]);
+                }
+                vulnerability
+            });
+            let affects = vulnerability["affects"].as_array_mut().unwrap();
+            let bom_ref = json!(reference(name, version));
+            if !affects.iter().any(|affect| affect["ref"] == bom_ref) {
+                affects.push(json!({ "ref": bom_ref }));
+            }
+        }
+    }
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": Utc::now().to_rfc3339(),
+            "tools": [{ "name": "ecr-tag", "version": env!("CARGO_PKG_VERSION") }],
+            "component": {
+                "type": "container",
+                "bom-ref": format!("{}@{}", repository, digest),
+                "name": repository,
+                "version": digest,
+            },
+        },
+        "components": components,
+        "vulnerabilities": vulnerabilities.into_values().collect::<Vec<_>>(),
+    })
+}
+
+fn spdx(repository: &str, digest: &str, findings: &[Finding]) -> Value {
+    // SPDX ids only allow letters, digits, `.` and `-`.
+    let spdx_id = |name: &str| {
+        let id: String = name
+            .chars()
+            .map(
+                |c| match c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    true => c,
+                    false => '-',
+                },
+            )
+            .collect();
+        format!("SPDXRef-{}", id)
+    };
+    let image_id = "SPDXRef-image";
+    let mut spdx_packages = vec![json!({
+        "SPDXID": image_id,
+        "name": repository,
+        "versionInfo": digest,
+        "downloadLocation": "NOASSERTION",
+        "primaryPackagePurpose": "CONTAINER",
+    })];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": image_id,
+    })];
+    for (index, ((name, version), findings)) in packages(findings).into_iter().enumerate() {
+        let id = spdx_id(&format!("{}-{}", index, reference(&name, &version)));
+        let external_refs: Vec<Value> = findings
+            .iter()
+            .map(|finding| {
+                json!({
+                    "referenceCategory": "SECURITY",
+                    "referenceType": "advisory",
+                    "referenceLocator": finding.uri.clone().unwrap_or_else(|| finding.name.clone()),
+                    "comment": format!("{} {}", finding.severity, finding.name),
+                })
+            })
+            .collect();
+        spdx_packages.push(json!({
+            "SPDXID": id,
+            "name": name,
+            "versionInfo": version,
+            "downloadLocation": "NOASSERTION",
+            "externalRefs": external_refs,
+        }));
+        relationships.push(json!({
+            "spdxElementId": image_id,
+            "relationshipType": "CONTAINS",
+            "relatedSpdxElement": id,
+        }));
+    }
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": format!("{}@{}", repository, digest),
+        "documentNamespace": format!(
+            "https://spdx.org/spdxdocs/ecr-tag/{}/{}",
+            repository,
+            digest.trim_start_matches("sha256:")
+        ),
+        "creationInfo": {
+            "created": Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: ecr-tag-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": spdx_packages,
+        "relationships": relationships,
+    })
+}
@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Context, Error, Result};
+use chrono::Utc;
+use inquire::ui::Color;
+
+use crate::audit;
+use crate::config::{PromotionPolicy, RepoConfig, Signing};
+use crate::ecr::EcrApi;
+use crate::junit::{Case, Checks};
+use crate::retention;
+use crate::sign;
+use crate::tags;
+
+/// ECR's finding severities, lowest first. `UNDEFINED` findings never trip a gate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Informational,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl FromStr for Severity {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.to_lowercase().as_str() {
+            "informational" | "info" => Self::Informational,
+            "low" => Self::Low,
+            "medium" => Self::Medium,
+            "high" => Self::High,
+            "critical" => Self::Critical,
+            _ => bail!(
+                "unknown severity `{}` (expected critical, high, medium, low or informational)",
+                value
+            ),
+        })
+    }
+}
+
+impl Severity {
+    /// The colour findings of this severity are drawn in.
+    pub fn color(self) -> Color {
+        match self {
+            Self::Critical => Color::LightMagenta,
+            Self::High => Color::LightRed,
+            Self::Medium => Color::LightYellow,
+            Self::Low => Color::LightBlue,
+            Self::Informational => Color::Grey,
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Informational => "INFORMATIONAL",
+            Self::Low => "LOW",
+            Self::Medium => "MEDIUM",
+            Self::High => "HIGH",
+            Self::Critical => "CRITICAL",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Refuses to tag images with findings at or above `fail_on`, or breaking
+/// a matching promotion policy in `repos`, unless forced.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gate<'a> {
+    pub fail_on: Option<Severity>,
+    pub force: bool,
+    /// Required to force past a promotion policy.
+    pub reason: Option<&'a str>,
+    pub repos: Option<&'a BTreeMap<String, RepoConfig>>,
+    /// Set to require a valid signature; not even `--force` skips it.
+    pub signing: Option<&'a Signing>,
+    /// The AWS profile cosign verifies with, instead of `AWS_PROFILE`.
+    pub profile: Option<&'a str>,
+    /// Where each check's outcome is recorded, for `--output junit`.
+    pub checks: Option<&'a Checks>,
+}
+
+impl Gate<'_> {
+    /// Checks `digest` before it's tagged `tag` in `repository`.
+    pub async fn check(
+        &self,
+        ecr: &dyn EcrApi,
+        repository: &str,
+        digest: &str,
+        tag: &str,
+    ) -> Result<()> {
+        self.check_age(ecr, repository, digest, tag).await?;
+        self.check_findings(ecr, repository, digest, tag).await
+    }
+
+    async fn check_findings(
+        &self,
+        ecr: &dyn EcrApi,
+        repository: &str,
+        digest: &str,
+        tag: &str,
+    ) -> Result<()> {
+        let policy = self.policy(repository, tag)?;
+        let threshold = match self.fail_on.into_iter().chain(policy.map(|p| p.0)).min() {
+            Some(threshold) => threshold,
+            None => return Ok(()),
+        };
+        let counts = ecr.severity_counts(repository, digest).await?;
+        let case = Case::new(
+            format!("{}:{}", repository, tag),
+            format!("no findings at or above {}", threshold),
+        );
+        let problem = match describe(counts.as_ref(), digest, threshold) {
+            Some(problem) => problem,
+            None => {
+                self.record(case);
+                return Ok(());
+            }
+        };
+        // Whether the policy blocks it too, rather than just --fail-on.
+        let policy = policy
+            .filter(|(severity, _)| describe(counts.as_ref(), digest, *severity).is_some())
+            .map(|(severity, pattern)| (pattern, format!("fail_on = {}", severity)));
+        let result = self.enforce(&problem, policy, repository, digest, tag);
+        self.record(match &result {
+            Ok(()) => case.with_output(format!("{} (forced)", problem)),
+            Err(e) => case.failed(format!("{:#}", e)),
+        });
+        result
+    }
+
+    /// Refuses images pushed more recently than the `min_age` of a policy
+    /// covering `tag`.
+    async fn check_age(
+        &self,
+        ecr: &dyn EcrApi,
+        repository: &str,
+        digest: &str,
+        tag: &str,
+    ) -> Result<()> {
+        let (min_age, pattern) = match self.min_age(repository, tag)? {
+            Some(min_age) => min_age,
+            None => return Ok(()),
+        };
+        let pushed = ecr
+            .images(repository)
+            .await?
+            .into_iter()
+            .find(|image| image.digest == digest)
+            .ok_or_else(|| anyhow!("{} isn't in {}", digest, repository))?
+            .created;
+        let case = Case::new(
+            format!("{}:{}", repository, tag),
+            format!("pushed at least {} ago", retention::format_age(min_age)),
+        );
+        let age = Utc::now() - pushed;
+        if age >= min_age {
+            self.record(case);
+            return Ok(());
+        }
+        let problem = format!(
+            "{} was pushed only {} ago",
+            digest,
+            retention::format_age(age)
+        );
+        let rule = format!("min_age = {}", retention::format_age(min_age));
+        let result = self.enforce(&problem, Some((pattern, rule)), repository, digest, tag);
+        self.record(match &result {
+            Ok(()) => case.with_output(format!("{} (forced)", problem)),
+            Err(e) => case.failed(format!("{:#}", e)),
+        });
+        result
+    }
+
+    /// Fails on `problem` unless forced, recording overrides of `policy`,
+    /// given as its tag pattern and the rule broken.
+    fn enforce(
+        &self,
+        problem: &str,
+        policy: Option<(&str, String)>,
+        repository: &str,
+        digest: &str,
+        tag: &str,
+    ) -> Result<()> {
+        match (policy, self.force) {
+            (None, false) => bail!("{}; pass --force to tag it anyway", problem),
+            (Some((pattern, _)), false) => bail!(
+                "{}, which {}'s promotion policy forbids for {} tags; pass --force --reason <why> to tag it anyway",
+                problem,
+                repository,
+                pattern
+            ),
+            (None, true) => {
+                eprintln!("warning: {} (tagging anyway, --force)", problem);
+                Ok(())
+            }
+            (Some((pattern, rule)), true) => {
+                let reason = self.reason.ok_or_else(|| {
+                    anyhow!(
+                        "{}; overriding {}'s promotion policy for {} tags needs --reason",
+                        problem,
+                        repository,
+                        pattern
+                    )
+                })?;
+                audit::record(&audit::Override {
+                    repository,
+                    tag,
+                    digest,
+                    policy: format!("{} ({})", pattern, rule),
+                    findings: problem,
+                    reason,
+                })?;
+                eprintln!(
+                    "warning: {} (overriding the promotion policy: {}; recorded in the audit log)",
+                    problem, reason
+                );
+                Ok(())
+            }
+        }
+    }
+
+    /// Refuses `repository@digest` unless it's signed, when signatures are
+    /// required.
+    pub async fn verify_signature(
+        &self,
+        ecr: &dyn EcrApi,
+        repository: &str,
+        digest: &str,
+    ) -> Result<()> {
+        let signing = match self.signing {
+            Some(signing) => signing,
+            None => return Ok(()),
+        };
+        let result = sign::verify(ecr, signing, self.profile, repository, digest).await;
+        let case = Case::new(format!("{}@{}", repository, digest), "signature");
+        self.record(match &result {
+            Ok(()) => case,
+            Err(e) => case.failed(format!("{:#}", e)),
+        });
+        result
+    }
+
+    fn record(&self, case: Case) {
+        if let Some(checks) = self.checks {
+            checks.record(case);
+        }
+    }
+
+    /// `repository`'s policies covering `tag`, with the tag pattern that
+    /// matched.
+    fn policies(&self, repository: &str, tag: &str) -> Vec<(&PromotionPolicy, &str)> {
+        let policies = match self.repos.and_then(|repos| repos.get(repository)) {
+            Some(repo) => &repo.policy,
+            None => return vec![],
+        };
+        policies
+            .iter()
+            .filter_map(|policy| {
+                match policy
+                    .tags
+                    .iter()
+                    .find(|pattern| tags::matches(pattern, tag))
+                {
+                    Some(pattern) => Some((policy, pattern.as_str())),
+                    None if policy.tags.is_empty() => Some((policy, "all")),
+                    None => None,
+                }
+            })
+            .collect()
+    }
+
+    /// The strictest threshold of `repository`'s policies covering `tag`,
+    /// with the tag pattern that set it.
+    fn policy(&self, repository: &str, tag: &str) -> Result<Option<(Severity, &str)>> {
+        let mut strictest: Option<(Severity, &str)> = None;
+        for (policy, pattern) in self.policies(repository, tag) {
+            let severity: Severity = match &policy.fail_on {
+                Some(fail_on) => fail_on.parse().with_context(|| {
+                    format!("invalid fail_on in the promotion policy of {}", repository)
+                })?,
+                None => continue,
+            };
+            if !matches!(strictest, Some((strictest, _)) if strictest <= severity) {
+                strictest = Some((severity, pattern));
+            }
+        }
+        Ok(strictest)
+    }
+
+    /// The longest `min_age` of `repository`'s policies covering `tag`,
+    /// with the tag pattern that set it.
+    fn min_age(&self, repository: &str, tag: &str) -> Result<Option<(chrono::Duration, &str)>> {
+        let mut longest: Option<(chrono::Duration, &str)> = None;
+        for (policy, pattern) in self.policies(repository, tag) {
+            let min_age = match &policy.min_age {
+                Some(min_age) => retention::parse_age(min_age).with_context(|| {
+                    format!("invalid min_age in the promotion policy of {}", repository)
+                })?,
+                None => continue,
+            };
+            if !matches!(longest, Some((longest, _)) if longest >= min_age) {
+                longest = Some((min_age, pattern));
+            }
+        }
+        Ok(longest)
+    }
+}
+
+/// Why `digest` can't be tagged at `threshold`, if it can't.
+fn describe(
+    counts: Option<&BTreeMap<String, i64>>,
+    digest: &str,
+    threshold: Severity,
+) -> Option<String> {
+    let counts = match counts {
+        Some(counts) => counts,
+        None => return Some(format!("{} has no scan results", digest)),
+    };
+    let blocking = blocking(counts, threshold);
+    match blocking.is_empty() {
+        true => None,
+        false => Some(format!(
+            "{} has findings at or above {}: {}",
+            digest,
+            threshold,
+            blocking.join(", ")
+        )),
+    }
+}
+
+/// "CRITICAL 2"-style entries for every severity at or above `threshold`.
+fn blocking(counts: &BTreeMap<String, i64>, threshold: Severity) -> Vec<String> {
+    let mut blocking: Vec<(Severity, i64)> = counts
+        .iter()
+        .filter(|(_, count)| **count > 0)
+        .filter_map(|(severity, count)| Some((severity.parse::<Severity>().ok()?, *count)))
+        .filter(|(severity, _)| *severity >= threshold)
+        .collect();
+    blocking.sort_by_key(|(severity, _)| std::cmp::Reverse(*severity));
+    blocking
+        .into_iter()
+        .map(|(severity, count)| format!("{} {}", severity, count))
+        .collect()
+}
@@ -0,0 +1,313 @@
+use std::process::Stdio;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use tokio::process::Command;
+
+use crate::config::Signing;
+use crate::ecr::{Decorator, Destination, EcrApi, Image};
+
+/// Signs every image tagged through `inner` with cosign, using the
+/// `[signing]` key, so signatures follow tags as they move.
+///
+/// cosign pushes the signature itself, so it needs registry credentials of
+/// its own, e.g. from `aws ecr get-login-password | docker login`. A failed
+/// signature fails the run, but the tag has already moved by then.
+pub struct SigningEcr {
+    inner: Box<dyn EcrApi>,
+    signing: Signing,
+    profile: Option<String>,
+}
+
+impl SigningEcr {
+    /// cosign runs with `AWS_PROFILE` set to `profile`, when given.
+    pub fn wrap(
+        inner: Box<dyn EcrApi>,
+        signing: &Signing,
+        profile: Option<&str>,
+    ) -> Box<dyn EcrApi> {
+        if signing.key.is_none() {
+            return inner;
+        }
+        Box::new(Self {
+            inner,
+            signing: signing.clone(),
+            profile: profile.map(str::to_string),
+        })
+    }
+}
+
+#[async_trait]
+impl Decorator for SigningEcr {
+    fn inner(&self) -> &dyn EcrApi {
+        self.inner.as_ref()
+    }
+
+    async fn put_image(&self, image: &Image, tag: &str, destination: &Destination) -> Result<()> {
+        self.inner.put_image(image, tag, destination).await?;
+        let reference = reference(
+            self.inner.as_ref(),
+            &image.repository_name,
+            destination,
+            &image.digest,
+        )
+        .await?;
+        sign(&self.signing, self.profile.as_deref(), &reference, tag)
+            .await
+            .with_context(|| format!("tagged {} but failed to sign {}", tag, reference))
+    }
+}
+
+/// The `registry/repository@digest` reference of `digest`, pushed to
+/// `repository` and put in `destination`.
+pub async fn reference(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    destination: &Destination,
+    digest: &str,
+) -> Result<String> {
+    Ok(format!(
+        "{}@{}",
+        repository_uri(ecr, repository, destination).await?,
+        digest
+    ))
+}
+
+/// The `registry/repository` that images pushed to `repository` end up in
+/// once put in `destination`.
+pub async fn repository_uri(
+    ecr: &dyn EcrApi,
+    repository: &str,
+    destination: &Destination,
+) -> Result<String> {
+    let source = ecr
+        .repositories()
+        .await?
+        .into_iter()
+        .find(|candidate| candidate.name == repository)
+        .ok_or_else(|| anyhow!("repository {} not found", repository))?;
+    let host = source.uri.split('/').next().unwrap_or_default();
+    let host = match (&destination.registry_id, host.split_once('.')) {
+        (Some(registry_id), Some((_, rest))) => format!("{}.{}", registry_id, rest),
+        _ => host.to_string(),
+    };
+    let repository = destination.repository_name.as_deref().unwrap_or(repository);
+    Ok(format!("{}/{}", host, repository))
+}
+
+/// Runs `cosign verify` on `repository@digest`, against the `[signing]` key
+/// or, without one, the keyless `identity` and `issuer`, with `AWS_PROFILE`
+/// set to `profile` when given.
+pub async fn verify(
+    ecr: &dyn EcrApi,
+    signing: &Signing,
+    profile: Option<&str>,
+    repository: &str,
+    digest: &str,
+) -> Result<()> {
+    let mut command = Command::new(signing.cosign.as_deref().unwrap_or("cosign"));
+    command.arg("verify");
+    if let Some(profile) = profile {
+        command.env("AWS_PROFILE", profile);
+    }
+    match (&signing.key, &signing.identity, &signing.issuer) {
+        (Some(key), _, _) => {
+            command.args(["--key", key]);
+        }
+        (None, Some(identity), Some(issuer)) => {
+            command
+                .args(["--certificate-identity", identity])
+                .args(["--certificate-oidc-issuer", issuer]);
+        }
+        _ => bail!("--require-signature needs a [signing] key, or an identity and issuer"),
+    }
+    let reference = reference(ecr, repository, &Destination::default(), digest).await?;
+    // cosign prints the verified payloads on stdout; only its verdict matters.
+    let status = command
+        .arg(&reference)
+        .stdout(Stdio::null())
+        .status()
+        .await
+        .context("failed to run cosign")?;
+    if !status.success() {
+        bail!(
+            "{} has no valid signature; refusing to tag it (--require-signature)",
+            reference
+        );
+    }
+    Ok(())
+}
+
+/// Runs `cosign sign` on `reference`, annotating the signature with the tag.
+async fn sign(signing: &Signing, profile: Option<&str>, reference: &str, tag: &str) -> Result<()> {
+    let cosign = signing.cosign.as_deref().unwrap_or("cosign");
+    let key = signing.key.as_deref().unwrap_or_default();
+    let mut command = Command::new(cosign);
+    if let Some(profile) = profile {
+        command.env("AWS_PROFILE", profile);
+    }
+    let status = command
+        .args(["sign", "--yes", "--key", key])
+        .args(["-a", &format!("tag={}", tag)])
+        .arg(reference)
+        .status()
+        .await
+        .with_context(|| format!("failed to run {}", cosign))?;
+    if !status.success() {
+        bail!("{} sign exited with {}", cosign, status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::ecr::MockEcr;
+
+    /// A stand-in for cosign logging its arguments to `<script>.log`, and
+    /// failing when given `status`.
+    fn fake_cosign(name: &str, status: i32) -> (PathBuf, PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("ecr-tag-cosign-{}-{}", name, std::process::id()));
+        let log = path.with_extension("log");
+        let _ = std::fs::remove_file(&log);
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho \"$AWS_PROFILE $*\" >> {}\nexit {}\n",
+                log.display(),
+                status
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        (path, log)
+    }
+
+    fn signing(cosign: &std::path::Path) -> Signing {
+        Signing {
+            key: Some("cosign.key".to_string()),
+            cosign: Some(cosign.display().to_string()),
+            ..Signing::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn signs_the_tagged_digest() {
+        let (cosign, log) = fake_cosign("sign", 0);
+        let ecr = SigningEcr::wrap(
+            Box::new(MockEcr::app(&[&["v1"], &[]])),
+            &signing(&cosign),
+            Some("ci"),
+        );
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+
+        ecr.put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            format!(
+                "ci sign --yes --key cosign.key -a tag=latest \
+                 000000000000.dkr.ecr.mock.amazonaws.com/app@{}\n",
+                digest
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn repository_uri_points_at_the_destination() {
+        let ecr = MockEcr::app(&[&["v1"]]);
+        let destination = Destination {
+            registry_id: Some("111111111111".to_string()),
+            repository_name: Some("app-prod".to_string()),
+        };
+
+        assert_eq!(
+            repository_uri(&ecr, "app", &destination).await.unwrap(),
+            "111111111111.dkr.ecr.mock.amazonaws.com/app-prod"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_failed_signature_fails_the_tag_but_keeps_it() {
+        let (cosign, _) = fake_cosign("sign-fails", 1);
+        let ecr = SigningEcr::wrap(
+            Box::new(MockEcr::app(&[&["v1"], &[]])),
+            &signing(&cosign),
+            None,
+        );
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let image = ecr.image(None, "app", &digest).await.unwrap();
+
+        let err = ecr
+            .put_image(&image, "latest", &Destination::default())
+            .await
+            .unwrap_err();
+
+        assert!(format!("{:#}", err).starts_with("tagged latest but failed to sign"));
+        assert_eq!(
+            ecr.resolve_tag(None, "app", "latest").await.unwrap(),
+            Some(digest)
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_checks_the_key_or_the_keyless_identity() {
+        let (cosign, log) = fake_cosign("verify", 0);
+        let ecr = MockEcr::app(&[&["v1"]]);
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let keyless = Signing {
+            cosign: Some(cosign.display().to_string()),
+            identity: Some("ci@example.com".to_string()),
+            issuer: Some("https://issuer.example.com".to_string()),
+            ..Signing::default()
+        };
+
+        verify(&ecr, &signing(&cosign), None, "app", &digest)
+            .await
+            .unwrap();
+        verify(&ecr, &keyless, None, "app", &digest).await.unwrap();
+
+        let reference = format!("000000000000.dkr.ecr.mock.amazonaws.com/app@{}", digest);
+        assert_eq!(
+            std::fs::read_to_string(&log).unwrap(),
+            format!(
+                " verify --key cosign.key {}\n \
+                 verify --certificate-identity ci@example.com \
+                 --certificate-oidc-issuer https://issuer.example.com {}\n",
+                reference, reference
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_refuses_unsigned_images_and_missing_settings() {
+        let (cosign, _) = fake_cosign("verify-fails", 1);
+        let ecr = MockEcr::app(&[&["v1"]]);
+        let digest = ecr.tag_digest("app", "v1").await.unwrap();
+        let unset = Signing {
+            cosign: Some(cosign.display().to_string()),
+            ..Signing::default()
+        };
+
+        let unsigned = verify(&ecr, &signing(&cosign), None, "app", &digest)
+            .await
+            .unwrap_err();
+        let unset = verify(&ecr, &unset, None, "app", &digest)
+            .await
+            .unwrap_err();
+
+        assert!(unsigned
+            .to_string()
+            .ends_with("has no valid signature; refusing to tag it (--require-signature)"));
+        assert!(unset
+            .to_string()
+            .starts_with("--require-signature needs a [signing] key"));
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Progress of a bulk run, saved after every step so `--resume` can pick up
+/// an interrupted run without redoing what already happened.
+pub struct StateFile<T> {
+    path: PathBuf,
+    run: Run<T>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Run<T> {
+    command: String,
+    plan: T,
+    done: BTreeSet<String>,
+}
+
+/// `--state-file`, or `<data dir>/ecr-tag/<command>.state.json`.
+pub fn path(explicit: Option<&Path>, command: &str) -> Result<PathBuf> {
+    match explicit {
+        Some(path) => Ok(path.to_path_buf()),
+        None => dirs_next::data_dir()
+            .map(|dir| dir.join("ecr-tag").join(format!("{}.state.json", command)))
+            .ok_or_else(|| anyhow!("no data directory for the state file, pass --state-file")),
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> StateFile<T> {
+    /// Records a new plan, replacing whatever an earlier run left behind.
+    pub fn start(path: PathBuf, command: &str, plan: T) -> Result<Self> {
+        let state = Self {
+            path,
+            run: Run {
+                command: command.to_string(),
+                plan,
+                done: BTreeSet::new(),
+            },
+        };
+        state.save()?;
+        Ok(state)
+    }
+
+    pub fn resume(path: PathBuf, command: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(&path).with_context(|| {
+            format!(
+                "no interrupted {} run to resume ({})",
+                command,
+                path.display()
+            )
+        })?;
+        let invalid = || format!("invalid state file {}", path.display());
+        let run: Run<serde_json::Value> = serde_json::from_str(&contents).with_context(invalid)?;
+        if run.command != command {
+            bail!(
+                "{} holds a {} run, not {}",
+                path.display(),
+                run.command,
+                command
+            );
+        }
+        let run = Run {
+            plan: serde_json::from_value(run.plan).with_context(invalid)?,
+            command: run.command,
+            done: run.done,
+        };
+        Ok(Self { path, run })
+    }
+
+    pub fn plan(&self) -> &T {
+        &self.run.plan
+    }
+
+    pub fn is_done(&self, step: &str) -> bool {
+        self.run.done.contains(step)
+    }
+
+    pub fn done(&self) -> usize {
+        self.run.done.len()
+    }
+
+    pub fn mark_done(&mut self, step: &str) -> Result<()> {
+        self.run.done.insert(step.to_string());
+        self.save()
+    }
+
+    /// Removes the state file once every step has succeeded.
+    pub fn finish(self) -> Result<()> {
+        std::fs::remove_file(&self.path)
+            .with_context(|| format!("failed to remove {}", self.path.display()))
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write then rename, so an interruption never leaves half a file.
+        let partial = self.path.with_extension("partial");
+        std::fs::write(&partial, serde_json::to_string_pretty(&self.run)?)
+            .with_context(|| format!("failed to write {}", partial.display()))?;
+        std::fs::rename(&partial, &self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+}
@@ -0,0 +1,107 @@
+use anyhow::{bail, Result};
+use chrono::Utc;
+use inquire::ui::Color;
+
+/// ECR rejects image tags longer than this.
+pub const MAX_TAG_LENGTH: usize = 300;
+
+/// Checks a tag against the rules PutImage enforces, so we can re-prompt
+/// instead of failing at the very end of the flow.
+pub fn validate(tag: &str) -> Result<()> {
+    if tag.is_empty() {
+        bail!("tag must not be empty");
+    }
+    if tag.len() > MAX_TAG_LENGTH {
+        bail!("tag must be at most {} characters", MAX_TAG_LENGTH);
+    }
+    let mut chars = tag.chars();
+    let first = chars.next().unwrap();
+    if !(first.is_ascii_alphanumeric() || first == '_') {
+        bail!("tag must start with a letter, digit or underscore");
+    }
+    if let Some(c) = chars.find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))) {
+        bail!(
+            "tag may only contain letters, digits, `_`, `.` and `-` (found `{}`)",
+            c
+        );
+    }
+    Ok(())
+}
+
+/// Whether `tag` matches `pattern`, where `*` stands for any run of characters.
+pub fn matches(pattern: &str, tag: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == tag,
+        Some((prefix, rest)) => {
+            tag.starts_with(prefix)
+                && (0..=tag.len() - prefix.len())
+                    .filter(|&i| tag.is_char_boundary(prefix.len() + i))
+                    .any(|i| matches(rest, &tag[prefix.len() + i..]))
+        }
+    }
+}
+
+/// Whether `tag` looks like a release, e.g. `v3`, `1.4` or `v2.0.1`; a
+/// bare number could as well be a build number.
+pub fn is_release(tag: &str) -> bool {
+    let (version, prefixed) = match tag.strip_prefix('v') {
+        Some(version) => (version, true),
+        None => (tag, false),
+    };
+    let parts: Vec<&str> = version.split('.').collect();
+    (prefixed || parts.len() >= 2)
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// The colour `tag` is listed in, so releases and `latest` stand out.
+pub fn color(tag: &str) -> Option<Color> {
+    match tag {
+        "latest" => Some(Color::LightYellow),
+        _ if is_release(tag) => Some(Color::LightGreen),
+        _ => None,
+    }
+}
+
+/// Expands `{date}` (today, YYYYMMDD) and `{n}` (1-based position) in a tag template.
+pub fn render_template(template: &str, n: usize) -> String {
+    template
+        .replace("{date}", &Utc::now().format("%Y%m%d").to_string())
+        .replace("{n}", &n.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_valid_tags() {
+        for tag in [
+            "latest",
+            "v1.2.3",
+            "_build",
+            "2023-01-01",
+            "a".repeat(MAX_TAG_LENGTH).as_str(),
+        ] {
+            assert!(validate(tag).is_ok(), "{} should be valid", tag);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_tags() {
+        assert_eq!(
+            validate("").unwrap_err().to_string(),
+            "tag must not be empty"
+        );
+        assert!(validate(&"a".repeat(MAX_TAG_LENGTH + 1)).is_err());
+        assert!(validate(".hidden").is_err());
+        assert!(validate("-flag").is_err());
+        assert_eq!(
+            validate("has space").unwrap_err().to_string(),
+            "tag may only contain letters, digits, `_`, `.` and `-` (found ` `)"
+        );
+        assert!(validate("café").is_err());
+        assert!(validate("é").is_err());
+    }
+}
@@ -0,0 +1,145 @@
+use anyhow::{bail, Result};
+use inquire::ui::{Attributes, Color, RenderConfig, StyleSheet, Styled};
+
+use crate::config::{Theme, ThemeStyle};
+
+pub const PRESETS: &[&str] = &["default", "ocean", "inquire"];
+
+/// Builds the prompt render config from the `[theme]` section of the config.
+pub fn render_config(theme: &Theme, unicode: bool) -> Result<RenderConfig> {
+    let mut config = preset(theme.preset.as_deref().unwrap_or("default"))?;
+    if !unicode {
+        config = ascii(config);
+    }
+
+    if let Some(style) = &theme.prompt_prefix {
+        config.prompt_prefix = apply_styled(config.prompt_prefix, style)?;
+    }
+    if let Some(style) = &theme.highlighted_option {
+        config.highlighted_option_prefix = apply_styled(config.highlighted_option_prefix, style)?;
+    }
+    if let Some(style) = &theme.selected_checkbox {
+        config.selected_checkbox = apply_styled(config.selected_checkbox, style)?;
+    }
+    if let Some(style) = &theme.answer {
+        config.answer = apply_sheet(config.answer, style)?;
+    }
+    if let Some(style) = &theme.help_message {
+        config.help_message = apply_sheet(config.help_message, style)?;
+    }
+
+    Ok(config)
+}
+
+fn preset(name: &str) -> Result<RenderConfig> {
+    let config = match name {
+        "default" => RenderConfig {
+            prompt_prefix: Styled::new("$").with_fg(Color::LightRed),
+            selected_checkbox: Styled::new("☑").with_fg(Color::LightGreen),
+            scroll_up_prefix: Styled::new("⇞"),
+            scroll_down_prefix: Styled::new("⇟"),
+            answer: StyleSheet::new()
+                .with_attr(Attributes::BOLD)
+                .with_fg(Color::LightGreen),
+            help_message: StyleSheet::new().with_fg(Color::DarkYellow),
+            ..Default::default()
+        },
+        "ocean" => RenderConfig {
+            prompt_prefix: Styled::new("❯").with_fg(Color::LightBlue),
+            answered_prompt_prefix: Styled::new("❯").with_fg(Color::DarkCyan),
+            highlighted_option_prefix: Styled::new("›").with_fg(Color::LightCyan),
+            selected_checkbox: Styled::new("◉").with_fg(Color::LightCyan),
+            unselected_checkbox: Styled::new("○"),
+            scroll_up_prefix: Styled::new("⇞"),
+            scroll_down_prefix: Styled::new("⇟"),
+            answer: StyleSheet::new()
+                .with_attr(Attributes::BOLD)
+                .with_fg(Color::LightCyan),
+            help_message: StyleSheet::new().with_fg(Color::DarkBlue),
+            ..Default::default()
+        },
+        "inquire" => RenderConfig::default_colored(),
+        _ => bail!(
+            "unknown theme preset `{}` (expected one of: {})",
+            name,
+            PRESETS.join(", ")
+        ),
+    };
+    Ok(config)
+}
+
+/// Swaps the presets' glyphs for ASCII ones, keeping their styles.
+fn ascii(mut config: RenderConfig) -> RenderConfig {
+    let swap = |styled: Styled<&'static str>, ascii: &'static str| match styled.content.is_ascii() {
+        true => styled,
+        false => Styled::new(ascii).with_style_sheet(styled.style),
+    };
+    config.prompt_prefix = swap(config.prompt_prefix, "?");
+    config.answered_prompt_prefix = swap(config.answered_prompt_prefix, ">");
+    config.highlighted_option_prefix = swap(config.highlighted_option_prefix, ">");
+    config.selected_checkbox = swap(config.selected_checkbox, "[x]");
+    config.unselected_checkbox = swap(config.unselected_checkbox, "[ ]");
+    config.scroll_up_prefix = swap(config.scroll_up_prefix, "^");
+    config.scroll_down_prefix = swap(config.scroll_down_prefix, "v");
+    config
+}
+
+fn apply_styled(styled: Styled<&'static str>, style: &ThemeStyle) -> Result<Styled<&'static str>> {
+    let content = match &style.symbol {
+        // The render config wants 'static strs; the config lives for the whole run anyway.
+        Some(symbol) => Box::leak(symbol.clone().into_boxed_str()),
+        None => styled.content,
+    };
+    Ok(Styled::new(content).with_style_sheet(apply_sheet(styled.style, style)?))
+}
+
+fn apply_sheet(mut sheet: StyleSheet, style: &ThemeStyle) -> Result<StyleSheet> {
+    if let Some(fg) = &style.fg {
+        sheet.fg = Some(parse_color(fg)?);
+    }
+    if let Some(bg) = &style.bg {
+        sheet.bg = Some(parse_color(bg)?);
+    }
+    if let Some(bold) = style.bold {
+        sheet.att.set(Attributes::BOLD, bold);
+    }
+    if let Some(italic) = style.italic {
+        sheet.att.set(Attributes::ITALIC, italic);
+    }
+    Ok(sheet)
+}
+
+/// Accepts color names (`light_red`, `dark_cyan`, ...), `#rrggbb` or an ANSI index.
+fn parse_color(value: &str) -> Result<Color> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(rgb) = u32::from_str_radix(hex, 16) {
+                return Ok(Color::rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+            }
+        }
+        bail!("invalid color `{}`", value);
+    }
+    if let Ok(index) = value.parse::<u8>() {
+        return Ok(Color::AnsiValue(index));
+    }
+    let color = match value.to_lowercase().replace('-', "_").as_str() {
+        "black" => Color::Black,
+        "light_red" | "red" => Color::LightRed,
+        "dark_red" => Color::DarkRed,
+        "light_green" | "green" => Color::LightGreen,
+        "dark_green" => Color::DarkGreen,
+        "light_yellow" | "yellow" => Color::LightYellow,
+        "dark_yellow" => Color::DarkYellow,
+        "light_blue" | "blue" => Color::LightBlue,
+        "dark_blue" => Color::DarkBlue,
+        "light_magenta" | "magenta" => Color::LightMagenta,
+        "dark_magenta" => Color::DarkMagenta,
+        "light_cyan" | "cyan" => Color::LightCyan,
+        "dark_cyan" => Color::DarkCyan,
+        "white" => Color::White,
+        "grey" | "gray" => Color::Grey,
+        "dark_grey" | "dark_gray" => Color::DarkGrey,
+        _ => bail!("invalid color `{}`", value),
+    };
+    Ok(color)
+}
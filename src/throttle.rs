@@ -0,0 +1,40 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use tokio::time::Instant;
+
+/// Spaces out API requests to at most `max_rps` a second, so bulk scans
+/// leave room for other automation sharing the account's limits. Waited on
+/// before every request, each page of a listing included, and shared with
+/// the registry's replicas.
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// `None` when no limit is set.
+    pub fn new(max_rps: Option<f64>) -> Result<Option<Arc<Self>>> {
+        let max_rps = match max_rps {
+            Some(max_rps) if max_rps > 0.0 => max_rps,
+            Some(max_rps) => bail!("max_rps must be positive, got {}", max_rps),
+            None => return Ok(None),
+        };
+        Ok(Some(Arc::new(Self {
+            interval: Duration::from_secs_f64(1.0 / max_rps),
+            next: Mutex::new(Instant::now()),
+        })))
+    }
+
+    /// Waits for the next free slot; slots are handed out in call order.
+    pub async fn wait(&self) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let slot = (*next).max(Instant::now());
+            *next = slot + self.interval;
+            slot
+        };
+        tokio::time::sleep_until(slot).await;
+    }
+}
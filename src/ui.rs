@@ -0,0 +1,121 @@
+use std::fmt::Display;
+
+use anyhow::Result;
+use crossterm::{style::Stylize, tty::IsTty};
+use inquire::{ui::RenderConfig, validator::Validation, Confirm, Text};
+
+use crate::config::Config;
+use crate::picker::{Keymap, Picker};
+use crate::tags;
+use crate::theme;
+
+/// Prompt settings shared by every prompt in a run.
+#[derive(Clone)]
+pub struct Ui {
+    pub render_config: RenderConfig,
+    pub keymap: Keymap,
+    /// Whether output on stdout is styled.
+    pub color: bool,
+    /// Whether prompts and messages on stderr are; either may be redirected
+    /// without the other.
+    pub stderr_color: bool,
+    /// Whether glyphs beyond ASCII can be drawn.
+    pub unicode: bool,
+}
+
+impl Ui {
+    pub fn from_config(config: &Config, no_color: bool) -> Result<Self> {
+        let color = !no_color && color_supported(std::io::stdout());
+        let stderr_color = !no_color && color_supported(std::io::stderr());
+        let unicode = config
+            .theme
+            .ascii
+            .map_or_else(unicode_supported, |ascii| !ascii);
+        Ok(Self {
+            render_config: match stderr_color {
+                true => theme::render_config(&config.theme, unicode)?,
+                false => RenderConfig::empty(),
+            },
+            keymap: Keymap::from_config(&config.keybindings)?,
+            color,
+            stderr_color,
+            unicode,
+        })
+    }
+
+    pub fn picker<'a, T: Display>(&self, message: &'a str, options: Vec<T>) -> Picker<'a, T> {
+        Picker::new(message, options)
+            .with_page_size(10)
+            .with_unicode(self.unicode)
+            .with_render_config(self.render_config)
+            .with_keymap(self.keymap.clone())
+    }
+
+    pub fn text<'a>(&self, message: &'a str) -> Text<'a> {
+        Text::new(message).with_render_config(self.render_config)
+    }
+
+    pub fn confirm<'a>(&self, message: &'a str) -> Confirm<'a> {
+        Confirm::new(message)
+            .with_default(false)
+            .with_render_config(self.render_config)
+    }
+
+    /// A text prompt that only accepts valid ECR tags.
+    pub fn tag<'a>(&self, message: &'a str) -> Text<'a> {
+        self.text(message).with_validator(|input: &str| {
+            Ok(match tags::validate(input) {
+                Ok(()) => Validation::Valid,
+                Err(err) => Validation::Invalid(err.to_string().into()),
+            })
+        })
+    }
+
+    /// Prints a warning that needs noticing, in yellow.
+    pub fn warning(&self, message: &str) {
+        match self.stderr_color {
+            true => eprintln!("{} {}", "warning:".yellow().bold(), message.yellow()),
+            false => eprintln!("warning: {}", message),
+        }
+    }
+
+    /// Prints a per-item result line, e.g. one row of a bulk summary.
+    pub fn status(&self, ok: bool, message: &str) {
+        match (self.color, ok) {
+            (true, true) if self.unicode => println!("{} {}", "✔".green(), message),
+            (true, false) if self.unicode => println!("{} {}", "✘".red(), message),
+            (true, true) => println!("{} {}", "ok:".green(), message),
+            (true, false) => println!("{} {}", "failed:".red(), message),
+            (false, true) => println!("ok: {}", message),
+            (false, false) => println!("failed: {}", message),
+        }
+    }
+}
+
+/// Whether the locale is UTF-8, taken from the first of `LC_ALL`,
+/// `LC_CTYPE` and `LANG` that's set; the Linux console's font has few of
+/// the glyphs either way. Windows terminals draw them regardless of locale.
+fn unicode_supported() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    if std::env::var("TERM").is_ok_and(|term| term == "linux") {
+        return false;
+    }
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|locale| {
+            let locale = locale.to_lowercase();
+            locale.contains("utf-8") || locale.contains("utf8")
+        })
+}
+
+/// Honours https://no-color.org and skips styling for dumb terminals, or
+/// when `stream` is redirected.
+fn color_supported(stream: impl IsTty) -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+    !no_color && !dumb && stream.is_tty()
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::io::{stderr, stdout, Write};
+
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyModifiers},
+    execute, queue,
+    style::{Print, PrintStyledContent, StyledContent, Stylize},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    tty::IsTty,
+};
+use inquire::error::InquireError;
+use serde_json::Value;
+
+/// One line of pretty printed JSON.
+struct Row<'v> {
+    depth: usize,
+    key: Option<String>,
+    kind: Kind<'v>,
+    /// JSON pointer of the container an `Open` row starts, used to collapse it.
+    path: String,
+    comma: bool,
+}
+
+enum Kind<'v> {
+    Open {
+        bracket: char,
+        len: usize,
+        collapsed: bool,
+    },
+    Close(char),
+    Scalar(&'v Value),
+}
+
+fn rows<'v>(value: &'v Value, collapsed: &HashSet<String>) -> Vec<Row<'v>> {
+    let mut out = vec![];
+    push_rows(value, None, String::new(), 0, false, collapsed, &mut out);
+    out
+}
+
+fn push_rows<'v>(
+    value: &'v Value,
+    key: Option<String>,
+    path: String,
+    depth: usize,
+    comma: bool,
+    collapsed: &HashSet<String>,
+    out: &mut Vec<Row<'v>>,
+) {
+    let children: Vec<(Option<String>, &Value)> = match value {
+        Value::Object(map) => map.iter().map(|(k, v)| (Some(k.clone()), v)).collect(),
+        Value::Array(items) => items.iter().map(|v| (None, v)).collect(),
+        scalar => {
+            out.push(Row {
+                depth,
+                key,
+                kind: Kind::Scalar(scalar),
+                path,
+                comma,
+            });
+            return;
+        }
+    };
+    let (open, close) = match value {
+        Value::Object(_) => ('{', '}'),
+        _ => ('[', ']'),
+    };
+    let is_collapsed = collapsed.contains(&path) && !children.is_empty();
+    out.push(Row {
+        depth,
+        key,
+        kind: Kind::Open {
+            bracket: open,
+            len: children.len(),
+            collapsed: is_collapsed,
+        },
+        path: path.clone(),
+        comma: comma && (is_collapsed || children.is_empty()),
+    });
+    if is_collapsed || children.is_empty() {
+        return;
+    }
+    let count = children.len();
+    for (i, (child_key, child)) in children.into_iter().enumerate() {
+        let segment = child_key.clone().unwrap_or_else(|| i.to_string());
+        push_rows(
+            child,
+            child_key,
+            format!("{}/{}", path, segment),
+            depth + 1,
+            i + 1 < count,
+            collapsed,
+            out,
+        );
+    }
+    out.push(Row {
+        depth,
+        key: None,
+        kind: Kind::Close(close),
+        path,
+        comma,
+    });
+}
+
+fn render_row(row: &Row, color: bool) -> Vec<StyledContent<String>> {
+    let paint = |text: String, painter: fn(String) -> StyledContent<String>| match color {
+        true => painter(text),
+        false => StyledContent::new(Default::default(), text),
+    };
+    let mut parts = vec![paint("  ".repeat(row.depth), |s| s.reset())];
+    if let Some(key) = &row.key {
+        parts.push(paint(Value::from(key.as_str()).to_string(), |s| s.cyan()));
+        parts.push(paint(": ".to_string(), |s| s.reset()));
+    }
+    match &row.kind {
+        Kind::Open {
+            bracket, len: 0, ..
+        } => {
+            let close = if *bracket == '{' { '}' } else { ']' };
+            parts.push(paint(format!("{}{}", bracket, close), |s| s.reset()));
+        }
+        Kind::Open {
+            bracket,
+            len,
+            collapsed: true,
+        } => {
+            let close = if *bracket == '{' { '}' } else { ']' };
+            parts.push(paint(format!("{}…{}", bracket, close), |s| s.reset()));
+            let noun = if *bracket == '{' { "key" } else { "item" };
+            let plural = if *len == 1 { "" } else { "s" };
+            parts.push(paint(format!(" {} {}{}", len, noun, plural), |s| {
+                s.dark_grey()
+            }));
+        }
+        Kind::Open { bracket, .. } => parts.push(paint(bracket.to_string(), |s| s.reset())),
+        Kind::Close(bracket) => parts.push(paint(bracket.to_string(), |s| s.reset())),
+        Kind::Scalar(value) => {
+            let text = value.to_string();
+            parts.push(match value {
+                Value::String(_) => paint(text, |s| s.green()),
+                Value::Number(_) => paint(text, |s| s.yellow()),
+                _ => paint(text, |s| s.magenta()),
+            });
+        }
+    }
+    if row.comma {
+        parts.push(paint(",".to_string(), |s| s.reset()));
+    }
+    parts
+}
+
+/// Prints `value` pretty printed, highlighted when `color` is set.
+pub fn print(value: &Value, color: bool) -> Result<()> {
+    let mut out = stdout();
+    for row in rows(value, &HashSet::new()) {
+        for part in render_row(&row, color) {
+            queue!(out, PrintStyledContent(part))?;
+        }
+        queue!(out, Print("\n"))?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Pages through `value` with collapsible objects and arrays when attached to
+/// a terminal; falls back to [`print`] otherwise.
+pub fn show(title: &str, value: &Value, color: bool) -> Result<()> {
+    if !color || !std::io::stdin().is_tty() || !stderr().is_tty() {
+        return print(value, color);
+    }
+
+    let mut out = stderr();
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, cursor::Hide)?;
+    let result = browse(title, value, &mut out);
+    execute!(out, LeaveAlternateScreen, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn browse(title: &str, value: &Value, out: &mut impl Write) -> Result<()> {
+    let mut collapsed = HashSet::new();
+    let mut cursor = 0usize;
+    let mut top = 0usize;
+    loop {
+        let rows = rows(value, &collapsed);
+        cursor = cursor.min(rows.len().saturating_sub(1));
+        let (width, height) = terminal::size().unwrap_or((80, 24));
+        let page = (height as usize).saturating_sub(2).max(1);
+        if cursor < top {
+            top = cursor;
+        } else if cursor >= top + page {
+            top = cursor + 1 - page;
+        }
+
+        queue!(out, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
+        queue!(out, PrintStyledContent(title.to_string().bold()))?;
+        for (line, row) in rows.iter().enumerate().skip(top).take(page) {
+            queue!(out, Print("\r\n"))?;
+            let marker = if line == cursor { "›" } else { " " };
+            queue!(
+                out,
+                PrintStyledContent(marker.to_string().cyan()),
+                Print(" ")
+            )?;
+            let mut used = 2;
+            for part in render_row(row, true) {
+                let available = (width as usize).saturating_sub(used);
+                let text: String = part.content().chars().take(available).collect();
+                used += text.chars().count();
+                queue!(
+                    out,
+                    PrintStyledContent(StyledContent::new(*part.style(), text))
+                )?;
+            }
+        }
+        queue!(
+            out,
+            cursor::MoveTo(0, height.saturating_sub(1)),
+            PrintStyledContent(
+                "[↑↓ to move, enter/space to fold, ←→ to collapse/expand, q to quit]"
+                    .to_string()
+                    .dark_yellow()
+            )
+        )?;
+        out.flush()?;
+
+        let key = match event::read()? {
+            Event::Key(key) => key,
+            _ => continue,
+        };
+        if key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL {
+            return Err(InquireError::OperationInterrupted.into());
+        }
+        let current = rows.get(cursor);
+        let foldable = current.filter(|row| matches!(row.kind, Kind::Open { len, .. } if len > 0));
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => cursor = cursor.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => cursor += 1,
+            KeyCode::PageUp => cursor = cursor.saturating_sub(page),
+            KeyCode::PageDown => cursor += page,
+            KeyCode::Home | KeyCode::Char('g') => cursor = 0,
+            KeyCode::End | KeyCode::Char('G') => cursor = usize::MAX,
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                if let Some(row) = foldable {
+                    if !collapsed.remove(&row.path) {
+                        collapsed.insert(row.path.clone());
+                    }
+                }
+            }
+            KeyCode::Left | KeyCode::Char('h') => {
+                if let Some(row) = foldable {
+                    collapsed.insert(row.path.clone());
+                }
+            }
+            KeyCode::Right | KeyCode::Char('l') => {
+                if let Some(row) = foldable {
+                    collapsed.remove(&row.path);
+                }
+            }
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            _ => {}
+        }
+    }
+}